@@ -1,5 +1,5 @@
 use crate::{
-    interval::{EMPTY_INTERVAL, Interval, UNIVERSE_INTERVAL}, ray::Ray, utils::double_eq, vec3::Point3
+    interval::{EMPTY_INTERVAL, Interval, UNIVERSE_INTERVAL}, ray::Ray, utils::double_eq, vec3::{Point3, Vec3}
 };
 
 #[derive(Debug, Copy, Clone, Default)]
@@ -39,6 +39,25 @@ impl AABB {
         }
     }
 
+    // Flat boxes, e.g. around a quad in an axis plane, padded so no side is thinner than delta
+    pub fn pad_to_minimums(&self) -> AABB {
+        let delta = 0.0001;
+        let pad = |i: Interval| if i.size() < delta { i.expand(delta) } else { i };
+        AABB::new(pad(self.x), pad(self.y), pad(self.z))
+    }
+
+    pub fn translate(&self, offset: Vec3) -> AABB {
+        let shift = |i: Interval, d: f64| Interval {
+            min: i.min + d,
+            max: i.max + d,
+        };
+        AABB::new(
+            shift(self.x, offset.x),
+            shift(self.y, offset.y),
+            shift(self.z, offset.z),
+        )
+    }
+
     pub fn axis_interval(&self, n: usize) -> Interval {
         assert!(n < 3);
 
@@ -99,7 +118,6 @@ pub const UNIVERSE_AABB: AABB = AABB {x: UNIVERSE_INTERVAL, y: UNIVERSE_INTERVAL
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::vec3::Vec3;
 
     fn assert_interval(interval: Interval, min: f64, max: f64) {
         assert!(
@@ -153,6 +171,7 @@ mod tests {
             origin: Vec3::new(-2.0, -2.0, -2.0),
             dir: Vec3::new(1.0, 1.0, 1.0),
             time: 0.0,
+            medium_sample: 0.0,
         };
 
         assert!(bbox.hit(&ray, UNIVERSE_INTERVAL));
@@ -165,6 +184,7 @@ mod tests {
             origin: Vec3::new(2.0, 0.0, 0.0),
             dir: Vec3::new(1.0, 1.0, 1.0),
             time: 0.0,
+            medium_sample: 0.0,
         };
 
         assert!(!bbox.hit(&ray, UNIVERSE_INTERVAL));
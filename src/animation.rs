@@ -0,0 +1,172 @@
+use std::io;
+
+use crate::{interval::Interval, utils::invalid_data, vec3::Vec3};
+
+// Values a keyframe track can blend between
+pub trait Lerp: Copy {
+    // self at t = 0, other at t = 1
+    fn lerp(self, other: Self, t: f64) -> Self;
+}
+
+impl Lerp for f64 {
+    fn lerp(self, other: f64, t: f64) -> f64 {
+        self + t * (other - self)
+    }
+}
+
+impl Lerp for Vec3 {
+    fn lerp(self, other: Vec3, t: f64) -> Vec3 {
+        self + t * (other - self)
+    }
+}
+
+// Value changing over time through (time, value) keyframes in increasing time order, linear in
+// between and held before the first and after the last
+#[derive(Clone, Debug)]
+pub struct Track<T> {
+    keys: Vec<(f64, T)>,
+}
+
+impl<T> Default for Track<T> {
+    fn default() -> Self {
+        Track { keys: Vec::new() }
+    }
+}
+
+impl<T: Lerp> Track<T> {
+    pub fn new(keys: Vec<(f64, T)>) -> io::Result<Self> {
+        if let Some(i) = (1..keys.len()).find(|&i| keys[i].0 <= keys[i - 1].0) {
+            return Err(invalid_data(format!(
+                "keyframe times must increase, {} follows {}",
+                keys[i].0,
+                keys[i - 1].0
+            )));
+        }
+        Ok(Track { keys })
+    }
+
+    pub fn keys(&self) -> &[(f64, T)] {
+        &self.keys
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    // None without keyframes
+    pub fn sample(&self, time: f64) -> Option<T> {
+        let after = self.keys.partition_point(|(t, _)| *t <= time);
+        if after == 0 {
+            return self.keys.first().map(|(_, v)| *v);
+        }
+        let (t0, v0) = self.keys[after - 1];
+        match self.keys.get(after) {
+            Some(&(t1, v1)) => Some(v0.lerp(v1, (time - t0) / (t1 - t0))),
+            None => Some(v0),
+        }
+    }
+}
+
+// Frames of an animation. Frame n starts at n / fps seconds, the time keyframes are given in,
+// and the shutter stays open for the fraction shutter of the frame: 0.5 is the classic 180
+// degree shutter, 0 renders an instant without motion blur.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Timeline {
+    pub fps: f64,
+    pub first_frame: u64,
+    pub last_frame: u64,
+    pub shutter: f64,
+}
+
+impl Default for Timeline {
+    fn default() -> Self {
+        Timeline {
+            fps: 24.0,
+            first_frame: 0,
+            last_frame: 0,
+            shutter: 0.5,
+        }
+    }
+}
+
+impl Timeline {
+    pub fn frame_time(&self, frame: u64) -> f64 {
+        frame as f64 / self.fps
+    }
+
+    pub fn shutter_interval(&self, frame: u64) -> Interval {
+        let open = self.frame_time(frame);
+        Interval {
+            min: open,
+            max: open + self.shutter / self.fps,
+        }
+    }
+}
+
+// File of frame from a pattern holding one printf style frame number, %d or zero padded %04d
+pub fn frame_file_name(pattern: &str, frame: u64) -> io::Result<String> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "output {} needs one frame number like %d or %04d, e.g. frame_%04d.png",
+                pattern
+            ),
+        )
+    };
+    let (before, rest) = pattern.split_once('%').ok_or_else(invalid)?;
+    let (width, after) = rest.split_once('d').ok_or_else(invalid)?;
+    if after.contains('%') || !width.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let width: usize = if width.is_empty() {
+        0
+    } else {
+        width.parse().map_err(|_| invalid())?
+    };
+    Ok(format!(
+        "{}{:0width$}{}",
+        before,
+        frame,
+        after,
+        width = width
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracks_interpolate_and_hold_the_ends() {
+        let track = Track::new(vec![(1.0, 10.0), (2.0, 20.0), (4.0, 0.0)]).unwrap();
+        let samples: Vec<f64> = [0.0, 1.0, 1.5, 3.0, 4.0, 9.0]
+            .iter()
+            .map(|t| track.sample(*t).unwrap())
+            .collect();
+        assert_eq!(samples, vec![10.0, 10.0, 15.0, 10.0, 0.0, 0.0]);
+        assert!(Track::<f64>::default().sample(1.0).is_none());
+
+        let err = Track::new(vec![(1.0, 0.0), (1.0, 1.0)]).unwrap_err();
+        assert_eq!(err.to_string(), "keyframe times must increase, 1 follows 1");
+    }
+
+    #[test]
+    fn frames_number_their_files() {
+        assert_eq!(
+            frame_file_name("out/frame_%04d.png", 7).unwrap(),
+            "out/frame_0007.png"
+        );
+        assert_eq!(frame_file_name("f%d.ppm", 12345).unwrap(), "f12345.ppm");
+        assert!(frame_file_name("frame.png", 1).is_err());
+        assert!(frame_file_name("%d_%d.png", 1).is_err());
+        assert!(frame_file_name("%4x.png", 1).is_err());
+
+        let timeline = Timeline {
+            fps: 25.0,
+            ..Default::default()
+        };
+        let shutter = timeline.shutter_interval(50);
+        assert_eq!((shutter.min, shutter.max), (2.0, 2.02));
+    }
+}
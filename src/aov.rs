@@ -0,0 +1,286 @@
+use std::{
+    collections::HashMap,
+    io::{self, Read, Write},
+    ops::Range,
+};
+
+use crate::vec3::{Color, Point3, Vec3};
+
+// Arbitrary output variable, a quantity of the surface the camera ray of a sample hits first,
+// for compositing and denoising
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Aov {
+    Depth,      // distance from the camera, infinity where nothing was hit
+    Normal,     // shading normal, facing the camera
+    Albedo,     // surface color independent of lighting
+    Position,   // world space
+    MaterialId, // 1, 2, ... in the order the render first meets them, 0 for the background
+    ObjectId,   // 1, 2, ... in the order the render first meets them, 0 for the background
+    Motion,     // screen space motion over the shutter interval in pixels, x right and y down
+}
+
+impl Aov {
+    pub const ALL: [Aov; 7] = [
+        Aov::Depth,
+        Aov::Normal,
+        Aov::Albedo,
+        Aov::Position,
+        Aov::MaterialId,
+        Aov::ObjectId,
+        Aov::Motion,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Position => "position",
+            Aov::MaterialId => "material_id",
+            Aov::ObjectId => "object_id",
+            Aov::Motion => "motion",
+        }
+    }
+}
+
+// What the camera ray of one sample hit first
+#[derive(Copy, Clone, Debug)]
+pub struct FirstHit {
+    pub depth: f64,
+    pub normal: Vec3,
+    pub albedo: Color,
+    pub position: Point3,
+    pub material: usize, // identity of the material, e.g. its address
+    pub object: usize,   // identity of the primitive
+    pub motion: (f64, f64),
+}
+
+// AOVs of one pixel, averaged over the samples that hit something. IDs can't be averaged,
+// a pixel keeps the IDs of its first sample that hit.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct AovPixel {
+    pub hits: u64,
+    depth_sum: f64,
+    normal_sum: Vec3,
+    albedo_sum: Color,
+    position_sum: Point3,
+    motion_sum: Vec3,
+    pub material_id: u32,
+    pub object_id: u32,
+}
+
+impl AovPixel {
+    pub fn value(&self, aov: Aov) -> Vec3 {
+        if self.hits == 0 {
+            return match aov {
+                Aov::Depth => Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+                _ => Vec3::default(),
+            };
+        }
+        let n = self.hits as f64;
+        match aov {
+            Aov::Depth => Vec3::new(1.0, 1.0, 1.0) * (self.depth_sum / n),
+            Aov::Normal if self.normal_sum.length() > 0.0 => self.normal_sum.unit_vector(),
+            Aov::Normal => Vec3::default(),
+            Aov::Albedo => self.albedo_sum / n,
+            Aov::Position => self.position_sum / n,
+            Aov::MaterialId => Vec3::new(1.0, 1.0, 1.0) * self.material_id as f64,
+            Aov::ObjectId => Vec3::new(1.0, 1.0, 1.0) * self.object_id as f64,
+            Aov::Motion => self.motion_sum / n,
+        }
+    }
+}
+
+const PIXEL_VALUES: usize = 16;
+
+impl AovPixel {
+    fn to_bits(self) -> [u64; PIXEL_VALUES] {
+        let vectors = [
+            self.normal_sum,
+            self.albedo_sum,
+            self.position_sum,
+            self.motion_sum,
+        ];
+        let mut v = [0; PIXEL_VALUES];
+        v[0] = self.hits;
+        v[1] = self.depth_sum.to_bits();
+        for (i, c) in vectors.iter().enumerate() {
+            v[2 + 3 * i..5 + 3 * i].copy_from_slice(&[c.x, c.y, c.z].map(f64::to_bits));
+        }
+        v[14] = u64::from(self.material_id);
+        v[15] = u64::from(self.object_id);
+        v
+    }
+
+    fn from_bits(v: [u64; PIXEL_VALUES]) -> Self {
+        let vector = |i: usize| {
+            let [x, y, z] = [v[i], v[i + 1], v[i + 2]].map(f64::from_bits);
+            Vec3::new(x, y, z)
+        };
+        AovPixel {
+            hits: v[0],
+            depth_sum: f64::from_bits(v[1]),
+            normal_sum: vector(2),
+            albedo_sum: vector(5),
+            position_sum: vector(8),
+            motion_sum: vector(11),
+            material_id: v[14] as u32,
+            object_id: v[15] as u32,
+        }
+    }
+}
+
+// AOV buffers of a film, row-major like its pixels
+#[derive(Clone)]
+pub struct AovFilm {
+    pub pixels: Vec<AovPixel>,
+    width: u64,
+    material_ids: IdMap,
+    object_ids: IdMap,
+}
+
+// Sequential IDs of materials or objects, assigned the first time they are seen. Addresses
+// don't survive a resumed render, so the IDs handed out before it are only counted and an
+// object first met by a pixel after resuming gets a new ID.
+#[derive(Clone, Default)]
+struct IdMap {
+    ids: HashMap<usize, u32>,
+    resumed: u32,
+}
+
+impl IdMap {
+    fn id(&mut self, key: usize) -> u32 {
+        let next = self.resumed + self.ids.len() as u32 + 1;
+        *self.ids.entry(key).or_insert(next)
+    }
+}
+
+impl AovFilm {
+    pub fn new(width: u64, height: u64) -> Self {
+        AovFilm {
+            pixels: vec![AovPixel::default(); (width * height) as usize],
+            width,
+            material_ids: IdMap::default(),
+            object_ids: IdMap::default(),
+        }
+    }
+
+    pub fn pixel(&self, x: u64, y: u64) -> &AovPixel {
+        &self.pixels[(y * self.width + x) as usize]
+    }
+
+    // A sample of pixel (x, y), hit is None when its camera ray hit nothing
+    pub fn add_sample(&mut self, x: u64, y: u64, hit: Option<&FirstHit>) {
+        let Some(hit) = hit else {
+            return;
+        };
+        let material_id = self.material_ids.id(hit.material);
+        let object_id = self.object_ids.id(hit.object);
+
+        let pixel = &mut self.pixels[(y * self.width + x) as usize];
+        if pixel.hits == 0 {
+            pixel.material_id = material_id;
+            pixel.object_id = object_id;
+        }
+        pixel.hits += 1;
+        pixel.depth_sum += hit.depth;
+        pixel.normal_sum += hit.normal;
+        pixel.albedo_sum += hit.albedo;
+        pixel.position_sum += hit.position;
+        pixel.motion_sum += Vec3::new(hit.motion.0, hit.motion.1, 0.0);
+    }
+
+    // The pixels in columns xs and rows ys
+    pub fn crop(&self, xs: Range<u64>, ys: Range<u64>) -> AovFilm {
+        let mut cropped = self.clone();
+        cropped.width = xs.end - xs.start;
+        cropped.pixels = ys
+            .flat_map(|y| xs.clone().map(move |x| (x, y)))
+            .map(|(x, y)| *self.pixel(x, y))
+            .collect();
+        cropped
+    }
+
+    // Only keep the pixels in columns xs and rows ys
+    pub fn clear_outside(&mut self, xs: Range<u64>, ys: Range<u64>) {
+        for (i, pixel) in self.pixels.iter_mut().enumerate() {
+            let (x, y) = (i as u64 % self.width, i as u64 / self.width);
+            if !xs.contains(&x) || !ys.contains(&y) {
+                *pixel = AovPixel::default();
+            }
+        }
+    }
+
+    // The values of one AOV in row-major order
+    pub fn values(&self, aov: Aov) -> Vec<Vec3> {
+        self.pixels.iter().map(|p| p.value(aov)).collect()
+    }
+
+    // Every pixel as little endian u64, like Film::write_raw
+    pub fn write_raw(&self, mut w: impl Write) -> io::Result<()> {
+        for pixel in &self.pixels {
+            for v in pixel.to_bits() {
+                w.write_all(&v.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read_raw(mut r: impl Read, width: u64, height: u64) -> io::Result<AovFilm> {
+        let mut aovs = AovFilm::new(width, height);
+        for pixel in aovs.pixels.iter_mut() {
+            let mut v = [0; PIXEL_VALUES];
+            for value in v.iter_mut() {
+                let mut bytes = [0; 8];
+                r.read_exact(&mut bytes)?;
+                *value = u64::from_le_bytes(bytes);
+            }
+            *pixel = AovPixel::from_bits(v);
+        }
+        // new materials and objects get IDs after the ones already in the pixels
+        aovs.material_ids.resumed = aovs.pixels.iter().map(|p| p.material_id).max().unwrap_or(0);
+        aovs.object_ids.resumed = aovs.pixels.iter().map(|p| p.object_id).max().unwrap_or(0);
+        Ok(aovs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit(depth: f64, material: usize) -> FirstHit {
+        FirstHit {
+            depth,
+            normal: Vec3::new(0.0, 0.0, 1.0),
+            albedo: Color::new(0.5, 0.5, 0.5),
+            position: Point3::new(0.0, 0.0, -depth),
+            material,
+            object: material,
+            motion: (1.0, 0.0),
+        }
+    }
+
+    #[test]
+    fn averages_hits_and_keeps_first_ids() {
+        let mut aovs = AovFilm::new(2, 1);
+        aovs.add_sample(0, 0, Some(&hit(1.0, 0xa0)));
+        aovs.add_sample(0, 0, None);
+        aovs.add_sample(0, 0, Some(&hit(3.0, 0xb0)));
+        aovs.add_sample(1, 0, Some(&hit(2.0, 0xb0)));
+
+        let pixel = aovs.pixel(0, 0);
+        assert_eq!(pixel.hits, 2);
+        assert_eq!(pixel.value(Aov::Depth).x, 2.0);
+        assert_eq!(pixel.value(Aov::Position).z, -2.0);
+        assert_eq!(pixel.value(Aov::MaterialId).x, 1.0);
+        assert_eq!(aovs.pixel(1, 0).value(Aov::ObjectId).x, 2.0);
+        assert_eq!(pixel.value(Aov::Motion).x, 1.0);
+    }
+
+    #[test]
+    fn background_pixels_are_infinitely_far() {
+        let aovs = AovFilm::new(1, 1);
+        assert_eq!(aovs.pixel(0, 0).value(Aov::Depth).x, f64::INFINITY);
+        assert_eq!(aovs.pixel(0, 0).value(Aov::ObjectId).x, 0.0);
+    }
+}
@@ -0,0 +1,276 @@
+use std::{f64::consts::PI, io, sync::Arc};
+
+use crate::{
+    checkpoint::Fingerprint,
+    image::Image,
+    sampler::Sampler,
+    utils::{degrees_to_radian, invalid_data},
+    vec3::Vec3,
+};
+
+// Opening of the lens diaphragm, the shape out-of-focus highlights (bokeh) take
+#[derive(Clone)]
+pub enum ApertureShape {
+    Circle,
+    // Regular polygon inscribed in the unit circle, one corner per diaphragm blade
+    Polygon { blades: u32 },
+    Mask(Arc<ApertureMask>),
+}
+
+#[derive(Clone)]
+pub struct Aperture {
+    pub shape: ApertureShape,
+    pub rotation: f64, // degrees, counter-clockwise
+    // Anamorphic squeeze, > 1.0 narrows the aperture horizontally giving oval bokeh
+    pub squeeze: f64,
+}
+
+impl Default for Aperture {
+    fn default() -> Self {
+        Aperture::circle()
+    }
+}
+
+impl Aperture {
+    pub fn circle() -> Self {
+        Aperture {
+            shape: ApertureShape::Circle,
+            rotation: 0.0,
+            squeeze: 1.0,
+        }
+    }
+
+    pub fn polygon(blades: u32, rotation: f64) -> Self {
+        assert!(blades >= 3, "an aperture polygon needs at least 3 blades");
+        Aperture {
+            shape: ApertureShape::Polygon { blades },
+            rotation,
+            squeeze: 1.0,
+        }
+    }
+
+    pub fn mask(mask: ApertureMask) -> Self {
+        Aperture {
+            shape: ApertureShape::Mask(Arc::new(mask)),
+            rotation: 0.0,
+            squeeze: 1.0,
+        }
+    }
+
+    pub fn with_squeeze(mut self, squeeze: f64) -> Self {
+        self.squeeze = squeeze;
+        self
+    }
+
+    // Add the shape, rotation and squeeze to the fingerprint of a render
+    pub fn fingerprint(&self, f: &mut Fingerprint) {
+        match &self.shape {
+            ApertureShape::Circle => f.name("circle"),
+            ApertureShape::Polygon { blades } => {
+                f.name("polygon");
+                f.add(u64::from(*blades));
+            }
+            ApertureShape::Mask(mask) => {
+                f.name("mask");
+                f.add(mask.width as u64);
+                let cdfs = std::iter::once(&mask.row_cdf).chain(&mask.column_cdfs);
+                cdfs.flatten().for_each(|c| f.number(*c));
+            }
+        }
+        f.number(self.rotation);
+        f.number(self.squeeze);
+    }
+
+    // Random point on the aperture in lens coordinates, within the unit disk (z = 0)
+    pub fn sample(&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let p = match &self.shape {
+            ApertureShape::Circle => Vec3::sample_in_unit_disk(sampler.get_2d()),
+            ApertureShape::Polygon { blades } => {
+                sample_polygon(*blades, sampler.get_1d(), sampler.get_2d())
+            }
+            ApertureShape::Mask(mask) => mask.sample(sampler.get_2d()),
+        };
+
+        let (sin, cos) = degrees_to_radian(self.rotation).sin_cos();
+        Vec3::new(
+            (p.x * cos - p.y * sin) / self.squeeze,
+            p.x * sin + p.y * cos,
+            0.0,
+        )
+    }
+}
+
+fn polygon_vertex(i: u32, blades: u32) -> (f64, f64) {
+    let angle = 2.0 * PI * i as f64 / blades as f64 + PI / 2.0;
+    (angle.cos(), angle.sin())
+}
+
+// Uniform sample of the polygon: pick one of the equal triangles fanning out from the center,
+// then a uniform point inside it
+fn sample_polygon(blades: u32, u_triangle: f64, u: (f64, f64)) -> Vec3 {
+    let i = ((u_triangle * blades as f64) as u32).min(blades - 1);
+    let (ax, ay) = polygon_vertex(i, blades);
+    let (bx, by) = polygon_vertex(i + 1, blades);
+
+    let (mut s, mut t) = u;
+    if s + t > 1.0 {
+        s = 1.0 - s;
+        t = 1.0 - t;
+    }
+    Vec3::new(s * ax + t * bx, s * ay + t * by, 0.0)
+}
+
+// Transmission of the aperture from a grayscale image, white is open and black is blocked.
+// The image is stretched over the square enclosing the unit disk.
+pub struct ApertureMask {
+    width: usize,
+    height: usize,
+    // cumulative transmission over the rows, then within every row, both normalized to 1
+    row_cdf: Vec<f64>,
+    column_cdfs: Vec<Vec<f64>>,
+}
+
+// Index of the bucket of a piecewise constant cdf (leading 0 included) holding u, and the
+// position of u inside that bucket
+fn sample_cdf(cdf: &[f64], u: f64) -> (usize, f64) {
+    let i = cdf
+        .windows(2)
+        .position(|c| u < c[1] && c[1] > c[0])
+        .unwrap_or_else(|| cdf.windows(2).rposition(|c| c[1] > c[0]).unwrap());
+    let offset = (u - cdf[i]) / (cdf[i + 1] - cdf[i]);
+    (i, offset.clamp(0.0, 1.0))
+}
+
+// Running sum of the values scaled to end at 1, with a leading 0
+fn normalized_cdf(values: impl Iterator<Item = f64>) -> Vec<f64> {
+    let mut cdf = vec![0.0];
+    for v in values {
+        cdf.push(cdf.last().unwrap() + v);
+    }
+    let total = *cdf.last().unwrap();
+    if total > 0.0 {
+        cdf.iter_mut().for_each(|c| *c /= total);
+    }
+    cdf
+}
+
+impl ApertureMask {
+    pub fn new(image: &Image) -> io::Result<Self> {
+        if image.width == 0 || image.height == 0 {
+            return Err(invalid_data("aperture mask image is empty".to_string()));
+        }
+        let weights: Vec<f64> = image
+            .pixels
+            .iter()
+            .map(|c| (0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z).clamp(0.0, 1.0))
+            .collect();
+        if weights.iter().all(|w| *w <= 0.0) {
+            return Err(invalid_data("aperture mask is fully opaque".to_string()));
+        }
+
+        let rows: Vec<&[f64]> = weights.chunks(image.width).collect();
+        let row_cdf = normalized_cdf(rows.iter().map(|row| row.iter().sum()));
+        let column_cdfs = rows
+            .iter()
+            .map(|row| normalized_cdf(row.iter().cloned()))
+            .collect();
+
+        Ok(ApertureMask {
+            width: image.width,
+            height: image.height,
+            row_cdf,
+            column_cdfs,
+        })
+    }
+
+    pub fn load(file_name: &str) -> io::Result<Self> {
+        ApertureMask::new(&Image::load_pnm(file_name)?)
+    }
+
+    // Point distributed proportionally to the mask transmission, by picking a row from the
+    // row sums and then a pixel within that row
+    fn sample(&self, u: (f64, f64)) -> Vec3 {
+        let (y, dy) = sample_cdf(&self.row_cdf, u.1);
+        let (x, dx) = sample_cdf(&self.column_cdfs[y], u.0);
+        let s = (x as f64 + dx) / self.width as f64;
+        let t = (y as f64 + dy) / self.height as f64;
+        // image rows go down, lens v goes up
+        Vec3::new(2.0 * s - 1.0, 1.0 - 2.0 * t, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sampler::SamplerKind, vec3::Color};
+
+    fn samples(aperture: &Aperture) -> Vec<Vec3> {
+        let mut sampler = SamplerKind::Independent.build(1, 0);
+        (0..1000)
+            .map(|i| {
+                sampler.start_pixel_sample(0, 0, i);
+                aperture.sample(sampler.as_mut())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn polygon_samples_stay_inside_polygon() {
+        let blades = 6;
+        let aperture = Aperture::polygon(blades, 0.0);
+        // inscribed circle radius of a regular polygon with circumradius 1
+        let apothem = (PI / blades as f64).cos();
+
+        for p in samples(&aperture) {
+            assert!(p.length() <= 1.0 + 1e-12);
+            for i in 0..blades {
+                // edge normal direction points between two consecutive vertices
+                let angle = 2.0 * PI * (i as f64 + 0.5) / blades as f64 + PI / 2.0;
+                assert!(p.x * angle.cos() + p.y * angle.sin() <= apothem + 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn squeeze_narrows_horizontally() {
+        let aperture = Aperture::circle().with_squeeze(2.0);
+        for p in samples(&aperture) {
+            assert!(p.x.abs() <= 0.5);
+        }
+    }
+
+    #[test]
+    fn mask_samples_only_open_pixels() {
+        // only the top right quarter is open
+        let black = Color::new(0.0, 0.0, 0.0);
+        let white = Color::new(1.0, 1.0, 1.0);
+        let image = Image {
+            width: 2,
+            height: 2,
+            pixels: vec![black, white, black, black],
+        };
+        let aperture = Aperture::mask(ApertureMask::new(&image).unwrap());
+
+        for p in samples(&aperture) {
+            assert!(p.x >= 0.0 && p.y >= 0.0);
+        }
+    }
+
+    #[test]
+    fn opaque_mask_is_rejected() {
+        let image = Image {
+            width: 1,
+            height: 1,
+            pixels: vec![Color::new(0.0, 0.0, 0.0)],
+        };
+        assert!(ApertureMask::new(&image).is_err());
+
+        let empty = Image {
+            width: 0,
+            height: 3,
+            pixels: Vec::new(),
+        };
+        let err = ApertureMask::new(&empty).err().unwrap();
+        assert_eq!(err.to_string(), "aperture mask image is empty");
+    }
+}
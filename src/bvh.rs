@@ -1,16 +1,17 @@
-use std::{cmp::Ordering, rc::Rc};
+use std::{cmp::Ordering, io, sync::Arc};
 
 use crate::{
     aabb::{AABB, EMPTY_AABB},
+    checkpoint::Fingerprint,
     hittable::{Hit_Record, Hittable, Hittable_List},
     interval::Interval,
     ray::Ray,
-    utils::random_int_range,
+    scene::SceneExport,
 };
 
 pub struct BVH_Node {
-    left: Rc<dyn Hittable>,
-    right: Rc<dyn Hittable>,
+    left: Arc<dyn Hittable>,
+    right: Arc<dyn Hittable>,
     bbox: AABB,
 }
 
@@ -22,15 +23,15 @@ impl BVH_Node {
         a_min.partial_cmp(&b_min).unwrap_or(Ordering::Equal)
     }
 
-    pub fn new_from_objects(objects: &mut Vec<Rc<dyn Hittable>>, start: usize, end: usize) -> Self {
+    pub fn new_from_objects(objects: &mut Vec<Arc<dyn Hittable>>, start: usize, end: usize) -> Self {
         assert!(start < end);
         let mut bbox = EMPTY_AABB;
         for obj in objects[start..end].iter() {
             bbox = AABB::new_from_bbox(bbox, obj.bounding_box());
         }
 
-        let left: Rc<dyn Hittable>;
-        let right: Rc<dyn Hittable>;
+        let left: Arc<dyn Hittable>;
+        let right: Arc<dyn Hittable>;
 
         let count = end - start;
         if count == 1 {
@@ -45,8 +46,8 @@ impl BVH_Node {
                 .sort_by(|a, b| BVH_Node::bbox_compare(a.as_ref(), b.as_ref(), axis));
 
             let mid = start + count / 2;
-            left = Rc::new(BVH_Node::new_from_objects(objects, start, mid));
-            right = Rc::new(BVH_Node::new_from_objects(objects, mid, end));
+            left = Arc::new(BVH_Node::new_from_objects(objects, start, mid));
+            right = Arc::new(BVH_Node::new_from_objects(objects, mid, end));
         }
 
 
@@ -66,7 +67,7 @@ impl Hittable for BVH_Node {
         }
 
         let left_result = self.left.hit(ray, ray_t);
-        if Rc::ptr_eq(&self.left, &self.right) {
+        if Arc::ptr_eq(&self.left, &self.right) {
             return left_result;
         }
 
@@ -84,4 +85,18 @@ impl Hittable for BVH_Node {
     fn bounding_box(&self) -> AABB {
         self.bbox
     }
+
+    fn fingerprint(&self, f: &mut Fingerprint) {
+        f.name("bvh");
+        self.left.fingerprint(f);
+        self.right.fingerprint(f);
+    }
+
+    fn export(&self, scene: &mut SceneExport) -> io::Result<()> {
+        self.left.export(scene)?;
+        if !Arc::ptr_eq(&self.left, &self.right) {
+            self.right.export(scene)?;
+        }
+        Ok(())
+    }
 }
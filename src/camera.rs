@@ -1,44 +1,201 @@
 use std::{
-    f64,
-    fs::File,
-    io::{self, BufWriter, Write},
+    f64::{
+        self,
+        consts::{FRAC_PI_2, PI},
+    },
+    io,
+    ops::Range,
+    path::Path,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
 };
 
 use crate::{
-    hittable::{Hittable, Hittable_List},
+    aov::{Aov, FirstHit},
+    aperture::Aperture,
+    checkpoint::{Checkpoint, Checkpointing, Fingerprint},
+    denoise::Denoiser,
+    film::{Film, ImageFormat, write_image, write_ppm},
+    filter::Filter,
+    hittable::{Hit_Record, Hittable, Hittable_List},
     interval::Interval,
+    lens::{LensSystem, RealisticLens},
+    progress::{CancelToken, ProgressReporter, ProgressTracker, Stage, StderrReporter},
     ray::Ray,
-    utils::{degrees_to_radian, linear_to_gamma, random_double},
-    vec3::{Color, Point3, Vec3, cross},
+    sampler::{Sampler, SamplerKind},
+    shutter::Shutter,
+    utils::{degrees_to_radian, invalid_data},
+    vec3::{Color, Point3, Vec3, cross, dot},
 };
 
-const INTENSITY: Interval = Interval {
-    min: 0.0,
-    max: 0.999,
-};
+// How the angle between a fisheye ray and the optical axis maps to the distance r from the
+// image circle center, r normalized to 1 at the edge of the circle.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FisheyeMapping {
+    Equidistant, // r = theta / (fov / 2)
+    Equisolid,   // r = sin(theta / 2) / sin(fov / 4)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Projection {
+    // Planar viewport at focus_dist, the only projection supporting defocus blur
+    Perspective,
+    // Circular image inscribed in the shorter image side, fov in degrees (may exceed 180)
+    Fisheye { mapping: FisheyeMapping, fov: f64 },
+    // Full sphere, longitude across the width and latitude down the height, use a 2:1 image
+    Equirectangular,
+    // Six square faces in a horizontal strip, ordered +X, -X, +Y, -Y, +Z, -Z in the camera
+    // frame (x = u, y = v, z = w, so -Z is the view direction), use a 6:1 image
+    CubeMap,
+}
+
+// Render in passes and stop sampling a pixel once the standard error of its display
+// luminance drops below threshold, between min_samples and max_samples samples per pixel
+#[derive(Clone, Debug)]
+pub struct AdaptiveSampling {
+    pub min_samples: u64,
+    pub max_samples: u64,
+    pub pass_samples: u64, // samples added to every unconverged pixel per pass
+    pub threshold: f64,
+    pub heatmap_file: Option<String>, // where to write the samples-per-pixel heatmap
+}
+
+impl Default for AdaptiveSampling {
+    fn default() -> Self {
+        AdaptiveSampling {
+            min_samples: 16,
+            max_samples: 1024,
+            pass_samples: 16,
+            threshold: 0.005,
+            heatmap_file: None,
+        }
+    }
+}
+
+// Rectangle of the image to render, x1 and y1 exclusive
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CropWindow {
+    Pixels { x0: u64, y0: u64, x1: u64, y1: u64 },
+    // fractions of the image width and height, rounded outwards to whole pixels
+    Normalized { x0: f64, y0: f64, x1: f64, y1: f64 },
+}
+
+impl CropWindow {
+    // Pixel columns and rows of the window, clamped to the image
+    pub fn bounds(&self, width: u64, height: u64) -> (Range<u64>, Range<u64>) {
+        let (x0, y0, x1, y1) = match *self {
+            CropWindow::Pixels { x0, y0, x1, y1 } => (x0, y0, x1, y1),
+            CropWindow::Normalized { x0, y0, x1, y1 } => (
+                (x0 * width as f64).floor().max(0.0) as u64,
+                (y0 * height as f64).floor().max(0.0) as u64,
+                (x1 * width as f64).ceil().max(0.0) as u64,
+                (y1 * height as f64).ceil().max(0.0) as u64,
+            ),
+        };
+        (x0.min(width)..x1.min(width), y0.min(height)..y1.min(height))
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CropOutput {
+    Cropped,   // an image of just the window
+    FullFrame, // the full image, black outside the window
+}
+
+// Render one sample per pixel over the whole image per pass, writing the image so far every
+// snapshot_passes passes and/or once snapshot_seconds passed since the last snapshot
+#[derive(Clone, Debug, Default)]
+pub struct ProgressiveRendering {
+    pub snapshot_passes: Option<u64>,
+    pub snapshot_seconds: Option<f64>,
+    pub snapshot_file: Option<String>, // defaults to the output file of the render
+}
+
+// Decides after every pass whether a snapshot or checkpoint is due
+struct PassSchedule {
+    passes: Option<u64>,
+    interval: Option<Duration>,
+    last_pass: u64,
+    last_time: Instant,
+}
+
+impl PassSchedule {
+    fn new(passes: Option<u64>, seconds: Option<f64>, first_pass: u64) -> Self {
+        PassSchedule {
+            passes,
+            interval: seconds.map(Duration::from_secs_f64),
+            last_pass: first_pass,
+            last_time: Instant::now(),
+        }
+    }
+
+    fn due(&mut self, pass: u64) -> bool {
+        let passes_due = self.passes.is_some_and(|n| pass - self.last_pass >= n);
+        let time_due = self
+            .interval
+            .is_some_and(|interval| self.last_time.elapsed() >= interval);
+        if passes_due || time_due {
+            self.last_pass = pass;
+            self.last_time = Instant::now();
+        }
+        passes_due || time_due
+    }
+}
 
-fn write_color(mut w: impl Write, color: Color) -> io::Result<()> {
-    let r = linear_to_gamma(color.x);
-    let g = linear_to_gamma(color.y);
-    let b = linear_to_gamma(color.z);
-    let ir = (INTENSITY.clamp(r) * 256.0) as u64;
-    let ig = (INTENSITY.clamp(g) * 256.0) as u64;
-    let ib = (INTENSITY.clamp(b) * 256.0) as u64;
+// A camera sample traced by a render thread, waiting to be added to the film
+struct TracedSample {
+    x: u64,
+    y: u64,
+    px: f64, // continuous image position
+    py: f64,
+    color: Color,
+    hit: Option<FirstHit>,
+    rays: u64,
+}
 
-    writeln!(w, "{} {} {}", ir, ig, ib)
+// The samples of the run of pixels batch[index] of Camera::sample_rows
+struct TracedSpan {
+    index: usize,
+    samples: Vec<TracedSample>,
+    active: u64, // pixels that took samples
 }
 
+// Samples a render thread takes per run of pixels, bounding the traced samples in memory
+const SPAN_SAMPLES: u64 = 4096;
+
+#[derive(Clone)]
 pub struct Camera {
-    _aspect_ratio: f64,
+    aspect_ratio: f64,
     image_width: u64,
     image_height: u64,
 
+    crop: Option<(CropWindow, CropOutput)>,
+
     sample_per_pixel: u16,
+    adaptive: Option<AdaptiveSampling>, // replaces the fixed sample_per_pixel when set
+    progressive: Option<ProgressiveRendering>,
+    checkpointing: Option<Checkpointing>,
+    progress: Arc<Mutex<dyn ProgressReporter>>,
+    cancel: CancelToken,
+    threads: usize,
+    sampler: SamplerKind,
+    filter: Filter, // pixel reconstruction filter
+    aovs: Vec<Aov>, // written next to the render, see aov_file_name
+    denoiser: Option<Denoiser>,
+    output_format: Option<ImageFormat>, // by default from the file extension, PPM without one
+    background: Option<Color>,          // color of rays that hit nothing, a sky gradient if None
+    seed: u64,                          // every random decision of the render derives from it
 
-    center: Point3,      // Camera center, point camera looking from
-    lookat: Point3,      // Point camera looking at
-    vup: Vec3,           // Camera relative "up" direction
-    vfov: f64,           // vertical view angle (field of view)
+    center: Point3, // Camera center, point camera looking from
+    lookat: Point3, // Point camera looking at
+    vup: Vec3,      // Camera relative "up" direction
+    vfov: f64,      // vertical view angle (field of view)
+    projection: Projection,
+    shutter: Shutter,
     pixel00_loc: Point3, // Location of pixel 0, 0
     pixel_delta_u: Vec3, // Offset to pixel to the right
     pixel_delta_v: Vec3, // Offset to pixel below
@@ -52,11 +209,19 @@ pub struct Camera {
     defocus_angle: f64, // angle of the cone with the apex at viewport center, for easy
     focus_dist: f64, // from camera lookfrom point to plane of focus, here it's the viewport plane
     defocus_radius: f64,
+    aperture: Aperture, // shape of the defocus disk
+    // Physically modeled lens replacing the viewport and defocus disk of the perspective camera
+    lens: Option<Arc<RealisticLens>>,
+
+    // Stereo eye, zero for a mono camera
+    eye_offset: f64,       // signed distance of this eye from the rig center along u
+    convergence_dist: f64, // distance of the plane where both eyes' frusta coincide
 
     max_depth: i16,
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         aspect_ratio: f64,
         image_width: u64,
@@ -69,73 +234,321 @@ impl Camera {
         sample_per_pixel: u16,
         max_depth: i16,
     ) -> Self {
-        let mut image_height: u64 = (image_width as f64 / aspect_ratio) as u64;
-        if image_height < 1 {
-            image_height = 1;
+        let mut camera = Camera {
+            aspect_ratio,
+            image_width,
+            image_height: 1,
+
+            crop: None,
+
+            sample_per_pixel,
+            adaptive: None,
+            progressive: None,
+            checkpointing: None,
+            progress: Arc::new(Mutex::new(StderrReporter)),
+            cancel: CancelToken::new(),
+            threads: 1,
+            sampler: SamplerKind::Independent,
+            filter: Filter::default(),
+            aovs: Vec::new(),
+            denoiser: None,
+            output_format: None,
+            background: None,
+            seed: 0,
+
+            center: lookfrom,
+            vfov,
+            vup,
+            lookat,
+            projection: Projection::Perspective,
+            shutter: Shutter::default(),
+            pixel00_loc: Point3::default(),
+            pixel_delta_u: Vec3::default(),
+            pixel_delta_v: Vec3::default(),
+            u: Vec3::default(),
+            v: Vec3::default(),
+            w: Vec3::default(),
+            defocus_angle,
+            focus_dist,
+            defocus_radius: 0.0,
+            aperture: Aperture::default(),
+            lens: None,
+            eye_offset: 0.0,
+            convergence_dist: 0.0,
+            max_depth,
+        };
+        camera.initialize();
+        camera
+    }
+
+    // Only trace the pixels inside window, projected exactly as in the full image. Fails for a
+    // window without pixels in the image.
+    pub fn with_crop(mut self, window: CropWindow, output: CropOutput) -> io::Result<Self> {
+        let (xs, ys) = window.bounds(self.image_width, self.image_height);
+        if xs.is_empty() || ys.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "the crop window is empty in the {}x{} image",
+                    self.image_width, self.image_height
+                ),
+            ));
+        }
+        self.crop = Some((window, output));
+        Ok(self)
+    }
+
+    pub fn with_projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    pub fn with_sampler(mut self, sampler: SamplerKind) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    // Fails unless 2 <= min_samples <= max_samples and a pass adds samples
+    pub fn with_adaptive_sampling(mut self, adaptive: AdaptiveSampling) -> io::Result<Self> {
+        if adaptive.min_samples < 2 || adaptive.min_samples > adaptive.max_samples {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "adaptive sampling needs 2 <= min_samples <= max_samples, got {} and {}",
+                    adaptive.min_samples, adaptive.max_samples
+                ),
+            ));
+        }
+        if adaptive.pass_samples == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "adaptive sampling needs at least one sample per pass",
+            ));
+        }
+        self.adaptive = Some(adaptive);
+        Ok(self)
+    }
+
+    // Fails for snapshots every 0 passes or a non-positive number of seconds
+    pub fn with_progressive(mut self, progressive: ProgressiveRendering) -> io::Result<Self> {
+        check_interval(
+            "snapshot",
+            progressive.snapshot_passes,
+            progressive.snapshot_seconds,
+        )?;
+        self.progressive = Some(progressive);
+        Ok(self)
+    }
+
+    // Save the render state while rendering and resume from the checkpoint file if it exists.
+    // Renders without adaptive sampling then run progressively, one sample per pixel per pass.
+    // Fails for checkpoints every 0 passes or a non-positive number of seconds.
+    pub fn with_checkpointing(mut self, checkpointing: Checkpointing) -> io::Result<Self> {
+        check_interval(
+            "checkpoint",
+            checkpointing.every_passes,
+            checkpointing.every_seconds,
+        )?;
+        self.checkpointing = Some(checkpointing);
+        Ok(self)
+    }
+
+    // Record the first hit of every camera ray and write the given AOVs as float images
+    pub fn with_aovs(mut self, aovs: &[Aov]) -> Self {
+        self.aovs = aovs.to_vec();
+        self
+    }
+
+    // Denoise the written image and snapshots, records the albedo and normal AOVs it needs
+    pub fn with_denoiser(mut self, denoiser: Denoiser) -> Self {
+        self.denoiser = Some(denoiser);
+        self
+    }
+
+    // Format of the image and snapshots whatever their file extension
+    pub fn with_output_format(mut self, format: ImageFormat) -> Self {
+        self.output_format = Some(format);
+        self
+    }
+
+    pub fn with_progress_reporter(mut self, reporter: Arc<Mutex<dyn ProgressReporter>>) -> Self {
+        self.progress = reporter;
+        self
+    }
+
+    // Stop rendering once the token is cancelled, render still writes the samples taken so far
+    pub fn with_cancel_token(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    // Trace on this many threads, the image is the same for any number. Fails for 0 threads.
+    pub fn with_threads(mut self, threads: usize) -> io::Result<Self> {
+        if threads == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a render needs at least one thread",
+            ));
+        }
+        self.threads = threads;
+        Ok(self)
+    }
+
+    // Uniform color instead of the sky gradient, black for scenes lit only by their lights
+    pub fn with_background(mut self, background: Color) -> Self {
+        self.background = Some(background);
+        self
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn with_shutter(mut self, shutter: Shutter) -> Self {
+        self.shutter = shutter;
+        self
+    }
+
+    pub fn with_aperture(mut self, aperture: Aperture) -> Self {
+        self.aperture = aperture;
+        self
+    }
+
+    // Trace camera rays through a lens prescription, focused at focus_dist. The film has the
+    // image aspect ratio and the given diagonal in scene units (e.g. 0.035 for 35mm).
+    pub fn with_lens_system(mut self, lens: LensSystem, film_diagonal: f64) -> io::Result<Self> {
+        let aspect_ratio = self.image_width as f64 / self.image_height as f64;
+        let lens = RealisticLens::new(lens, film_diagonal, aspect_ratio, self.focus_dist)?;
+        self.lens = Some(Arc::new(lens));
+        Ok(self)
+    }
+
+    pub fn image_width(&self) -> u64 {
+        self.image_width
+    }
+
+    pub fn image_height(&self) -> u64 {
+        self.image_height
+    }
+
+    // Pixels of the crop window, the whole image without one
+    fn crop_bounds(&self) -> (Range<u64>, Range<u64>) {
+        match &self.crop {
+            Some((window, _)) => window.bounds(self.image_width, self.image_height),
+            None => (0..self.image_width, 0..self.image_height),
+        }
+    }
+
+    // Pixels to trace: the crop window grown by the reach of the filter, so the pixels on its
+    // border get the same samples as in the full image
+    fn sample_bounds(&self) -> (Range<u64>, Range<u64>) {
+        let (xs, ys) = self.crop_bounds();
+        if self.crop.is_none() {
+            return (xs, ys);
+        }
+        let margin = (self.filter.radius - 0.5).ceil().max(0.0) as u64;
+        (
+            xs.start.saturating_sub(margin)..(xs.end + margin).min(self.image_width),
+            ys.start.saturating_sub(margin)..(ys.end + margin).min(self.image_height),
+        )
+    }
+
+    // The film as it is output, cropped to the window or with nothing outside of it
+    pub(crate) fn output_film(&self, film: Film) -> Film {
+        let (xs, ys) = self.crop_bounds();
+        match &self.crop {
+            None => film,
+            Some((_, CropOutput::Cropped)) => film.crop(xs, ys),
+            Some((_, CropOutput::FullFrame)) => film.clear_outside(xs, ys),
+        }
+    }
+
+    // Camera for one eye of a stereo pair, eye_offset is negative for the left eye
+    pub(crate) fn stereo_eye(&self, eye_offset: f64, convergence_dist: f64) -> Camera {
+        let mut eye = self.clone();
+        eye.eye_offset = eye_offset;
+        eye.convergence_dist = convergence_dist;
+        // omni-directional stereo keeps the rig center and offsets every ray instead
+        if self.projection != Projection::Equirectangular {
+            // parallel optical axes, convergence comes from shifting the viewport, not toe-in
+            let shift = eye_offset * self.u;
+            eye.center = self.center + shift;
+            eye.lookat = self.lookat + shift;
+        }
+        eye.initialize();
+        eye
+    }
+
+    // Derive the image size, camera basis and viewport from the user facing parameters
+    fn initialize(&mut self) {
+        self.image_height = (self.image_width as f64 / self.aspect_ratio) as u64;
+        if self.image_height < 1 {
+            self.image_height = 1;
         }
         // We'll also have the y-axis go up, the x-axis to the right,
         // and the negative z-axis pointing in the viewing direction.
         // (This is commonly referred to as right-handed coordinates.)
 
         // Camera
-        let theta = degrees_to_radian(vfov);
+        let theta = degrees_to_radian(self.vfov);
         let h = (theta / 2.0).tan();
-        let viewport_height: f64 = focus_dist * h * 2.0;
-        let viewport_width = viewport_height * (image_width as f64 / image_height as f64);
+        let viewport_height: f64 = self.focus_dist * h * 2.0;
+        let viewport_width = viewport_height * (self.image_width as f64 / self.image_height as f64);
 
         // basis vectors
-        let w = (lookfrom - lookat).unit_vector();
-        let u = cross(vup, w).unit_vector();
-        let v = cross(w, u);
+        self.w = (self.center - self.lookat).unit_vector();
+        self.u = cross(self.vup, self.w).unit_vector();
+        self.v = cross(self.w, self.u);
 
         // viewport vectors
         // horizontal
-        let viewport_u = viewport_width * u;
+        let viewport_u = viewport_width * self.u;
         // vertical, y-axis pointing up
-        let viewport_v = viewport_height * -v;
+        let viewport_v = viewport_height * -self.v;
 
         // Horizontal and vertical delta vectors from pixel to pixel
-        let pixel_delta_u = viewport_u / (image_width as f64);
-        let pixel_delta_v = viewport_v / (image_height as f64);
+        self.pixel_delta_u = viewport_u / (self.image_width as f64);
+        self.pixel_delta_v = viewport_v / (self.image_height as f64);
 
         // locations of the upper left pixel
-        let viewport_upper_left = lookfrom - focus_dist * w - viewport_u / 2.0 - viewport_v / 2.0;
+        let viewport_upper_left =
+            self.center - self.focus_dist * self.w - viewport_u / 2.0 - viewport_v / 2.0;
         // (0,0) ┌──────────┐
         //       │    •     │ ← center
         //       └──────────┘
-        let pixel00_loc = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
+        self.pixel00_loc = viewport_upper_left + 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
 
-        // Defocus disk
-        let defocus_radius = focus_dist * (degrees_to_radian(defocus_angle / 2.0)).tan();
-
-        Camera {
-            _aspect_ratio: aspect_ratio,
-            image_width,
-            image_height,
-
-            sample_per_pixel,
-
-            center: lookfrom,
-            vfov,
-            vup,
-            lookat,
-            pixel00_loc,
-            pixel_delta_u,
-            pixel_delta_v,
-            u,
-            v,
-            w,
-            defocus_angle,
-            focus_dist,
-            defocus_radius,
-            max_depth,
+        // Off-axis stereo: slide the viewport back towards the rig center so both eyes see
+        // the same window at the convergence distance
+        if self.eye_offset != 0.0 && self.convergence_dist > 0.0 {
+            self.pixel00_loc -=
+                self.eye_offset * (self.focus_dist / self.convergence_dist) * self.u;
         }
+
+        // Defocus disk
+        self.defocus_radius = self.focus_dist * (degrees_to_radian(self.defocus_angle / 2.0)).tan();
     }
 
-    fn ray_color(&self, ray: &Ray, world: &Hittable_List, depth: i16) -> Color {
+    // Radiance along ray. first, when given, is set to what the ray hits, for the AOVs.
+    fn ray_color(
+        &self,
+        ray: &Ray,
+        world: &Hittable_List,
+        depth: i16,
+        sampler: &mut dyn Sampler,
+        rays: &mut u64,
+        first: Option<&mut Option<FirstHit>>,
+    ) -> Color {
         if depth <= 0 {
             return Color::new(0.0, 0.0, 0.0);
         }
+        *rays += 1;
 
         let rec = world.hit(
             ray,
@@ -146,14 +559,23 @@ impl Camera {
         );
 
         if let Some(rec) = rec {
-            let (attenuation, scattered_ray) = rec.material.scatter(ray, &rec);
+            if let Some(first) = first {
+                *first = Some(self.first_hit(ray, &rec));
+            }
+            let emitted = rec.material.emitted(rec.u, rec.v, rec.p);
+            let (attenuation, scattered_ray) = rec.material.scatter(ray, &rec, sampler);
             if let Some(scattered_ray) = scattered_ray {
-                return attenuation * self.ray_color(&scattered_ray, world, depth - 1);
+                return emitted
+                    + attenuation
+                        * self.ray_color(&scattered_ray, world, depth - 1, sampler, rays, None);
             }
-            return attenuation; // default (0, 0, 0)
+            return emitted + attenuation; // default (0, 0, 0)
             // return Color::new(0.0, 0.0, 0.0);
         }
 
+        if let Some(background) = self.background {
+            return background;
+        }
         let unit_direction = ray.dir.unit_vector();
         let a = 0.5 * (unit_direction.y + 1.0);
         // TODO: lerp function
@@ -161,57 +583,1332 @@ impl Camera {
         (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
     }
 
-    // Construct a camera ray originating from the defocus disk and directed at randomly sampled
-    // point around the pixel location (x, y)
-    fn get_ray(&self, x: u64, y: u64) -> Ray {
-        let offset = Vec3::new(random_double() - 0.5, random_double() - 0.5, 0.0);
+    // Construct a camera ray through the point (ox, oy) inside pixel (x, y), both in [0, 1).
+    // Returns None when the sample falls outside the area covered by the projection,
+    // e.g. the corners of a fisheye image.
+    fn get_ray(
+        &self,
+        x: u64,
+        y: u64,
+        (ox, oy): (f64, f64),
+        sampler: &mut dyn Sampler,
+    ) -> Option<Ray> {
+        let offset = Vec3::new(ox - 0.5, oy - 0.5, 0.0);
+        // scanline position for a rolling shutter, 0 at the top row and 1 at the bottom one
+        let row = y as f64 / (self.image_height - 1).max(1) as f64;
+        let ray_time = self.shutter.sample_time(sampler.get_1d(), row);
+        // continuous image coordinates of the sample, pixel (x, y) covers [x, x + 1)
+        let px = x as f64 + 0.5 + offset.x;
+        let py = y as f64 + 0.5 + offset.y;
+
+        let direction = match self.projection {
+            Projection::Perspective => {
+                if let Some(lens) = &self.lens {
+                    return self.get_lens_ray(lens, px, py, ray_time, sampler);
+                }
+                return Some(self.get_perspective_ray(x, y, offset, ray_time, sampler));
+            }
+            Projection::Fisheye { mapping, fov } => self.fisheye_direction(px, py, mapping, fov)?,
+            Projection::Equirectangular => self.equirectangular_direction(px, py),
+            Projection::CubeMap => self.cube_map_direction(x, px, py),
+        };
+
+        Some(Ray {
+            origin: self.panorama_origin(px),
+            dir: direction,
+            time: ray_time,
+            medium_sample: sampler.get_1d(),
+        })
+    }
+
+    // Omni-directional stereo moves the eye around a circle of radius |eye_offset| so that
+    // every viewing direction gets a baseline perpendicular to it
+    fn panorama_origin(&self, px: f64) -> Point3 {
+        if self.eye_offset == 0.0 || self.projection != Projection::Equirectangular {
+            return self.center;
+        }
+        let longitude = self.longitude(px);
+        self.center + self.eye_offset * (longitude.cos() * self.u + longitude.sin() * self.w)
+    }
+
+    // Ray originating from the defocus disk and directed at the sampled point on the viewport
+    fn get_perspective_ray(
+        &self,
+        x: u64,
+        y: u64,
+        offset: Vec3,
+        time: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Ray {
         let pixel_center = self.pixel00_loc
             + (x as f64 + offset.x) * self.pixel_delta_u
             + (y as f64 + offset.y) * self.pixel_delta_v;
         let ray_origin = if self.defocus_angle <= 0.0 {
             self.center
         } else {
-            self.defocus_disk_sample()
+            self.defocus_disk_sample(sampler)
         };
         let ray_direction = pixel_center - ray_origin;
-        let ray_time = random_double();
 
         Ray {
             origin: ray_origin,
             dir: ray_direction,
-            time: ray_time,
+            time,
+            medium_sample: sampler.get_1d(),
+        }
+    }
+
+    // Ray leaving the front element of the lens system, None when the lens blocks it
+    fn get_lens_ray(
+        &self,
+        lens: &RealisticLens,
+        px: f64,
+        py: f64,
+        time: f64,
+        sampler: &mut dyn Sampler,
+    ) -> Option<Ray> {
+        let s = px / self.image_width as f64;
+        let t = py / self.image_height as f64;
+        let lens_ray = lens.generate_ray(s, t, sampler)?;
+
+        // lens space shares the camera frame, the film sits at the camera center
+        let origin = self.center
+            + self.camera_to_world(lens_ray.origin.x, lens_ray.origin.y, lens_ray.origin.z);
+        Some(Ray {
+            origin,
+            dir: self.camera_to_world(lens_ray.dir.x, lens_ray.dir.y, lens_ray.dir.z),
+            time,
+            medium_sample: sampler.get_1d(),
+        })
+    }
+
+    // Direction in world space for camera frame coordinates (x along u, y along v, z along w)
+    fn camera_to_world(&self, x: f64, y: f64, z: f64) -> Vec3 {
+        x * self.u + y * self.v + z * self.w
+    }
+
+    fn fisheye_direction(
+        &self,
+        px: f64,
+        py: f64,
+        mapping: FisheyeMapping,
+        fov: f64,
+    ) -> Option<Vec3> {
+        // image circle centered on the image, radius is half of the shorter side
+        let radius = 0.5 * self.image_width.min(self.image_height) as f64;
+        let nx = (px - 0.5 * self.image_width as f64) / radius;
+        let ny = (0.5 * self.image_height as f64 - py) / radius;
+        let r = (nx * nx + ny * ny).sqrt();
+        if r > 1.0 {
+            return None;
         }
+
+        let half_fov = degrees_to_radian(fov) / 2.0;
+        // angle from the optical axis
+        let theta = match mapping {
+            FisheyeMapping::Equidistant => r * half_fov,
+            FisheyeMapping::Equisolid => 2.0 * (r * (half_fov / 2.0).sin()).clamp(-1.0, 1.0).asin(),
+        };
+        let phi = ny.atan2(nx);
+
+        Some(self.camera_to_world(
+            theta.sin() * phi.cos(),
+            theta.sin() * phi.sin(),
+            -theta.cos(),
+        ))
     }
 
-    fn defocus_disk_sample(&self) -> Point3 {
-        let p = Vec3::random_in_unit_disk();
+    // longitude 0 is the view direction, increasing towards u
+    fn longitude(&self, px: f64) -> f64 {
+        (px / self.image_width as f64 - 0.5) * 2.0 * PI
+    }
+
+    fn equirectangular_direction(&self, px: f64, py: f64) -> Vec3 {
+        let longitude = self.longitude(px);
+        let latitude = FRAC_PI_2 - (py / self.image_height as f64) * PI;
+
+        self.camera_to_world(
+            latitude.cos() * longitude.sin(),
+            latitude.sin(),
+            -latitude.cos() * longitude.cos(),
+        )
+    }
+
+    fn cube_map_direction(&self, x: u64, px: f64, py: f64) -> Vec3 {
+        // pick the face from the unjittered pixel so samples never bleed into a neighbour face
+        let face_width = self.image_width as f64 / 6.0;
+        let face = ((x as f64 / face_width) as u64).min(5);
+        // face local coordinates in [-1, 1], t pointing down like the image rows
+        let sc = 2.0 * (px - face as f64 * face_width) / face_width - 1.0;
+        let tc = 2.0 * py / self.image_height as f64 - 1.0;
+
+        // OpenGL cube map face conventions
+        match face {
+            0 => self.camera_to_world(1.0, -tc, -sc),
+            1 => self.camera_to_world(-1.0, -tc, sc),
+            2 => self.camera_to_world(sc, 1.0, tc),
+            3 => self.camera_to_world(sc, -1.0, -tc),
+            4 => self.camera_to_world(sc, -tc, 1.0),
+            _ => self.camera_to_world(-sc, -tc, -1.0),
+        }
+    }
+
+    fn defocus_disk_sample(&self, sampler: &mut dyn Sampler) -> Point3 {
+        let p = self.aperture.sample(sampler);
         self.center + p.x * self.defocus_radius * self.u + p.y * self.defocus_radius * self.v
     }
 
-    pub fn render(&self, world: &Hittable_List, file_name: &str) -> io::Result<()> {
-        let file = File::create(file_name)?;
-        let mut out = BufWriter::new(file);
+    // Continuous image position of a world point seen by a pinhole perspective camera, None
+    // behind the camera or for other projections
+    fn project(&self, p: Point3) -> Option<(f64, f64)> {
+        if self.projection != Projection::Perspective || self.lens.is_some() {
+            return None;
+        }
+        let d = p - self.center;
+        let z = dot(d, -self.w);
+        if z <= 0.0 {
+            return None;
+        }
+        // onto the viewport plane, relative to the center of pixel (0, 0)
+        let q = self.center + d * (self.focus_dist / z) - self.pixel00_loc;
+        Some((
+            dot(q, self.pixel_delta_u) / self.pixel_delta_u.length_squared() + 0.5,
+            dot(q, self.pixel_delta_v) / self.pixel_delta_v.length_squared() + 0.5,
+        ))
+    }
+
+    fn first_hit(&self, ray: &Ray, rec: &Hit_Record) -> FirstHit {
+        // where the hit point is at shutter open and close, zero for other projections
+        let open = rec.p + (self.shutter.open - ray.time) * rec.velocity;
+        let close = rec.p + (self.shutter.close - ray.time) * rec.velocity;
+        let motion = match (self.project(open), self.project(close)) {
+            (Some(a), Some(b)) => (b.0 - a.0, b.1 - a.1),
+            _ => (0.0, 0.0),
+        };
+
+        FirstHit {
+            depth: rec.t * ray.dir.length(),
+            normal: rec.normal,
+            albedo: rec.material.albedo(rec),
+            position: rec.p,
+            material: Arc::as_ptr(&rec.material).addr(),
+            object: rec.object,
+            motion,
+        }
+    }
+
+    // Empty film to render into, with AOV buffers when any are used
+    fn new_film(&self) -> Film {
+        let film = Film::new(self.image_width, self.image_height);
+        if self.aovs.is_empty() && self.denoiser.is_none() {
+            film
+        } else {
+            film.with_aovs()
+        }
+    }
 
-        // Render
-        writeln!(out, "P3\n{} {}\n255", self.image_width, self.image_height)?;
+    // Trace sample sample_index of pixel (x, y), with its first hit when aovs is set
+    fn trace_sample(
+        &self,
+        x: u64,
+        y: u64,
+        sample_index: u64,
+        world: &Hittable_List,
+        sampler: &mut dyn Sampler,
+        aovs: bool,
+    ) -> TracedSample {
+        sampler.start_pixel_sample(x, y, sample_index);
+        let pixel_offset = sampler.get_2d();
+        let mut rays = 0;
+        // samples outside the projection contribute black
+        let ray = self.get_ray(x, y, pixel_offset, sampler);
+        let mut hit = None;
+        let first = aovs.then_some(&mut hit);
+        let color = match &ray {
+            Some(ray) => self.ray_color(ray, world, self.max_depth, sampler, &mut rays, first),
+            None => Color::new(0.0, 0.0, 0.0),
+        };
+        TracedSample {
+            x,
+            y,
+            px: x as f64 + pixel_offset.0,
+            py: y as f64 + pixel_offset.1,
+            color,
+            hit,
+            rays,
+        }
+    }
 
-        let pixel_samples_scale = 1.0 / self.sample_per_pixel as f64;
+    fn add_sample(&self, film: &mut Film, sample: &TracedSample, progress: &mut ProgressTracker) {
+        let TracedSample { x, y, px, py, .. } = *sample;
+        if let Some(aovs) = film.aovs.as_mut() {
+            aovs.add_sample(x, y, sample.hit.as_ref());
+        }
+        film.add_sample(x, y, px, py, sample.color, &self.filter);
+        progress.add_sample(sample.rays);
+    }
 
-        for y in 0..self.image_height {
-            eprint!("\rScanlines remaining: {} ", self.image_height - y);
+    // Take samples(film, x, y), the sample indices due in pixel (x, y), for every pixel of the
+    // sample bounds on self.threads threads. The samples are added to the film in scanline
+    // order as a single thread would, so the image doesn't depend on the number of threads.
+    // after_row gets the number of rows left whenever a row is done. Returns how many pixels
+    // took samples, None when the render was cancelled.
+    fn sample_rows(
+        &self,
+        world: &Hittable_List,
+        film: &mut Film,
+        sampler_samples: u64,
+        samples: &(dyn Fn(&Film, u64, u64) -> Range<u64> + Sync),
+        progress: &mut ProgressTracker,
+        after_row: &mut dyn FnMut(u64, &mut ProgressTracker),
+    ) -> Option<u64> {
+        let (xs, ys) = self.sample_bounds();
+        // runs of pixels of one row, the unit of work of a thread
+        let span = (SPAN_SAMPLES / sampler_samples.max(1)).max(1);
+        let spans: Vec<(u64, Range<u64>)> = ys
+            .clone()
+            .flat_map(|y| {
+                let end = xs.end;
+                xs.clone()
+                    .step_by(span as usize)
+                    .map(move |x| (y, x..(x + span).min(end)))
+            })
+            .collect();
 
-            for x in 0..self.image_width {
-                let mut pixel_color = Color::new(0.0, 0.0, 0.0);
+        let aovs = film.aovs.is_some();
+        let mut active = 0;
+        for batch in spans.chunks(self.threads * 16) {
+            if self.cancel.is_cancelled() {
+                return None;
+            }
+            let next = AtomicUsize::new(0);
+            let traced_film = &*film;
+            let mut traced: Vec<TracedSpan> = thread::scope(|scope| {
+                let threads: Vec<_> = (0..self.threads.min(batch.len()))
+                    .map(|_| {
+                        scope.spawn(|| {
+                            let mut sampler = self.sampler.build(sampler_samples, self.seed);
+                            let mut traced = Vec::new();
+                            while !self.cancel.is_cancelled() {
+                                let index = next.fetch_add(1, Ordering::Relaxed);
+                                let Some((y, xs)) = batch.get(index) else {
+                                    break;
+                                };
+                                let mut span = TracedSpan {
+                                    index,
+                                    samples: Vec::new(),
+                                    active: 0,
+                                };
+                                for x in xs.clone() {
+                                    let due = samples(traced_film, x, *y);
+                                    span.active += u64::from(!due.is_empty());
+                                    span.samples.extend(due.map(|i| {
+                                        self.trace_sample(x, *y, i, world, sampler.as_mut(), aovs)
+                                    }));
+                                }
+                                traced.push(span);
+                            }
+                            traced
+                        })
+                    })
+                    .collect();
+                threads
+                    .into_iter()
+                    .flat_map(|thread| thread.join().unwrap())
+                    .collect()
+            });
 
-                for _ in 0..self.sample_per_pixel {
-                    let ray = self.get_ray(x, y);
-                    pixel_color += self.ray_color(&ray, world, self.max_depth);
+            traced.sort_by_key(|span| span.index);
+            for span in &traced {
+                span.samples
+                    .iter()
+                    .for_each(|sample| self.add_sample(film, sample, progress));
+                active += span.active;
+                let (y, span_xs) = &batch[span.index];
+                if span_xs.end == xs.end {
+                    after_row(ys.end - y - 1, progress);
                 }
-                pixel_color *= pixel_samples_scale;
-                write_color(&mut out, pixel_color)?;
             }
         }
-        eprintln!("\rDone.                 ");
+        if self.cancel.is_cancelled() {
+            return None;
+        }
+        Some(active)
+    }
+
+    pub fn render_film(&self, world: &Hittable_List) -> Film {
+        self.render_film_with_snapshots(world, &mut |_| {})
+    }
+
+    // Render, handing the film so far to snapshot whenever a progressive snapshot is due
+    pub fn render_film_with_snapshots(
+        &self,
+        world: &Hittable_List,
+        snapshot: &mut dyn FnMut(&Film),
+    ) -> Film {
+        let progressive = self.progressive.as_ref();
+        let mut snapshots = PassSchedule::new(
+            progressive.and_then(|p| p.snapshot_passes),
+            progressive.and_then(|p| p.snapshot_seconds),
+            0,
+        );
+
+        let mut film = self.new_film();
+        self.render_passes(world, &mut film, 0, &mut |pass, film| {
+            if snapshots.due(pass) {
+                snapshot(&self.output_film(film.clone()))
+            }
+        });
+        self.output_film(film)
+    }
+
+    // Add samples to film, continuing after first_pass passes, and call after_pass with the
+    // number of passes done after every pass. Returns the number of passes done.
+    fn render_passes(
+        &self,
+        world: &Hittable_List,
+        film: &mut Film,
+        first_pass: u64,
+        after_pass: &mut dyn FnMut(u64, &Film),
+    ) -> u64 {
+        let (xs, ys) = self.sample_bounds();
+        let pixels = (xs.end - xs.start) * (ys.end - ys.start);
+        let samples_per_pixel = match &self.adaptive {
+            Some(adaptive) => adaptive.max_samples,
+            None => self.sample_per_pixel as u64,
+        };
+        let start_samples = film.pixels.iter().map(|p| p.sample_count).sum();
+        let mut progress = ProgressTracker::new(
+            self.progress.clone(),
+            start_samples,
+            (pixels * samples_per_pixel).max(start_samples),
+        );
+
+        let passes = match &self.adaptive {
+            Some(adaptive) => {
+                self.render_adaptive(world, film, adaptive, first_pass, after_pass, &mut progress)
+            }
+            None if self.progressive.is_some() || self.checkpointing.is_some() => {
+                self.render_progressive(world, film, first_pass, after_pass, &mut progress)
+            }
+            None => {
+                self.render_fixed(world, film, &mut progress);
+                self.sample_per_pixel as u64
+            }
+        };
+        progress.finish(self.cancel.is_cancelled());
+        passes
+    }
+
+    // sample_per_pixel samples for every pixel
+    fn render_fixed(&self, world: &Hittable_List, film: &mut Film, progress: &mut ProgressTracker) {
+        let samples = self.sample_per_pixel as u64;
+        self.sample_rows(
+            world,
+            film,
+            samples,
+            &|_, _, _| 0..samples,
+            progress,
+            &mut |remaining, progress| progress.report(Stage::Scanline { remaining }),
+        );
+    }
+
+    // One sample for every pixel per pass, sample_per_pixel passes
+    fn render_progressive(
+        &self,
+        world: &Hittable_List,
+        film: &mut Film,
+        first_pass: u64,
+        after_pass: &mut dyn FnMut(u64, &Film),
+        progress: &mut ProgressTracker,
+    ) -> u64 {
+        let passes = self.sample_per_pixel as u64;
+
+        let (xs, ys) = self.sample_bounds();
+        for pass in first_pass..passes {
+            let samples = |_: &Film, _, _| pass..pass + 1;
+            if self
+                .sample_rows(world, film, passes, &samples, progress, &mut |_, _| {})
+                .is_none()
+            {
+                return pass;
+            }
+
+            progress.report(Stage::Pass {
+                pass: pass + 1,
+                passes: Some(passes),
+                active_pixels: (xs.end - xs.start) * (ys.end - ys.start),
+            });
+            after_pass(pass + 1, film);
+        }
+        passes.max(first_pass)
+    }
+
+    fn render_adaptive(
+        &self,
+        world: &Hittable_List,
+        film: &mut Film,
+        adaptive: &AdaptiveSampling,
+        first_pass: u64,
+        after_pass: &mut dyn FnMut(u64, &Film),
+        progress: &mut ProgressTracker,
+    ) -> u64 {
+        // the samples a pixel takes this pass, none once it converged or used up the budget
+        let samples = |film: &Film, x, y| {
+            let pixel = film.pixel(x, y);
+            let taken = pixel.sample_count;
+            let converged =
+                taken >= adaptive.min_samples && pixel.standard_error() < adaptive.threshold;
+            if taken >= adaptive.max_samples || converged {
+                return taken..taken;
+            }
+            let count = if taken < adaptive.min_samples {
+                adaptive.min_samples
+            } else {
+                adaptive.pass_samples.min(adaptive.max_samples - taken)
+            };
+            taken..taken + count
+        };
+
+        let mut pass = first_pass;
+        loop {
+            let active = match self.sample_rows(
+                world,
+                film,
+                adaptive.max_samples,
+                &samples,
+                progress,
+                &mut |_, _| {},
+            ) {
+                Some(active) => active,
+                None => return pass,
+            };
+
+            pass += 1;
+            progress.report(Stage::Pass {
+                pass,
+                passes: None,
+                active_pixels: active,
+            });
+            if active == 0 {
+                break;
+            }
+            after_pass(pass, film);
+        }
+        pass
+    }
+
+    // Average the samples of every pixel, returns linear colors in row-major order
+    pub fn render_pixels(&self, world: &Hittable_List) -> Vec<Color> {
+        self.render_film(world).colors()
+    }
+
+    // Fingerprint of everything deciding what the samples of this render see: the camera
+    // settings, the aperture and every object of the world with its material. Sample counts
+    // are left out, so a resumed render may take more. Fails for a part without a fingerprint.
+    pub fn scene_hash(&self, world: &Hittable_List) -> io::Result<u64> {
+        let settings = format!(
+            "{:?}",
+            (
+                (self.image_width, self.image_height, self.seed, self.sampler),
+                (self.filter, self.projection, &self.shutter, &self.lens),
+                (self.center, self.lookat, self.vup, self.vfov),
+                (self.defocus_angle, self.focus_dist),
+                (self.eye_offset, self.convergence_dist),
+                (self.max_depth, self.adaptive.is_some(), self.crop),
+                self.background,
+            )
+        );
+        let mut f = Fingerprint::default();
+        f.name(&settings);
+        self.aperture.fingerprint(&mut f);
+        world.fingerprint(&mut f);
+        f.finish()
+    }
+
+    // Film and passes done of an interrupted run of this render, or an empty film
+    fn resume(&self, scene_hash: u64) -> io::Result<(Film, u64)> {
+        if let Some(checkpointing) = &self.checkpointing
+            && Path::new(&checkpointing.file).exists()
+        {
+            let checkpoint = Checkpoint::load(&checkpointing.file)?;
+            if checkpoint.scene_hash != scene_hash {
+                return Err(invalid_data(format!(
+                    "checkpoint {} was saved for a different scene or camera",
+                    checkpointing.file
+                )));
+            }
+            self.message(&format!(
+                "Resuming {} after {} passes",
+                checkpointing.file, checkpoint.passes
+            ));
+            let mut film = checkpoint.film;
+            let records_aovs = !self.aovs.is_empty() || self.denoiser.is_some();
+            if !records_aovs {
+                film.aovs = None;
+            } else if film.aovs.is_none() {
+                // AOVs of only the later passes would misguide the denoiser
+                return Err(invalid_data(format!(
+                    "checkpoint {} was saved without AOVs, start the render over",
+                    checkpointing.file
+                )));
+            }
+            return Ok((film, checkpoint.passes));
+        }
+        Ok((self.new_film(), 0))
+    }
+
+    fn message(&self, text: &str) {
+        self.progress.lock().unwrap().message(text);
+    }
+
+    // Colors of the output film, denoised when the camera has a denoiser
+    pub(crate) fn output_colors(&self, film: &Film) -> Vec<Color> {
+        match &self.denoiser {
+            Some(denoiser) => denoiser.denoise(film),
+            None => film.colors(),
+        }
+    }
+
+    // Write in the output format of the camera, see with_output_format
+    pub(crate) fn write_colors(
+        &self,
+        file_name: &str,
+        width: u64,
+        height: u64,
+        colors: &[Color],
+    ) -> io::Result<()> {
+        let format = self
+            .output_format
+            .or_else(|| ImageFormat::from_file_name(file_name))
+            .unwrap_or(ImageFormat::Ppm);
+        write_image(format, file_name, width, height, colors)
+    }
+
+    fn write_image(&self, film: &Film, file_name: &str) -> io::Result<()> {
+        self.write_colors(
+            file_name,
+            film.width,
+            film.height,
+            &self.output_colors(film),
+        )
+    }
+
+    pub fn render(&self, world: &Hittable_List, file_name: &str) -> io::Result<()> {
+        // only a checkpoint needs to know the scene
+        let scene_hash = match self.checkpointing {
+            Some(_) => self.scene_hash(world)?,
+            None => 0,
+        };
+        let (mut film, first_pass) = self.resume(scene_hash)?;
+
+        let progressive = self.progressive.as_ref();
+        let snapshot_file = progressive.map(|p| p.snapshot_file.as_deref().unwrap_or(file_name));
+        let mut snapshots = PassSchedule::new(
+            progressive.and_then(|p| p.snapshot_passes),
+            progressive.and_then(|p| p.snapshot_seconds),
+            first_pass,
+        );
+        let checkpointing = self.checkpointing.as_ref();
+        let mut checkpoints = PassSchedule::new(
+            checkpointing.and_then(|c| c.every_passes),
+            checkpointing.and_then(|c| c.every_seconds),
+            first_pass,
+        );
+
+        // a failed snapshot or checkpoint is not worth losing the render over
+        let passes = self.render_passes(world, &mut film, first_pass, &mut |pass, film| {
+            if let Some(snapshot_file) = snapshot_file
+                && snapshots.due(pass)
+                && let Err(err) = self.write_image(&self.output_film(film.clone()), snapshot_file)
+            {
+                self.message(&format!(
+                    "Failed to write snapshot {}: {}",
+                    snapshot_file, err
+                ));
+            }
+            if let Some(checkpointing) = checkpointing
+                && checkpoints.due(pass)
+                && let Err(err) = Checkpoint::save(&checkpointing.file, scene_hash, pass, film)
+            {
+                self.message(&format!(
+                    "Failed to write checkpoint {}: {}",
+                    checkpointing.file, err
+                ));
+            }
+        });
+        // a render cancelled halfway through a pass keeps its last periodic checkpoint
+        if let Some(checkpointing) = checkpointing
+            && !self.cancel.is_cancelled()
+        {
+            Checkpoint::save(&checkpointing.file, scene_hash, passes, &film)?;
+        }
+        let film = self.output_film(film);
+        self.write_image(&film, file_name)?;
+
+        if let Some(heatmap_file) = self.adaptive.as_ref().and_then(|a| a.heatmap_file.as_ref()) {
+            write_ppm(
+                heatmap_file,
+                film.width,
+                film.height,
+                &film.sample_heatmap(),
+            )?;
+        }
+        for aov in &self.aovs {
+            film.write_aov(*aov, &aov_file_name(file_name, *aov))?;
+        }
         Ok(())
     }
 }
+
+// Snapshots or checkpoints are due every passes passes or seconds seconds, both positive
+fn check_interval(what: &str, passes: Option<u64>, seconds: Option<f64>) -> io::Result<()> {
+    if passes == Some(0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("a {} every 0 passes is never due", what),
+        ));
+    }
+    if let Some(seconds) = seconds
+        && (seconds.is_nan() || seconds <= 0.0)
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("a {} every {} seconds is never due", what, seconds),
+        ));
+    }
+    Ok(())
+}
+
+// out/image.ppm writes the depth AOV to out/image.depth.pfm
+pub fn aov_file_name(file_name: &str, aov: Aov) -> String {
+    Path::new(file_name)
+        .with_extension(format!("{}.pfm", aov.name()))
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        filter::FilterKind,
+        hittable::Sphere,
+        material::{DiffuseLight, Lambertian},
+        progress::{Progress, SilentReporter},
+        vec3::dot,
+    };
+
+    fn test_camera(aspect_ratio: f64, image_width: u64, projection: Projection) -> Camera {
+        Camera::new(
+            aspect_ratio,
+            image_width,
+            90.0,
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.0,
+            1.0,
+            1,
+            1,
+        )
+        .with_projection(projection)
+    }
+
+    fn assert_direction(actual: Vec3, expected: Vec3) {
+        let cos = dot(actual.unit_vector(), expected.unit_vector());
+        assert!(
+            cos > 1.0 - 1e-9,
+            "expected direction {:?}, got {:?}",
+            expected,
+            actual
+        );
+    }
+
+    #[test]
+    fn render_is_reproducible_for_a_seed() {
+        let mut world = Hittable_List::new();
+        world.add(Arc::new(Sphere::new_static(
+            Point3::new(0.0, 0.0, -2.0),
+            1.0,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )));
+
+        for sampler in [SamplerKind::Independent, SamplerKind::Sobol] {
+            let render = |seed: u64| {
+                let mut camera = test_camera(1.0, 8, Projection::Perspective)
+                    .with_sampler(sampler)
+                    .with_seed(seed);
+                camera.sample_per_pixel = 4;
+                camera.max_depth = 5;
+                camera.render_pixels(&world)
+            };
+
+            let first: Vec<(f64, f64, f64)> = render(7).iter().map(|c| (c.x, c.y, c.z)).collect();
+            let second: Vec<(f64, f64, f64)> = render(7).iter().map(|c| (c.x, c.y, c.z)).collect();
+            let other: Vec<(f64, f64, f64)> = render(8).iter().map(|c| (c.x, c.y, c.z)).collect();
+            assert_eq!(first, second);
+            assert_ne!(first, other);
+        }
+    }
+
+    #[test]
+    fn lights_shine_against_the_background() {
+        let mut world = Hittable_List::new();
+        world.add(Arc::new(Sphere::new_static(
+            Point3::new(0.0, 0.0, -2.0),
+            1.0,
+            Arc::new(DiffuseLight::new(Color::new(4.0, 2.0, 1.0))),
+        )));
+
+        let mut camera =
+            test_camera(1.0, 3, Projection::Perspective).with_background(Color::new(0.0, 0.0, 0.0));
+        camera.sample_per_pixel = 4;
+        let pixels = camera.render_pixels(&world);
+        // the light fills the middle pixel, the corners only see the black background
+        let center = pixels[4];
+        assert_eq!((center.x, center.y, center.z), (4.0, 2.0, 1.0));
+        assert_eq!(pixels[0].length(), 0.0);
+
+        // the sky gradient without a background
+        let camera = test_camera(1.0, 3, Projection::Perspective);
+        assert!(camera.render_pixels(&world)[0].length() > 0.0);
+    }
+
+    #[test]
+    fn progressive_render_matches_fixed_render() {
+        let mut world = Hittable_List::new();
+        world.add(Arc::new(Sphere::new_static(
+            Point3::new(0.0, 0.0, -2.0),
+            1.0,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )));
+
+        let mut camera = test_camera(1.0, 6, Projection::Perspective).with_seed(3);
+        camera.sample_per_pixel = 5;
+        camera.max_depth = 5;
+        let fixed = camera.render_pixels(&world);
+
+        let camera = camera
+            .with_progressive(ProgressiveRendering {
+                snapshot_passes: Some(2),
+                ..Default::default()
+            })
+            .unwrap();
+        let mut snapshots = Vec::new();
+        let film = camera.render_film_with_snapshots(&world, &mut |film| {
+            snapshots.push(film.pixel(0, 0).sample_count)
+        });
+
+        // passes 2 and 4, the final pass is the render itself
+        assert_eq!(snapshots, vec![2, 4]);
+        let progressive = film.colors();
+        for (a, b) in fixed.iter().zip(&progressive) {
+            assert_eq!((a.x, a.y, a.z), (b.x, b.y, b.z));
+        }
+    }
+
+    #[test]
+    fn resumed_render_matches_uninterrupted_render() {
+        let mut world = Hittable_List::new();
+        world.add(Arc::new(Sphere::new_static(
+            Point3::new(0.0, 0.0, -2.0),
+            1.0,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )));
+
+        let dir = std::env::temp_dir();
+        let file = |name: &str| {
+            let path = dir.join(format!("{}_{}", std::process::id(), name));
+            path.to_str().unwrap().to_string()
+        };
+        let checkpoint_file = file("resume.ckpt");
+        let camera = |spp: u16, seed: u64| {
+            let mut camera = test_camera(1.0, 6, Projection::Perspective)
+                .with_seed(seed)
+                .with_aovs(&[Aov::Depth, Aov::ObjectId])
+                .with_checkpointing(Checkpointing {
+                    file: checkpoint_file.clone(),
+                    every_passes: Some(1),
+                    every_seconds: None,
+                })
+                .unwrap();
+            camera.sample_per_pixel = spp;
+            camera.max_depth = 5;
+            camera
+        };
+
+        // interrupted after 2 passes, then resumed with the full budget
+        camera(2, 3).render(&world, &file("partial.ppm")).unwrap();
+        camera(5, 3).render(&world, &file("resumed.ppm")).unwrap();
+        assert!(camera(5, 4).render(&world, &file("other.ppm")).is_err());
+        std::fs::remove_file(&checkpoint_file).unwrap();
+        camera(5, 3).render(&world, &file("full.ppm")).unwrap();
+
+        // the AOVs cover the passes before the resume too
+        let read = |name: &str| std::fs::read(file(name)).unwrap();
+        let resumed = [read("resumed.ppm"), read("resumed.depth.pfm")];
+        let full = [read("full.ppm"), read("full.depth.pfm")];
+        for name in ["resume.ckpt", "partial", "resumed", "full"] {
+            if name.ends_with(".ckpt") {
+                std::fs::remove_file(file(name)).unwrap();
+                continue;
+            }
+            for extension in ["ppm", "depth.pfm", "object_id.pfm"] {
+                std::fs::remove_file(file(&format!("{}.{}", name, extension))).unwrap();
+            }
+        }
+        assert_eq!(resumed, full);
+
+        // a checkpoint without AOVs can't be resumed by a render recording them
+        camera(2, 3)
+            .with_aovs(&[])
+            .render(&world, &file("partial.ppm"))
+            .unwrap();
+        assert!(camera(5, 3).render(&world, &file("resumed.ppm")).is_err());
+        for name in ["resume.ckpt", "partial.ppm"] {
+            std::fs::remove_file(file(name)).unwrap();
+        }
+    }
+
+    #[test]
+    fn scene_hash_covers_what_the_camera_does_not_see() {
+        // a sphere behind the camera, which no camera ray reaches
+        let world = |radius: f64, albedo: f64| {
+            let mut world = Hittable_List::new();
+            world.add(Arc::new(Sphere::new_static(
+                Point3::new(0.0, 0.0, -2.0),
+                1.0,
+                Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+            )));
+            world.add(Arc::new(Sphere::new_static(
+                Point3::new(0.0, 0.0, 5.0),
+                radius,
+                Arc::new(Lambertian::new(Color::new(albedo, albedo, albedo))),
+            )));
+            world
+        };
+        let camera = test_camera(1.0, 6, Projection::Perspective);
+        let hash = camera.scene_hash(&world(0.5, 0.5)).unwrap();
+        assert_eq!(hash, camera.scene_hash(&world(0.5, 0.5)).unwrap());
+        assert_ne!(hash, camera.scene_hash(&world(0.6, 0.5)).unwrap());
+        assert_ne!(hash, camera.scene_hash(&world(0.5, 0.7)).unwrap());
+
+        let hexagon =
+            test_camera(1.0, 6, Projection::Perspective).with_aperture(Aperture::polygon(6, 0.0));
+        let pentagon =
+            test_camera(1.0, 6, Projection::Perspective).with_aperture(Aperture::polygon(5, 0.0));
+        let world = world(0.5, 0.5);
+        assert_ne!(
+            hexagon.scene_hash(&world).unwrap(),
+            pentagon.scene_hash(&world).unwrap()
+        );
+        assert_ne!(
+            hexagon.scene_hash(&world).unwrap(),
+            camera.scene_hash(&world).unwrap()
+        );
+
+        // an object that can't add itself leaves nothing to tell its renders apart
+        struct Opaque;
+        impl Hittable for Opaque {
+            fn hit(&self, _ray: &Ray, _ray_t: Interval) -> Option<Hit_Record> {
+                None
+            }
+            fn bounding_box(&self) -> crate::aabb::AABB {
+                crate::aabb::AABB::default()
+            }
+        }
+        let mut world = world;
+        world.add(Arc::new(Opaque));
+        let err = camera.scene_hash(&world).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "Opaque has no fingerprint, a render with it can't be checkpointed"
+        );
+        camera.render_film(&world);
+    }
+
+    #[test]
+    fn progress_is_reported_and_cancellation_stops_the_render() {
+        struct Recorder(Vec<Progress>, bool);
+        impl ProgressReporter for Recorder {
+            fn update(&mut self, progress: &Progress) {
+                self.0.push(*progress);
+            }
+            fn finish(&mut self, _progress: &Progress, cancelled: bool) {
+                self.1 = cancelled;
+            }
+        }
+
+        let world = Hittable_List::new();
+        let recorder = Arc::new(Mutex::new(Recorder(Vec::new(), false)));
+        let mut camera =
+            test_camera(2.0, 8, Projection::Perspective).with_progress_reporter(recorder.clone());
+        camera.sample_per_pixel = 3;
+        let film = camera.render_film(&world);
+
+        let updates = recorder.lock().unwrap().0.clone();
+        assert_eq!(updates.len(), 4);
+        assert_eq!(updates[3].stage, Stage::Scanline { remaining: 0 });
+        assert_eq!(updates[3].samples, film.pixels.len() as u64 * 3);
+        assert_eq!(updates[3].percent(), 100.0);
+        // every camera ray misses the empty world
+        assert_eq!(updates[3].rays, updates[3].samples);
+        assert!(!recorder.lock().unwrap().1);
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let camera = camera.with_cancel_token(cancel);
+        let film = camera.render_film(&world);
+        assert!(film.pixels.iter().all(|p| p.sample_count == 0));
+        assert!(recorder.lock().unwrap().1);
+    }
+
+    #[test]
+    fn failed_snapshots_are_reported_and_the_render_goes_on() {
+        struct Recorder(Vec<String>);
+        impl ProgressReporter for Recorder {
+            fn update(&mut self, _progress: &Progress) {}
+            fn message(&mut self, text: &str) {
+                self.0.push(text.to_string());
+            }
+        }
+
+        let dir = std::env::temp_dir();
+        let file = dir.join(format!("{}_unreported.ppm", std::process::id()));
+        let snapshot_file = dir.join("no such directory").join("snapshot.ppm");
+        let snapshot_file = snapshot_file.to_str().unwrap().to_string();
+        let recorder = Arc::new(Mutex::new(Recorder(Vec::new())));
+        let mut camera = test_camera(1.0, 4, Projection::Perspective)
+            .with_progress_reporter(recorder.clone())
+            .with_progressive(ProgressiveRendering {
+                snapshot_passes: Some(1),
+                snapshot_file: Some(snapshot_file.clone()),
+                ..Default::default()
+            })
+            .unwrap();
+        camera.sample_per_pixel = 2;
+        camera
+            .render(&Hittable_List::new(), file.to_str().unwrap())
+            .unwrap();
+        std::fs::remove_file(&file).unwrap();
+
+        let messages = recorder.lock().unwrap().0.clone();
+        // one failed snapshot per pass
+        assert_eq!(messages.len(), 2);
+        let prefix = format!("Failed to write snapshot {}: ", snapshot_file);
+        assert!(messages.iter().all(|m| m.starts_with(&prefix)));
+    }
+
+    #[test]
+    fn builders_reject_settings_that_never_finish() {
+        let camera = || test_camera(1.0, 4, Projection::Perspective);
+        let err = |result: io::Result<Camera>| result.err().unwrap().to_string();
+
+        assert_eq!(
+            err(camera().with_threads(0)),
+            "a render needs at least one thread"
+        );
+        assert_eq!(
+            err(camera().with_adaptive_sampling(AdaptiveSampling {
+                min_samples: 1,
+                ..Default::default()
+            })),
+            "adaptive sampling needs 2 <= min_samples <= max_samples, got 1 and 1024"
+        );
+        assert!(
+            camera()
+                .with_adaptive_sampling(AdaptiveSampling {
+                    pass_samples: 0,
+                    ..Default::default()
+                })
+                .is_err()
+        );
+        assert_eq!(
+            err(camera().with_progressive(ProgressiveRendering {
+                snapshot_passes: Some(0),
+                ..Default::default()
+            })),
+            "a snapshot every 0 passes is never due"
+        );
+        assert_eq!(
+            err(camera().with_checkpointing(Checkpointing {
+                file: "render.ckpt".to_string(),
+                every_passes: None,
+                every_seconds: Some(f64::NAN),
+            })),
+            "a checkpoint every NaN seconds is never due"
+        );
+    }
+
+    #[test]
+    fn crop_window_matches_the_full_render() {
+        let mut world = Hittable_List::new();
+        world.add(Arc::new(Sphere::new_static(
+            Point3::new(0.0, 0.0, -2.0),
+            1.0,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )));
+
+        let mut camera = test_camera(1.0, 10, Projection::Perspective)
+            .with_filter(Filter::new(crate::filter::FilterKind::Tent, 1.5))
+            .with_seed(5);
+        camera.sample_per_pixel = 2;
+        camera.max_depth = 3;
+        let full = camera.render_film(&world);
+
+        let window = CropWindow::Normalized {
+            x0: 0.25,
+            y0: 0.5,
+            x1: 0.55,
+            y1: 0.7,
+        };
+        assert_eq!(window.bounds(10, 10), (2..6, 5..7));
+        let cropped = camera
+            .clone()
+            .with_crop(window, CropOutput::Cropped)
+            .unwrap()
+            .render_film(&world);
+        assert_eq!((cropped.width, cropped.height), (4, 2));
+        for y in 0..2 {
+            for x in 0..4 {
+                let a = full.pixel(x + 2, y + 5).color();
+                let b = cropped.pixel(x, y).color();
+                assert!((a - b).length() < 1e-12);
+            }
+        }
+
+        let framed = camera
+            .clone()
+            .with_crop(window, CropOutput::FullFrame)
+            .unwrap()
+            .render_film(&world);
+        assert_eq!((framed.width, framed.height), (10, 10));
+        assert_eq!(framed.pixel(1, 5).weight_sum, 0.0);
+        assert_eq!(framed.pixel(2, 4).weight_sum, 0.0);
+        assert!((framed.pixel(5, 6).color() - full.pixel(5, 6).color()).length() < 1e-12);
+
+        let outside = CropWindow::Pixels {
+            x0: 10,
+            y0: 0,
+            x1: 12,
+            y1: 4,
+        };
+        let err = camera
+            .with_crop(outside, CropOutput::Cropped)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "the crop window is empty in the 10x10 image"
+        );
+    }
+
+    #[test]
+    fn aovs_record_the_first_hit() {
+        let mut world = Hittable_List::new();
+        // moves 0.5 to the right over the shutter interval
+        world.add(Arc::new(Sphere::new_moving(
+            Point3::new(0.0, 0.0, -2.0),
+            Point3::new(0.5, 0.0, -2.0),
+            1.0,
+            Arc::new(Lambertian::new(Color::new(0.2, 0.4, 0.6))),
+        )));
+
+        let mut camera = test_camera(1.0, 9, Projection::Perspective).with_aovs(&Aov::ALL);
+        camera.sample_per_pixel = 4;
+        let film = camera.render_film(&world);
+        let aovs = film.aovs.as_ref().unwrap();
+
+        let center = aovs.pixel(4, 4);
+        assert_eq!(center.hits, 4);
+        assert!((center.value(Aov::Depth).x - 1.0).abs() < 0.1);
+        assert!(center.value(Aov::Normal).z > 0.95);
+        assert!((center.value(Aov::Albedo).y - 0.4).abs() < 1e-12);
+        assert_eq!(center.value(Aov::ObjectId).x, 1.0);
+        // the front of the sphere at depth 1 moves 0.5, the viewport there is 2 wide for 9 pixels
+        assert!((center.value(Aov::Motion).x - 2.25).abs() < 0.1);
+        assert!(center.value(Aov::Motion).y.abs() < 1e-9);
+
+        let corner = aovs.pixel(0, 0);
+        assert_eq!(corner.value(Aov::Depth).x, f64::INFINITY);
+        assert_eq!(corner.value(Aov::MaterialId).x, 0.0);
+        assert_eq!(
+            aov_file_name("out/image.ppm", Aov::Depth),
+            "out/image.depth.pfm"
+        );
+    }
+
+    #[test]
+    fn adaptive_sampling_respects_budget() {
+        // the sky gradient barely changes inside a pixel, a loose threshold stops at once
+        let world = Hittable_List::new();
+        let render = |threshold: f64| {
+            test_camera(1.0, 4, Projection::Perspective)
+                .with_adaptive_sampling(AdaptiveSampling {
+                    min_samples: 4,
+                    max_samples: 12,
+                    pass_samples: 3,
+                    threshold,
+                    heatmap_file: None,
+                })
+                .unwrap()
+                .render_film(&world)
+        };
+
+        let loose = render(1.0);
+        assert!(loose.pixels.iter().all(|p| p.sample_count == 4));
+
+        let strict = render(0.0);
+        assert!(strict.pixels.iter().all(|p| p.sample_count == 12));
+    }
+
+    #[test]
+    fn threads_render_the_same_image() {
+        let mut world = Hittable_List::new();
+        world.add(Arc::new(Sphere::new_static(
+            Point3::new(0.0, 0.0, -1.5),
+            1.0,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )));
+        // a filter reaching into the neighbouring rows, runs of 3 pixels for a thread
+        let camera = |threads: usize| {
+            let mut camera = test_camera(1.0, 8, Projection::Perspective)
+                .with_filter(Filter::new(FilterKind::Gaussian { sigma: 0.5 }, 1.5))
+                .with_aovs(&[Aov::Normal])
+                .with_progress_reporter(Arc::new(Mutex::new(SilentReporter)))
+                .with_threads(threads)
+                .unwrap();
+            camera.sample_per_pixel = 1100;
+            camera.max_depth = 3;
+            camera
+        };
+        let adaptive = AdaptiveSampling {
+            min_samples: 4,
+            max_samples: 40,
+            pass_samples: 4,
+            threshold: 0.05,
+            heatmap_file: None,
+        };
+        let bits = |film: Film| {
+            let normals = film.aovs.as_ref().unwrap().values(Aov::Normal);
+            film.colors()
+                .iter()
+                .chain(&normals)
+                .flat_map(|c| [c.x, c.y, c.z].map(f64::to_bits))
+                .collect::<Vec<u64>>()
+        };
+
+        let single = bits(camera(1).render_film(&world));
+        assert_eq!(bits(camera(3).render_film(&world)), single);
+        let single = bits(
+            camera(1)
+                .with_adaptive_sampling(adaptive.clone())
+                .unwrap()
+                .render_film(&world),
+        );
+        let threaded = camera(4)
+            .with_adaptive_sampling(adaptive)
+            .unwrap()
+            .render_film(&world);
+        assert_eq!(bits(threaded), single);
+    }
+
+    #[test]
+    fn stereo_eyes_converge_on_axis() {
+        let camera = test_camera(1.0, 100, Projection::Perspective);
+        let convergence = 4.0;
+        let target = Point3::new(0.0, 0.0, -convergence);
+
+        for eye_offset in [-0.032, 0.032] {
+            let eye = camera.stereo_eye(eye_offset, convergence);
+            // ray through the exact image center
+            let mut sampler = SamplerKind::Independent.build(1, 0);
+            let ray =
+                eye.get_perspective_ray(49, 49, Vec3::new(0.5, 0.5, 0.0), 0.0, sampler.as_mut());
+            assert!((ray.origin.x - eye_offset).abs() < 1e-12);
+            assert_direction(ray.dir, target - ray.origin);
+        }
+    }
+
+    #[test]
+    fn omni_directional_stereo_baseline_is_perpendicular() {
+        let camera = test_camera(2.0, 200, Projection::Equirectangular);
+        let eye = camera.stereo_eye(0.5, 1.0);
+
+        for px in [0.0, 37.0, 100.0, 150.0] {
+            let origin = eye.panorama_origin(px);
+            let dir = eye.equirectangular_direction(px, 50.0);
+            assert!((origin.length() - 0.5).abs() < 1e-12);
+            assert!(dot(origin, dir).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn fisheye_center_looks_forward_and_corners_are_empty() {
+        for mapping in [FisheyeMapping::Equidistant, FisheyeMapping::Equisolid] {
+            let camera = test_camera(
+                1.0,
+                100,
+                Projection::Fisheye {
+                    mapping,
+                    fov: 180.0,
+                },
+            );
+
+            let center = camera
+                .fisheye_direction(50.0, 50.0, mapping, 180.0)
+                .unwrap();
+            assert_direction(center, Vec3::new(0.0, 0.0, -1.0));
+
+            // the edge of the image circle is 90 degrees off axis for a 180 degree lens
+            let right = camera
+                .fisheye_direction(100.0, 50.0, mapping, 180.0)
+                .unwrap();
+            assert_direction(right, Vec3::new(1.0, 0.0, 0.0));
+
+            assert!(camera.fisheye_direction(0.0, 0.0, mapping, 180.0).is_none());
+        }
+    }
+
+    #[test]
+    fn equirectangular_covers_full_sphere() {
+        let camera = test_camera(2.0, 200, Projection::Equirectangular);
+
+        assert_direction(
+            camera.equirectangular_direction(100.0, 50.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        );
+        assert_direction(
+            camera.equirectangular_direction(150.0, 50.0),
+            Vec3::new(1.0, 0.0, 0.0),
+        );
+        assert_direction(
+            camera.equirectangular_direction(0.0, 50.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        assert_direction(
+            camera.equirectangular_direction(100.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn cube_map_face_centers_follow_axis_order() {
+        let camera = test_camera(6.0, 600, Projection::CubeMap);
+        let expected = [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, -1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, -1.0),
+        ];
+
+        for (face, dir) in expected.iter().enumerate() {
+            let x = face as u64 * 100 + 50;
+            let d = camera.cube_map_direction(x, x as f64, 50.0);
+            assert_direction(d, *dir);
+        }
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+};
+
+use crate::{film::Film, sampler::mix_bits, utils::invalid_data, vec3::Vec3};
+
+const MAGIC: &[u8; 8] = b"RTCKPT02";
+
+// Where and how often a render saves its state, see Camera::with_checkpointing.
+// A checkpoint is written whenever every_passes passes or every_seconds seconds went by since
+// the last one, and once more when the render finishes.
+#[derive(Clone, Debug)]
+pub struct Checkpointing {
+    pub file: String,
+    pub every_passes: Option<u64>,
+    pub every_seconds: Option<f64>,
+}
+
+// State of an interrupted render. The samplers derive every value from the seed, the pixel and
+// the sample index, so the passes done and the per-pixel sample counts kept in the film are
+// all the sampler state there is.
+pub struct Checkpoint {
+    pub scene_hash: u64, // fingerprint of the scene and camera the film belongs to
+    pub passes: u64,
+    pub film: Film,
+}
+
+// Running hash of everything deciding what a render sees, to tell whether a checkpoint belongs
+// to it. Objects, materials and textures add themselves with their fingerprint methods, each
+// starting with its type name so different shapes of the same numbers differ.
+pub struct Fingerprint {
+    hash: u64,
+    unknown: Option<&'static str>, // the first type without a fingerprint
+}
+
+impl Default for Fingerprint {
+    fn default() -> Self {
+        Fingerprint {
+            hash: 0x9e3779b97f4a7c15,
+            unknown: None,
+        }
+    }
+}
+
+impl Fingerprint {
+    pub fn add(&mut self, value: u64) {
+        self.hash = mix_bits(self.hash ^ mix_bits(value));
+    }
+
+    pub fn name(&mut self, name: &str) {
+        name.bytes().for_each(|b| self.add(u64::from(b)));
+        self.add(u64::MAX); // so "ab" "c" differs from "a" "bc"
+    }
+
+    pub fn number(&mut self, x: f64) {
+        self.add(x.to_bits());
+    }
+
+    pub fn vec3(&mut self, v: Vec3) {
+        [v.x, v.y, v.z].into_iter().for_each(|x| self.number(x));
+    }
+
+    // Mark the fingerprint incomplete, a type that can't add itself leaves the checkpoint
+    // unable to tell renders of it apart
+    pub fn unknown(&mut self, type_name: &'static str) {
+        self.unknown.get_or_insert(type_name);
+    }
+
+    pub fn finish(&self) -> io::Result<u64> {
+        match self.unknown {
+            None => Ok(self.hash),
+            Some(type_name) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} has no fingerprint, a render with it can't be checkpointed",
+                    type_name.rsplit("::").next().unwrap_or_default()
+                ),
+            )),
+        }
+    }
+}
+
+impl Checkpoint {
+    // Written to a temporary file first and renamed over file_name, so being killed while
+    // saving leaves the previous checkpoint intact
+    pub fn save(file_name: &str, scene_hash: u64, passes: u64, film: &Film) -> io::Result<()> {
+        let tmp_name = format!("{}.tmp", file_name);
+        {
+            let mut out = BufWriter::new(File::create(&tmp_name)?);
+            out.write_all(MAGIC)?;
+            out.write_all(&scene_hash.to_le_bytes())?;
+            out.write_all(&passes.to_le_bytes())?;
+            film.write_raw(&mut out)?;
+            out.into_inner()?.sync_all()?;
+        }
+        fs::rename(tmp_name, file_name)
+    }
+
+    pub fn load(file_name: &str) -> io::Result<Checkpoint> {
+        let file = File::open(file_name)?;
+        let len = file.metadata()?.len();
+        let mut r = BufReader::new(file);
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data(format!(
+                "{}: not a render checkpoint",
+                file_name
+            )));
+        }
+
+        let mut read_u64 = || -> io::Result<u64> {
+            let mut bytes = [0; 8];
+            r.read_exact(&mut bytes)?;
+            Ok(u64::from_le_bytes(bytes))
+        };
+        let scene_hash = read_u64()?;
+        let passes = read_u64()?;
+        // the magic, scene hash and passes come before the film
+        let film = Film::read_raw(&mut r, len.saturating_sub(24))
+            .map_err(|e| invalid_data(format!("{}: truncated checkpoint ({})", file_name, e)))?;
+
+        Ok(Checkpoint {
+            scene_hash,
+            passes,
+            film,
+        })
+    }
+}
@@ -0,0 +1,146 @@
+use std::{io, sync::Arc};
+
+use crate::{
+    aabb::AABB,
+    checkpoint::Fingerprint,
+    hittable::{Hit_Record, Hittable},
+    interval::{Interval, UNIVERSE_INTERVAL},
+    material::{Isotropic, Material},
+    ray::Ray,
+    scene::{ObjectDescription, SceneExport},
+    texture::{SolidColor, Texture},
+    vec3::{Color, Vec3},
+};
+
+// Smoke or fog of constant density filling a convex boundary. A ray passing through scatters
+// after an exponentially distributed distance, or leaves the volume without hitting it.
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    density: f64,
+    neg_inv_density: f64,
+    phase_function: Arc<Isotropic>,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Arc<dyn Hittable>, density: f64, albedo: Color) -> Self {
+        ConstantMedium::from_texture(boundary, density, Arc::new(SolidColor { albedo }))
+    }
+
+    pub fn from_texture(
+        boundary: Arc<dyn Hittable>,
+        density: f64,
+        texture: Arc<dyn Texture>,
+    ) -> Self {
+        ConstantMedium {
+            boundary,
+            density,
+            neg_inv_density: -1.0 / density,
+            phase_function: Arc::new(Isotropic { texture }),
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<Hit_Record> {
+        // where the ray enters and leaves the boundary, even when it starts inside
+        let rec1 = self.boundary.hit(ray, UNIVERSE_INTERVAL)?;
+        let rec2 = self.boundary.hit(
+            ray,
+            Interval {
+                min: rec1.t + 0.0001,
+                max: f64::INFINITY,
+            },
+        )?;
+
+        let t_enter = rec1.t.max(ray_t.min).max(0.0);
+        let t_exit = rec2.t.min(ray_t.max);
+        if t_enter >= t_exit {
+            return None;
+        }
+
+        let ray_length = ray.dir.length();
+        let distance_inside_boundary = (t_exit - t_enter) * ray_length;
+        let hit_distance = self.neg_inv_density * (1.0 - ray.medium_sample).ln();
+        if hit_distance > distance_inside_boundary {
+            return None;
+        }
+
+        let t = t_enter + hit_distance / ray_length;
+        Some(Hit_Record {
+            p: ray.at(t),
+            normal: Vec3::new(1.0, 0.0, 0.0), // arbitrary, isotropic scattering ignores it
+            t,
+            u: 0.0,
+            v: 0.0,
+            front_face: true,
+            material: self.phase_function.clone(),
+            velocity: rec1.velocity,
+            object: std::ptr::from_ref(self).addr(),
+        })
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.boundary.bounding_box()
+    }
+
+    fn fingerprint(&self, f: &mut Fingerprint) {
+        f.name("constant_medium");
+        f.number(self.neg_inv_density);
+        self.phase_function.fingerprint(f);
+        self.boundary.fingerprint(f);
+    }
+
+    fn export(&self, scene: &mut SceneExport) -> io::Result<()> {
+        let boundary = scene.objects(|scene| self.boundary.export(scene))?;
+        scene.add(ObjectDescription::ConstantMedium {
+            density: self.density,
+            albedo: self.phase_function.texture.describe()?,
+            boundary,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hittable::Sphere, material::Lambertian, vec3::Point3};
+
+    // Fraction of rays through the middle of a unit ball of medium that scatter in it
+    fn scattered(density: f64) -> f64 {
+        let ball = Arc::new(Sphere::new_static(
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        ));
+        let medium = ConstantMedium::new(ball, density, Color::new(1.0, 1.0, 1.0));
+        let n = 1000;
+        let hits = (0..n)
+            .filter(|i| {
+                let ray = Ray {
+                    origin: Point3::new(0.0, 0.0, 5.0),
+                    dir: Vec3::new(0.0, 0.0, -1.0),
+                    time: 0.0,
+                    medium_sample: (*i as f64 + 0.5) / n as f64,
+                };
+                let everything = Interval {
+                    min: 0.001,
+                    max: f64::INFINITY,
+                };
+                medium
+                    .hit(&ray, everything)
+                    .is_some_and(|rec| rec.p.z.abs() <= 1.0)
+            })
+            .count();
+        hits as f64 / n as f64
+    }
+
+    #[test]
+    fn scattering_follows_the_density() {
+        // through 2 units of medium a ray scatters with probability 1 - exp(-2 density)
+        for density in [0.1f64, 1.0] {
+            let expected = 1.0 - (-2.0 * density).exp();
+            assert!((scattered(density) - expected).abs() < 0.05);
+        }
+    }
+}
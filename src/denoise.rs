@@ -0,0 +1,262 @@
+use crate::{
+    aov::Aov,
+    film::Film,
+    utils::linear_to_gamma,
+    vec3::{Color, Vec3, dot},
+};
+
+// Edge-avoiding à-trous wavelet filter (Dammertz et al. 2010) guided by the per-pixel variance
+// like SVGF: every iteration blurs with a 5x5 B3 spline kernel spread twice as wide as the
+// last one, weighting neighbours down where luminance, normal or albedo differ. Lighting is
+// filtered with the albedo divided out, so texture detail survives.
+#[derive(Copy, Clone, Debug)]
+pub struct Denoiser {
+    pub iterations: u32,
+    pub sigma_luminance: f64, // allowed luminance difference in standard errors
+    pub normal_power: f64,    // sharpness of the normal edge stop
+    pub sigma_albedo: f64,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser {
+            iterations: 5,
+            sigma_luminance: 4.0,
+            normal_power: 128.0,
+            sigma_albedo: 0.1,
+        }
+    }
+}
+
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+fn luminance(c: Color) -> f64 {
+    linear_to_gamma(0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z)
+}
+
+// Albedo a color is divided by, never zero so black surfaces keep their lighting
+fn safe_albedo(albedo: Color) -> Color {
+    Color::new(albedo.x.max(0.01), albedo.y.max(0.01), albedo.z.max(0.01))
+}
+
+impl Denoiser {
+    // Denoised colors of the film in row-major order. Uses the albedo and normal AOVs when the
+    // film recorded them, otherwise only the luminance guides the filter.
+    pub fn denoise(&self, film: &Film) -> Vec<Color> {
+        let (w, h) = (film.width as i64, film.height as i64);
+        let colors = film.colors();
+        let aovs = film.aovs.as_ref();
+        let albedo: Vec<Color> = match aovs {
+            Some(aovs) => aovs
+                .values(Aov::Albedo)
+                .into_iter()
+                .zip(&aovs.pixels)
+                .map(|(a, p)| {
+                    if p.hits > 0 {
+                        a
+                    } else {
+                        Color::new(1.0, 1.0, 1.0)
+                    }
+                })
+                .collect(),
+            None => vec![Color::new(1.0, 1.0, 1.0); colors.len()],
+        };
+        let normals: Option<Vec<Vec3>> = aovs.map(|aovs| aovs.values(Aov::Normal));
+
+        let mut irradiance: Vec<Color> = colors
+            .iter()
+            .zip(&albedo)
+            .map(|(c, a)| *c / safe_albedo(*a))
+            .collect();
+        // the edge stop compares the luminance of the beauty, the unit the variance is in
+        let lum: Vec<f64> = colors.iter().map(|c| luminance(*c)).collect();
+        // variance of the pixel mean. Pixels with fewer than two samples have none, like SVGF
+        // they take the luminance variance of their 3x3 neighbourhood instead.
+        let mut variance: Vec<f64> = film
+            .pixels
+            .iter()
+            .enumerate()
+            .map(|(p, pixel)| {
+                let variance = pixel.standard_error().powi(2);
+                if variance.is_finite() {
+                    return variance;
+                }
+                let (x, y) = (p as i64 % w, p as i64 / w);
+                let neighbours: Vec<f64> = (y - 1..=y + 1)
+                    .flat_map(|qy| (x - 1..=x + 1).map(move |qx| (qx, qy)))
+                    .filter(|(qx, qy)| (0..w).contains(qx) && (0..h).contains(qy))
+                    .map(|(qx, qy)| lum[(qy * w + qx) as usize])
+                    .collect();
+                let n = neighbours.len() as f64;
+                let mean = neighbours.iter().sum::<f64>() / n;
+                neighbours.iter().map(|l| (l - mean).powi(2)).sum::<f64>() / n
+            })
+            .collect();
+
+        for iteration in 0..self.iterations {
+            let step = 1i64 << iteration;
+            let mut next_irradiance = irradiance.clone();
+            let mut next_variance = variance.clone();
+
+            for y in 0..h {
+                for x in 0..w {
+                    let p = (y * w + x) as usize;
+                    let sigma_l = self.sigma_luminance * variance[p].sqrt() + 1e-6;
+
+                    let mut sum = Color::default();
+                    let mut weight_sum = 0.0;
+                    let mut variance_sum = 0.0;
+                    for (j, ky) in KERNEL.iter().enumerate() {
+                        for (i, kx) in KERNEL.iter().enumerate() {
+                            let qx = x + (i as i64 - 2) * step;
+                            let qy = y + (j as i64 - 2) * step;
+                            if qx < 0 || qy < 0 || qx >= w || qy >= h {
+                                continue;
+                            }
+                            let q = (qy * w + qx) as usize;
+
+                            let mut weight = kx * ky;
+                            weight *= (-(lum[p] - lum[q]).abs() / sigma_l).exp();
+                            if let Some(normals) = &normals {
+                                weight *=
+                                    dot(normals[p], normals[q]).max(0.0).powf(self.normal_power);
+                            }
+                            let da = albedo[p] - albedo[q];
+                            weight *= (-da.length_squared() / self.sigma_albedo.powi(2)).exp();
+                            // the center always counts, so a pixel can't lose all its weight
+                            if q == p {
+                                weight = kx * ky;
+                            }
+                            if weight == 0.0 {
+                                continue;
+                            }
+
+                            sum += weight * irradiance[q];
+                            weight_sum += weight;
+                            variance_sum += weight * weight * variance[q];
+                        }
+                    }
+                    next_irradiance[p] = sum / weight_sum;
+                    next_variance[p] = variance_sum / (weight_sum * weight_sum);
+                }
+            }
+            irradiance = next_irradiance;
+            variance = next_variance;
+        }
+
+        irradiance
+            .iter()
+            .zip(&albedo)
+            .map(|(e, a)| *e * safe_albedo(*a))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aov::FirstHit;
+
+    // Left half red facing the camera, right half blue facing up, every pixel a noisy sample
+    // checkerboard in its albedo color
+    fn noisy_film() -> Film {
+        let mut film = Film::new(16, 8).with_aovs();
+        for y in 0..8 {
+            for x in 0..16 {
+                let left = x < 8;
+                for s in 0..4u64 {
+                    let base = if (x + y) % 2 == 0 { 0.7 } else { 0.3 };
+                    let g = base + if s % 2 == 0 { 0.2 } else { -0.2 };
+                    let color = if left {
+                        Color::new(g, 0.0, 0.0)
+                    } else {
+                        Color::new(0.0, 0.0, g)
+                    };
+                    film.pixel_mut(x, y).add_sample(color);
+                    let hit = FirstHit {
+                        depth: 1.0,
+                        normal: if left {
+                            Vec3::new(0.0, 0.0, 1.0)
+                        } else {
+                            Vec3::new(0.0, 1.0, 0.0)
+                        },
+                        albedo: if left {
+                            Color::new(1.0, 0.0, 0.0)
+                        } else {
+                            Color::new(0.0, 0.0, 1.0)
+                        },
+                        position: Vec3::default(),
+                        material: left as usize,
+                        object: left as usize,
+                        motion: (0.0, 0.0),
+                    };
+                    film.aovs.as_mut().unwrap().add_sample(x, y, Some(&hit));
+                }
+            }
+        }
+        film
+    }
+
+    #[test]
+    fn smooths_noise_and_keeps_edges() {
+        let film = noisy_film();
+        let denoised = Denoiser::default().denoise(&film);
+
+        let spread = |colors: &[Color]| {
+            let values: Vec<f64> = colors.iter().take(8).map(|c| c.x).collect();
+            let max = values.iter().cloned().fold(f64::MIN, f64::max);
+            let min = values.iter().cloned().fold(f64::MAX, f64::min);
+            max - min
+        };
+        assert!(spread(&denoised) < 0.5 * spread(&film.colors()));
+
+        // nothing bleeds across the albedo and normal edge
+        for y in 0..8 {
+            for x in 0..16 {
+                let p = (y * 16 + x) as usize;
+                assert!(denoised[p].x.is_finite());
+                if x < 8 {
+                    assert!(denoised[p].z < 1e-9);
+                } else {
+                    assert!(denoised[p].x < 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn single_samples_stay_finite() {
+        // one sample per pixel, perpendicular normals on the two halves and a background pixel
+        let mut film = Film::new(8, 4).with_aovs();
+        for y in 0..4 {
+            for x in 0..8 {
+                let g = if (x + y) % 2 == 0 { 0.8 } else { 0.2 };
+                film.pixel_mut(x, y).add_sample(Color::new(g, g, g));
+                if (x, y) == (3, 1) {
+                    continue;
+                }
+                let hit = FirstHit {
+                    depth: 1.0,
+                    normal: if x < 4 {
+                        Vec3::new(0.0, 0.0, 1.0)
+                    } else {
+                        Vec3::new(1.0, 0.0, 0.0)
+                    },
+                    albedo: Color::new(0.5, 0.5, 0.5),
+                    position: Vec3::default(),
+                    material: 1,
+                    object: 1,
+                    motion: (0.0, 0.0),
+                };
+                film.aovs.as_mut().unwrap().add_sample(x, y, Some(&hit));
+            }
+        }
+
+        let denoised = Denoiser::default().denoise(&film);
+        for c in &denoised {
+            assert!(c.x.is_finite() && c.y.is_finite() && c.z.is_finite());
+        }
+        // the background pixel has no neighbour to share with and keeps its own color
+        assert!((denoised[11].x - 0.8).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,554 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    ops::Range,
+    path::Path,
+};
+
+use crate::{
+    aov::{Aov, AovFilm},
+    filter::Filter,
+    interval::Interval,
+    utils::{invalid_data, linear_to_gamma},
+    vec3::{Color, Vec3},
+};
+
+const INTENSITY: Interval = Interval {
+    min: 0.0,
+    max: 0.999,
+};
+
+// Gamma corrected 8-bit channels of a linear color
+fn to_bytes(color: Color) -> [u8; 3] {
+    [color.x, color.y, color.z].map(|c| (INTENSITY.clamp(linear_to_gamma(c)) * 256.0) as u8)
+}
+
+// File format of a rendered image
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ImageFormat {
+    Ppm, // plain text, gamma corrected 8 bits per channel
+    Png, // gamma corrected 8 bits per channel, stored without compression
+    Pfm, // linear 32-bit floats
+}
+
+impl ImageFormat {
+    pub fn from_name(name: &str) -> Option<ImageFormat> {
+        match name.to_ascii_lowercase().as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "png" => Some(ImageFormat::Png),
+            "pfm" => Some(ImageFormat::Pfm),
+            _ => None,
+        }
+    }
+
+    // Format named by the extension of file_name
+    pub fn from_file_name(file_name: &str) -> Option<ImageFormat> {
+        Path::new(file_name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .and_then(ImageFormat::from_name)
+    }
+}
+
+// Write linear colors in the given format, pixels in row-major order
+pub(crate) fn write_image(
+    format: ImageFormat,
+    file_name: &str,
+    image_width: u64,
+    image_height: u64,
+    pixels: &[Color],
+) -> io::Result<()> {
+    match format {
+        ImageFormat::Ppm => write_ppm(file_name, image_width, image_height, pixels),
+        ImageFormat::Png => write_png(file_name, image_width, image_height, pixels),
+        ImageFormat::Pfm => write_pfm(file_name, image_width, image_height, pixels),
+    }
+}
+
+// Write linear colors as a gamma corrected plain PPM, pixels in row-major order
+pub(crate) fn write_ppm(
+    file_name: &str,
+    image_width: u64,
+    image_height: u64,
+    pixels: &[Color],
+) -> io::Result<()> {
+    let file = File::create(file_name)?;
+    let mut out = BufWriter::new(file);
+
+    writeln!(out, "P3\n{} {}\n255", image_width, image_height)?;
+    for pixel_color in pixels {
+        let [r, g, b] = to_bytes(*pixel_color);
+        writeln!(out, "{} {} {}", r, g, b)?;
+    }
+    Ok(())
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for b in bytes {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in bytes.chunks(5552) {
+        for x in chunk {
+            a += *x as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+fn write_png_chunk(mut w: impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    let mut chunk = kind.to_vec();
+    chunk.extend_from_slice(data);
+    w.write_all(&chunk)?;
+    w.write_all(&crc32(&chunk).to_be_bytes())
+}
+
+// Write linear colors as a gamma corrected 8-bit RGB PNG. The image data goes into stored
+// deflate blocks, no compression, so no compressor is needed.
+pub(crate) fn write_png(
+    file_name: &str,
+    image_width: u64,
+    image_height: u64,
+    pixels: &[Color],
+) -> io::Result<()> {
+    let file = File::create(file_name)?;
+    let mut out = BufWriter::new(file);
+
+    // every row starts with filter type 0, none
+    let mut raw = Vec::with_capacity(pixels.len() * 3 + image_height as usize);
+    for row in pixels.chunks(image_width as usize) {
+        raw.push(0);
+        for pixel_color in row {
+            raw.extend_from_slice(&to_bytes(*pixel_color));
+        }
+    }
+    let mut zlib = vec![0x78, 0x01];
+    let blocks = raw.chunks(65535);
+    let count = blocks.len();
+    for (i, block) in blocks.enumerate() {
+        zlib.push((i + 1 == count) as u8);
+        let len = block.len() as u16;
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(image_width as u32).to_be_bytes());
+    header.extend_from_slice(&(image_height as u32).to_be_bytes());
+    // 8 bits per channel, RGB, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    out.write_all(b"\x89PNG\r\n\x1a\n")?;
+    write_png_chunk(&mut out, b"IHDR", &header)?;
+    write_png_chunk(&mut out, b"IDAT", &zlib)?;
+    write_png_chunk(&mut out, b"IEND", &[])
+}
+
+// Write raw linear values as a little endian portable float map, which stores the rows bottom
+// to top
+pub(crate) fn write_pfm(
+    file_name: &str,
+    image_width: u64,
+    image_height: u64,
+    pixels: &[Vec3],
+) -> io::Result<()> {
+    let file = File::create(file_name)?;
+    let mut out = BufWriter::new(file);
+
+    write!(out, "PF\n{} {}\n-1.0\n", image_width, image_height)?;
+    for row in pixels.chunks(image_width as usize).rev() {
+        for v in row {
+            for c in [v.x, v.y, v.z] {
+                out.write_all(&(c as f32).to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn luminance(c: Color) -> f64 {
+    0.2126 * c.x + 0.7152 * c.y + 0.0722 * c.z
+}
+
+// Accumulated samples of one pixel: the filter weighted sum of every sample splatted onto it,
+// and statistics of the samples taken inside it. The statistics are a running mean and
+// variance (Welford) of the display luminance, i.e. after gamma correction, so the error
+// estimate matches what the eye sees in the output image.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FilmPixel {
+    pub weighted_sum: Color,
+    pub weight_sum: f64,
+    pub sample_count: u64,
+    luminance_mean: f64,
+    luminance_m2: f64,
+}
+
+// Every accumulated value of a pixel, in the order they are stored in a checkpoint
+const PIXEL_VALUES: usize = 7;
+
+impl FilmPixel {
+    // Sample taken inside this pixel with a box filter
+    pub fn add_sample(&mut self, color: Color) {
+        self.add_statistics(color);
+        self.add_weighted(color, 1.0);
+    }
+
+    pub fn add_statistics(&mut self, color: Color) {
+        self.sample_count += 1;
+
+        let l = linear_to_gamma(luminance(color));
+        let delta = l - self.luminance_mean;
+        self.luminance_mean += delta / self.sample_count as f64;
+        self.luminance_m2 += delta * (l - self.luminance_mean);
+    }
+
+    pub fn add_weighted(&mut self, color: Color, weight: f64) {
+        self.weighted_sum += weight * color;
+        self.weight_sum += weight;
+    }
+
+    pub fn color(&self) -> Color {
+        // negative filter lobes can leave a pixel without positive weight
+        if self.weight_sum <= 0.0 {
+            return Color::default();
+        }
+        self.weighted_sum / self.weight_sum
+    }
+
+    fn to_bits(self) -> [u64; PIXEL_VALUES] {
+        [
+            self.weighted_sum.x.to_bits(),
+            self.weighted_sum.y.to_bits(),
+            self.weighted_sum.z.to_bits(),
+            self.weight_sum.to_bits(),
+            self.sample_count,
+            self.luminance_mean.to_bits(),
+            self.luminance_m2.to_bits(),
+        ]
+    }
+
+    fn from_bits(v: [u64; PIXEL_VALUES]) -> Self {
+        FilmPixel {
+            weighted_sum: Color::new(
+                f64::from_bits(v[0]),
+                f64::from_bits(v[1]),
+                f64::from_bits(v[2]),
+            ),
+            weight_sum: f64::from_bits(v[3]),
+            sample_count: v[4],
+            luminance_mean: f64::from_bits(v[5]),
+            luminance_m2: f64::from_bits(v[6]),
+        }
+    }
+
+    // Unbiased sample variance of the display luminance
+    pub fn variance(&self) -> f64 {
+        if self.sample_count < 2 {
+            return 0.0;
+        }
+        self.luminance_m2 / (self.sample_count - 1) as f64
+    }
+
+    // Estimated standard deviation of the pixel value around the true pixel value
+    pub fn standard_error(&self) -> f64 {
+        if self.sample_count < 2 {
+            return f64::INFINITY;
+        }
+        (self.variance() / self.sample_count as f64).sqrt()
+    }
+}
+
+// Framebuffer accumulating camera samples, row-major
+#[derive(Clone)]
+pub struct Film {
+    pub width: u64,
+    pub height: u64,
+    pub pixels: Vec<FilmPixel>,
+    pub aovs: Option<AovFilm>, // first-hit AOVs, when recorded
+}
+
+impl Film {
+    pub fn new(width: u64, height: u64) -> Self {
+        Film {
+            width,
+            height,
+            pixels: vec![FilmPixel::default(); (width * height) as usize],
+            aovs: None,
+        }
+    }
+
+    pub fn with_aovs(mut self) -> Self {
+        self.aovs = Some(AovFilm::new(self.width, self.height));
+        self
+    }
+
+    pub fn pixel(&self, x: u64, y: u64) -> &FilmPixel {
+        &self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn pixel_mut(&mut self, x: u64, y: u64) -> &mut FilmPixel {
+        &mut self.pixels[(y * self.width + x) as usize]
+    }
+
+    // Splat a sample taken in pixel (x, y) at the continuous image position (px, py) to every
+    // pixel the filter reaches, pixel (i, j) has its center at (i + 0.5, j + 0.5)
+    pub fn add_sample(&mut self, x: u64, y: u64, px: f64, py: f64, color: Color, filter: &Filter) {
+        self.pixel_mut(x, y).add_statistics(color);
+
+        let r = filter.radius;
+        let x0 = (px - 0.5 - r).ceil().max(0.0) as u64;
+        let x1 = ((px - 0.5 + r).floor() as i64).min(self.width as i64 - 1);
+        let y0 = (py - 0.5 - r).ceil().max(0.0) as u64;
+        let y1 = ((py - 0.5 + r).floor() as i64).min(self.height as i64 - 1);
+        if x1 < 0 || y1 < 0 {
+            return;
+        }
+
+        for j in y0..=y1 as u64 {
+            for i in x0..=x1 as u64 {
+                let weight = filter.evaluate(i as f64 + 0.5 - px, j as f64 + 0.5 - py);
+                if weight != 0.0 {
+                    self.pixel_mut(i, j).add_weighted(color, weight);
+                }
+            }
+        }
+    }
+
+    // The pixels in columns xs and rows ys as a film of their own
+    pub fn crop(&self, xs: Range<u64>, ys: Range<u64>) -> Film {
+        let mut cropped = Film::new(xs.end - xs.start, ys.end - ys.start);
+        for y in ys.clone() {
+            for x in xs.clone() {
+                *cropped.pixel_mut(x - xs.start, y - ys.start) = *self.pixel(x, y);
+            }
+        }
+        cropped.aovs = self.aovs.as_ref().map(|aovs| aovs.crop(xs, ys));
+        cropped
+    }
+
+    // Only keep the pixels in columns xs and rows ys
+    pub fn clear_outside(mut self, xs: Range<u64>, ys: Range<u64>) -> Film {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if !xs.contains(&x) || !ys.contains(&y) {
+                    *self.pixel_mut(x, y) = FilmPixel::default();
+                }
+            }
+        }
+        if let Some(aovs) = self.aovs.as_mut() {
+            aovs.clear_outside(xs, ys);
+        }
+        self
+    }
+
+    pub fn colors(&self) -> Vec<Color> {
+        self.pixels.iter().map(|p| p.color()).collect()
+    }
+
+    // Samples taken per pixel as a false color ramp, blue for the fewest and red for the most
+    pub fn sample_heatmap(&self) -> Vec<Color> {
+        let min = self
+            .pixels
+            .iter()
+            .map(|p| p.sample_count)
+            .min()
+            .unwrap_or(0);
+        let max = self
+            .pixels
+            .iter()
+            .map(|p| p.sample_count)
+            .max()
+            .unwrap_or(0);
+        let range = (max - min).max(1) as f64;
+
+        self.pixels
+            .iter()
+            .map(|p| {
+                let t = (p.sample_count - min) as f64 / range;
+                if t < 0.5 {
+                    Vec3::new(0.0, 2.0 * t, 1.0 - 2.0 * t)
+                } else {
+                    Vec3::new(2.0 * t - 1.0, 2.0 - 2.0 * t, 0.0)
+                }
+            })
+            .collect()
+    }
+
+    pub fn write_ppm(&self, file_name: &str) -> io::Result<()> {
+        write_ppm(file_name, self.width, self.height, &self.colors())
+    }
+
+    pub fn write_aov(&self, aov: Aov, file_name: &str) -> io::Result<()> {
+        let aovs = self.aovs.as_ref().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "the film has no AOVs recorded")
+        })?;
+        write_pfm(file_name, self.width, self.height, &aovs.values(aov))
+    }
+
+    // Exact binary copy of the accumulation buffers: width, height, then every pixel, all as
+    // little endian u64 (floats by their bits), and 1 followed by the AOVs when recorded or 0.
+    pub fn write_raw(&self, mut w: impl Write) -> io::Result<()> {
+        w.write_all(&self.width.to_le_bytes())?;
+        w.write_all(&self.height.to_le_bytes())?;
+        for pixel in &self.pixels {
+            for v in pixel.to_bits() {
+                w.write_all(&v.to_le_bytes())?;
+            }
+        }
+        w.write_all(&u64::from(self.aovs.is_some()).to_le_bytes())?;
+        match &self.aovs {
+            Some(aovs) => aovs.write_raw(w),
+            None => Ok(()),
+        }
+    }
+
+    // r holds at most len bytes, a film too large for them is rejected before allocating it
+    pub fn read_raw(mut r: impl Read, len: u64) -> io::Result<Film> {
+        let mut read_u64 = || -> io::Result<u64> {
+            let mut bytes = [0; 8];
+            r.read_exact(&mut bytes)?;
+            Ok(u64::from_le_bytes(bytes))
+        };
+
+        let width = read_u64()?;
+        let height = read_u64()?;
+        let pixel_bytes = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(8 * PIXEL_VALUES as u64));
+        if pixel_bytes.is_none_or(|bytes| bytes > len.saturating_sub(16)) {
+            return Err(invalid_data(format!(
+                "a {}x{} film doesn't fit in {} bytes",
+                width, height, len
+            )));
+        }
+        let mut film = Film::new(width, height);
+        for pixel in film.pixels.iter_mut() {
+            let mut v = [0; PIXEL_VALUES];
+            for value in v.iter_mut() {
+                *value = read_u64()?;
+            }
+            *pixel = FilmPixel::from_bits(v);
+        }
+        if read_u64()? != 0 {
+            film.aovs = Some(AovFilm::read_raw(r, width, height)?);
+        }
+        Ok(film)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_tracks_mean_and_variance() {
+        let mut pixel = FilmPixel::default();
+        // display luminance of gray g is sqrt(g)
+        for g in [0.0, 0.25, 1.0, 0.25] {
+            pixel.add_sample(Color::new(g, g, g));
+        }
+
+        assert_eq!(pixel.sample_count, 4);
+        assert!((pixel.color().y - 0.375).abs() < 1e-12);
+        // display values 0, 0.5, 1, 0.5: mean 0.5, sample variance 1/6
+        assert!((pixel.variance() - 1.0 / 6.0).abs() < 1e-12);
+        assert!((pixel.standard_error() - (1.0 / 24.0f64).sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn box_filter_keeps_samples_in_their_pixel() {
+        let mut film = Film::new(3, 1);
+        let filter = Filter::default();
+        film.add_sample(1, 0, 1.2, 0.5, Color::new(1.0, 1.0, 1.0), &filter);
+        film.add_sample(1, 0, 1.9, 0.5, Color::new(0.0, 0.0, 0.0), &filter);
+
+        assert_eq!(film.pixel(0, 0).weight_sum, 0.0);
+        assert_eq!(film.pixel(2, 0).weight_sum, 0.0);
+        assert_eq!(film.pixel(1, 0).color().x, 0.5);
+        assert_eq!(film.pixel(1, 0).sample_count, 2);
+    }
+
+    #[test]
+    fn wide_filter_splats_to_neighbours() {
+        let mut film = Film::new(3, 3);
+        let filter = Filter::new(crate::filter::FilterKind::Tent, 1.5);
+        film.add_sample(1, 1, 1.5, 1.5, Color::new(1.0, 1.0, 1.0), &filter);
+
+        // centered sample: full weight in the middle, less in the neighbours, none counted
+        let center = film.pixel(1, 1).weight_sum;
+        assert!((center - 2.25).abs() < 1e-12);
+        assert!((film.pixel(0, 1).weight_sum - 0.75).abs() < 1e-12);
+        assert!((film.pixel(2, 2).weight_sum - 0.25).abs() < 1e-12);
+        assert_eq!(film.pixel(0, 0).sample_count, 0);
+        assert_eq!(film.pixel(0, 0).color().x, 1.0);
+    }
+
+    #[test]
+    fn raw_round_trip_is_exact() {
+        let mut film = Film::new(2, 2);
+        let filter = Filter::new(crate::filter::FilterKind::Tent, 1.5);
+        film.add_sample(0, 1, 0.3, 1.7, Color::new(0.1, 0.2, 0.3), &filter);
+        film.add_sample(0, 1, 0.6, 1.2, Color::new(0.7, 0.0, 1.0), &filter);
+
+        let mut bytes = Vec::new();
+        film.write_raw(&mut bytes).unwrap();
+        let len = bytes.len() as u64;
+        let read = Film::read_raw(bytes.as_slice(), len).unwrap();
+
+        assert_eq!((read.width, read.height), (2, 2));
+        for (a, b) in film.pixels.iter().zip(&read.pixels) {
+            assert_eq!(a.to_bits(), b.to_bits());
+        }
+        assert!(Film::read_raw(&bytes[..bytes.len() - 1], len - 1).is_err());
+
+        // dimensions that don't fit the data fail before the film is allocated
+        let mut huge = bytes.clone();
+        huge[..8].copy_from_slice(&u64::MAX.to_le_bytes());
+        let err = Film::read_raw(huge.as_slice(), len).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        huge[..8].copy_from_slice(&(1u64 << 40).to_le_bytes());
+        assert!(Film::read_raw(huge.as_slice(), len).is_err());
+    }
+
+    #[test]
+    fn heatmap_spans_blue_to_red() {
+        let mut film = Film::new(2, 1);
+        film.pixel_mut(0, 0).add_sample(Color::default());
+        for _ in 0..3 {
+            film.pixel_mut(1, 0).add_sample(Color::default());
+        }
+
+        let heatmap = film.sample_heatmap();
+        assert_eq!((heatmap[0].z, heatmap[0].x), (1.0, 0.0));
+        assert_eq!((heatmap[1].z, heatmap[1].x), (0.0, 1.0));
+    }
+
+    #[test]
+    fn png_checksums_match_known_values() {
+        assert_eq!(crc32(b"IEND"), 0xae426082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+        assert_eq!(
+            ImageFormat::from_file_name("out/a.PNG"),
+            Some(ImageFormat::Png)
+        );
+        assert_eq!(ImageFormat::from_file_name("out/a"), None);
+    }
+}
@@ -0,0 +1,133 @@
+use std::f64::consts::PI;
+
+// Shape of the pixel reconstruction filter, evaluated separately along x and y
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FilterKind {
+    Box,
+    Tent,
+    // Gaussian with standard deviation sigma, shifted down to reach 0 at the radius
+    Gaussian { sigma: f64 },
+    // Mitchell-Netravali cubic, b = c = 1/3 is the recommended balance of blur and ringing
+    Mitchell { b: f64, c: f64 },
+    // sinc windowed by a wider sinc, tau is the number of sinc lobes inside the radius
+    Lanczos { tau: f64 },
+}
+
+// Weight of a sample as a function of its offset from a pixel center. Every sample is splatted
+// to all pixels whose center lies within radius, in pixels, of the sample position.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Filter {
+    pub kind: FilterKind,
+    pub radius: f64,
+}
+
+impl Default for Filter {
+    // Box over exactly one pixel, each sample only counts for the pixel it was taken in
+    fn default() -> Self {
+        Filter {
+            kind: FilterKind::Box,
+            radius: 0.5,
+        }
+    }
+}
+
+fn gaussian(x: f64, sigma: f64) -> f64 {
+    (-x * x / (2.0 * sigma * sigma)).exp()
+}
+
+fn mitchell(x: f64, b: f64, c: f64) -> f64 {
+    let x = x.abs();
+    if x <= 1.0 {
+        ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+            + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+            + (6.0 - 2.0 * b))
+            / 6.0
+    } else if x <= 2.0 {
+        ((-b - 6.0 * c) * x * x * x
+            + (6.0 * b + 30.0 * c) * x * x
+            + (-12.0 * b - 48.0 * c) * x
+            + (8.0 * b + 24.0 * c))
+            / 6.0
+    } else {
+        0.0
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 {
+        return 1.0;
+    }
+    (PI * x).sin() / (PI * x)
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind, radius: f64) -> Self {
+        assert!(radius > 0.0, "filter radius must be positive");
+        Filter { kind, radius }
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        let r = self.radius;
+        if x.abs() > r {
+            return 0.0;
+        }
+        match self.kind {
+            FilterKind::Box => 1.0,
+            FilterKind::Tent => r - x.abs(),
+            FilterKind::Gaussian { sigma } => (gaussian(x, sigma) - gaussian(r, sigma)).max(0.0),
+            // the cubic spans [-2, 2], stretch it over the radius
+            FilterKind::Mitchell { b, c } => mitchell(2.0 * x / r, b, c),
+            FilterKind::Lanczos { tau } => sinc(x) * sinc(x * tau / r),
+        }
+    }
+
+    pub fn evaluate(&self, dx: f64, dy: f64) -> f64 {
+        self.evaluate_1d(dx) * self.evaluate_1d(dy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filters_peak_at_center_and_vanish_past_radius() {
+        let kinds = [
+            FilterKind::Box,
+            FilterKind::Tent,
+            FilterKind::Gaussian { sigma: 0.5 },
+            FilterKind::Mitchell {
+                b: 1.0 / 3.0,
+                c: 1.0 / 3.0,
+            },
+            FilterKind::Lanczos { tau: 3.0 },
+        ];
+        for kind in kinds {
+            let filter = Filter::new(kind, 1.5);
+            let center = filter.evaluate(0.0, 0.0);
+            assert!(center > 0.0, "{:?}", kind);
+            assert!(filter.evaluate(0.3, 0.2) <= center, "{:?}", kind);
+            assert_eq!(filter.evaluate(1.6, 0.0), 0.0, "{:?}", kind);
+            assert_eq!(filter.evaluate(0.0, -1.6), 0.0, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn mitchell_has_negative_lobes() {
+        let filter = Filter::new(
+            FilterKind::Mitchell {
+                b: 1.0 / 3.0,
+                c: 1.0 / 3.0,
+            },
+            2.0,
+        );
+        assert!(filter.evaluate(1.5, 0.0) < 0.0);
+        assert!((filter.evaluate(0.0, 0.0) - (8.0f64 / 9.0).powi(2)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn gaussian_reaches_zero_at_radius() {
+        let filter = Filter::new(FilterKind::Gaussian { sigma: 0.5 }, 1.5);
+        assert!(filter.evaluate(1.5, 0.0).abs() < 1e-12);
+    }
+}
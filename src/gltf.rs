@@ -0,0 +1,834 @@
+use std::{fs, io, path::Path, sync::Arc};
+
+use crate::{
+    aabb::{AABB, EMPTY_AABB},
+    bvh::BVH_Node,
+    hittable::{Hittable, Hittable_List},
+    json::{Json, JsonValue},
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+    matrix::{IDENTITY_MATRIX, Matrix4},
+    mesh::TriangleMesh,
+    scene::{CameraSettings, ImportedScene, RenderSettings},
+    utils::invalid_data,
+    vec3::{Color, Point3, Vec3},
+};
+
+// How a glTF material renders here. Diffuse surfaces keep their base color apart, so vertex
+// colors can be multiplied in.
+#[derive(Clone)]
+enum Surface {
+    Diffuse(Color),
+    Other(Arc<dyn Material>),
+}
+
+// What primitives without a material get, a light gray instead of glTF's default of a
+// rough white metal
+// Largest number of values of an accessor without a buffer view, whose count nothing else
+// bounds
+const MAX_ZERO_VALUES: usize = 1 << 24;
+
+const DEFAULT_SURFACE: Color = Color {
+    x: 0.8,
+    y: 0.8,
+    z: 0.8,
+};
+
+struct Document<'a> {
+    json: &'a Json,
+    buffers: Vec<Vec<u8>>,
+    surfaces: Vec<Surface>,
+    warnings: Vec<String>,
+}
+
+// Item index of array key of the top level, e.g. ("meshes", 2)
+fn item<'a>(json: &'a Json, key: &str, index: &Json) -> io::Result<&'a Json> {
+    let i = index.as_f64()?;
+    let items = match json.get(key) {
+        Some(items) => items.as_array()?,
+        None => &[],
+    };
+    if i < 0.0 || i.fract() != 0.0 || i as usize >= items.len() {
+        return Err(index.error(format!(
+            "{} is not an index into the {} {}",
+            i,
+            items.len(),
+            key
+        )));
+    }
+    Ok(&items[i as usize])
+}
+
+fn number_or(json: &Json, key: &str, default: f64) -> io::Result<f64> {
+    json.get(key).map_or(Ok(default), Json::as_f64)
+}
+
+fn numbers<const N: usize>(json: &Json) -> io::Result<[f64; N]> {
+    let items = json.as_array()?;
+    if items.len() != N {
+        return Err(json.error(format!(
+            "expected an array of {} numbers, found {} items",
+            N,
+            items.len()
+        )));
+    }
+    let mut values = [0.0; N];
+    for (value, item) in values.iter_mut().zip(items) {
+        *value = item.as_f64()?;
+    }
+    Ok(values)
+}
+
+// Standard base64 as in data: URIs, padding optional
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut bits = 0u32;
+    let mut count = 0;
+    for c in text.trim_end_matches('=').bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+        bits = (bits << 6) | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+// URIs of external files are relative and percent-encoded
+fn decode_uri(uri: &str) -> String {
+    let bytes = uri.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// Split a .glb into its JSON chunk and its optional binary chunk
+fn split_glb(bytes: &[u8]) -> io::Result<(&str, Option<&[u8]>)> {
+    let u32_at = |at: usize| {
+        bytes
+            .get(at..at + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+            .ok_or_else(|| invalid_data("truncated glb".to_string()))
+    };
+    if u32_at(4)? != 2 {
+        return Err(invalid_data(format!(
+            "glb version {}, expected 2",
+            u32_at(4)?
+        )));
+    }
+    let length = u32_at(8)?.min(bytes.len());
+
+    let mut json = None;
+    let mut bin = None;
+    let mut at = 12;
+    while at + 8 <= length {
+        let chunk_length = u32_at(at)?;
+        let chunk = bytes
+            .get(at + 8..at + 8 + chunk_length)
+            .ok_or_else(|| invalid_data("truncated glb chunk".to_string()))?;
+        match &bytes[at + 4..at + 8] {
+            b"JSON" if json.is_none() => {
+                json = Some(
+                    std::str::from_utf8(chunk)
+                        .map_err(|_| invalid_data("the glb JSON is not UTF-8".to_string()))?,
+                );
+            }
+            b"BIN\0" if bin.is_none() => bin = Some(chunk),
+            _ => {} // unknown chunks are to be skipped
+        }
+        at += 8 + chunk_length;
+    }
+    let json = json.ok_or_else(|| invalid_data("glb without a JSON chunk".to_string()))?;
+    Ok((json, bin))
+}
+
+impl ImportedScene {
+    pub fn load_gltf(file_name: &str) -> io::Result<ImportedScene> {
+        let bytes = fs::read(file_name)?;
+        let dir = Path::new(file_name).parent().unwrap_or(Path::new(""));
+        ImportedScene::parse_gltf(&bytes, dir)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}:{}", file_name, e)))
+    }
+
+    // A glTF 2.0 scene, .gltf or .glb, with external buffers looked up in dir. Reads the node
+    // hierarchy of the default scene with its triangle meshes, the first camera and the
+    // metallic-roughness materials mapped onto the closest material here.
+    pub fn parse_gltf(bytes: &[u8], dir: &Path) -> io::Result<ImportedScene> {
+        let (text, bin) = if bytes.starts_with(b"glTF") {
+            split_glb(bytes)?
+        } else {
+            let text = std::str::from_utf8(bytes)
+                .map_err(|_| invalid_data("not a glTF file".to_string()))?;
+            (text, None)
+        };
+        let json = Json::parse(text)?;
+
+        let asset = json
+            .get("asset")
+            .ok_or_else(|| json.error("not a glTF file, \"asset\" is missing"))?;
+        let version = asset
+            .get("version")
+            .ok_or_else(|| asset.error("the asset has no version"))?;
+        if !version.as_str()?.starts_with("2.") {
+            return Err(version.error(format!("glTF {}, expected 2.x", version.as_str()?)));
+        }
+
+        let mut document = Document {
+            json: &json,
+            buffers: Vec::new(),
+            surfaces: Vec::new(),
+            warnings: Vec::new(),
+        };
+        document.load_buffers(bin, dir)?;
+        document.load_materials()?;
+        if let Some(required) = json.get("extensionsRequired") {
+            for extension in required.as_array()? {
+                document.warnings.push(format!(
+                    "extension {} is required but not supported",
+                    extension.as_str()?
+                ));
+            }
+        }
+
+        let mut objects: Vec<Arc<dyn Hittable>> = Vec::new();
+        let mut cameras = Vec::new();
+        let scene = match json.get("scene") {
+            Some(index) => Some(item(&json, "scenes", index)?),
+            None => json.get("scenes").and_then(|s| s.as_array().ok()?.first()),
+        };
+        match scene {
+            Some(scene) => {
+                for node in scene.get("nodes").map_or(Ok(&[][..]), Json::as_array)? {
+                    document.add_node(node, &IDENTITY_MATRIX, 0, &mut objects, &mut cameras)?;
+                }
+            }
+            None => document.warnings.push("the file has no scene".to_string()),
+        }
+
+        let mut camera = CameraSettings::default();
+        let bbox = objects.iter().fold(EMPTY_AABB, |bbox, o| {
+            AABB::new_from_bbox(bbox, o.bounding_box())
+        });
+        match cameras.first() {
+            Some(first) => camera = *first,
+            None => {
+                document
+                    .warnings
+                    .push("no camera, looking at the scene along -z".to_string());
+                frame(&mut camera, &bbox);
+            }
+        }
+        if cameras.len() > 1 {
+            document
+                .warnings
+                .push(format!("{} cameras, using the first", cameras.len()));
+        }
+
+        let world = if objects.is_empty() {
+            Hittable_List::new()
+        } else {
+            let len = objects.len();
+            let bvh = BVH_Node::new_from_objects(&mut objects, 0, len);
+            Hittable_List::new_from_hittable(Arc::new(bvh))
+        };
+        Ok(ImportedScene {
+            camera,
+            render: RenderSettings::default(),
+            world,
+            warnings: document.warnings,
+        })
+    }
+}
+
+// Point the camera down -z at the middle of bbox, far enough back to see all of it
+fn frame(camera: &mut CameraSettings, bbox: &AABB) {
+    if bbox.x.min > bbox.x.max {
+        return;
+    }
+    let center = Point3::new(
+        0.5 * (bbox.x.min + bbox.x.max),
+        0.5 * (bbox.y.min + bbox.y.max),
+        0.5 * (bbox.z.min + bbox.z.max),
+    );
+    let radius = 0.5
+        * Vec3::new(bbox.x.size(), bbox.y.size(), bbox.z.size())
+            .length()
+            .max(1e-3);
+    let distance = radius / (0.5 * camera.vfov.to_radians()).sin();
+    camera.lookat = center;
+    camera.lookfrom = center + Vec3::new(0.0, 0.0, distance);
+    camera.focus_dist = distance;
+}
+
+impl Document<'_> {
+    fn load_buffers(&mut self, bin: Option<&[u8]>, dir: &Path) -> io::Result<()> {
+        let Some(buffers) = self.json.get("buffers") else {
+            return Ok(());
+        };
+        for (i, buffer) in buffers.as_array()?.iter().enumerate() {
+            let length = number_or(buffer, "byteLength", 0.0)? as usize;
+            let data = match buffer.get("uri") {
+                Some(uri) => {
+                    let uri_text = uri.as_str()?;
+                    if let Some(data) = uri_text.strip_prefix("data:") {
+                        let (_, encoded) = data
+                            .split_once(";base64,")
+                            .ok_or_else(|| uri.error("only base64 data URIs are supported"))?;
+                        decode_base64(encoded).ok_or_else(|| uri.error("invalid base64"))?
+                    } else {
+                        let path = dir.join(decode_uri(uri_text));
+                        fs::read(&path).map_err(|e| {
+                            uri.error(format!("buffer {}: {}: {}", i, path.display(), e))
+                        })?
+                    }
+                }
+                // the binary chunk of a .glb is the first buffer, the one without a uri
+                None if i == 0 && bin.is_some() => bin.unwrap_or_default().to_vec(),
+                None => return Err(buffer.error("buffer without a uri")),
+            };
+            if data.len() < length {
+                return Err(buffer.error(format!(
+                    "buffer {} has {} bytes, expected {}",
+                    i,
+                    data.len(),
+                    length
+                )));
+            }
+            self.buffers.push(data);
+        }
+        Ok(())
+    }
+
+    // metallic-roughness approximated by the materials here: emissive ones become lights,
+    // transmissive ones glass, metallic ones metal fuzzed by the roughness, the rest diffuse
+    fn load_materials(&mut self) -> io::Result<()> {
+        let Some(materials) = self.json.get("materials") else {
+            return Ok(());
+        };
+        for (i, material) in materials.as_array()?.iter().enumerate() {
+            let no_object = Json {
+                value: JsonValue::Object(Vec::new()),
+                line: material.line,
+                column: material.column,
+            };
+            let pbr = material.get("pbrMetallicRoughness").unwrap_or(&no_object);
+            let extensions = material.get("extensions").unwrap_or(&no_object);
+            let extension = |name: &str| extensions.get(name).unwrap_or(&no_object);
+
+            let base = match pbr.get("baseColorFactor") {
+                Some(factor) => {
+                    let [r, g, b, _] = numbers::<4>(factor)?;
+                    Color::new(r, g, b)
+                }
+                None => Color::new(1.0, 1.0, 1.0),
+            };
+            let metallic = number_or(pbr, "metallicFactor", 1.0)?;
+            let roughness = number_or(pbr, "roughnessFactor", 1.0)?;
+            let emissive = match material.get("emissiveFactor") {
+                Some(factor) => {
+                    let [r, g, b] = numbers::<3>(factor)?;
+                    let strength = number_or(
+                        extension("KHR_materials_emissive_strength"),
+                        "emissiveStrength",
+                        1.0,
+                    )?;
+                    strength * Color::new(r, g, b)
+                }
+                None => Color::default(),
+            };
+            let transmission = number_or(
+                extension("KHR_materials_transmission"),
+                "transmissionFactor",
+                0.0,
+            )?;
+            let ior = number_or(extension("KHR_materials_ior"), "ior", 1.5)?;
+
+            for texture in ["baseColorTexture", "metallicRoughnessTexture"] {
+                if pbr.get(texture).is_some() {
+                    self.warnings.push(format!(
+                        "material {}: {} is not supported, using the constant factors",
+                        i, texture
+                    ));
+                }
+            }
+
+            let surface = if emissive.length_squared() > 0.0 {
+                Surface::Other(Arc::new(DiffuseLight::new(emissive)))
+            } else if transmission >= 0.5 {
+                Surface::Other(Arc::new(Dielectric {
+                    refraction_index: ior,
+                }))
+            } else if metallic >= 0.5 {
+                Surface::Other(Arc::new(Metal {
+                    albedo: base,
+                    fuzz: roughness.clamp(0.0, 1.0),
+                }))
+            } else {
+                Surface::Diffuse(base)
+            };
+            self.surfaces.push(surface);
+        }
+        Ok(())
+    }
+
+    fn add_node(
+        &mut self,
+        index: &Json,
+        parent: &Matrix4,
+        depth: usize,
+        objects: &mut Vec<Arc<dyn Hittable>>,
+        cameras: &mut Vec<CameraSettings>,
+    ) -> io::Result<()> {
+        let node = item(self.json, "nodes", index)?;
+        // a hierarchy can't be deeper than it has nodes, unless it has a cycle
+        let node_count = self
+            .json
+            .get("nodes")
+            .map_or(0, |n| n.as_array().map_or(0, <[_]>::len));
+        if depth > node_count {
+            return Err(index.error("the node hierarchy has a cycle"));
+        }
+
+        let local = match node.get("matrix") {
+            Some(matrix) => Matrix4::from_column_major(&numbers::<16>(matrix)?),
+            None => {
+                let t = node.get("translation").map_or(Ok([0.0; 3]), numbers::<3>)?;
+                let r = node
+                    .get("rotation")
+                    .map_or(Ok([0.0, 0.0, 0.0, 1.0]), numbers::<4>)?;
+                let s = node.get("scale").map_or(Ok([1.0; 3]), numbers::<3>)?;
+                Matrix4::translation(Vec3::new(t[0], t[1], t[2]))
+                    * Matrix4::from_quaternion(r)
+                    * Matrix4::scaling(Vec3::new(s[0], s[1], s[2]))
+            }
+        };
+        let world = *parent * local;
+
+        if let Some(mesh) = node.get("mesh") {
+            self.add_mesh(item(self.json, "meshes", mesh)?, &world, objects)?;
+        }
+        if let Some(camera) = node.get("camera")
+            && let Some(settings) = self.camera(item(self.json, "cameras", camera)?, &world)?
+        {
+            cameras.push(settings);
+        }
+        if let Some(children) = node.get("children") {
+            for child in children.as_array()? {
+                self.add_node(child, &world, depth + 1, objects, cameras)?;
+            }
+        }
+        Ok(())
+    }
+
+    // glTF cameras look down their -z with y up
+    fn camera(&mut self, camera: &Json, world: &Matrix4) -> io::Result<Option<CameraSettings>> {
+        let Some(perspective) = camera.get("perspective") else {
+            self.warnings
+                .push("only perspective cameras are supported, skipped one".to_string());
+            return Ok(None);
+        };
+        let yfov = perspective
+            .get("yfov")
+            .ok_or_else(|| perspective.error("perspective camera without yfov"))?
+            .as_f64()?;
+
+        let mut settings = CameraSettings::default();
+        if let Some(aspect_ratio) = perspective.get("aspectRatio") {
+            settings.aspect_ratio = aspect_ratio.as_f64()?;
+        }
+        settings.vfov = yfov.to_degrees();
+        settings.lookfrom = world.transform_point(Point3::default());
+        settings.lookat = settings.lookfrom
+            + world
+                .transform_vector(Vec3::new(0.0, 0.0, -1.0))
+                .unit_vector();
+        settings.vup = world.transform_vector(Vec3::new(0.0, 1.0, 0.0));
+        Ok(Some(settings))
+    }
+
+    fn add_mesh(
+        &mut self,
+        mesh: &Json,
+        world: &Matrix4,
+        objects: &mut Vec<Arc<dyn Hittable>>,
+    ) -> io::Result<()> {
+        let primitives = mesh
+            .get("primitives")
+            .ok_or_else(|| mesh.error("mesh without primitives"))?;
+        for primitive in primitives.as_array()? {
+            let mode = number_or(primitive, "mode", 4.0)?;
+            if !(4.0..=6.0).contains(&mode) {
+                self.warnings.push(format!(
+                    "skipped a primitive of mode {}, only triangles are supported",
+                    mode
+                ));
+                continue;
+            }
+            let attributes = primitive
+                .get("attributes")
+                .ok_or_else(|| primitive.error("primitive without attributes"))?;
+            let position = attributes
+                .get("POSITION")
+                .ok_or_else(|| attributes.error("primitive without POSITION"))?;
+
+            let mut triangles = TriangleMesh::default();
+            let vec3s = |values: Vec<f64>, width: usize| {
+                values
+                    .chunks_exact(width)
+                    .map(|v| Vec3::new(v[0], v[1], v[2]))
+                    .collect::<Vec<_>>()
+            };
+            let (values, width) = self.accessor(position, &[3])?;
+            triangles.positions = vec3s(values, width);
+            if let Some(normal) = attributes.get("NORMAL") {
+                let (values, width) = self.accessor(normal, &[3])?;
+                triangles.normals = vec3s(values, width);
+            }
+
+            let surface = match primitive.get("material") {
+                Some(index) => {
+                    item(self.json, "materials", index)?;
+                    self.surfaces[index.as_f64()? as usize].clone()
+                }
+                None => Surface::Diffuse(DEFAULT_SURFACE),
+            };
+            if let Some(color) = attributes.get("COLOR_0")
+                && let Surface::Diffuse(base) = surface
+            {
+                // alpha is dropped, glTF vertex colors are linear already
+                let (values, width) = self.accessor(color, &[3, 4])?;
+                triangles.colors = vec3s(values, width).into_iter().map(|c| base * c).collect();
+            }
+
+            let vertices = match primitive.get("indices") {
+                Some(indices) => self
+                    .accessor(indices, &[1])?
+                    .0
+                    .into_iter()
+                    .map(|i| i as usize)
+                    .collect(),
+                None => (0..triangles.positions.len()).collect::<Vec<_>>(),
+            };
+            match mode as u32 {
+                4 => {
+                    for tri in vertices.chunks_exact(3) {
+                        triangles.triangles.push([tri[0], tri[1], tri[2]]);
+                    }
+                }
+                // strips alternate their winding, fans turn around the first vertex
+                5 => {
+                    for (i, tri) in vertices.windows(3).enumerate() {
+                        let [a, b, c] = [tri[0], tri[1], tri[2]];
+                        triangles
+                            .triangles
+                            .push(if i % 2 == 0 { [a, b, c] } else { [b, a, c] });
+                    }
+                }
+                _ => triangles.add_polygon(&vertices),
+            }
+            triangles
+                .validate()
+                .map_err(|e| primitive.error(e.to_string()))?;
+            triangles.transform_by(world);
+
+            let built = match surface {
+                Surface::Diffuse(_) if !triangles.colors.is_empty() => {
+                    triangles.build_vertex_colored(|t| Arc::new(Lambertian::from_texture(t)))?
+                }
+                Surface::Diffuse(base) => triangles.build(Arc::new(Lambertian::new(base))),
+                Surface::Other(material) => triangles.build(material),
+            };
+            objects.extend(built.objects);
+        }
+        Ok(())
+    }
+
+    // The values of an accessor flattened, and the number of components per element, one of
+    // widths. Normalized integers map to [0, 1] or [-1, 1].
+    fn accessor(&self, index: &Json, widths: &[usize]) -> io::Result<(Vec<f64>, usize)> {
+        let accessor = item(self.json, "accessors", index)?;
+        if accessor.get("sparse").is_some() {
+            return Err(accessor.error("sparse accessors are not supported"));
+        }
+        let count = number_or(accessor, "count", 0.0)? as usize;
+        let kind = accessor
+            .get("type")
+            .ok_or_else(|| accessor.error("accessor without a type"))?;
+        let width = match kind.as_str()? {
+            "SCALAR" => 1,
+            "VEC2" => 2,
+            "VEC3" => 3,
+            "VEC4" => 4,
+            _ => 0,
+        };
+        if !widths.contains(&width) {
+            return Err(kind.error(format!("unexpected accessor type {}", kind.as_str()?)));
+        }
+        let component_type = accessor
+            .get("componentType")
+            .ok_or_else(|| accessor.error("accessor without a componentType"))?;
+        let size = match component_type.as_f64()? as u32 {
+            5120 | 5121 => 1,
+            5122 | 5123 => 2,
+            5125 | 5126 => 4,
+            other => return Err(component_type.error(format!("unknown componentType {}", other))),
+        };
+        let normalized = accessor
+            .get("normalized")
+            .map_or(Ok(false), Json::as_bool)?;
+
+        // without a buffer view all values are zero, only a sanity limit bounds the count
+        let Some(view_index) = accessor.get("bufferView") else {
+            let len = count
+                .checked_mul(width)
+                .filter(|len| *len <= MAX_ZERO_VALUES)
+                .ok_or_else(|| accessor.error(format!("{} zero elements are too many", count)))?;
+            return Ok((vec![0.0; len], width));
+        };
+        let view = item(self.json, "bufferViews", view_index)?;
+        let buffer_index = view
+            .get("buffer")
+            .ok_or_else(|| view.error("buffer view without a buffer"))?;
+        item(self.json, "buffers", buffer_index)?;
+        let buffer = &self.buffers[buffer_index.as_f64()? as usize];
+        let view_start = number_or(view, "byteOffset", 0.0)? as usize;
+        let view_length = number_or(view, "byteLength", 0.0)? as usize;
+        let stride = number_or(view, "byteStride", 0.0)? as usize;
+        let element = width * size;
+        let stride = if stride == 0 { element } else { stride };
+        if stride < element {
+            return Err(view.error(format!(
+                "byte stride {} is smaller than an element of {} bytes",
+                stride, element
+            )));
+        }
+        let offset = number_or(accessor, "byteOffset", 0.0)? as usize;
+
+        // the count comes from the file, it has to fit in the view before anything is allocated
+        let start = view_start.checked_add(offset);
+        let end = match count {
+            0 => start,
+            _ => (count - 1)
+                .checked_mul(stride)
+                .and_then(|n| n.checked_add(start?))
+                .and_then(|n| n.checked_add(element)),
+        };
+        let view_end = view_start
+            .checked_add(view_length)
+            .filter(|view_end| *view_end <= buffer.len());
+        let start = match (start, end, view_end) {
+            (Some(start), Some(end), Some(view_end)) if end <= view_end => start,
+            _ => return Err(accessor.error("accessor reaches past the end of its buffer")),
+        };
+
+        let mut values = Vec::with_capacity(count * width);
+        for i in 0..count {
+            for c in 0..width {
+                let at = start + i * stride + c * size;
+                let b = &buffer[at..at + size];
+                let value = match component_type.as_f64()? as u32 {
+                    5120 => (b[0] as i8 as f64, 127.0),
+                    5121 => (b[0] as f64, 255.0),
+                    5122 => (i16::from_le_bytes([b[0], b[1]]) as f64, 32767.0),
+                    5123 => (u16::from_le_bytes([b[0], b[1]]) as f64, 65535.0),
+                    5125 => (u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64, 1.0),
+                    _ => (f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64, 1.0),
+                };
+                values.push(if normalized {
+                    (value.0 / value.1).max(-1.0)
+                } else {
+                    value.0
+                });
+            }
+        }
+        Ok((values, width))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interval::Interval, ray::Ray};
+
+    // One triangle in the z = 0 plane, indexed, as a base64 buffer: three float positions
+    // followed by three u16 indices
+    fn triangle_buffer() -> String {
+        let mut bytes = Vec::new();
+        for v in [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]] {
+            for c in v {
+                bytes.extend(c.to_le_bytes());
+            }
+        }
+        for i in [0u16, 1, 2] {
+            bytes.extend(i.to_le_bytes());
+        }
+        let table = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        let mut text = String::new();
+        for chunk in bytes.chunks(3) {
+            let n = chunk.iter().fold(0u32, |n, b| (n << 8) | *b as u32) << (8 * (3 - chunk.len()));
+            for i in 0..=chunk.len() {
+                text.push(table[(n >> (18 - 6 * i)) as usize & 63] as char);
+            }
+        }
+        text
+    }
+
+    fn document(buffer_uri: &str) -> String {
+        format!(
+            r#"{{
+  "asset": {{"version": "2.0"}},
+  "scene": 0,
+  "scenes": [{{"nodes": [0, 1]}}],
+  "nodes": [
+    {{"children": [2], "translation": [0, 0, -5]}},
+    {{"camera": 0, "translation": [0, 0, 2], "rotation": [0, 0.7071067811865476, 0, 0.7071067811865476]}},
+    {{"mesh": 0, "scale": [2, 2, 2]}}
+  ],
+  "cameras": [{{"type": "perspective", "perspective": {{"yfov": 0.5, "aspectRatio": 2.0, "znear": 0.1}}}}],
+  "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}, "indices": 1, "material": 0}}]}}],
+  "materials": [{{"pbrMetallicRoughness": {{"baseColorFactor": [1, 0.5, 0.25, 1], "metallicFactor": 0}}}}],
+  "accessors": [
+    {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3"}},
+    {{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}}
+  ],
+  "bufferViews": [
+    {{"buffer": 0, "byteOffset": 0, "byteLength": 36}},
+    {{"buffer": 0, "byteOffset": 36, "byteLength": 6}}
+  ],
+  "buffers": [{{"byteLength": 42{}}}]
+}}"#,
+            buffer_uri
+        )
+    }
+
+    fn hit_material(scene: &ImportedScene, origin: Point3) -> Option<Color> {
+        let ray = Ray {
+            origin,
+            dir: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+            medium_sample: 0.0,
+        };
+        let rec = scene.world.hit(
+            &ray,
+            Interval {
+                min: 0.001,
+                max: f64::INFINITY,
+            },
+        )?;
+        Some(rec.material.albedo(&rec))
+    }
+
+    #[test]
+    fn reads_hierarchy_camera_and_materials() {
+        let text = document(&format!(
+            r#", "uri": "data:application/octet-stream;base64,{}""#,
+            triangle_buffer()
+        ));
+        let scene = ImportedScene::parse_gltf(text.as_bytes(), Path::new("")).unwrap();
+        assert!(scene.warnings.is_empty(), "{:?}", scene.warnings);
+
+        // the mesh is scaled by 2 and moved back by its parent
+        let albedo = hit_material(&scene, Point3::new(1.5, 0.25, 0.0)).unwrap();
+        assert!((albedo - Color::new(1.0, 0.5, 0.25)).length() < 1e-12);
+        assert!(hit_material(&scene, Point3::new(1.5, 0.75, 0.0)).is_none());
+
+        // turned 90 degrees about y the camera looks down -x
+        let camera = scene.camera;
+        assert!((camera.lookfrom - Point3::new(0.0, 0.0, 2.0)).length() < 1e-12);
+        assert!((camera.lookat - Point3::new(-1.0, 0.0, 2.0)).length() < 1e-12);
+        assert!((camera.vfov - 0.5f64.to_degrees()).abs() < 1e-12);
+        assert_eq!(camera.aspect_ratio, 2.0);
+    }
+
+    #[test]
+    fn reads_glb_binary_chunks() {
+        let json = document("");
+        let mut bin = decode_base64(&triangle_buffer()).unwrap();
+        bin.resize(44, 0);
+        let mut json = json.into_bytes();
+        json.resize(json.len().next_multiple_of(4), b' ');
+
+        let mut glb = b"glTF".to_vec();
+        glb.extend(2u32.to_le_bytes());
+        glb.extend(((12 + 8 + json.len() + 8 + bin.len()) as u32).to_le_bytes());
+        glb.extend((json.len() as u32).to_le_bytes());
+        glb.extend(b"JSON");
+        glb.extend(&json);
+        glb.extend((bin.len() as u32).to_le_bytes());
+        glb.extend(b"BIN\0");
+        glb.extend(&bin);
+
+        let scene = ImportedScene::parse_gltf(&glb, Path::new("")).unwrap();
+        assert!(hit_material(&scene, Point3::new(0.5, 0.5, 0.0)).is_some());
+    }
+
+    #[test]
+    fn reports_bad_references() {
+        let text = document(r#", "uri": "data:application/octet-stream;base64,AAAA""#);
+        let err = ImportedScene::parse_gltf(text.as_bytes(), Path::new(""))
+            .err()
+            .unwrap()
+            .to_string();
+        assert_eq!(err, "21:15: buffer 0 has 3 bytes, expected 42");
+
+        let text = document(&format!(
+            r#", "uri": "data:application/octet-stream;base64,{}""#,
+            triangle_buffer()
+        ))
+        .replace("\"mesh\": 0", "\"mesh\": 3");
+        let err = ImportedScene::parse_gltf(text.as_bytes(), Path::new(""))
+            .err()
+            .unwrap()
+            .to_string();
+        assert_eq!(err, "8:14: 3 is not an index into the 1 meshes");
+
+        // counts that overflow or don't fit the buffer fail before anything is allocated
+        let huge = |accessor: &str| {
+            let text = document(&format!(
+                r#", "uri": "data:application/octet-stream;base64,{}""#,
+                triangle_buffer()
+            ))
+            .replace(
+                r#""bufferView": 0, "componentType": 5126, "count": 3"#,
+                accessor,
+            );
+            ImportedScene::parse_gltf(text.as_bytes(), Path::new(""))
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        for count in ["1e30", "4611686018427387904", "4"] {
+            let accessor = format!(
+                r#""bufferView": 0, "componentType": 5126, "count": {}"#,
+                count
+            );
+            assert!(huge(&accessor).ends_with("accessor reaches past the end of its buffer"));
+        }
+        let err = huge(r#""componentType": 5126, "count": 1e30"#);
+        assert!(err.ends_with("zero elements are too many"), "{}", err);
+    }
+}
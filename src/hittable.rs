@@ -1,10 +1,14 @@
-use std::rc::Rc;
+use std::{any::type_name, f64::consts::PI, io, sync::Arc};
 
 use crate::{
-    aabb::AABB,
+    aabb::{AABB, EMPTY_AABB},
+    animation::Track,
+    checkpoint::Fingerprint,
     interval::Interval,
     material::Material,
     ray::Ray,
+    scene::{ObjectDescription, SceneExport, Transform, cannot_save},
+    utils::degrees_to_radian,
     vec3::{Point3, Vec3, dot},
 };
 
@@ -13,12 +17,16 @@ pub struct Hit_Record {
     pub p: Point3,
     pub normal: Vec3,
     pub t: f64,
+    pub u: f64, // surface coordinates of the hit point, for textures
+    pub v: f64,
     pub front_face: bool,
-    pub material: Rc<dyn Material>,
+    pub material: Arc<dyn Material>,
+    pub velocity: Vec3, // motion of the surface point per unit of time
+    pub object: usize,  // address of the primitive hit, identifies it during a render
 }
 
 impl Hit_Record {
-    fn set_face_normal(&mut self, ray: &Ray, outward_normal: Vec3) {
+    pub(crate) fn set_face_normal(&mut self, ray: &Ray, outward_normal: Vec3) {
         self.front_face = dot(ray.dir, outward_normal) < 0.0;
         self.normal = if self.front_face {
             outward_normal
@@ -28,20 +36,35 @@ impl Hit_Record {
     }
 }
 
-pub trait Hittable {
+pub trait Hittable: Send + Sync {
     fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<Hit_Record>;
     fn bounding_box(&self) -> AABB;
+
+    // Add the geometry and materials of this object to the fingerprint of a render. By default
+    // the object is unknown to it and the render can't be checkpointed.
+    fn fingerprint(&self, f: &mut Fingerprint) {
+        f.unknown(type_name::<Self>());
+    }
+
+    // Add the primitives of this object to a scene being saved
+    fn export(&self, _scene: &mut SceneExport) -> io::Result<()> {
+        Err(cannot_save(
+            type_name::<Self>().rsplit("::").next().unwrap_or_default(),
+        ))
+    }
 }
 
 pub struct Sphere {
-    pub center: Ray,
+    pub center: Ray, // center at time 0 as origin, moving to center + dir at time 1
+    // times the sphere is at the start and end of its motion, it rests outside of them
+    pub motion_time: Interval,
     pub radius: f64,
-    pub material: Rc<dyn Material>,
+    pub material: Arc<dyn Material>,
     pub bbox: AABB,
 }
 
 impl Sphere {
-    pub fn new_static(static_center: Point3, radius: f64, material: Rc<dyn Material>) -> Self {
+    pub fn new_static(static_center: Point3, radius: f64, material: Arc<dyn Material>) -> Self {
         let ray = Ray {
             origin: static_center,
             dir: Vec3 {
@@ -50,6 +73,7 @@ impl Sphere {
                 z: 0.0,
             },
             time: 0.0,
+            medium_sample: 0.0,
         };
 
         // bounding box
@@ -58,22 +82,59 @@ impl Sphere {
 
         Sphere {
             center: ray,
+            motion_time: Interval { min: 0.0, max: 1.0 },
             radius,
             material,
             bbox,
         }
     }
 
+    // Moves from center1 at time 0 to center2 at time 1
     pub fn new_moving(
         center1: Point3,
         center2: Point3,
         radius: f64,
-        material: Rc<dyn Material>,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        Sphere::new_moving_between(
+            center1,
+            center2,
+            Interval { min: 0.0, max: 1.0 },
+            radius,
+            material,
+        )
+    }
+
+    // Moves from center1 at motion_time.min to center1 + offset at motion_time.max
+    pub fn new_moving_by(
+        center1: Point3,
+        offset: Vec3,
+        motion_time: Interval,
+        radius: f64,
+        material: Arc<dyn Material>,
     ) -> Self {
+        let mut sphere =
+            Sphere::new_moving_between(center1, center1 + offset, motion_time, radius, material);
+        // the offset as given, center1 + offset - center1 may round differently
+        sphere.center.dir = offset;
+        sphere
+    }
+
+    // Moves from center1 at motion_time.min to center2 at motion_time.max, so the motion can
+    // line up with any camera shutter interval
+    pub fn new_moving_between(
+        center1: Point3,
+        center2: Point3,
+        motion_time: Interval,
+        radius: f64,
+        material: Arc<dyn Material>,
+    ) -> Self {
+        assert!(motion_time.min < motion_time.max);
         let ray = Ray {
             origin: center1,
             dir: center2 - center1,
             time: 0.0,
+            medium_sample: 0.0,
         };
 
         // bounding box
@@ -84,6 +145,7 @@ impl Sphere {
 
         Sphere {
             center: ray,
+            motion_time,
             radius,
             material,
             bbox,
@@ -91,9 +153,21 @@ impl Sphere {
     }
 }
 
+impl Sphere {
+    // (u, v) of a point p on the unit sphere: u is the angle around the y axis from x = -1,
+    // v the angle from y = -1, both scaled to [0, 1]
+    fn get_sphere_uv(p: Point3) -> (f64, f64) {
+        let theta = (-p.y).acos();
+        let phi = (-p.z).atan2(p.x) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+}
+
 impl Hittable for Sphere {
     fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<Hit_Record> {
-        let current_center = self.center.at(ray.time);
+        let motion =
+            (self.motion_time.clamp(ray.time) - self.motion_time.min) / self.motion_time.size();
+        let current_center = self.center.at(motion);
         let oc = current_center - ray.origin;
         let a = dot(ray.dir, ray.dir);
         let h = dot(ray.dir, oc);
@@ -117,12 +191,22 @@ impl Hittable for Sphere {
 
         let point = ray.at(root);
         let normal = (point - current_center) / self.radius;
+        let (u, v) = Sphere::get_sphere_uv(normal);
+        let velocity = if self.motion_time.contains(ray.time) {
+            self.center.dir / self.motion_time.size()
+        } else {
+            Vec3::default()
+        };
         let mut rec = Hit_Record {
             p: point,
             t: root,
+            u,
+            v,
             normal,
             front_face: true,
-            material: Rc::clone(&self.material),
+            material: Arc::clone(&self.material),
+            velocity,
+            object: std::ptr::from_ref(self).addr(),
         };
         rec.set_face_normal(ray, normal);
 
@@ -132,26 +216,70 @@ impl Hittable for Sphere {
     fn bounding_box(&self) -> AABB {
         self.bbox
     }
+
+    fn fingerprint(&self, f: &mut Fingerprint) {
+        f.name("sphere");
+        f.vec3(self.center.origin);
+        f.vec3(self.center.dir);
+        f.number(self.motion_time.min);
+        f.number(self.motion_time.max);
+        f.number(self.radius);
+        self.material.fingerprint(f);
+    }
+
+    fn export(&self, scene: &mut SceneExport) -> io::Result<()> {
+        let material = scene.material(&self.material)?;
+        let Ray {
+            origin: center,
+            dir: offset,
+            ..
+        } = self.center;
+        scene.add(if offset.length_squared() == 0.0 {
+            ObjectDescription::Sphere {
+                center,
+                radius: self.radius,
+                material,
+            }
+        } else {
+            ObjectDescription::MovingSphere {
+                center,
+                offset,
+                motion_time: self.motion_time,
+                radius: self.radius,
+                material,
+            }
+        });
+        Ok(())
+    }
 }
 
-#[derive(Default)]
 pub struct Hittable_List {
-    pub objects: Vec<Rc<dyn Hittable>>,
+    pub objects: Vec<Arc<dyn Hittable>>,
     bbox: AABB,
 }
 
+impl Default for Hittable_List {
+    fn default() -> Self {
+        // empty, so the first object's box doesn't get stretched to the origin
+        Hittable_List {
+            objects: Vec::new(),
+            bbox: EMPTY_AABB,
+        }
+    }
+}
+
 impl Hittable_List {
     pub fn new() -> Self {
         Self::default()
     }
 
-    pub fn new_from_hittable(object: Rc<dyn Hittable>) -> Self {
+    pub fn new_from_hittable(object: Arc<dyn Hittable>) -> Self {
         let mut hl = Hittable_List::new();
         hl.add(object);
         hl
     }
 
-    pub fn add(&mut self, object: Rc<dyn Hittable>) {
+    pub fn add(&mut self, object: Arc<dyn Hittable>) {
         self.bbox = AABB::new_from_bbox(self.bbox, object.bounding_box());
         self.objects.push(object);
     }
@@ -186,4 +314,239 @@ impl Hittable for Hittable_List {
     fn bounding_box(&self) -> AABB {
         self.bbox
     }
+
+    fn fingerprint(&self, f: &mut Fingerprint) {
+        f.name("list");
+        f.add(self.objects.len() as u64);
+        for object in &self.objects {
+            object.fingerprint(f);
+        }
+    }
+
+    fn export(&self, scene: &mut SceneExport) -> io::Result<()> {
+        for object in &self.objects {
+            object.export(scene)?;
+        }
+        Ok(())
+    }
+}
+
+// Instance of an object moved by offset
+pub struct Translate {
+    object: Arc<dyn Hittable>,
+    offset: Vec3,
+    bbox: AABB,
+}
+
+impl Translate {
+    pub fn new(object: Arc<dyn Hittable>, offset: Vec3) -> Self {
+        let bbox = object.bounding_box().translate(offset);
+        Translate {
+            object,
+            offset,
+            bbox,
+        }
+    }
+}
+
+impl Hittable for Translate {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<Hit_Record> {
+        // move the ray into object space instead of the object into world space
+        let offset_ray = Ray {
+            origin: ray.origin - self.offset,
+            dir: ray.dir,
+            ..*ray
+        };
+        let mut rec = self.object.hit(&offset_ray, ray_t)?;
+        rec.p += self.offset;
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+
+    fn fingerprint(&self, f: &mut Fingerprint) {
+        f.name("translate");
+        f.vec3(self.offset);
+        self.object.fingerprint(f);
+    }
+
+    fn export(&self, scene: &mut SceneExport) -> io::Result<()> {
+        let objects = scene.objects(|scene| self.object.export(scene))?;
+        scene.add(ObjectDescription::Group {
+            transform: Transform {
+                translate: self.offset,
+                ..Transform::default()
+            },
+            keyframes: Track::default(),
+            objects,
+        });
+        Ok(())
+    }
+}
+
+// Instance of an object rotated about the y axis
+pub struct Rotate_Y {
+    object: Arc<dyn Hittable>,
+    angle: f64, // degrees
+    sin_theta: f64,
+    cos_theta: f64,
+    bbox: AABB,
+}
+
+impl Rotate_Y {
+    pub fn new(object: Arc<dyn Hittable>, angle: f64) -> Self {
+        let (sin_theta, cos_theta) = degrees_to_radian(angle).sin_cos();
+        let bbox = object.bounding_box();
+
+        // box around the rotated corners of the object's box
+        let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for x in [bbox.x.min, bbox.x.max] {
+            for y in [bbox.y.min, bbox.y.max] {
+                for z in [bbox.z.min, bbox.z.max] {
+                    let rotated = Vec3::new(
+                        cos_theta * x + sin_theta * z,
+                        y,
+                        -sin_theta * x + cos_theta * z,
+                    );
+                    min = Point3::new(
+                        min.x.min(rotated.x),
+                        min.y.min(rotated.y),
+                        min.z.min(rotated.z),
+                    );
+                    max = Point3::new(
+                        max.x.max(rotated.x),
+                        max.y.max(rotated.y),
+                        max.z.max(rotated.z),
+                    );
+                }
+            }
+        }
+
+        Rotate_Y {
+            object,
+            angle,
+            sin_theta,
+            cos_theta,
+            bbox: AABB::new_from_extrema(min, max),
+        }
+    }
+
+    // Object space to world space
+    fn rotate(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x + self.sin_theta * v.z,
+            v.y,
+            -self.sin_theta * v.x + self.cos_theta * v.z,
+        )
+    }
+
+    // World space to object space
+    fn unrotate(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v.x - self.sin_theta * v.z,
+            v.y,
+            self.sin_theta * v.x + self.cos_theta * v.z,
+        )
+    }
+}
+
+impl Hittable for Rotate_Y {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<Hit_Record> {
+        let rotated_ray = Ray {
+            origin: self.unrotate(ray.origin),
+            dir: self.unrotate(ray.dir),
+            ..*ray
+        };
+        let mut rec = self.object.hit(&rotated_ray, ray_t)?;
+        rec.p = self.rotate(rec.p);
+        rec.normal = self.rotate(rec.normal);
+        rec.velocity = self.rotate(rec.velocity);
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+
+    fn fingerprint(&self, f: &mut Fingerprint) {
+        f.name("rotate_y");
+        f.number(self.sin_theta);
+        f.number(self.cos_theta);
+        self.object.fingerprint(f);
+    }
+
+    fn export(&self, scene: &mut SceneExport) -> io::Result<()> {
+        let objects = scene.objects(|scene| self.object.export(scene))?;
+        scene.add(ObjectDescription::Group {
+            transform: Transform {
+                rotate: Vec3::new(0.0, self.angle, 0.0),
+                ..Transform::default()
+            },
+            keyframes: Track::default(),
+            objects,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::vec3::Color;
+
+    fn unit_sphere() -> Arc<dyn Hittable> {
+        Arc::new(Sphere::new_static(
+            Point3::new(1.0, 0.0, 0.0),
+            0.5,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        ))
+    }
+
+    #[test]
+    fn instances_move_hits_and_bounds() {
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        let everything = Interval {
+            min: 0.001,
+            max: f64::INFINITY,
+        };
+
+        let moved = Translate::new(unit_sphere(), Vec3::new(0.0, 0.0, 2.0));
+        let ray = Ray {
+            origin: Point3::new(1.0, 5.0, 2.0),
+            dir: down,
+            time: 0.0,
+            medium_sample: 0.0,
+        };
+        let rec = moved.hit(&ray, everything).unwrap();
+        assert!((rec.p - Point3::new(1.0, 0.5, 2.0)).length() < 1e-12);
+        assert_eq!(moved.bounding_box().z.min, 1.5);
+
+        // a quarter turn takes +x to -z
+        let turned = Rotate_Y::new(unit_sphere(), 90.0);
+        let ray = Ray {
+            origin: Point3::new(0.0, 5.0, -1.0),
+            dir: down,
+            time: 0.0,
+            medium_sample: 0.0,
+        };
+        let rec = turned.hit(&ray, everything).unwrap();
+        assert!((rec.p - Point3::new(0.0, 0.5, -1.0)).length() < 1e-12);
+        assert!((rec.normal - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-12);
+        assert!((turned.bounding_box().z.min + 1.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn sphere_uv_wraps_around_y() {
+        let (u, v) = Sphere::get_sphere_uv(Point3::new(-1.0, 0.0, 0.0));
+        assert!(u.abs() < 1e-12 || (u - 1.0).abs() < 1e-12);
+        assert!((v - 0.5).abs() < 1e-12);
+        let (u, v) = Sphere::get_sphere_uv(Point3::new(0.0, 0.0, 1.0));
+        assert!((u - 0.25).abs() < 1e-12);
+        assert!((v - 0.5).abs() < 1e-12);
+        assert!((Sphere::get_sphere_uv(Point3::new(0.0, 1.0, 0.0)).1 - 1.0).abs() < 1e-12);
+    }
 }
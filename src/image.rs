@@ -0,0 +1,166 @@
+use std::{fs, io};
+
+use crate::{utils::invalid_data, vec3::Color};
+
+// An RGB raster with channels normalized to [0, 1], rows stored top to bottom
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    // Load a netpbm image: plain or binary graymap (P2, P5) or pixmap (P3, P6)
+    pub fn load_pnm(file_name: &str) -> io::Result<Image> {
+        let bytes = fs::read(file_name)?;
+        Image::parse_pnm(&bytes).map_err(|e| invalid_data(format!("{}: {}", file_name, e)))
+    }
+
+    pub fn parse_pnm(bytes: &[u8]) -> io::Result<Image> {
+        let mut pos = 0;
+        let magic = next_token(bytes, &mut pos)?;
+        let (channels, binary) = match magic.as_str() {
+            "P2" => (1, false),
+            "P3" => (3, false),
+            "P5" => (1, true),
+            "P6" => (3, true),
+            _ => {
+                return Err(invalid_data(format!(
+                    "unsupported netpbm magic {:?}",
+                    magic
+                )));
+            }
+        };
+        let width = next_number(bytes, &mut pos)?;
+        let height = next_number(bytes, &mut pos)?;
+        let maxval = next_number(bytes, &mut pos)?;
+        if maxval == 0 || maxval > 65535 {
+            return Err(invalid_data(format!("invalid maxval {}", maxval)));
+        }
+
+        let count = width
+            .checked_mul(height)
+            .and_then(|n| n.checked_mul(channels))
+            .ok_or_else(|| invalid_data(format!("image of {}x{} is too large", width, height)))?;
+        // every sample takes at least a byte, so a bigger count is truncated anyway
+        let mut samples = Vec::with_capacity(count.min(bytes.len()));
+        if binary {
+            // exactly one whitespace byte separates the header from the raster
+            pos += 1;
+            let sample_size = if maxval < 256 { 1 } else { 2 };
+            let raster = count
+                .checked_mul(sample_size)
+                .and_then(|size| bytes.get(pos..pos.checked_add(size)?))
+                .ok_or_else(|| invalid_data("truncated raster".to_string()))?;
+            for chunk in raster.chunks(sample_size) {
+                let value = if sample_size == 1 {
+                    chunk[0] as usize
+                } else {
+                    (chunk[0] as usize) << 8 | chunk[1] as usize
+                };
+                samples.push(value);
+            }
+        } else {
+            for _ in 0..count {
+                samples.push(next_number(bytes, &mut pos)?);
+            }
+        }
+
+        let scale = 1.0 / maxval as f64;
+        let pixels = samples
+            .chunks(channels)
+            .map(|c| {
+                if channels == 1 {
+                    let g = c[0] as f64 * scale;
+                    Color::new(g, g, g)
+                } else {
+                    Color::new(
+                        c[0] as f64 * scale,
+                        c[1] as f64 * scale,
+                        c[2] as f64 * scale,
+                    )
+                }
+            })
+            .collect();
+
+        Ok(Image {
+            width,
+            height,
+            pixels,
+        })
+    }
+}
+
+// Next whitespace separated header token, skipping '#' comments
+fn next_token(bytes: &[u8], pos: &mut usize) -> io::Result<String> {
+    loop {
+        while *pos < bytes.len() && bytes[*pos].is_ascii_whitespace() {
+            *pos += 1;
+        }
+        if *pos < bytes.len() && bytes[*pos] == b'#' {
+            while *pos < bytes.len() && bytes[*pos] != b'\n' {
+                *pos += 1;
+            }
+        } else {
+            break;
+        }
+    }
+
+    let start = *pos;
+    while *pos < bytes.len() && !bytes[*pos].is_ascii_whitespace() {
+        *pos += 1;
+    }
+    if start == *pos {
+        return Err(invalid_data("unexpected end of file".to_string()));
+    }
+    Ok(String::from_utf8_lossy(&bytes[start..*pos]).into_owned())
+}
+
+fn next_number(bytes: &[u8], pos: &mut usize) -> io::Result<usize> {
+    let token = next_token(bytes, pos)?;
+    token
+        .parse()
+        .map_err(|_| invalid_data(format!("expected a number, got {:?}", token)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_plain_pixmap_with_comment() {
+        let image = Image::parse_pnm(b"P3\n# comment\n2 1\n255\n255 0 0  0 0 255\n").unwrap();
+
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.pixel(0, 0).x, 1.0);
+        assert_eq!(image.pixel(1, 0).z, 1.0);
+    }
+
+    #[test]
+    fn parse_binary_graymap() {
+        let image = Image::parse_pnm(b"P5 2 2 255\n\x00\xff\xff\x00").unwrap();
+
+        assert_eq!(image.pixel(0, 0).x, 0.0);
+        assert_eq!(image.pixel(1, 0).y, 1.0);
+        assert_eq!(image.pixel(0, 1).z, 1.0);
+    }
+
+    #[test]
+    fn parse_rejects_truncated_raster() {
+        assert!(Image::parse_pnm(b"P6 2 2 255\n\x00\x00").is_err());
+        assert!(Image::parse_pnm(b"P7 2 2 255\n").is_err());
+        // sizes whose sample counts overflow, or don't fit in the file
+        let err = Image::parse_pnm(b"P6 4294967296 4294967296 255\n").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "image of 4294967296x4294967296 is too large"
+        );
+        assert!(Image::parse_pnm(b"P6 3074457345618258602 1 65535\n").is_err());
+        assert!(Image::parse_pnm(b"P3 100000 100000 255\n1 2 3\n").is_err());
+    }
+}
@@ -29,6 +29,15 @@ impl Interval {
         x
     }
 
+    // Interval grown by delta, half on each side
+    pub fn expand(&self, delta: f64) -> Interval {
+        let padding = delta / 2.0;
+        Interval {
+            min: self.min - padding,
+            max: self.max + padding,
+        }
+    }
+
     pub fn enclosing_interval(a: Interval, b: Interval) -> Self {
         let min = f64::min(a.min, b.min);
         let max = f64::max(a.max, b.max);
@@ -0,0 +1,408 @@
+use std::io;
+
+// Minimal JSON reader that remembers where every value starts, so whoever interprets the
+// document can point at the offending value
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>), // in document order, keys are unique
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Json {
+    pub value: JsonValue,
+    pub line: usize,   // 1-based
+    pub column: usize, // 1-based, in characters
+}
+
+impl Json {
+    // Error pointing at this value
+    pub fn error(&self, msg: impl AsRef<str>) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}:{}: {}", self.line, self.column, msg.as_ref()),
+        )
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self.value {
+            JsonValue::Null => "null",
+            JsonValue::Bool(_) => "a boolean",
+            JsonValue::Number(_) => "a number",
+            JsonValue::String(_) => "a string",
+            JsonValue::Array(_) => "an array",
+            JsonValue::Object(_) => "an object",
+        }
+    }
+
+    fn expected(&self, what: &str) -> io::Error {
+        self.error(format!("expected {}, found {}", what, self.kind()))
+    }
+
+    pub fn as_f64(&self) -> io::Result<f64> {
+        match self.value {
+            JsonValue::Number(n) => Ok(n),
+            _ => Err(self.expected("a number")),
+        }
+    }
+
+    pub fn as_bool(&self) -> io::Result<bool> {
+        match self.value {
+            JsonValue::Bool(b) => Ok(b),
+            _ => Err(self.expected("a boolean")),
+        }
+    }
+
+    pub fn as_str(&self) -> io::Result<&str> {
+        match &self.value {
+            JsonValue::String(s) => Ok(s),
+            _ => Err(self.expected("a string")),
+        }
+    }
+
+    pub fn as_array(&self) -> io::Result<&[Json]> {
+        match &self.value {
+            JsonValue::Array(items) => Ok(items),
+            _ => Err(self.expected("an array")),
+        }
+    }
+
+    pub fn as_object(&self) -> io::Result<&[(String, Json)]> {
+        match &self.value {
+            JsonValue::Object(entries) => Ok(entries),
+            _ => Err(self.expected("an object")),
+        }
+    }
+
+    // Value of key if this is an object that has it
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match &self.value {
+            JsonValue::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn parse(text: &str) -> io::Result<Json> {
+        let mut parser = Parser {
+            text,
+            pos: 0,
+            line: 1,
+            line_start: 0,
+        };
+        parser.skip_whitespace();
+        let json = parser.value(0)?;
+        parser.skip_whitespace();
+        if parser.pos < text.len() {
+            return Err(parser.error("unexpected content after the document"));
+        }
+        Ok(json)
+    }
+}
+
+// text as a JSON string literal
+pub fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Nesting deeper than this is rejected instead of overflowing the stack
+const MAX_DEPTH: usize = 256;
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize, // byte offset
+    line: usize,
+    line_start: usize, // byte offset of the current line
+}
+
+impl Parser<'_> {
+    fn column(&self) -> usize {
+        self.text[self.line_start..self.pos].chars().count() + 1
+    }
+
+    fn error(&self, msg: impl AsRef<str>) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}:{}: {}", self.line, self.column(), msg.as_ref()),
+        )
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            match c {
+                b'\n' => {
+                    self.pos += 1;
+                    self.line += 1;
+                    self.line_start = self.pos;
+                }
+                b' ' | b'\t' | b'\r' => self.pos += 1,
+                _ => break,
+            }
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> io::Result<()> {
+        if self.peek() != Some(c) {
+            return Err(self.error(format!("expected '{}'", c as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn value(&mut self, depth: usize) -> io::Result<Json> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("document nested too deeply"));
+        }
+        let (line, column) = (self.line, self.column());
+        let value = match self.peek() {
+            Some(b'{') => self.object(depth)?,
+            Some(b'[') => self.array(depth)?,
+            Some(b'"') => JsonValue::String(self.string()?),
+            Some(b'-' | b'0'..=b'9') => self.number()?,
+            Some(b't') => self.literal("true", JsonValue::Bool(true))?,
+            Some(b'f') => self.literal("false", JsonValue::Bool(false))?,
+            Some(b'n') => self.literal("null", JsonValue::Null)?,
+            Some(_) => return Err(self.error("expected a value")),
+            None => return Err(self.error("unexpected end of document")),
+        };
+        Ok(Json {
+            value,
+            line,
+            column,
+        })
+    }
+
+    fn literal(&mut self, word: &str, value: JsonValue) -> io::Result<JsonValue> {
+        if !self.text[self.pos..].starts_with(word) {
+            return Err(self.error("expected a value"));
+        }
+        self.pos += word.len();
+        Ok(value)
+    }
+
+    fn number(&mut self) -> io::Result<JsonValue> {
+        let start = self.pos;
+        let bytes = self.text.as_bytes();
+        let digits = |pos: &mut usize| {
+            let from = *pos;
+            while bytes.get(*pos).is_some_and(u8::is_ascii_digit) {
+                *pos += 1;
+            }
+            *pos > from
+        };
+
+        let mut pos = self.pos;
+        if bytes[pos] == b'-' {
+            pos += 1;
+        }
+        let int_start = pos;
+        let valid = digits(&mut pos)
+            // no leading zeros
+            && !(bytes[int_start] == b'0' && pos - int_start > 1)
+            && (bytes.get(pos) != Some(&b'.') || {
+                pos += 1;
+                digits(&mut pos)
+            })
+            && (!matches!(bytes.get(pos), Some(b'e' | b'E')) || {
+                pos += 1;
+                if matches!(bytes.get(pos), Some(b'+' | b'-')) {
+                    pos += 1;
+                }
+                digits(&mut pos)
+            });
+        if !valid {
+            return Err(self.error("invalid number"));
+        }
+
+        self.pos = pos;
+        let n: f64 = self.text[start..pos].parse().unwrap();
+        Ok(JsonValue::Number(n))
+    }
+
+    fn hex4(&mut self) -> io::Result<u32> {
+        let hex = self.text.get(self.pos..self.pos + 4).unwrap_or("");
+        let code = u32::from_str_radix(hex, 16)
+            .ok()
+            .filter(|_| hex.len() == 4 && hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .ok_or_else(|| self.error("invalid \\u escape"))?;
+        self.pos += 4;
+        Ok(code)
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        self.expect(b'"')?;
+        let mut s = String::new();
+        loop {
+            let Some(c) = self.text[self.pos..].chars().next() else {
+                return Err(self.error("unterminated string"));
+            };
+            match c {
+                '"' => {
+                    self.pos += 1;
+                    return Ok(s);
+                }
+                '\\' => {
+                    self.pos += 1;
+                    let escape = self
+                        .peek()
+                        .ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += 1;
+                    match escape {
+                        b'"' => s.push('"'),
+                        b'\\' => s.push('\\'),
+                        b'/' => s.push('/'),
+                        b'b' => s.push('\u{8}'),
+                        b'f' => s.push('\u{c}'),
+                        b'n' => s.push('\n'),
+                        b'r' => s.push('\r'),
+                        b't' => s.push('\t'),
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            // surrogate pair for characters outside the basic multilingual plane
+                            if (0xd800..0xdc00).contains(&code)
+                                && self.text[self.pos..].starts_with("\\u")
+                            {
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    return Err(self.error("invalid surrogate pair"));
+                                }
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            s.push(
+                                char::from_u32(code)
+                                    .ok_or_else(|| self.error("invalid \\u escape"))?,
+                            );
+                        }
+                        _ => {
+                            self.pos -= 1;
+                            return Err(self.error("invalid escape"));
+                        }
+                    }
+                }
+                c if (c as u32) < 0x20 => {
+                    return Err(self.error("control character in string"));
+                }
+                c => {
+                    s.push(c);
+                    self.pos += c.len_utf8();
+                }
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> io::Result<JsonValue> {
+        self.expect(b'[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b']') {
+            self.pos += 1;
+            return Ok(JsonValue::Array(items));
+        }
+        loop {
+            self.skip_whitespace();
+            items.push(self.value(depth + 1)?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b']') => {
+                    self.pos += 1;
+                    return Ok(JsonValue::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> io::Result<JsonValue> {
+        self.expect(b'{')?;
+        let mut entries: Vec<(String, Json)> = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(b'}') {
+            self.pos += 1;
+            return Ok(JsonValue::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some(b'"') {
+                return Err(self.error("expected a key string"));
+            }
+            let (line, column) = (self.line, self.column());
+            let key = self.string()?;
+            if entries.iter().any(|(k, _)| *k == key) {
+                let msg = format!("{}:{}: duplicate key \"{}\"", line, column, key);
+                return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+            }
+            self.skip_whitespace();
+            self.expect(b':')?;
+            self.skip_whitespace();
+            entries.push((key, self.value(depth + 1)?));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b',') => self.pos += 1,
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(JsonValue::Object(entries));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nested_values_with_positions() {
+        let json = Json::parse("{\n  \"a\": [1, -2.5e1, true, null],\n  \"b\": \"x\\u00e9\\n\"\n}")
+            .unwrap();
+        let entries = json.as_object().unwrap();
+        assert_eq!(entries[0].0, "a");
+        let a = entries[0].1.as_array().unwrap();
+        assert_eq!(a[1].as_f64().unwrap(), -25.0);
+        assert_eq!(a[2].value, JsonValue::Bool(true));
+        assert_eq!((a[1].line, a[1].column), (2, 12));
+        assert_eq!(entries[1].1.as_str().unwrap(), "xé\n");
+    }
+
+    #[test]
+    fn reports_where_parsing_failed() {
+        let err = |text: &str| Json::parse(text).unwrap_err().to_string();
+        assert_eq!(err("[1,\n 2,,]"), "2:4: expected a value");
+        assert_eq!(err("{\"a\": 1, \"a\": 2}"), "1:10: duplicate key \"a\"");
+        assert_eq!(err("[01]"), "1:2: invalid number");
+        assert_eq!(err("[1] 2"), "1:5: unexpected content after the document");
+        assert_eq!(err("\"abc"), "1:5: unterminated string");
+    }
+
+    #[test]
+    fn values_report_type_mismatches() {
+        let json = Json::parse("[\"x\"]").unwrap();
+        let item = &json.as_array().unwrap()[0];
+        assert_eq!(
+            item.as_f64().unwrap_err().to_string(),
+            "1:2: expected a number, found a string"
+        );
+    }
+}
@@ -0,0 +1,417 @@
+use std::{fs, io};
+
+use crate::{
+    sampler::Sampler,
+    utils::invalid_data,
+    vec3::{Point3, Vec3, dot, refract},
+};
+
+// Lens space: the film is the z = 0 plane, the optical axis is z and the scene lies towards -z.
+// Prescriptions list the interfaces from the front (scene side) to the rear (film side).
+
+// One refracting surface or the aperture stop of a lens prescription, lengths in scene units
+#[derive(Copy, Clone, Debug)]
+pub struct LensInterface {
+    pub curvature_radius: f64, // 0 for the aperture stop, positive when convex towards the scene
+    pub thickness: f64,        // distance along the axis to the next interface (or the film)
+    pub eta: f64,              // index of refraction behind the interface, 0 or 1 for air
+    pub aperture_radius: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct LensSystem {
+    pub interfaces: Vec<LensInterface>,
+}
+
+// A ray in lens space, not the scene Ray since it has no time
+#[derive(Copy, Clone, Debug)]
+pub struct LensRay {
+    pub origin: Point3,
+    pub dir: Vec3,
+}
+
+impl LensRay {
+    fn at(&self, t: f64) -> Point3 {
+        self.origin + t * self.dir
+    }
+}
+
+// Refract through the interface, normal on the side of the incoming ray. None on total
+// internal reflection.
+fn refract_checked(dir: Vec3, normal: Vec3, eta_over_etap: f64) -> Option<Vec3> {
+    let uv = dir.unit_vector();
+    let cos_theta = dot(-uv, normal).min(1.0);
+    let sin2_theta_t = eta_over_etap * eta_over_etap * (1.0 - cos_theta * cos_theta);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+    Some(refract(uv, normal, eta_over_etap))
+}
+
+// Intersection of the ray with the spherical cap of an interface centered at z_center.
+// Returns (t, normal facing the incoming ray).
+fn intersect_spherical_element(radius: f64, z_center: f64, ray: &LensRay) -> Option<(f64, Vec3)> {
+    let o = ray.origin - Vec3::new(0.0, 0.0, z_center);
+    let a = dot(ray.dir, ray.dir);
+    let h = dot(ray.dir, o);
+    let c = dot(o, o) - radius * radius;
+    let discriminant = h * h - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let sqrtd = discriminant.sqrt();
+    let t0 = (-h - sqrtd) / a;
+    let t1 = (-h + sqrtd) / a;
+
+    // the cap the lens uses is the half of the sphere facing the vertex
+    let use_closer = (ray.dir.z > 0.0) ^ (radius < 0.0);
+    let t = if use_closer { t0.min(t1) } else { t0.max(t1) };
+    if t < 0.0 {
+        return None;
+    }
+
+    let mut normal = (o + t * ray.dir).unit_vector();
+    if dot(normal, ray.dir) > 0.0 {
+        normal = -normal;
+    }
+    Some((t, normal))
+}
+
+impl LensSystem {
+    // Parse a prescription table in the pbrt realistic camera format: one interface per line
+    // with radius, thickness, index of refraction and aperture diameter, all in millimeters.
+    // '#' starts a comment. scale converts millimeters to scene units.
+    pub fn parse(text: &str, scale: f64) -> io::Result<Self> {
+        let mut interfaces = Vec::new();
+        for (line_no, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let values: Vec<f64> = line
+                .split_whitespace()
+                .map(|v| v.parse::<f64>())
+                .collect::<Result<_, _>>()
+                .map_err(|e| invalid_data(format!("line {}: {}", line_no + 1, e)))?;
+            if values.len() != 4 {
+                return Err(invalid_data(format!(
+                    "line {}: expected 4 values (radius thickness ior aperture), got {}",
+                    line_no + 1,
+                    values.len()
+                )));
+            }
+            interfaces.push(LensInterface {
+                curvature_radius: values[0] * scale,
+                thickness: values[1] * scale,
+                eta: values[2],
+                aperture_radius: values[3] * scale / 2.0,
+            });
+        }
+
+        if interfaces.is_empty() {
+            return Err(invalid_data(
+                "lens prescription has no interfaces".to_string(),
+            ));
+        }
+        Ok(LensSystem { interfaces })
+    }
+
+    // Load a prescription file, lengths in millimeters converted to meters (scene units)
+    pub fn load(file_name: &str) -> io::Result<Self> {
+        let text = fs::read_to_string(file_name)?;
+        LensSystem::parse(&text, 0.001).map_err(|e| invalid_data(format!("{}: {}", file_name, e)))
+    }
+
+    // Distance between the rear interface and the film
+    pub fn film_distance(&self) -> f64 {
+        self.interfaces.last().unwrap().thickness
+    }
+
+    pub fn rear_z(&self) -> f64 {
+        -self.film_distance()
+    }
+
+    pub fn front_z(&self) -> f64 {
+        -self.interfaces.iter().map(|i| i.thickness).sum::<f64>()
+    }
+
+    pub fn rear_aperture_radius(&self) -> f64 {
+        self.interfaces.last().unwrap().aperture_radius
+    }
+
+    fn medium_eta(eta: f64) -> f64 {
+        if eta == 0.0 { 1.0 } else { eta }
+    }
+
+    // Trace a ray leaving the film through every interface, rear to front. None when it is
+    // blocked by an aperture or totally internally reflected.
+    pub fn trace_from_film(&self, ray: LensRay) -> Option<LensRay> {
+        let mut ray = ray;
+        let mut element_z = 0.0;
+        for i in (0..self.interfaces.len()).rev() {
+            let element = &self.interfaces[i];
+            element_z -= element.thickness;
+            let (t, normal) = self.intersect_interface(element, element_z, &ray)?;
+
+            let p_hit = ray.at(t);
+            if p_hit.x * p_hit.x + p_hit.y * p_hit.y > element.aperture_radius.powi(2) {
+                return None;
+            }
+            ray.origin = p_hit;
+
+            if element.curvature_radius != 0.0 {
+                let eta_i = LensSystem::medium_eta(element.eta);
+                let eta_t = if i > 0 {
+                    LensSystem::medium_eta(self.interfaces[i - 1].eta)
+                } else {
+                    1.0
+                };
+                ray.dir = refract_checked(ray.dir, normal, eta_i / eta_t)?;
+            }
+        }
+        Some(ray)
+    }
+
+    // Trace a ray entering the front of the lens through every interface, front to rear
+    pub fn trace_from_scene(&self, ray: LensRay) -> Option<LensRay> {
+        let mut ray = ray;
+        let mut element_z = self.front_z();
+        for i in 0..self.interfaces.len() {
+            let element = &self.interfaces[i];
+            let (t, normal) = self.intersect_interface(element, element_z, &ray)?;
+
+            let p_hit = ray.at(t);
+            if p_hit.x * p_hit.x + p_hit.y * p_hit.y > element.aperture_radius.powi(2) {
+                return None;
+            }
+            ray.origin = p_hit;
+
+            if element.curvature_radius != 0.0 {
+                let eta_i = if i > 0 {
+                    LensSystem::medium_eta(self.interfaces[i - 1].eta)
+                } else {
+                    1.0
+                };
+                let eta_t = LensSystem::medium_eta(element.eta);
+                ray.dir = refract_checked(ray.dir, normal, eta_i / eta_t)?;
+            }
+            element_z += element.thickness;
+        }
+        Some(ray)
+    }
+
+    fn intersect_interface(
+        &self,
+        element: &LensInterface,
+        element_z: f64,
+        ray: &LensRay,
+    ) -> Option<(f64, Vec3)> {
+        if element.curvature_radius == 0.0 {
+            // aperture stop: a plane
+            if ray.dir.z == 0.0 {
+                return None;
+            }
+            let t = (element_z - ray.origin.z) / ray.dir.z;
+            if t < 0.0 {
+                return None;
+            }
+            Some((t, Vec3::new(0.0, 0.0, -ray.dir.z.signum())))
+        } else {
+            let radius = element.curvature_radius;
+            intersect_spherical_element(radius, element_z + radius, ray)
+        }
+    }
+
+    // z of the principal plane and of the focal point for a ray that entered parallel to the axis
+    fn cardinal_points(ray_in: &LensRay, ray_out: &LensRay) -> (f64, f64) {
+        let tf = -ray_out.origin.x / ray_out.dir.x;
+        let fz = ray_out.at(tf).z;
+        let tp = (ray_in.origin.x - ray_out.origin.x) / ray_out.dir.x;
+        let pz = ray_out.at(tp).z;
+        (pz, fz)
+    }
+
+    // Thick lens approximation from paraxial rays: principal plane and focal point on the
+    // scene side and on the film side, as ([scene, film] principal planes, [scene, film] focal
+    // points)
+    pub fn thick_lens_approximation(&self) -> Option<([f64; 2], [f64; 2])> {
+        // small height off the axis so the rays stay paraxial
+        let x = 0.001 * self.rear_aperture_radius();
+
+        let scene_ray = LensRay {
+            origin: Point3::new(x, 0.0, self.front_z() - 1.0),
+            dir: Vec3::new(0.0, 0.0, 1.0),
+        };
+        let film_side = self.trace_from_scene(scene_ray)?;
+        let (pz_film, fz_film) = LensSystem::cardinal_points(&scene_ray, &film_side);
+
+        let film_ray = LensRay {
+            origin: Point3::new(x, 0.0, self.rear_z() + 1.0),
+            dir: Vec3::new(0.0, 0.0, -1.0),
+        };
+        let scene_side = self.trace_from_film(film_ray)?;
+        let (pz_scene, fz_scene) = LensSystem::cardinal_points(&film_ray, &scene_side);
+
+        Some(([pz_scene, pz_film], [fz_scene, fz_film]))
+    }
+
+    pub fn focal_length(&self) -> Option<f64> {
+        let (pz, fz) = self.thick_lens_approximation()?;
+        Some(fz[1] - pz[1])
+    }
+
+    // Move the lens along the axis so that points focus_dist in front of the film are sharp.
+    // Returns None when the lens cannot focus that close.
+    pub fn focus(&mut self, focus_dist: f64) -> Option<()> {
+        let (pz, fz) = self.thick_lens_approximation()?;
+        let f = fz[1] - pz[1];
+
+        // Growing the film distance by delta moves every element by -delta. With
+        // a = s_o + delta and b = s_i - delta the thin lens equation 1/s_o + 1/s_i = 1/f becomes
+        // delta^2 - (a - b) delta - (ab - f(a + b)) = 0, take the root closest to the lens.
+        let a = pz[0] + focus_dist;
+        let b = -pz[1];
+        let c = (a + b) * (a + b - 4.0 * f);
+        if c < 0.0 {
+            return None;
+        }
+        let delta = 0.5 * (a - b - c.sqrt());
+
+        let rear = self.interfaces.last_mut().unwrap();
+        rear.thickness += delta;
+        if rear.thickness <= 0.0 {
+            return None;
+        }
+        Some(())
+    }
+}
+
+// A lens system focused in front of a film of the given size, generates camera rays in lens
+// space
+#[derive(Clone, Debug)]
+pub struct RealisticLens {
+    pub system: LensSystem,
+    pub film_width: f64,
+    pub film_height: f64,
+}
+
+impl RealisticLens {
+    // film_diagonal is in scene units, the film has the aspect ratio of the image
+    pub fn new(
+        mut system: LensSystem,
+        film_diagonal: f64,
+        aspect_ratio: f64,
+        focus_dist: f64,
+    ) -> io::Result<Self> {
+        system
+            .focus(focus_dist)
+            .ok_or_else(|| invalid_data(format!("lens system cannot focus at {}", focus_dist)))?;
+
+        let film_height = film_diagonal / (1.0 + aspect_ratio * aspect_ratio).sqrt();
+        Ok(RealisticLens {
+            system,
+            film_width: film_height * aspect_ratio,
+            film_height,
+        })
+    }
+
+    // Ray for the normalized image position (s, t), s to the right and t down, both in [0, 1].
+    // The lens forms an inverted image, so the film point is mirrored through the axis.
+    pub fn generate_ray(&self, s: f64, t: f64, sampler: &mut dyn Sampler) -> Option<LensRay> {
+        let p_film = Point3::new(
+            -(s - 0.5) * self.film_width,
+            (t - 0.5) * self.film_height,
+            0.0,
+        );
+
+        // aim at a uniformly sampled point on the rear element
+        let radius = self.system.rear_aperture_radius();
+        let p = Vec3::sample_in_unit_disk(sampler.get_2d());
+        let p_rear = Point3::new(p.x * radius, p.y * radius, self.system.rear_z());
+
+        self.system.trace_from_film(LensRay {
+            origin: p_film,
+            dir: p_rear - p_film,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SamplerKind;
+
+    // D-GAUSS F/2 22deg HFOV, US patent 2,673,491 Tronnier, scaled to 50mm
+    const DOUBLE_GAUSS: &str = "
+        # radius thickness ior aperture
+        29.475   3.76   1.67   25.2
+        84.83    0.12   1      25.2
+        19.275   4.025  1.67   23
+        40.77    3.275  1.699  23
+        12.75    5.705  1      18
+        0        4.5    0      17.1
+        -14.495  1.18   1.603  17
+        40.77    6.065  1.658  20
+        -20.385  0.19   1      20
+        437.065  3.22   1.717  20
+        -39.73   0      1      20
+    ";
+
+    #[test]
+    fn parse_rejects_malformed_lines() {
+        assert!(LensSystem::parse("1 2 3", 1.0).is_err());
+        assert!(LensSystem::parse("1 2 x 4", 1.0).is_err());
+        assert!(LensSystem::parse("# only a comment", 1.0).is_err());
+    }
+
+    #[test]
+    fn double_gauss_has_50mm_focal_length() {
+        let lens = LensSystem::parse(DOUBLE_GAUSS, 1.0).unwrap();
+        let f = lens.focal_length().unwrap();
+        assert!((f - 50.0).abs() < 5.0, "focal length {}", f);
+    }
+
+    #[test]
+    fn focus_brings_on_axis_point_to_the_film() {
+        let mut lens = LensSystem::parse(DOUBLE_GAUSS, 1.0).unwrap();
+        let focus_dist = 1000.0;
+        lens.focus(focus_dist).unwrap();
+
+        // rays from an on-axis point at the focus distance converge on the film plane
+        let source = Point3::new(0.0, 0.0, -focus_dist);
+        for height in [0.5, 1.0, 2.0] {
+            let target = Point3::new(height, 0.0, lens.front_z());
+            let out = lens
+                .trace_from_scene(LensRay {
+                    origin: source,
+                    dir: target - source,
+                })
+                .unwrap();
+            let t = -out.origin.z / out.dir.z;
+            let p = out.at(t);
+            assert!(
+                p.x.abs() < 0.05,
+                "ray at height {} lands at {}",
+                height,
+                p.x
+            );
+        }
+    }
+
+    #[test]
+    fn generated_rays_leave_towards_the_scene() {
+        let lens = LensSystem::parse(DOUBLE_GAUSS, 0.001).unwrap();
+        let realistic = RealisticLens::new(lens, 0.035, 1.5, 10.0).unwrap();
+        let mut sampler = SamplerKind::Sobol.build(256, 0);
+
+        let mut passed = 0;
+        for i in 0..256 {
+            sampler.start_pixel_sample(0, 0, i);
+            if let Some(ray) = realistic.generate_ray(0.5, 0.5, sampler.as_mut()) {
+                assert!(ray.dir.z < 0.0);
+                passed += 1;
+            }
+        }
+        assert!(passed > 0);
+    }
+}
@@ -1,11 +1,39 @@
 #![allow(nonstandard_style)]
 
 pub mod aabb;
+pub mod animation;
+pub mod aov;
+pub mod aperture;
 pub mod bvh;
 pub mod camera;
+pub mod checkpoint;
+pub mod constant_medium;
+pub mod denoise;
+pub mod film;
+pub mod filter;
+pub mod gltf;
 pub mod hittable;
-mod interval;
+pub mod image;
+pub mod interval;
+pub mod json;
+pub mod lens;
 pub mod material;
+pub mod matrix;
+pub mod mesh;
+pub mod motion;
+pub mod pbrt;
+pub mod perlin;
+pub mod ply;
+pub mod progress;
+pub mod quad;
+pub mod quaternion;
 mod ray;
+pub mod registry;
+pub mod sampler;
+pub mod scene;
+pub mod shutter;
+pub mod stereo;
+pub mod stl;
+pub mod texture;
 pub mod utils;
 pub mod vec3;
@@ -1,119 +1,604 @@
-use std::{f64, rc::Rc};
+use std::{env, fs, io, path::Path, process::ExitCode, thread};
 
 use raytracing_rs::{
-    bvh::BVH_Node,
-    camera::Camera,
-    hittable::{Hittable_List, Sphere},
-    material::{Dielectric, Lambertian, Metal},
-    utils::{random_double, random_double_range},
-    vec3::{Color, Point3, Vec3},
+    animation::frame_file_name,
+    camera::{Camera, CropOutput, CropWindow, ProgressiveRendering},
+    film::ImageFormat,
+    registry::{self, SCENES},
+    scene::{CameraSettings, ImportedScene, RenderSettings, SceneDescription},
+    shutter::Shutter,
+    vec3::{Point3, Vec3},
 };
 
-fn main() {
-    // World
-    let mut world = Hittable_List::new();
-
-    let ground_mat = Rc::new(Lambertian {
-        albedo: Color::new(0.5, 0.5, 0.5),
-    });
-    world.add(Rc::new(Sphere::new_static(
-        Point3::new(0.0, -1000.5, 0.0),
-        1000.0,
-        ground_mat,
-    )));
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let choose_mat = random_double();
-            let center = Point3::new(
-                a as f64 + 0.9 * random_double(),
-                0.2,
-                b as f64 + 0.9 * random_double(),
-            );
-
-            // move small balls away from the big balls
-            if (center - Point3::new(4.0, -0.2, 0.0)).length() > 0.9 {
-                if choose_mat < 0.8 {
-                    // diffuse
-                    let material = Rc::new(Lambertian {
-                        albedo: Color::random() * Color::random(),
-                    });
-                    let center2 = center + Vec3::new(0.0, random_double_range(0.0, 0.5), 0.0);
-                    world.add(Rc::new(Sphere::new_moving(center, center2, 0.2, material)));
-                } else if choose_mat < 0.95 {
-                    // metal
-                    let material = Rc::new(Metal {
-                        albedo: Color::random_range(0.5, 1.0),
-                        fuzz: random_double_range(0.0, 0.5),
+const USAGE: &str = "\
+Usage: raytracing_rs [OPTIONS] [SCENE]
+
+Renders SCENE, the name of a built-in scene, a JSON scene file, a glTF file (.gltf, .glb)
+or a pbrt-v3 file (.pbrt) [default: random_spheres]. A JSON scene with an animation renders
+each of its frames to a file numbered by the output pattern.
+
+Options:
+  -o, --output FILE          image to write [default: out/<scene>.<format>], for an animation
+                             a pattern like frame_%04d.png [default: out/<scene>/frame_%04d]
+  -f, --format FORMAT        ppm, png or pfm [default: from the output extension, else ppm]
+  -r, --resolution WxH       image size in pixels
+  -w, --width W              image width in pixels, keeping the aspect ratio
+  -s, --spp N                samples per pixel
+  -d, --max-depth N          maximum number of bounces per path
+      --seed N               seed of every random decision, including built-in scene layouts
+  -t, --threads N            render threads, the image is the same for any number
+                             [default: one per CPU core]
+      --snapshot SECONDS     rewrite the image every SECONDS while rendering, to watch the
+                             render converge
+      --crop X0,Y0,X1,Y1     only render these pixels, X1 and Y1 exclusive
+      --crop-fraction X0,Y0,X1,Y1
+                             only render this part of the image, as fractions from 0 to 1
+      --full-frame           write a cropped render into the full image, black around it
+      --lookfrom X,Y,Z       camera position
+      --lookat X,Y,Z         point the camera looks at
+      --vup X,Y,Z            camera up direction
+      --vfov DEGREES         vertical field of view
+      --defocus-angle DEGREES
+                             cone angle of the defocus blur, 0 for a pinhole
+      --focus-dist D         distance of the plane in focus
+      --frames FIRST-LAST    only render these frames of an animation, or one frame N
+      --save-scene FILE      write the scene, options applied, as a JSON scene file instead
+                             of rendering it, e.g. to keep a randomly generated layout
+  -h, --help                 print this help
+
+Built-in scenes:
+";
+
+#[derive(Debug, Default)]
+struct Options {
+    help: bool,
+    scene: Option<String>,
+    output: Option<String>,
+    format: Option<ImageFormat>,
+    resolution: Option<(u64, u64)>,
+    width: Option<u64>,
+    samples_per_pixel: Option<u16>,
+    max_depth: Option<i16>,
+    seed: Option<u64>,
+    threads: Option<u64>,
+    snapshot: Option<f64>,
+    crop: Option<CropWindow>,
+    full_frame: bool,
+    lookfrom: Option<Point3>,
+    lookat: Option<Point3>,
+    vup: Option<Vec3>,
+    vfov: Option<f64>,
+    defocus_angle: Option<f64>,
+    focus_dist: Option<f64>,
+    frames: Option<(u64, u64)>,
+    save_scene: Option<String>,
+}
+
+fn invalid(option: &str, value: &str, expected: &str) -> String {
+    format!(
+        "invalid value \"{}\" for {}, expected {}",
+        value, option, expected
+    )
+}
+
+fn parse_integer(option: &str, value: &str, min: u64, max: u64) -> Result<u64, String> {
+    value
+        .parse::<u64>()
+        .ok()
+        .filter(|n| (min..=max).contains(n))
+        .ok_or_else(|| {
+            let expected = format!("a whole number from {} to {}", min, max);
+            invalid(option, value, &expected)
+        })
+}
+
+fn parse_number(option: &str, value: &str) -> Result<f64, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite())
+        .ok_or_else(|| invalid(option, value, "a number"))
+}
+
+fn parse_list<T>(
+    option: &str,
+    value: &str,
+    count: usize,
+    parse: impl Fn(&str) -> Option<T>,
+    expected: &str,
+) -> Result<Vec<T>, String> {
+    let items: Vec<T> = value
+        .split(',')
+        .map(|v| parse(v.trim()))
+        .collect::<Option<_>>()
+        .ok_or_else(|| invalid(option, value, expected))?;
+    if items.len() != count {
+        return Err(invalid(option, value, expected));
+    }
+    Ok(items)
+}
+
+fn parse_vec3(option: &str, value: &str) -> Result<Vec3, String> {
+    let v = parse_list(
+        option,
+        value,
+        3,
+        |v| v.parse::<f64>().ok().filter(|n| n.is_finite()),
+        "3 comma separated numbers",
+    )?;
+    Ok(Vec3::new(v[0], v[1], v[2]))
+}
+
+impl Options {
+    fn parse(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // --option=value is the same as --option value
+            let (option, inline_value) = match arg.split_once('=') {
+                Some((option, value)) if arg.starts_with("--") => {
+                    (option.to_string(), Some(value.to_string()))
+                }
+                _ => (arg.clone(), None),
+            };
+            let mut value = || {
+                inline_value
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("{} needs a value", option))
+            };
+
+            match option.as_str() {
+                "-h" | "--help" => options.help = true,
+                "-o" | "--output" => options.output = Some(value()?),
+                "-f" | "--format" => {
+                    let v = value()?;
+                    let format = ImageFormat::from_name(&v)
+                        .ok_or_else(|| invalid(&option, &v, "ppm, png or pfm"))?;
+                    options.format = Some(format);
+                }
+                "-r" | "--resolution" => {
+                    let v = value()?;
+                    let expected = "WIDTHxHEIGHT, e.g. 1920x1080";
+                    let (w, h) = v
+                        .split_once('x')
+                        .ok_or_else(|| invalid(&option, &v, expected))?;
+                    let w = parse_integer(&option, w, 1, 65536)
+                        .map_err(|_| invalid(&option, &v, expected))?;
+                    let h = parse_integer(&option, h, 1, 65536)
+                        .map_err(|_| invalid(&option, &v, expected))?;
+                    options.resolution = Some((w, h));
+                }
+                "-w" | "--width" => {
+                    options.width = Some(parse_integer(&option, &value()?, 1, 65536)?)
+                }
+                "-s" | "--spp" => {
+                    let spp = parse_integer(&option, &value()?, 1, u16::MAX as u64)?;
+                    options.samples_per_pixel = Some(spp as u16);
+                }
+                "-d" | "--max-depth" => {
+                    let depth = parse_integer(&option, &value()?, 1, i16::MAX as u64)?;
+                    options.max_depth = Some(depth as i16);
+                }
+                "--seed" => options.seed = Some(parse_integer(&option, &value()?, 0, u64::MAX)?),
+                "-t" | "--threads" => {
+                    options.threads = Some(parse_integer(&option, &value()?, 1, 1024)?)
+                }
+                "--snapshot" => {
+                    let v = value()?;
+                    let seconds = parse_number(&option, &v)?;
+                    if seconds <= 0.0 {
+                        return Err(invalid(&option, &v, "a positive number of seconds"));
+                    }
+                    options.snapshot = Some(seconds);
+                }
+                "--crop" => {
+                    let v = value()?;
+                    let c = parse_list(
+                        &option,
+                        &v,
+                        4,
+                        |v| v.parse::<u64>().ok(),
+                        "4 comma separated pixel coordinates",
+                    )?;
+                    options.crop = Some(CropWindow::Pixels {
+                        x0: c[0],
+                        y0: c[1],
+                        x1: c[2],
+                        y1: c[3],
                     });
-                    world.add(Rc::new(Sphere::new_static(center, 0.2, material)));
-                } else {
-                    // glass
-                    let material = Rc::new(Dielectric {
-                        refraction_index: 1.5,
+                }
+                "--crop-fraction" => {
+                    let v = value()?;
+                    let c = parse_list(
+                        &option,
+                        &v,
+                        4,
+                        |v| v.parse::<f64>().ok().filter(|n| (0.0..=1.0).contains(n)),
+                        "4 comma separated numbers from 0 to 1",
+                    )?;
+                    options.crop = Some(CropWindow::Normalized {
+                        x0: c[0],
+                        y0: c[1],
+                        x1: c[2],
+                        y1: c[3],
                     });
-                    world.add(Rc::new(Sphere::new_static(center, 0.2, material)));
                 }
+                "--full-frame" => options.full_frame = true,
+                "--lookfrom" => options.lookfrom = Some(parse_vec3(&option, &value()?)?),
+                "--lookat" => options.lookat = Some(parse_vec3(&option, &value()?)?),
+                "--vup" => options.vup = Some(parse_vec3(&option, &value()?)?),
+                "--vfov" => {
+                    let v = value()?;
+                    let vfov = parse_number(&option, &v)?;
+                    if vfov <= 0.0 || vfov >= 180.0 {
+                        return Err(invalid(&option, &v, "an angle between 0 and 180 degrees"));
+                    }
+                    options.vfov = Some(vfov);
+                }
+                "--defocus-angle" => {
+                    let v = value()?;
+                    let angle = parse_number(&option, &v)?;
+                    if angle < 0.0 {
+                        return Err(invalid(&option, &v, "a non-negative angle"));
+                    }
+                    options.defocus_angle = Some(angle);
+                }
+                "--focus-dist" => {
+                    let v = value()?;
+                    let dist = parse_number(&option, &v)?;
+                    if dist <= 0.0 {
+                        return Err(invalid(&option, &v, "a positive distance"));
+                    }
+                    options.focus_dist = Some(dist);
+                }
+                "--frames" => {
+                    let v = value()?;
+                    let expected = "a frame N or frames FIRST-LAST";
+                    let (first, last) = v.split_once('-').unwrap_or((&v, &v));
+                    let first = parse_integer(&option, first, 0, u64::MAX)
+                        .map_err(|_| invalid(&option, &v, expected))?;
+                    let last = parse_integer(&option, last, first, u64::MAX)
+                        .map_err(|_| invalid(&option, &v, expected))?;
+                    options.frames = Some((first, last));
+                }
+                "--save-scene" => options.save_scene = Some(value()?),
+                _ if option.starts_with('-') && option.len() > 1 => {
+                    return Err(format!("unknown option {}", option));
+                }
+                _ if options.scene.is_some() => {
+                    return Err(format!(
+                        "unexpected argument {}, only one scene can be rendered",
+                        arg
+                    ));
+                }
+                _ => options.scene = Some(arg),
+            }
+        }
+
+        if options.resolution.is_some() && options.width.is_some() {
+            return Err("--resolution and --width can't be combined".to_string());
+        }
+        if options.full_frame && options.crop.is_none() {
+            return Err("--full-frame needs --crop or --crop-fraction".to_string());
+        }
+        Ok(options)
+    }
+
+    // Image format and file, the format named by --format or else the output extension
+    fn output(&self, scene: &str) -> Result<(ImageFormat, String), String> {
+        self.output_or(&format!("out/{}", scene))
+    }
+
+    // Image format and file pattern of the frames of an animation
+    fn frame_output(&self, scene: &str) -> Result<(ImageFormat, String), String> {
+        self.output_or(&format!("out/{}/frame_%04d", scene))
+    }
+
+    // Without --output the file is default_stem with the format's extension
+    fn output_or(&self, default_stem: &str) -> Result<(ImageFormat, String), String> {
+        match (&self.output, self.format) {
+            (Some(file), Some(format)) => Ok((format, file.clone())),
+            (Some(file), None) => match ImageFormat::from_file_name(file) {
+                Some(format) => Ok((format, file.clone())),
+                None => Err(format!(
+                    "can't tell the image format of {}, name it .ppm, .png or .pfm or use --format",
+                    file
+                )),
+            },
+            (None, format) => {
+                let format = format.unwrap_or(ImageFormat::Ppm);
+                let extension = match format {
+                    ImageFormat::Ppm => "ppm",
+                    ImageFormat::Png => "png",
+                    ImageFormat::Pfm => "pfm",
+                };
+                Ok((format, format!("{}.{}", default_stem, extension)))
             }
         }
     }
 
-    let material1 = Rc::new(Dielectric {
-        refraction_index: 1.5,
-    });
-    world.add(Rc::new(Sphere::new_static(
-        Point3::new(0.0, 1.0, 0.0),
-        1.0,
-        material1,
-    )));
-    let material2 = Rc::new(Lambertian {
-        albedo: Color::new(0.4, 0.2, 0.1),
-    });
-    world.add(Rc::new(Sphere::new_static(
-        Point3::new(-4.0, 1.0, 0.0),
-        1.0,
-        material2,
-    )));
-    let material3 = Rc::new(Metal {
-        albedo: Color::new(0.8, 0.6, 0.5),
-        fuzz: 0.0,
-    });
-    world.add(Rc::new(Sphere::new_static(
-        Point3::new(4.0, 1.0, 0.0),
-        1.0,
-        material3,
-    )));
-
-    let bvh = BVH_Node::new(&mut world);
-    world = Hittable_List::new_from_hittable(Rc::new(bvh));
-
-    // Camera
-    let aspect_ratio: f64 = 16.0 / 9.0;
-    let image_width: u64 = 400;
-
-    let lookfrom = Point3::new(13.0, 2.0, 3.0);
-    let lookat = Point3::new(0.0, 0.0, 0.0);
-    let vup = Vec3::new(0.0, 1.0, 0.0);
-    let vfov = 20.0;
-
-    let defocus_angle = 0.6;
-    let focus_dist = 10.0;
-
-    let sample_per_pixel = 100;
-    let max_depth = 50;
-
-    let camera = Camera::new(
-        aspect_ratio,
-        image_width,
-        vfov,
-        lookfrom,
-        lookat,
-        vup,
-        defocus_angle,
-        focus_dist,
-        sample_per_pixel,
-        max_depth,
-    );
-    let output_file = "out/bvh.ppm";
-    camera.render(&world, output_file).expect("render failed");
+    fn apply(&self, camera: &mut CameraSettings, render: &mut RenderSettings) {
+        if let Some((w, h)) = self.resolution {
+            camera.set_resolution(w, h);
+        }
+        if let Some(w) = self.width {
+            camera.image_width = w;
+        }
+        if let Some(spp) = self.samples_per_pixel {
+            render.samples_per_pixel = spp;
+        }
+        if let Some(depth) = self.max_depth {
+            render.max_depth = depth;
+        }
+        if let Some(seed) = self.seed {
+            render.seed = seed;
+        }
+        camera.lookfrom = self.lookfrom.unwrap_or(camera.lookfrom);
+        camera.lookat = self.lookat.unwrap_or(camera.lookat);
+        camera.vup = self.vup.unwrap_or(camera.vup);
+        camera.vfov = self.vfov.unwrap_or(camera.vfov);
+        camera.defocus_angle = self.defocus_angle.unwrap_or(camera.defocus_angle);
+        camera.focus_dist = self.focus_dist.unwrap_or(camera.focus_dist);
+    }
+}
+
+fn main() -> ExitCode {
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("error: {}\n\nRun with --help for usage.", msg);
+            return ExitCode::FAILURE;
+        }
+    };
+    if options.help {
+        print!("{}", USAGE);
+        for entry in SCENES {
+            println!("  {:<26} {}", entry.name, entry.description);
+        }
+        return ExitCode::SUCCESS;
+    }
+    match render(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// Loader of a format imported by extension, None for a JSON scene file
+fn importer(file_name: &str) -> Option<fn(&str) -> io::Result<ImportedScene>> {
+    let extension = Path::new(file_name)
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("gltf" | "glb") => Some(ImportedScene::load_gltf),
+        Some("pbrt") => Some(ImportedScene::load_pbrt),
+        _ => None,
+    }
+}
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+fn render(options: &Options) -> io::Result<()> {
+    let scene = options.scene.as_deref().unwrap_or("random_spheres");
+
+    let (mut camera_settings, mut render_settings, world, name) =
+        if let Some(entry) = registry::find(scene) {
+            let built = entry.build(options.seed.unwrap_or(42))?;
+            (
+                built.camera,
+                built.render,
+                built.world,
+                entry.name.to_string(),
+            )
+        } else if fs::exists(scene)? {
+            let name = Path::new(scene)
+                .file_stem()
+                .map_or("scene".into(), |s| s.to_string_lossy().into_owned());
+            if let Some(import) = importer(scene) {
+                let imported = import(scene)?;
+                for warning in &imported.warnings {
+                    eprintln!("warning: {}: {}", scene, warning);
+                }
+                (imported.camera, imported.render, imported.world, name)
+            } else {
+                let description = SceneDescription::load(scene)?;
+                if description.animation.timeline.is_some() {
+                    return render_animation(options, description, &name);
+                }
+                let world = description.build_world()?;
+                (description.camera_at(0.0), description.render, world, name)
+            }
+        } else {
+            let names: Vec<&str> = SCENES.iter().map(|e| e.name).collect();
+            return Err(invalid_input(format!(
+                "no scene file {} and no built-in scene of that name, the built-in scenes are: {}",
+                scene,
+                names.join(", ")
+            )));
+        };
+    if options.frames.is_some() {
+        return Err(invalid_input(format!(
+            "--frames needs an animated scene, {} has no animation",
+            scene
+        )));
+    }
+    options.apply(&mut camera_settings, &mut render_settings);
+
+    if let Some(file) = &options.save_scene {
+        let description = SceneDescription::from_world(camera_settings, render_settings, &world)?;
+        return description.save(file);
+    }
+
+    let (format, output_file) = options.output(&name).map_err(invalid_input)?;
+    let camera = camera_settings
+        .build(&render_settings)
+        .with_output_format(format);
+    let camera = configure(options, camera)?;
+    create_parent_dir(&output_file)?;
+    camera.render(&world, &output_file)
+}
+
+// Render the frames of an animated scene, each to its own file
+fn render_animation(
+    options: &Options,
+    mut description: SceneDescription,
+    name: &str,
+) -> io::Result<()> {
+    if let Some(file) = &options.save_scene {
+        options.apply(&mut description.camera, &mut description.render);
+        return description.save(file);
+    }
+
+    let timeline = description.animation.timeline.unwrap_or_default();
+    let (first, last) = options
+        .frames
+        .unwrap_or((timeline.first_frame, timeline.last_frame));
+    let (format, pattern) = options.frame_output(name).map_err(invalid_input)?;
+    // a bad pattern fails before any frame is rendered
+    frame_file_name(&pattern, first)?;
+
+    for frame in first..=last {
+        let output_file = frame_file_name(&pattern, frame)?;
+        eprintln!("frame {} of {}-{}: {}", frame, first, last, output_file);
+        let mut built = description.build_frame(frame)?;
+        let mut render_settings = description.render;
+        options.apply(&mut built.camera, &mut render_settings);
+        let camera = built
+            .camera
+            .build(&render_settings)
+            .with_shutter(Shutter::new(built.shutter.min, built.shutter.max))
+            .with_output_format(format);
+        let camera = configure(options, camera)?;
+        create_parent_dir(&output_file)?;
+        camera.render(&built.world, &output_file)?;
+    }
+    Ok(())
+}
+
+// Camera with the render threads and snapshots of the options, rendering only the window of
+// --crop or --crop-fraction if given
+fn configure(options: &Options, camera: Camera) -> io::Result<Camera> {
+    let threads = match options.threads {
+        Some(threads) => threads as usize,
+        None => thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+    let mut camera = camera.with_threads(threads)?;
+    if let Some(seconds) = options.snapshot {
+        camera = camera.with_progressive(ProgressiveRendering {
+            snapshot_seconds: Some(seconds),
+            ..Default::default()
+        })?;
+    }
+    let Some(window) = options.crop else {
+        return Ok(camera);
+    };
+    let output = if options.full_frame {
+        CropOutput::FullFrame
+    } else {
+        CropOutput::Cropped
+    };
+    camera.with_crop(window, output)
+}
+
+fn create_parent_dir(file_name: &str) -> io::Result<()> {
+    if let Some(dir) = Path::new(file_name).parent()
+        && !dir.as_os_str().is_empty()
+    {
+        fs::create_dir_all(dir)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Options, String> {
+        Options::parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn parses_options_and_overrides_the_scene() {
+        let options = parse(
+            "scenes/a.json -r 320x180 --spp=8 -d 5 --seed 7 --crop 0,0,10,10 --full-frame \
+             --lookfrom 1,2,3 --vfov 30",
+        )
+        .unwrap();
+        assert_eq!(options.scene.as_deref(), Some("scenes/a.json"));
+        assert_eq!(
+            options.crop,
+            Some(CropWindow::Pixels {
+                x0: 0,
+                y0: 0,
+                x1: 10,
+                y1: 10
+            })
+        );
+
+        let mut camera = CameraSettings::default();
+        let mut render = RenderSettings::default();
+        options.apply(&mut camera, &mut render);
+        let built = camera.build(&render);
+        assert_eq!((built.image_width(), built.image_height()), (320, 180));
+        assert_eq!(
+            (render.samples_per_pixel, render.max_depth, render.seed),
+            (8, 5, 7)
+        );
+        assert_eq!((camera.lookfrom.y, camera.vfov), (2.0, 30.0));
+
+        assert_eq!(
+            options.output("a").unwrap(),
+            (ImageFormat::Ppm, "out/a.ppm".to_string())
+        );
+        let options = parse("-o out/x.png").unwrap();
+        assert_eq!(options.output("a").unwrap().0, ImageFormat::Png);
+
+        let options = parse("--frames 3-9 -f png").unwrap();
+        assert_eq!(options.frames, Some((3, 9)));
+        assert_eq!(
+            options.frame_output("a").unwrap().1,
+            "out/a/frame_%04d.png".to_string()
+        );
+        assert_eq!(parse("--frames 5").unwrap().frames, Some((5, 5)));
+
+        let options = parse("-t 4 --snapshot 2.5").unwrap();
+        assert_eq!((options.threads, options.snapshot), (Some(4), Some(2.5)));
+    }
+
+    #[test]
+    fn rejects_invalid_options() {
+        let err = |args: &str| parse(args).unwrap_err();
+        assert_eq!(
+            err("--spp 0"),
+            "invalid value \"0\" for --spp, expected a whole number from 1 to 65535"
+        );
+        assert_eq!(
+            err("--resolution 100"),
+            "invalid value \"100\" for --resolution, expected WIDTHxHEIGHT, e.g. 1920x1080"
+        );
+        assert_eq!(
+            err("--lookat 1,2"),
+            "invalid value \"1,2\" for --lookat, expected 3 comma separated numbers"
+        );
+        assert_eq!(
+            err("--frames 9-3"),
+            "invalid value \"9-3\" for --frames, expected a frame N or frames FIRST-LAST"
+        );
+        assert_eq!(err("--bogus"), "unknown option --bogus");
+        assert_eq!(err("--vfov"), "--vfov needs a value");
+        assert_eq!(
+            err("--snapshot 0"),
+            "invalid value \"0\" for --snapshot, expected a positive number of seconds"
+        );
+        assert_eq!(
+            err("a b"),
+            "unexpected argument b, only one scene can be rendered"
+        );
+        assert!(parse("-o out/x.tga").unwrap().output("x").is_err());
+    }
 }
@@ -1,28 +1,83 @@
+use std::{any::type_name, io, sync::Arc};
+
 use crate::{
+    checkpoint::Fingerprint,
     hittable::Hit_Record,
     ray::Ray,
-    utils::random_double,
-    vec3::{Color, Vec3, dot, reflect, refract},
+    sampler::Sampler,
+    scene::{MaterialDescription, cannot_save},
+    texture::{SolidColor, Texture},
+    vec3::{Color, Point3, Vec3, dot, reflect, refract},
 };
 
-pub trait Material {
+pub trait Material: Send + Sync {
     // returns: (attenuation, scattered ray)
-    fn scatter(&self, _ray_in: &Ray, _rec: &Hit_Record) -> (Color, Option<Ray>) {
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        _rec: &Hit_Record,
+        _sampler: &mut dyn Sampler,
+    ) -> (Color, Option<Ray>) {
         (Color::new(0.0, 0.0, 0.0), Option::None)
     }
+
+    // Light given off at a point of the surface
+    fn emitted(&self, _u: f64, _v: f64, _p: Point3) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // Surface color at a hit independent of lighting, for the albedo AOV and the denoiser
+    fn albedo(&self, _rec: &Hit_Record) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
+
+    // Add the parameters of this material to the fingerprint of a render, by default it can't
+    // be checkpointed
+    fn fingerprint(&self, f: &mut Fingerprint) {
+        f.unknown(type_name::<Self>());
+    }
+
+    // This material in the scene format, to save a scene
+    fn describe(&self) -> io::Result<MaterialDescription> {
+        Err(cannot_save(
+            type_name::<Self>().rsplit("::").next().unwrap_or_default(),
+        ))
+    }
 }
 
 pub struct Lambertian {
     // Whiteness of the diffused ray
-    pub albedo: Color,
+    pub texture: Arc<dyn Texture>,
+}
+
+impl Lambertian {
+    pub fn new(albedo: Color) -> Self {
+        Lambertian {
+            texture: Arc::new(SolidColor { albedo }),
+        }
+    }
+
+    pub fn from_texture(texture: Arc<dyn Texture>) -> Self {
+        Lambertian { texture }
+    }
 }
 
 impl Material for Lambertian {
-    fn scatter(&self, ray_in: &Ray, rec: &Hit_Record) -> (Color, Option<Ray>) {
+    fn fingerprint(&self, f: &mut Fingerprint) {
+        f.name("lambertian");
+        self.texture.fingerprint(f);
+    }
+
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &Hit_Record,
+        sampler: &mut dyn Sampler,
+    ) -> (Color, Option<Ray>) {
         // NOTE: this also generates a direction on the same hemisphere as the normal
         // but the distribution changed, the added normal vector shifts the distribution towards the normal,
         // it is no longer the uniform distribution.
-        let mut scatter_direction = rec.normal + Vec3::random_unit_vector();
+        let mut scatter_direction = rec.normal + Vec3::sample_unit_vector(sampler.get_2d());
         if scatter_direction.near_zero() {
             scatter_direction = rec.normal;
         }
@@ -31,9 +86,20 @@ impl Material for Lambertian {
             origin: rec.p,
             dir: scatter_direction,
             time: ray_in.time,
+            medium_sample: sampler.get_1d(),
         };
 
-        (self.albedo, Option::Some(scattered_ray))
+        (self.albedo(rec), Option::Some(scattered_ray))
+    }
+
+    fn albedo(&self, rec: &Hit_Record) -> Color {
+        self.texture.value(rec.u, rec.v, rec.p)
+    }
+
+    fn describe(&self) -> io::Result<MaterialDescription> {
+        Ok(MaterialDescription::Lambertian {
+            albedo: self.texture.describe()?,
+        })
     }
 }
 
@@ -43,13 +109,26 @@ pub struct Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray_in: &Ray, rec: &Hit_Record) -> (Color, Option<Ray>) {
+    fn fingerprint(&self, f: &mut Fingerprint) {
+        f.name("metal");
+        f.vec3(self.albedo);
+        f.number(self.fuzz);
+    }
+
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &Hit_Record,
+        sampler: &mut dyn Sampler,
+    ) -> (Color, Option<Ray>) {
         let mut reflected = reflect(ray_in.dir, rec.normal);
-        reflected = reflected.unit_vector() + self.fuzz * Vec3::random_unit_vector();
+        reflected =
+            reflected.unit_vector() + self.fuzz * Vec3::sample_unit_vector(sampler.get_2d());
         let scattered_ray = Ray {
             origin: rec.p,
             dir: reflected,
             time: ray_in.time,
+            medium_sample: sampler.get_1d(),
         };
 
         if dot(scattered_ray.dir, rec.normal) > 0.0 {
@@ -58,6 +137,17 @@ impl Material for Metal {
             (self.albedo, Option::None)
         }
     }
+
+    fn albedo(&self, _rec: &Hit_Record) -> Color {
+        self.albedo
+    }
+
+    fn describe(&self) -> io::Result<MaterialDescription> {
+        Ok(MaterialDescription::Metal {
+            albedo: self.albedo,
+            fuzz: self.fuzz,
+        })
+    }
 }
 
 pub struct Dielectric {
@@ -75,7 +165,17 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray_in: &Ray, rec: &Hit_Record) -> (Color, Option<Ray>) {
+    fn fingerprint(&self, f: &mut Fingerprint) {
+        f.name("dielectric");
+        f.number(self.refraction_index);
+    }
+
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &Hit_Record,
+        sampler: &mut dyn Sampler,
+    ) -> (Color, Option<Ray>) {
         let attenuation = Color::new(1.0, 1.0, 1.0);
         let ri = if rec.front_face {
             1.0 / self.refraction_index
@@ -95,7 +195,7 @@ impl Material for Dielectric {
         } else {
             // Monte-carlo ray tracing
             // samples energy based on probability
-            if Dielectric::reflectance(cos_theta, ri) > random_double() {
+            if Dielectric::reflectance(cos_theta, ri) > sampler.get_1d() {
                 reflect(unit_direction, rec.normal)
             } else {
                 refract(unit_direction, rec.normal, ri)
@@ -106,8 +206,88 @@ impl Material for Dielectric {
             origin: rec.p,
             dir: direction,
             time: ray_in.time,
+            medium_sample: sampler.get_1d(),
         };
 
         (attenuation, Option::Some(scattered_ray))
     }
+
+    fn describe(&self) -> io::Result<MaterialDescription> {
+        Ok(MaterialDescription::Dielectric {
+            refraction_index: self.refraction_index,
+        })
+    }
+}
+
+// Emits its texture's color and scatters nothing
+pub struct DiffuseLight {
+    pub texture: Arc<dyn Texture>,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        DiffuseLight {
+            texture: Arc::new(SolidColor { albedo: emit }),
+        }
+    }
+
+    pub fn from_texture(texture: Arc<dyn Texture>) -> Self {
+        DiffuseLight { texture }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn fingerprint(&self, f: &mut Fingerprint) {
+        f.name("diffuse_light");
+        self.texture.fingerprint(f);
+    }
+
+    fn emitted(&self, u: f64, v: f64, p: Point3) -> Color {
+        self.texture.value(u, v, p)
+    }
+
+    fn describe(&self) -> io::Result<MaterialDescription> {
+        Ok(MaterialDescription::DiffuseLight {
+            emit: self.texture.describe()?,
+        })
+    }
+}
+
+// Phase function of a participating medium, scatters uniformly in all directions
+pub struct Isotropic {
+    pub texture: Arc<dyn Texture>,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Self {
+        Isotropic {
+            texture: Arc::new(SolidColor { albedo }),
+        }
+    }
+}
+
+impl Material for Isotropic {
+    fn fingerprint(&self, f: &mut Fingerprint) {
+        f.name("isotropic");
+        self.texture.fingerprint(f);
+    }
+
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &Hit_Record,
+        sampler: &mut dyn Sampler,
+    ) -> (Color, Option<Ray>) {
+        let scattered_ray = Ray {
+            origin: rec.p,
+            dir: Vec3::sample_unit_vector(sampler.get_2d()),
+            time: ray_in.time,
+            medium_sample: sampler.get_1d(),
+        };
+        (self.albedo(rec), Option::Some(scattered_ray))
+    }
+
+    fn albedo(&self, rec: &Hit_Record) -> Color {
+        self.texture.value(rec.u, rec.v, rec.p)
+    }
 }
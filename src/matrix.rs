@@ -0,0 +1,239 @@
+use std::ops::Mul;
+
+use crate::{
+    utils::degrees_to_radian,
+    vec3::{Point3, Vec3, cross},
+};
+
+// Affine transform as a 4x4 matrix, m[row][column], applied to column vectors: the product
+// a * b applies b first
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Matrix4 {
+    pub m: [[f64; 4]; 4],
+}
+
+pub const IDENTITY_MATRIX: Matrix4 = Matrix4 {
+    m: [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ],
+};
+
+impl Default for Matrix4 {
+    fn default() -> Self {
+        IDENTITY_MATRIX
+    }
+}
+
+impl Matrix4 {
+    pub fn new(m: [[f64; 4]; 4]) -> Self {
+        Matrix4 { m }
+    }
+
+    // Sixteen numbers column after column, the order of glTF and of pbrt's Transform
+    pub fn from_column_major(values: &[f64; 16]) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, value) in values.iter().enumerate() {
+            m[i % 4][i / 4] = *value;
+        }
+        Matrix4 { m }
+    }
+
+    pub fn translation(offset: Vec3) -> Self {
+        let mut t = IDENTITY_MATRIX;
+        t.m[0][3] = offset.x;
+        t.m[1][3] = offset.y;
+        t.m[2][3] = offset.z;
+        t
+    }
+
+    pub fn scaling(scale: Vec3) -> Self {
+        let mut t = IDENTITY_MATRIX;
+        t.m[0][0] = scale.x;
+        t.m[1][1] = scale.y;
+        t.m[2][2] = scale.z;
+        t
+    }
+
+    // Counter-clockwise rotation about axis, looking down the axis towards the origin
+    pub fn rotation(axis: Vec3, degrees: f64) -> Self {
+        let a = axis.unit_vector();
+        let (sin, cos) = degrees_to_radian(degrees).sin_cos();
+        let mut t = IDENTITY_MATRIX;
+        let axis = [a.x, a.y, a.z];
+        for i in 0..3 {
+            for j in 0..3 {
+                t.m[i][j] = axis[i] * axis[j] * (1.0 - cos);
+            }
+            t.m[i][i] += cos;
+        }
+        t.m[0][1] -= a.z * sin;
+        t.m[0][2] += a.y * sin;
+        t.m[1][0] += a.z * sin;
+        t.m[1][2] -= a.x * sin;
+        t.m[2][0] -= a.y * sin;
+        t.m[2][1] += a.x * sin;
+        t
+    }
+
+    // Rotation by the unit quaternion [x, y, z, w]
+    pub fn from_quaternion(q: [f64; 4]) -> Self {
+        let [x, y, z, w] = q;
+        Matrix4::new([
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - z * w),
+                2.0 * (x * z + y * w),
+                0.0,
+            ],
+            [
+                2.0 * (x * y + z * w),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - x * w),
+                0.0,
+            ],
+            [
+                2.0 * (x * z - y * w),
+                2.0 * (y * z + x * w),
+                1.0 - 2.0 * (x * x + y * y),
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    // World to camera transform of a camera at eye looking at at, in pbrt's left-handed
+    // convention: x right, y up, z forward
+    pub fn look_at(eye: Point3, at: Point3, up: Vec3) -> Option<Self> {
+        let dir = (at - eye).unit_vector();
+        let right = cross(up.unit_vector(), dir);
+        if right.length_squared() == 0.0 || !right.length_squared().is_finite() {
+            return None;
+        }
+        let right = right.unit_vector();
+        let new_up = cross(dir, right);
+        let camera_to_world = Matrix4::new([
+            [right.x, new_up.x, dir.x, eye.x],
+            [right.y, new_up.y, dir.y, eye.y],
+            [right.z, new_up.z, dir.z, eye.z],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        camera_to_world.inverse()
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut t = [[0.0; 4]; 4];
+        for (i, row) in t.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.m[j][i];
+            }
+        }
+        Matrix4 { m: t }
+    }
+
+    // Gauss-Jordan elimination with partial pivoting, None for a singular matrix
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inv = IDENTITY_MATRIX.m;
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|i, j| a[*i][col].abs().total_cmp(&a[*j][col].abs()))?;
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+            let scale = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let factor = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= factor * a[col][j];
+                        inv[row][j] -= factor * inv[col][j];
+                    }
+                }
+            }
+        }
+        Some(Matrix4 { m: inv })
+    }
+
+    // Determinant of the linear part, negative when the transform mirrors
+    pub fn determinant3(&self) -> f64 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn transform_point(&self, p: Point3) -> Point3 {
+        let m = &self.m;
+        let x = m[0][0] * p.x + m[0][1] * p.y + m[0][2] * p.z + m[0][3];
+        let y = m[1][0] * p.x + m[1][1] * p.y + m[1][2] * p.z + m[1][3];
+        let z = m[2][0] * p.x + m[2][1] * p.y + m[2][2] * p.z + m[2][3];
+        let w = m[3][0] * p.x + m[3][1] * p.y + m[3][2] * p.z + m[3][3];
+        if w == 1.0 {
+            Point3::new(x, y, z)
+        } else {
+            Point3::new(x, y, z) / w
+        }
+    }
+
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x + m[0][1] * v.y + m[0][2] * v.z,
+            m[1][0] * v.x + m[1][1] * v.y + m[1][2] * v.z,
+            m[2][0] * v.x + m[2][1] * v.y + m[2][2] * v.z,
+        )
+    }
+}
+
+impl Mul for Matrix4 {
+    type Output = Matrix4;
+
+    fn mul(self, rhs: Matrix4) -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[i][k] * rhs.m[k][j]).sum();
+            }
+        }
+        Matrix4 { m }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-12
+    }
+
+    #[test]
+    fn compositions_apply_right_to_left() {
+        let t = Matrix4::translation(Vec3::new(1.0, 0.0, 0.0))
+            * Matrix4::rotation(Vec3::new(0.0, 0.0, 1.0), 90.0)
+            * Matrix4::scaling(Vec3::new(2.0, 2.0, 2.0));
+        let p = t.transform_point(Point3::new(1.0, 0.0, 0.0));
+        assert!(close(p, Point3::new(1.0, 2.0, 0.0)));
+
+        let back = t.inverse().unwrap().transform_point(p);
+        assert!(close(back, Point3::new(1.0, 0.0, 0.0)));
+        assert!((t.determinant3() - 8.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn quaternions_match_axis_angle() {
+        let half = degrees_to_radian(30.0);
+        let q = Matrix4::from_quaternion([0.0, half.sin(), 0.0, half.cos()]);
+        let r = Matrix4::rotation(Vec3::new(0.0, 1.0, 0.0), 60.0);
+        let v = Vec3::new(1.0, 2.0, 3.0);
+        assert!(close(q.transform_vector(v), r.transform_vector(v)));
+    }
+}
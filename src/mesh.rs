@@ -0,0 +1,548 @@
+use std::{io, sync::Arc};
+
+use crate::{
+    aabb::{AABB, EMPTY_AABB},
+    animation::Lerp,
+    bvh::BVH_Node,
+    checkpoint::Fingerprint,
+    hittable::{Hit_Record, Hittable, Hittable_List},
+    interval::Interval,
+    material::Material,
+    matrix::Matrix4,
+    ray::Ray,
+    texture::Texture,
+    utils::invalid_data,
+    vec3::{Color, Point3, Vec3, cross, dot},
+};
+
+// Indexed triangle mesh as read from a model file, per-vertex attributes are either empty or
+// one per position.
+//
+// A deforming mesh has position_keys, every vertex's position at increasing times. Rays see
+// the vertices where they are at the ray's time, moving linearly between keys and held before
+// the first and after the last, and positions is only the pose used for anything else.
+#[derive(Clone, Debug, Default)]
+pub struct TriangleMesh {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub colors: Vec<Color>, // linear
+    pub triangles: Vec<[usize; 3]>,
+    pub position_keys: Vec<(f64, Vec<Point3>)>,
+}
+
+impl TriangleMesh {
+    // Load a .ply or .stl file, picked by extension
+    pub fn load(file_name: &str) -> io::Result<TriangleMesh> {
+        let extension = file_name
+            .rsplit_once('.')
+            .map(|(_, e)| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("ply") => TriangleMesh::load_ply(file_name),
+            Some("stl") => TriangleMesh::load_stl(file_name),
+            _ => Err(invalid_data(format!(
+                "{}: unknown mesh format, expected .ply or .stl",
+                file_name
+            ))),
+        }
+    }
+
+    // Add a polygon as a fan of triangles around its first vertex
+    pub fn add_polygon(&mut self, vertices: &[usize]) {
+        for i in 1..vertices.len().saturating_sub(1) {
+            self.triangles
+                .push([vertices[0], vertices[i], vertices[i + 1]]);
+        }
+    }
+
+    // Check the attribute counts and that every index points at a vertex
+    pub fn validate(&self) -> io::Result<()> {
+        let n = self.positions.len();
+        if !self.normals.is_empty() && self.normals.len() != n {
+            return Err(invalid_data(format!(
+                "{} normals for {} vertices",
+                self.normals.len(),
+                n
+            )));
+        }
+        if !self.colors.is_empty() && self.colors.len() != n {
+            return Err(invalid_data(format!(
+                "{} colors for {} vertices",
+                self.colors.len(),
+                n
+            )));
+        }
+        for (i, (time, positions)) in self.position_keys.iter().enumerate() {
+            if positions.len() != n {
+                return Err(invalid_data(format!(
+                    "{} positions at time {} for {} vertices",
+                    positions.len(),
+                    time,
+                    n
+                )));
+            }
+            if i > 0 && *time <= self.position_keys[i - 1].0 {
+                return Err(invalid_data(format!(
+                    "position keys at time {} after time {}, times must increase",
+                    time,
+                    self.position_keys[i - 1].0
+                )));
+            }
+        }
+        if let Some((t, index)) = self
+            .triangles
+            .iter()
+            .enumerate()
+            .find_map(|(t, tri)| tri.iter().find(|i| **i >= n).map(|i| (t, *i)))
+        {
+            return Err(invalid_data(format!(
+                "triangle {} uses vertex {} of {}",
+                t, index, n
+            )));
+        }
+        Ok(())
+    }
+
+    // Box around the mesh in every pose it takes
+    pub fn bounding_box(&self) -> AABB {
+        self.all_positions().fold(EMPTY_AABB, |bbox, p| {
+            AABB::new_from_bbox(bbox, AABB::new_from_extrema(*p, *p))
+        })
+    }
+
+    // Where the vertices are at time, positions for a mesh without keys
+    pub fn positions_at(&self, time: f64) -> Vec<Point3> {
+        let keys = &self.position_keys;
+        let after = keys.partition_point(|(t, _)| *t <= time);
+        if keys.is_empty() {
+            return self.positions.clone();
+        } else if after == 0 || after == keys.len() {
+            return keys[after.saturating_sub(1)].1.clone();
+        }
+        let ((t0, from), (t1, to)) = (&keys[after - 1], &keys[after]);
+        let s = (time - t0) / (t1 - t0);
+        from.iter().zip(to).map(|(a, b)| a.lerp(*b, s)).collect()
+    }
+
+    // positions and the positions of every key
+    fn all_positions(&self) -> impl Iterator<Item = &Point3> {
+        self.positions
+            .iter()
+            .chain(self.position_keys.iter().flat_map(|(_, p)| p))
+    }
+
+    fn all_positions_mut(&mut self) -> impl Iterator<Item = &mut Point3> {
+        self.positions
+            .iter_mut()
+            .chain(self.position_keys.iter_mut().flat_map(|(_, p)| p))
+    }
+
+    // Move and uniformly scale the mesh, e.g. to fit a model made in millimeters into a scene
+    pub fn transform(&mut self, scale: f64, offset: Vec3) {
+        for p in self.all_positions_mut() {
+            *p = scale * *p + offset;
+        }
+    }
+
+    // Apply an affine transform. Normals go through the inverse transpose, and a mirroring
+    // transform reverses the winding so the faces keep pointing out.
+    pub fn transform_by(&mut self, matrix: &Matrix4) {
+        for p in self.all_positions_mut() {
+            *p = matrix.transform_point(*p);
+        }
+        let normal_matrix = matrix.inverse().unwrap_or_default().transpose();
+        for n in &mut self.normals {
+            *n = normal_matrix.transform_vector(*n).unit_vector();
+        }
+        if matrix.determinant3() < 0.0 {
+            for tri in &mut self.triangles {
+                tri.swap(1, 2);
+            }
+        }
+    }
+
+    // The triangles in a BVH, all of one material. Degenerate triangles are dropped.
+    pub fn build(&self, material: Arc<dyn Material>) -> Hittable_List {
+        self.build_with(|_| Arc::clone(&material))
+    }
+
+    // The triangles in a BVH with their vertex colors as texture, surface turns the texture
+    // of each triangle into its material, e.g. |t| Arc::new(Lambertian::from_texture(t))
+    pub fn build_vertex_colored(
+        &self,
+        surface: impl Fn(Arc<dyn Texture>) -> Arc<dyn Material>,
+    ) -> io::Result<Hittable_List> {
+        if self.colors.is_empty() {
+            return Err(invalid_data("the mesh has no vertex colors".to_string()));
+        }
+        Ok(self.build_with(|[a, b, c]| {
+            surface(Arc::new(VertexColorTexture {
+                colors: [self.colors[a], self.colors[b], self.colors[c]],
+            }))
+        }))
+    }
+
+    // Smooth normals for positions, at every vertex the sum of the normals of the faces around
+    // it weighted by their area, left for the triangles to normalize
+    fn vertex_normals(&self, positions: &[Point3]) -> Vec<Vec3> {
+        let mut normals = vec![Vec3::default(); positions.len()];
+        for [a, b, c] in &self.triangles {
+            let n = cross(positions[*b] - positions[*a], positions[*c] - positions[*a]);
+            for i in [a, b, c] {
+                normals[*i] += n;
+            }
+        }
+        normals
+    }
+
+    fn build_with(
+        &self,
+        mut material: impl FnMut([usize; 3]) -> Arc<dyn Material>,
+    ) -> Hittable_List {
+        // the shading normals of a deforming mesh bend with it, so recompute them at every key
+        let times: Arc<[f64]> = self.position_keys.iter().map(|(t, _)| *t).collect();
+        let key_normals: Vec<Vec<Vec3>> = if self.normals.is_empty() {
+            Vec::new()
+        } else {
+            self.position_keys
+                .iter()
+                .map(|(_, p)| self.vertex_normals(p))
+                .collect()
+        };
+
+        let mut objects: Vec<Arc<dyn Hittable>> = Vec::new();
+        for tri in &self.triangles {
+            let [a, b, c] = *tri;
+            let triangle = if self.position_keys.is_empty() {
+                let normals = (!self.normals.is_empty())
+                    .then(|| [self.normals[a], self.normals[b], self.normals[c]]);
+                let vertices = [self.positions[a], self.positions[b], self.positions[c]];
+                Triangle::new(vertices, normals, material(*tri))
+            } else {
+                let motion = VertexMotion {
+                    times: Arc::clone(&times),
+                    vertices: self
+                        .position_keys
+                        .iter()
+                        .map(|(_, p)| [p[a], p[b], p[c]])
+                        .collect(),
+                    normals: key_normals.iter().map(|n| [n[a], n[b], n[c]]).collect(),
+                };
+                Triangle::new_deforming(motion, material(*tri))
+            };
+            if let Some(triangle) = triangle {
+                objects.push(Arc::new(triangle));
+            }
+        }
+        if objects.is_empty() {
+            return Hittable_List::new();
+        }
+        let len = objects.len();
+        let bvh = BVH_Node::new_from_objects(&mut objects, 0, len);
+        Hittable_List::new_from_hittable(Arc::new(bvh))
+    }
+}
+
+pub struct Triangle {
+    vertices: [Point3; 3],
+    normals: Option<[Vec3; 3]>, // shading normals, interpolated over the face
+    normal: Vec3,               // geometric normal, counter-clockwise winding faces out
+    motion: Option<VertexMotion>,
+    material: Arc<dyn Material>,
+    bbox: AABB,
+}
+
+// Keyframed vertices of a deforming triangle, with the shading normals at every key when it
+// has them. The key times are shared by all triangles of a mesh.
+struct VertexMotion {
+    times: Arc<[f64]>,
+    vertices: Vec<[Point3; 3]>,
+    normals: Vec<[Vec3; 3]>,
+}
+
+impl VertexMotion {
+    // The vertices at time, how fast they move then and the shading normals
+    fn at(&self, time: f64) -> ([Point3; 3], [Vec3; 3], Option<[Vec3; 3]>) {
+        let normals = |i: usize| self.normals.get(i).copied();
+        let after = self.times.partition_point(|t| *t <= time);
+        if after == 0 || after == self.times.len() {
+            let i = after.saturating_sub(1);
+            return (self.vertices[i], [Vec3::default(); 3], normals(i));
+        }
+        let (t0, t1) = (self.times[after - 1], self.times[after]);
+        let s = (time - t0) / (t1 - t0);
+        let (from, to) = (self.vertices[after - 1], self.vertices[after]);
+        let vertices = std::array::from_fn(|i| from[i].lerp(to[i], s));
+        let velocities = std::array::from_fn(|i| (to[i] - from[i]) / (t1 - t0));
+        let normals = normals(after - 1)
+            .zip(normals(after))
+            .map(|(from, to)| std::array::from_fn(|i| from[i].lerp(to[i], s)));
+        (vertices, velocities, normals)
+    }
+}
+
+// Face normal of vertices, None for a triangle without area
+fn face_normal([a, b, c]: [Point3; 3]) -> Option<Vec3> {
+    let n = cross(b - a, c - a);
+    (n.length_squared() != 0.0 && n.length_squared().is_finite()).then(|| n.unit_vector())
+}
+
+fn triangle_box([a, b, c]: [Point3; 3]) -> AABB {
+    AABB::new_from_bbox(AABB::new_from_extrema(a, b), AABB::new_from_extrema(c, c))
+}
+
+impl Triangle {
+    // None for a triangle without area
+    pub fn new(
+        vertices: [Point3; 3],
+        normals: Option<[Vec3; 3]>,
+        material: Arc<dyn Material>,
+    ) -> Option<Self> {
+        Some(Triangle {
+            vertices,
+            normals,
+            normal: face_normal(vertices)?,
+            motion: None,
+            material,
+            bbox: triangle_box(vertices).pad_to_minimums(),
+        })
+    }
+
+    // Triangle whose vertices move linearly from key to key. The vertices stay inside the
+    // boxes around the keys they move between, so the union of those covers the triangle at
+    // any time. None when it has no area at any key.
+    fn new_deforming(motion: VertexMotion, material: Arc<dyn Material>) -> Option<Self> {
+        let vertices = motion.vertices[0];
+        let normal = motion.vertices.iter().find_map(|v| face_normal(*v))?;
+        let bbox = motion
+            .vertices
+            .iter()
+            .fold(EMPTY_AABB, |bbox, v| {
+                AABB::new_from_bbox(bbox, triangle_box(*v))
+            })
+            .pad_to_minimums();
+        Some(Triangle {
+            vertices,
+            normals: None,
+            normal,
+            motion: Some(motion),
+            material,
+            bbox,
+        })
+    }
+}
+
+impl Hittable for Triangle {
+    // Möller-Trumbore: solve origin + t dir = a + u (b - a) + v (c - a)
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<Hit_Record> {
+        let (vertices, velocities, normals, normal) = match &self.motion {
+            None => (
+                self.vertices,
+                [Vec3::default(); 3],
+                self.normals,
+                self.normal,
+            ),
+            Some(motion) => {
+                let (vertices, velocities, normals) = motion.at(ray.time);
+                (vertices, velocities, normals, face_normal(vertices)?)
+            }
+        };
+        let [a, b, c] = vertices;
+        let edge1 = b - a;
+        let edge2 = c - a;
+        let pvec = cross(ray.dir, edge2);
+        let det = dot(edge1, pvec);
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        let inv_det = 1.0 / det;
+
+        let tvec = ray.origin - a;
+        let u = dot(tvec, pvec) * inv_det;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let qvec = cross(tvec, edge1);
+        let v = dot(ray.dir, qvec) * inv_det;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let t = dot(edge2, qvec) * inv_det;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+
+        let [va, vb, vc] = velocities;
+        let mut rec = Hit_Record {
+            p: ray.at(t),
+            normal,
+            t,
+            u,
+            v,
+            front_face: true,
+            material: Arc::clone(&self.material),
+            velocity: (1.0 - u - v) * va + u * vb + v * vc,
+            object: std::ptr::from_ref(self).addr(),
+        };
+        rec.set_face_normal(ray, normal);
+        if let Some([na, nb, nc]) = normals {
+            let shading = ((1.0 - u - v) * na + u * nb + v * nc).unit_vector();
+            // shading normals only bend the normal, the face it is on stays the one hit
+            let side = if dot(shading, rec.normal) < 0.0 {
+                -1.0
+            } else {
+                1.0
+            };
+            if shading.length_squared().is_finite() {
+                rec.normal = side * shading;
+            }
+        }
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+
+    fn fingerprint(&self, f: &mut Fingerprint) {
+        f.name("triangle");
+        self.vertices.iter().for_each(|v| f.vec3(*v));
+        if let Some(normals) = self.normals {
+            f.name("normals");
+            normals.iter().for_each(|n| f.vec3(*n));
+        }
+        if let Some(motion) = &self.motion {
+            f.name("motion");
+            motion.times.iter().for_each(|t| f.number(*t));
+            motion.vertices.iter().flatten().for_each(|v| f.vec3(*v));
+            motion.normals.iter().flatten().for_each(|n| f.vec3(*n));
+        }
+        self.material.fingerprint(f);
+    }
+}
+
+// Colors of the three vertices of a triangle blended by the barycentric (u, v) of its hits
+pub struct VertexColorTexture {
+    pub colors: [Color; 3],
+}
+
+impl Texture for VertexColorTexture {
+    fn fingerprint(&self, f: &mut Fingerprint) {
+        f.name("vertex_colors");
+        self.colors.iter().for_each(|c| f.vec3(*c));
+    }
+
+    fn value(&self, u: f64, v: f64, _p: Point3) -> Color {
+        let [a, b, c] = self.colors;
+        (1.0 - u - v) * a + u * b + v * c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+
+    fn quad_mesh() -> TriangleMesh {
+        let mut mesh = TriangleMesh {
+            positions: vec![
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(1.0, 0.0, 0.0),
+                Point3::new(1.0, 1.0, 0.0),
+                Point3::new(0.0, 1.0, 0.0),
+            ],
+            colors: vec![
+                Color::new(1.0, 0.0, 0.0),
+                Color::new(0.0, 1.0, 0.0),
+                Color::new(0.0, 0.0, 1.0),
+                Color::new(1.0, 1.0, 1.0),
+            ],
+            ..Default::default()
+        };
+        mesh.add_polygon(&[0, 1, 2, 3]);
+        mesh
+    }
+
+    #[test]
+    fn hits_report_barycentrics_and_vertex_colors() {
+        let mesh = quad_mesh();
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [0, 2, 3]]);
+        mesh.validate().unwrap();
+
+        let world = mesh
+            .build_vertex_colored(|t| Arc::new(Lambertian::from_texture(t)))
+            .unwrap();
+        let ray = Ray {
+            origin: Point3::new(0.75, 0.25, 1.0),
+            dir: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+            medium_sample: 0.0,
+        };
+        let rec = world
+            .hit(
+                &ray,
+                Interval {
+                    min: 0.001,
+                    max: f64::INFINITY,
+                },
+            )
+            .unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert!(rec.front_face);
+        // (0.75, 0.25) = a + 0.5 (b - a) + 0.25 (c - a)
+        assert!((rec.u - 0.5).abs() < 1e-12 && (rec.v - 0.25).abs() < 1e-12);
+        let albedo = rec.material.albedo(&rec);
+        assert!((albedo - Color::new(0.25, 0.5, 0.25)).length() < 1e-12);
+    }
+
+    #[test]
+    fn deforming_meshes_are_hit_where_the_ray_time_has_them() {
+        let mut mesh = quad_mesh();
+        // the top edge rises by 2 between t = 1 and t = 2
+        let raised: Vec<Point3> = mesh
+            .positions
+            .iter()
+            .map(|p| *p + Vec3::new(0.0, 2.0 * p.y, 0.0))
+            .collect();
+        mesh.position_keys = vec![(1.0, mesh.positions.clone()), (2.0, raised)];
+        mesh.validate().unwrap();
+        let world = mesh.build(Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))));
+        let bbox = world.bounding_box();
+        assert_eq!((bbox.y.min, bbox.y.max), (0.0, 3.0));
+
+        let hit = |y: f64, time: f64| {
+            let ray = Ray {
+                origin: Point3::new(0.5, y, 1.0),
+                dir: Vec3::new(0.0, 0.0, -1.0),
+                time,
+                medium_sample: 0.0,
+            };
+            let everywhere = Interval {
+                min: 0.001,
+                max: f64::INFINITY,
+            };
+            world.hit(&ray, everywhere)
+        };
+        assert!(hit(1.5, 0.0).is_none());
+        assert!(hit(1.5, 1.2).is_none());
+        // halfway the top edge is at y = 2, a point at y = 1.5 on it moves up at 1.5
+        let rec = hit(1.5, 1.5).unwrap();
+        assert!((rec.velocity.y - 1.5).abs() < 1e-9 && rec.velocity.x.abs() < 1e-9);
+        assert!(hit(2.9, 5.0).is_some());
+
+        mesh.position_keys[1].0 = 1.0;
+        assert_eq!(
+            mesh.validate().unwrap_err().to_string(),
+            "position keys at time 1 after time 1, times must increase"
+        );
+    }
+
+    #[test]
+    fn validate_catches_bad_indices() {
+        let mut mesh = quad_mesh();
+        mesh.triangles.push([0, 1, 4]);
+        assert_eq!(
+            mesh.validate().unwrap_err().to_string(),
+            "triangle 2 uses vertex 4 of 4"
+        );
+    }
+}
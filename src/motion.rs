@@ -0,0 +1,287 @@
+use std::sync::Arc;
+
+use crate::{
+    aabb::{AABB, EMPTY_AABB},
+    animation::{Lerp, Track},
+    checkpoint::Fingerprint,
+    hittable::{Hit_Record, Hittable},
+    interval::{Interval, UNIVERSE_INTERVAL},
+    quaternion::Quaternion,
+    ray::Ray,
+    vec3::{Point3, Vec3},
+};
+
+// Placement of an object: scale along its axes, then rotation, then translation
+#[derive(Copy, Clone, Debug)]
+pub struct Pose {
+    pub translate: Vec3,
+    pub rotation: Quaternion,
+    pub scale: Vec3,
+}
+
+impl Default for Pose {
+    fn default() -> Self {
+        Pose {
+            translate: Vec3::default(),
+            rotation: Quaternion::default(),
+            scale: Vec3::new(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+impl Pose {
+    pub fn apply(&self, p: Point3) -> Point3 {
+        self.rotation.rotate(self.scale * p) + self.translate
+    }
+
+    pub fn apply_vector(&self, v: Vec3) -> Vec3 {
+        self.rotation.rotate(self.scale * v)
+    }
+
+    // Normals scale inversely, the inverse transpose of scale and rotation
+    pub fn apply_normal(&self, n: Vec3) -> Vec3 {
+        self.rotation.rotate(n / self.scale).unit_vector()
+    }
+
+    fn unapply(&self, p: Point3) -> Point3 {
+        self.unapply_vector(p - self.translate)
+    }
+
+    fn unapply_vector(&self, v: Vec3) -> Vec3 {
+        self.rotation.conjugate().rotate(v) / self.scale
+    }
+}
+
+// Translation and scale change linearly, the rotation turns at constant speed
+impl Lerp for Pose {
+    fn lerp(self, other: Pose, t: f64) -> Pose {
+        Pose {
+            translate: self.translate.lerp(other.translate, t),
+            rotation: self.rotation.slerp(other.rotation, t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+}
+
+// Each stretch between two keyframes is bounded in this many steps
+const BOUND_STEPS: usize = 64;
+
+// Instance of an object moving through keyframed poses, placed by the pose at each ray's time,
+// so it blurs along every segment of the motion over the shutter interval
+pub struct MotionTransform {
+    object: Arc<dyn Hittable>,
+    poses: Track<Pose>,
+    bbox: AABB,
+}
+
+impl MotionTransform {
+    pub fn new(object: Arc<dyn Hittable>, poses: Track<Pose>) -> Self {
+        MotionTransform::new_over(object, poses, UNIVERSE_INTERVAL)
+    }
+
+    // Only the motion during time is bounded, enough for rays that never leave it, e.g. with
+    // time the shutter interval of a frame in a long animation
+    pub fn new_over(object: Arc<dyn Hittable>, poses: Track<Pose>, time: Interval) -> Self {
+        assert!(!poses.is_empty(), "a motion needs at least one pose");
+        assert!(
+            poses.keys().iter().all(|(_, pose)| {
+                pose.scale.x > 0.0 && pose.scale.y > 0.0 && pose.scale.z > 0.0
+            }),
+            "scales must be positive"
+        );
+        let bbox = motion_bounds(object.bounding_box(), &poses, time);
+        MotionTransform {
+            object,
+            poses,
+            bbox,
+        }
+    }
+}
+
+// Box around every place poses move bbox to during time. Each stretch between keyframes is cut
+// in steps, and the box at the start of a step grows by how far any point can get from it before
+// the step ends: the change in translation, the change in scale, and the arc swept by the
+// rotation.
+fn motion_bounds(bbox: AABB, poses: &Track<Pose>, time: Interval) -> AABB {
+    let keys = poses.keys();
+    let first = keys[0].0;
+    let last = keys[keys.len() - 1].0;
+    let clamp = |t: f64| t.max(first).min(last);
+    let (start, end) = (clamp(time.min), clamp(time.max));
+    let mut times = vec![start];
+    times.extend(
+        keys.iter()
+            .map(|(t, _)| *t)
+            .filter(|t| start < *t && *t < end),
+    );
+    times.push(end);
+
+    let mut corners = Vec::new();
+    for x in [bbox.x.min, bbox.x.max] {
+        for y in [bbox.y.min, bbox.y.max] {
+            for z in [bbox.z.min, bbox.z.max] {
+                corners.push(Point3::new(x, y, z));
+            }
+        }
+    }
+    let posed = |pose: &Pose, pad: f64| {
+        corners.iter().fold(EMPTY_AABB, |b, c| {
+            let p = pose.apply(*c);
+            let d = Vec3::new(pad, pad, pad);
+            AABB::new_from_bbox(b, AABB::new_from_extrema(p - d, p + d))
+        })
+    };
+    let farthest = |scale: Vec3| {
+        corners
+            .iter()
+            .map(|c| (scale * *c).length())
+            .fold(0.0, f64::max)
+    };
+
+    let mut result = EMPTY_AABB;
+    for stretch in times.windows(2) {
+        let (t0, t1) = (stretch[0], stretch[1]);
+        for i in 0..BOUND_STEPS {
+            let ta = t0 + (t1 - t0) * i as f64 / BOUND_STEPS as f64;
+            let tb = t0 + (t1 - t0) * (i + 1) as f64 / BOUND_STEPS as f64;
+            let (a, b) = (poses.sample(ta).unwrap(), poses.sample(tb).unwrap());
+            let pad = (b.translate - a.translate).length()
+                + farthest(b.scale - a.scale)
+                + a.rotation.angle_to(b.rotation) * farthest(a.scale);
+            result = AABB::new_from_bbox(result, posed(&a, pad));
+        }
+    }
+    AABB::new_from_bbox(result, posed(&poses.sample(end).unwrap(), 0.0))
+}
+
+impl Hittable for MotionTransform {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<Hit_Record> {
+        let pose = self.poses.sample(ray.time)?;
+        // the ray into object space, its direction isn't normalized so t stays the same
+        let local_ray = Ray {
+            origin: pose.unapply(ray.origin),
+            dir: pose.unapply_vector(ray.dir),
+            ..*ray
+        };
+        let mut rec = self.object.hit(&local_ray, ray_t)?;
+        let local_p = rec.p;
+        rec.p = pose.apply(local_p);
+        rec.normal = pose.apply_normal(rec.normal);
+        // the motion of the pose at the hit point, plus the object's own motion
+        let h = 1e-4;
+        let later = self.poses.sample(ray.time + h)?;
+        rec.velocity = (later.apply(local_p) - rec.p) / h + pose.apply_vector(rec.velocity);
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+
+    fn fingerprint(&self, f: &mut Fingerprint) {
+        f.name("motion_transform");
+        for (time, pose) in self.poses.keys() {
+            f.number(*time);
+            f.vec3(pose.translate);
+            f.vec3(pose.rotation.v);
+            f.number(pose.rotation.w);
+            f.vec3(pose.scale);
+        }
+        self.object.fingerprint(f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{hittable::Sphere, material::Lambertian, vec3::Color};
+
+    fn sphere() -> Arc<dyn Hittable> {
+        Arc::new(Sphere::new_static(
+            Point3::new(1.0, 0.0, 0.0),
+            0.25,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        ))
+    }
+
+    // A sphere at x = 1 swinging a half turn about y and moving up by 2
+    fn swing() -> Track<Pose> {
+        let y = Vec3::new(0.0, 1.0, 0.0);
+        Track::new(vec![
+            (0.0, Pose::default()),
+            (
+                1.0,
+                Pose {
+                    translate: Vec3::new(0.0, 2.0, 0.0),
+                    rotation: Quaternion::from_axis_angle(y, 180.0),
+                    ..Default::default()
+                },
+            ),
+        ])
+        .unwrap()
+    }
+
+    fn down(x: f64, z: f64, time: f64) -> Ray {
+        Ray {
+            origin: Point3::new(x, 10.0, z),
+            dir: Vec3::new(0.0, -1.0, 0.0),
+            time,
+            medium_sample: 0.0,
+        }
+    }
+
+    #[test]
+    fn objects_follow_the_pose_at_the_ray_time() {
+        let moving = MotionTransform::new(sphere(), swing());
+        let everywhere = Interval {
+            min: 0.001,
+            max: f64::INFINITY,
+        };
+        // a quarter turn and halfway up at t = 0.5: at [0, 1, -1]
+        let rec = moving.hit(&down(0.0, -1.0, 0.5), everywhere).unwrap();
+        assert!((rec.p.y - 1.25).abs() < 1e-9);
+        assert!((rec.normal.y - 1.0).abs() < 1e-9);
+        assert!(moving.hit(&down(1.0, 0.0, 0.5), everywhere).is_none());
+        // turning at pi per unit of time about y, the top point moves along -x and up
+        assert!((rec.velocity.x + std::f64::consts::PI).abs() < 1e-3);
+        assert!((rec.velocity.y - 2.0).abs() < 1e-6);
+
+        let squashed = Track::new(vec![(
+            0.0,
+            Pose {
+                scale: Vec3::new(1.0, 4.0, 1.0),
+                ..Default::default()
+            },
+        )])
+        .unwrap();
+        let tall = MotionTransform::new(sphere(), squashed);
+        let rec = tall.hit(&down(1.0, 0.0, 0.0), everywhere).unwrap();
+        assert!((rec.p.y - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn bounds_cover_the_swept_path() {
+        let moving = MotionTransform::new(sphere(), swing());
+        let bbox = moving.bounding_box();
+        // the sphere sweeps x from 1.25 through z = -1.25 to x = -1.25
+        for t in 0..=100 {
+            let pose = swing().sample(t as f64 / 100.0).unwrap();
+            let c = pose.apply(Point3::new(1.0, 0.0, 0.0));
+            for (axis, r) in [(c.x, bbox.x), (c.y, bbox.y), (c.z, bbox.z)] {
+                assert!(r.min <= axis - 0.25 && axis + 0.25 <= r.max);
+            }
+        }
+        assert!(bbox.x.max < 1.4 && bbox.z.min > -1.4 && bbox.y.max < 2.4);
+
+        // only the first tenth of the motion, the sphere stays near x = 1
+        let early = MotionTransform::new_over(
+            sphere(),
+            swing(),
+            Interval {
+                min: -1.0,
+                max: 0.1,
+            },
+        );
+        assert!(early.bounding_box().x.min > 0.5);
+    }
+}
@@ -0,0 +1,1024 @@
+use std::{cell::RefCell, collections::HashMap, fs, io, path::Path, sync::Arc};
+
+use crate::{
+    bvh::BVH_Node,
+    filter::{Filter, FilterKind},
+    hittable::{Hittable, Hittable_List, Sphere},
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+    matrix::{IDENTITY_MATRIX, Matrix4},
+    mesh::TriangleMesh,
+    sampler::SamplerKind,
+    scene::{CameraSettings, ImportedScene, RenderSettings},
+    utils::invalid_data,
+    vec3::{Color, Point3, Vec3},
+};
+
+// Where a token came from, the file is None for the one being parsed and the name of an
+// Include otherwise
+#[derive(Clone, Debug)]
+struct Location {
+    file: Option<Arc<str>>,
+    line: usize,
+}
+
+impl Location {
+    fn error(&self, msg: impl AsRef<str>) -> io::Error {
+        invalid_data(format!("{}: {}", self, msg.as_ref()))
+    }
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "line {} of {}", self.line, file),
+            None => write!(f, "line {}", self.line),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Identifier(String),
+    String(String),
+    Number(f64),
+    Open,
+    Close,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Identifier(s) => write!(f, "{}", s),
+            Token::String(s) => write!(f, "{:?}", s),
+            Token::Number(n) => write!(f, "{}", n),
+            Token::Open => write!(f, "["),
+            Token::Close => write!(f, "]"),
+        }
+    }
+}
+
+fn tokenize(text: &str, file: Option<Arc<str>>) -> io::Result<Vec<(Token, Location)>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+    let mut line = 1;
+    while let Some((start, c)) = chars.next() {
+        let at = Location {
+            file: file.clone(),
+            line,
+        };
+        let token = match c {
+            '\n' => {
+                line += 1;
+                continue;
+            }
+            c if c.is_whitespace() => continue,
+            '#' => {
+                while chars.next_if(|(_, c)| *c != '\n').is_some() {}
+                continue;
+            }
+            '[' => Token::Open,
+            ']' => Token::Close,
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, 'n')) => s.push('\n'),
+                            Some((_, 't')) => s.push('\t'),
+                            Some((_, c)) => s.push(c),
+                            None => return Err(at.error("unterminated string")),
+                        },
+                        Some((_, '\n')) | None => return Err(at.error("unterminated string")),
+                        Some((_, c)) => s.push(c),
+                    }
+                }
+                Token::String(s)
+            }
+            _ => {
+                let mut end = start + c.len_utf8();
+                while let Some((i, c)) =
+                    chars.next_if(|(_, c)| !c.is_whitespace() && !matches!(c, '[' | ']' | '"'))
+                {
+                    end = i + c.len_utf8();
+                }
+                let word = &text[start..end];
+                if c.is_ascii_alphabetic() {
+                    Token::Identifier(word.to_string())
+                } else {
+                    Token::Number(
+                        word.parse()
+                            .map_err(|_| at.error(format!("unexpected {:?}", word)))?,
+                    )
+                }
+            }
+        };
+        tokens.push((token, at));
+    }
+    Ok(tokens)
+}
+
+#[derive(Debug)]
+enum Values {
+    Numbers(Vec<f64>),
+    Strings(Vec<String>),
+}
+
+#[derive(Debug)]
+struct Param {
+    ty: String,
+    name: String,
+    values: Values,
+    at: Location,
+}
+
+// The "type name" value pairs after a directive. Reading a parameter marks it used, finish
+// warns about the rest.
+struct Params {
+    what: String,
+    params: Vec<Param>,
+    read: RefCell<Vec<bool>>,
+    warnings: RefCell<Vec<String>>,
+}
+
+impl Params {
+    // The parameter called name, None if missing or of another type than one of types.
+    // Types that can't be used here, like textures and spectra, are warned about.
+    fn find(&self, types: &[&str], name: &str) -> Option<&Param> {
+        let i = self.params.iter().position(|p| p.name == name)?;
+        let param = &self.params[i];
+        self.read.borrow_mut()[i] = true;
+        if types.contains(&param.ty.as_str()) {
+            return Some(param);
+        }
+        self.warnings.borrow_mut().push(format!(
+            "{}: {}: \"{} {}\" is not supported, using the default",
+            param.at, self.what, param.ty, param.name
+        ));
+        None
+    }
+
+    // The numbers of a parameter and where it is
+    fn numbers(&self, types: &[&str], name: &str) -> io::Result<Option<(&[f64], &Location)>> {
+        match self.find(types, name) {
+            Some(Param {
+                values: Values::Numbers(n),
+                at,
+                ..
+            }) => Ok(Some((n, at))),
+            Some(param) => Err(param
+                .at
+                .error(format!("expected numbers for {}", param.name))),
+            None => Ok(None),
+        }
+    }
+
+    fn float(&self, name: &str, default: f64) -> io::Result<f64> {
+        Ok(match self.numbers(&["float"], name)? {
+            Some(([value, ..], _)) => *value,
+            _ => default,
+        })
+    }
+
+    fn integer(&self, name: &str, default: i64) -> io::Result<i64> {
+        Ok(match self.numbers(&["integer"], name)? {
+            Some(([value, ..], _)) => *value as i64,
+            _ => default,
+        })
+    }
+
+    fn rgb(&self, name: &str, default: Color) -> io::Result<Color> {
+        match self.numbers(&["rgb", "color"], name)? {
+            Some(([r, g, b], _)) => Ok(Color::new(*r, *g, *b)),
+            Some((_, at)) => Err(at.error(format!("{} needs 3 numbers", name))),
+            None => Ok(default),
+        }
+    }
+
+    // Triples of numbers, e.g. "point P" or "normal N"
+    fn vec3s(&self, types: &[&str], name: &str) -> io::Result<Option<Vec<Vec3>>> {
+        let Some((numbers, at)) = self.numbers(types, name)? else {
+            return Ok(None);
+        };
+        if numbers.len() % 3 != 0 {
+            return Err(at.error(format!("{} needs a multiple of 3 numbers", name)));
+        }
+        Ok(Some(
+            numbers
+                .chunks_exact(3)
+                .map(|v| Vec3::new(v[0], v[1], v[2]))
+                .collect(),
+        ))
+    }
+
+    fn string(&self, name: &str) -> io::Result<Option<&str>> {
+        match self.find(&["string"], name) {
+            Some(Param {
+                values: Values::Strings(s),
+                ..
+            }) if s.len() == 1 => Ok(Some(&s[0])),
+            Some(param) => Err(param.at.error(format!("expected one string for {}", name))),
+            None => Ok(None),
+        }
+    }
+
+    // Mark a parameter used that has no effect here
+    fn ignore(&self, name: &str) {
+        if let Some(i) = self.params.iter().position(|p| p.name == name) {
+            self.read.borrow_mut()[i] = true;
+        }
+    }
+
+    fn finish(self, warnings: &mut Vec<String>) {
+        warnings.append(&mut self.warnings.borrow_mut());
+        for (param, read) in self.params.iter().zip(self.read.borrow().iter()) {
+            if !read {
+                warnings.push(format!(
+                    "{}: {}: ignored \"{} {}\"",
+                    param.at, self.what, param.ty, param.name
+                ));
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct GraphicsState {
+    ctm: Matrix4,
+    material: Option<Arc<dyn Material>>, // None for "none", shapes that only bound a medium
+    area_light: Option<Color>,
+}
+
+struct Parser<'a> {
+    tokens: Vec<(Token, Location)>,
+    pos: usize,
+    dir: &'a Path,
+    includes: usize,
+
+    state: GraphicsState,
+    stack: Vec<GraphicsState>,
+    named_materials: HashMap<String, Option<Arc<dyn Material>>>,
+    in_object: bool,
+
+    camera_from_world: Option<(Matrix4, Location)>,
+    fov: f64,
+    lens_radius: f64,
+    focal_distance: f64,
+    resolution: (u64, u64),
+    render: RenderSettings,
+    objects: Vec<Arc<dyn Hittable>>,
+    warnings: Vec<String>,
+}
+
+// pbrt's world is left-handed and the camera here right-handed, so the whole scene is seen
+// through a mirror flipping x to get the same image
+const MIRROR: Matrix4 = Matrix4 {
+    m: [
+        [-1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ],
+};
+
+// Nested includes beyond this are taken for an include cycle
+const MAX_INCLUDES: usize = 100;
+
+impl Parser<'_> {
+    fn next(&mut self) -> Option<(Token, Location)> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn last_location(&self) -> Location {
+        self.tokens.get(self.pos.saturating_sub(1)).map_or(
+            Location {
+                file: None,
+                line: 1,
+            },
+            |(_, at)| at.clone(),
+        )
+    }
+
+    fn number(&mut self) -> io::Result<f64> {
+        match self.next() {
+            Some((Token::Number(n), _)) => Ok(n),
+            Some((token, at)) => Err(at.error(format!("expected a number, found {}", token))),
+            None => Err(self.last_location().error("expected a number")),
+        }
+    }
+
+    fn numbers<const N: usize>(&mut self) -> io::Result<[f64; N]> {
+        let mut values = [0.0; N];
+        for value in &mut values {
+            *value = self.number()?;
+        }
+        Ok(values)
+    }
+
+    fn string(&mut self) -> io::Result<(String, Location)> {
+        match self.next() {
+            Some((Token::String(s), at)) => Ok((s, at)),
+            Some((token, at)) => Err(at.error(format!("expected a string, found {}", token))),
+            None => Err(self.last_location().error("expected a string")),
+        }
+    }
+
+    // Sixteen numbers, bracketed or not
+    fn matrix(&mut self) -> io::Result<Matrix4> {
+        let bracketed = self.peek() == Some(&Token::Open);
+        if bracketed {
+            self.pos += 1;
+        }
+        let m = Matrix4::from_column_major(&self.numbers::<16>()?);
+        if bracketed {
+            match self.next() {
+                Some((Token::Close, _)) => {}
+                _ => return Err(self.last_location().error("expected ] after 16 numbers")),
+            }
+        }
+        Ok(m)
+    }
+
+    fn params(&mut self, what: String) -> io::Result<Params> {
+        let mut params = Vec::new();
+        while let Some(Token::String(_)) = self.peek() {
+            let (declaration, at) = self.string()?;
+            let [ty, name] = declaration.split_whitespace().collect::<Vec<_>>()[..] else {
+                return Err(at.error(format!("expected \"type name\", found {}", declaration)));
+            };
+            let mut numbers = Vec::new();
+            let mut strings = Vec::new();
+            let mut value = |token: Token, at: &Location| match token {
+                Token::Number(n) => {
+                    numbers.push(n);
+                    Ok(())
+                }
+                Token::String(s) => {
+                    strings.push(s);
+                    Ok(())
+                }
+                _ => Err(at.error(format!("expected a value for {}", name))),
+            };
+            match self.next() {
+                Some((Token::Open, _)) => loop {
+                    match self.next() {
+                        Some((Token::Close, _)) => break,
+                        Some((token, at)) => value(token, &at)?,
+                        None => return Err(at.error("unterminated [")),
+                    }
+                },
+                Some((token, at)) => value(token, &at)?,
+                None => return Err(at.error(format!("expected a value for {}", name))),
+            }
+            let values = match (numbers.is_empty(), strings.is_empty()) {
+                (_, true) => Values::Numbers(numbers),
+                (true, false) => Values::Strings(strings),
+                (false, false) => return Err(at.error(format!("mixed values for {}", name))),
+            };
+            params.push(Param {
+                ty: ty.to_string(),
+                name: name.to_string(),
+                values,
+                at,
+            });
+        }
+        let read = RefCell::new(vec![false; params.len()]);
+        Ok(Params {
+            what,
+            params,
+            read,
+            warnings: RefCell::new(Vec::new()),
+        })
+    }
+
+    fn parse(&mut self) -> io::Result<()> {
+        while let Some((token, at)) = self.next() {
+            let Token::Identifier(directive) = token else {
+                return Err(at.error(format!("expected a directive, found {}", token)));
+            };
+            match directive.as_str() {
+                "LookAt" => {
+                    let [ex, ey, ez, lx, ly, lz, ux, uy, uz] = self.numbers::<9>()?;
+                    let look_at = Matrix4::look_at(
+                        Point3::new(ex, ey, ez),
+                        Point3::new(lx, ly, lz),
+                        Vec3::new(ux, uy, uz),
+                    )
+                    .ok_or_else(|| at.error("LookAt: the up vector is along the view"))?;
+                    self.state.ctm = self.state.ctm * look_at;
+                }
+                "Translate" => {
+                    let [x, y, z] = self.numbers::<3>()?;
+                    self.state.ctm = self.state.ctm * Matrix4::translation(Vec3::new(x, y, z));
+                }
+                "Scale" => {
+                    let [x, y, z] = self.numbers::<3>()?;
+                    self.state.ctm = self.state.ctm * Matrix4::scaling(Vec3::new(x, y, z));
+                }
+                "Rotate" => {
+                    let [angle, x, y, z] = self.numbers::<4>()?;
+                    self.state.ctm = self.state.ctm * Matrix4::rotation(Vec3::new(x, y, z), angle);
+                }
+                "Transform" => self.state.ctm = self.matrix()?,
+                "ConcatTransform" => self.state.ctm = self.state.ctm * self.matrix()?,
+                "Identity" => self.state.ctm = IDENTITY_MATRIX,
+                "Camera" => self.camera(at)?,
+                "Film" => {
+                    let (_, params) = self.named(&directive)?;
+                    let width = params.integer("xresolution", 640)?;
+                    let height = params.integer("yresolution", 480)?;
+                    if width < 1 || height < 1 {
+                        return Err(at.error(format!("Film: {}x{} pixels", width, height)));
+                    }
+                    self.resolution = (width as u64, height as u64);
+                    // the command line picks the output file
+                    params.ignore("filename");
+                    params.finish(&mut self.warnings);
+                }
+                "Sampler" => self.sampler(at)?,
+                "Integrator" => {
+                    let (_, params) = self.named(&directive)?;
+                    self.render.max_depth = params.integer("maxdepth", 5)?.clamp(1, 1000) as i16;
+                    params.finish(&mut self.warnings);
+                }
+                "PixelFilter" => self.pixel_filter(at)?,
+                "WorldBegin" => self.state.ctm = IDENTITY_MATRIX,
+                "WorldEnd" => {}
+                "AttributeBegin" | "TransformBegin" => self.stack.push(self.state.clone()),
+                "AttributeEnd" | "TransformEnd" => {
+                    let saved = self
+                        .stack
+                        .pop()
+                        .ok_or_else(|| at.error(format!("{} without a begin", directive)))?;
+                    if directive == "AttributeEnd" {
+                        self.state = saved;
+                    } else {
+                        self.state.ctm = saved.ctm;
+                    }
+                }
+                "ReverseOrientation" => {} // both sides of every surface scatter and emit here
+                "Material" => {
+                    let (name, params) = self.named(&directive)?;
+                    self.state.material = self.material(&name, &params)?;
+                    params.finish(&mut self.warnings);
+                }
+                "MakeNamedMaterial" => {
+                    let (name, params) = self.named(&directive)?;
+                    let kind = params.string("type")?.unwrap_or("matte").to_string();
+                    let material = self.material(&kind, &params)?;
+                    params.finish(&mut self.warnings);
+                    self.named_materials.insert(name, material);
+                }
+                "NamedMaterial" => {
+                    let (name, at) = self.string()?;
+                    self.state.material = self
+                        .named_materials
+                        .get(&name)
+                        .cloned()
+                        .ok_or_else(|| at.error(format!("undefined material \"{}\"", name)))?;
+                }
+                "AreaLightSource" => {
+                    let (name, params) = self.named(&directive)?;
+                    if name == "diffuse" {
+                        let l = params.rgb("L", Color::new(1.0, 1.0, 1.0))?;
+                        let scale = params.rgb("scale", Color::new(1.0, 1.0, 1.0))?;
+                        self.state.area_light = Some(l * scale);
+                        params.ignore("twosided"); // lights here are always two-sided
+                    } else {
+                        self.warnings
+                            .push(format!("{}: unsupported area light \"{}\"", at, name));
+                    }
+                    params.finish(&mut self.warnings);
+                }
+                "LightSource" => {
+                    let (name, params) = self.named(&directive)?;
+                    if name == "infinite" {
+                        let l = params.rgb("L", Color::new(1.0, 1.0, 1.0))?;
+                        let scale = params.rgb("scale", Color::new(1.0, 1.0, 1.0))?;
+                        self.render.background = Some(l * scale);
+                    } else {
+                        // only surfaces that are hit emit, a point has no area to hit
+                        self.warnings.push(format!(
+                            "{}: unsupported light source \"{}\", skipped",
+                            at, name
+                        ));
+                        params.read.borrow_mut().fill(true);
+                    }
+                    params.finish(&mut self.warnings);
+                }
+                "Shape" => self.shape(at)?,
+                "ObjectBegin" => {
+                    self.string()?;
+                    self.warnings.push(format!(
+                        "{}: object instancing is not supported, skipped",
+                        at
+                    ));
+                    self.in_object = true;
+                }
+                "ObjectEnd" => self.in_object = false,
+                "Include" => {
+                    let (file, at) = self.string()?;
+                    self.includes += 1;
+                    if self.includes > MAX_INCLUDES {
+                        return Err(at.error("too many includes, do they include each other?"));
+                    }
+                    let path = self.dir.join(&file);
+                    let text = fs::read_to_string(&path)
+                        .map_err(|e| at.error(format!("{}: {}", path.display(), e)))?;
+                    let included = tokenize(&text, Some(file.into()))?;
+                    self.tokens.splice(self.pos..self.pos, included);
+                }
+                _ => {
+                    self.warnings.push(format!(
+                        "{}: unsupported directive {}, skipped",
+                        at, directive
+                    ));
+                    while self
+                        .peek()
+                        .is_some_and(|t| !matches!(t, Token::Identifier(_)))
+                    {
+                        self.pos += 1;
+                    }
+                }
+            }
+        }
+        if !self.stack.is_empty() {
+            return Err(self.last_location().error("AttributeBegin without an end"));
+        }
+        Ok(())
+    }
+
+    // The quoted name after a directive and its parameters
+    fn named(&mut self, directive: &str) -> io::Result<(String, Params)> {
+        let (name, _) = self.string()?;
+        let params = self.params(format!("{} \"{}\"", directive, name))?;
+        Ok((name, params))
+    }
+
+    fn camera(&mut self, at: Location) -> io::Result<()> {
+        let (name, params) = self.named("Camera")?;
+        if name != "perspective" {
+            self.warnings.push(format!(
+                "{}: unsupported camera \"{}\", using a perspective one",
+                at, name
+            ));
+        }
+        self.fov = params.float("fov", 90.0)?;
+        self.lens_radius = params.float("lensradius", 0.0)?;
+        self.focal_distance = params.float("focaldistance", 1e6)?;
+        params.finish(&mut self.warnings);
+        self.camera_from_world = Some((self.state.ctm, at));
+        Ok(())
+    }
+
+    fn sampler(&mut self, at: Location) -> io::Result<()> {
+        let (name, params) = self.named("Sampler")?;
+        let (kind, samples) = match name.as_str() {
+            "random" => (
+                SamplerKind::Independent,
+                params.integer("pixelsamples", 16)?,
+            ),
+            "stratified" => (
+                SamplerKind::Stratified,
+                params.integer("xsamples", 4)? * params.integer("ysamples", 4)?,
+            ),
+            "halton" => (SamplerKind::Halton, params.integer("pixelsamples", 16)?),
+            "sobol" | "zerotwosequence" | "lowdiscrepancy" | "02sequence" => {
+                (SamplerKind::Sobol, params.integer("pixelsamples", 16)?)
+            }
+            _ => {
+                self.warnings.push(format!(
+                    "{}: unsupported sampler \"{}\", using sobol",
+                    at, name
+                ));
+                (SamplerKind::Sobol, params.integer("pixelsamples", 16)?)
+            }
+        };
+        self.render.sampler = kind;
+        self.render.samples_per_pixel = samples.clamp(1, u16::MAX as i64) as u16;
+        params.finish(&mut self.warnings);
+        Ok(())
+    }
+
+    fn pixel_filter(&mut self, at: Location) -> io::Result<()> {
+        let (name, params) = self.named("PixelFilter")?;
+        let width = |default| -> io::Result<f64> {
+            let x = params.float("xwidth", default)?;
+            let y = params.float("ywidth", default)?;
+            Ok(x.max(y))
+        };
+        self.render.filter = match name.as_str() {
+            "box" => Filter {
+                kind: FilterKind::Box,
+                radius: width(0.5)?,
+            },
+            "triangle" => Filter {
+                kind: FilterKind::Tent,
+                radius: width(2.0)?,
+            },
+            // pbrt weighs exp(-alpha x^2)
+            "gaussian" => Filter {
+                kind: FilterKind::Gaussian {
+                    sigma: (0.5 / params.float("alpha", 2.0)?).sqrt(),
+                },
+                radius: width(2.0)?,
+            },
+            "mitchell" => Filter {
+                kind: FilterKind::Mitchell {
+                    b: params.float("B", 1.0 / 3.0)?,
+                    c: params.float("C", 1.0 / 3.0)?,
+                },
+                radius: width(2.0)?,
+            },
+            "sinc" => Filter {
+                kind: FilterKind::Lanczos {
+                    tau: params.float("tau", 3.0)?,
+                },
+                radius: width(4.0)?,
+            },
+            _ => {
+                self.warnings
+                    .push(format!("{}: unsupported filter \"{}\"", at, name));
+                self.render.filter
+            }
+        };
+        params.finish(&mut self.warnings);
+        Ok(())
+    }
+
+    // pbrt's materials approximated by the ones here: plastic loses its highlight and metal
+    // keeps only its color at normal incidence
+    fn material(&mut self, name: &str, params: &Params) -> io::Result<Option<Arc<dyn Material>>> {
+        Ok(Some(match name {
+            "matte" => Arc::new(Lambertian::new(
+                params.rgb("Kd", Color::new(0.5, 0.5, 0.5))?,
+            )),
+            "plastic" => Arc::new(Lambertian::new(
+                params.rgb("Kd", Color::new(0.25, 0.25, 0.25))?,
+            )),
+            "metal" => {
+                // copper by default
+                let eta = params.rgb("eta", Color::new(0.2004, 0.9240, 1.1022))?;
+                let k = params.rgb("k", Color::new(3.9129, 2.4528, 2.1422))?;
+                let reflectance = |eta: f64, k: f64| {
+                    ((eta - 1.0).powi(2) + k * k) / ((eta + 1.0).powi(2) + k * k)
+                };
+                Arc::new(Metal {
+                    albedo: Color::new(
+                        reflectance(eta.x, k.x),
+                        reflectance(eta.y, k.y),
+                        reflectance(eta.z, k.z),
+                    ),
+                    fuzz: params.float("roughness", 0.01)?.clamp(0.0, 1.0),
+                })
+            }
+            "mirror" => Arc::new(Metal {
+                albedo: params.rgb("Kr", Color::new(0.9, 0.9, 0.9))?,
+                fuzz: 0.0,
+            }),
+            "glass" => Arc::new(Dielectric {
+                refraction_index: params.float("index", params.float("eta", 1.5)?)?,
+            }),
+            "" | "none" => return Ok(None),
+            _ => {
+                self.warnings.push(format!(
+                    "{}: unsupported material \"{}\", using matte",
+                    self.last_location(),
+                    name
+                ));
+                params.read.borrow_mut().fill(true);
+                Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+            }
+        }))
+    }
+
+    fn shape(&mut self, at: Location) -> io::Result<()> {
+        let (name, params) = self.named("Shape")?;
+        if self.in_object {
+            params.read.borrow_mut().fill(true);
+            return Ok(());
+        }
+        let material: Arc<dyn Material> = match (self.state.area_light, &self.state.material) {
+            (Some(l), _) => Arc::new(DiffuseLight::new(l)),
+            (None, Some(material)) => Arc::clone(material),
+            // the boundary of a medium, which isn't supported, rather than a surface
+            (None, None) => {
+                self.warnings.push(format!(
+                    "{}: shape \"{}\" with material \"none\", skipped",
+                    at, name
+                ));
+                params.read.borrow_mut().fill(true);
+                return Ok(());
+            }
+        };
+        let transform = MIRROR * self.state.ctm;
+
+        match name.as_str() {
+            "sphere" => {
+                let radius = params.float("radius", 1.0)?;
+                // a sphere stays one under uniform scales only, others take their average
+                let scale = transform.determinant3().abs().cbrt();
+                self.objects.push(Arc::new(Sphere::new_static(
+                    transform.transform_point(Point3::default()),
+                    radius * scale,
+                    material,
+                )));
+            }
+            "trianglemesh" => {
+                let mut mesh = TriangleMesh {
+                    positions: params
+                        .vec3s(&["point", "point3"], "P")?
+                        .ok_or_else(|| at.error("trianglemesh without \"point P\""))?,
+                    normals: params
+                        .vec3s(&["normal", "normal3"], "N")?
+                        .unwrap_or_default(),
+                    ..Default::default()
+                };
+                match params.numbers(&["integer"], "indices")? {
+                    Some((indices, _)) => {
+                        if indices.len() % 3 != 0 || indices.iter().any(|i| *i < 0.0) {
+                            return Err(at.error("trianglemesh indices must be triples"));
+                        }
+                        for tri in indices.chunks_exact(3) {
+                            mesh.triangles.push([
+                                tri[0] as usize,
+                                tri[1] as usize,
+                                tri[2] as usize,
+                            ]);
+                        }
+                    }
+                    None if mesh.positions.len() == 3 => mesh.triangles.push([0, 1, 2]),
+                    None => return Err(at.error("trianglemesh without \"integer indices\"")),
+                }
+                mesh.validate().map_err(|e| at.error(e.to_string()))?;
+                self.add_mesh(mesh, &transform, material);
+            }
+            "plymesh" => {
+                let file = params
+                    .string("filename")?
+                    .ok_or_else(|| at.error("plymesh without \"string filename\""))?;
+                let path = self.dir.join(file);
+                let mesh = TriangleMesh::load_ply(&path.to_string_lossy())
+                    .map_err(|e| at.error(e.to_string()))?;
+                self.add_mesh(mesh, &transform, material);
+            }
+            _ => {
+                self.warnings
+                    .push(format!("{}: unsupported shape \"{}\", skipped", at, name));
+                params.read.borrow_mut().fill(true);
+            }
+        }
+        params.finish(&mut self.warnings);
+        Ok(())
+    }
+
+    fn add_mesh(
+        &mut self,
+        mut mesh: TriangleMesh,
+        transform: &Matrix4,
+        material: Arc<dyn Material>,
+    ) {
+        mesh.transform_by(transform);
+        self.objects.extend(mesh.build(material).objects);
+    }
+
+    // The camera settings once Camera and Film are both read
+    fn camera_settings(&mut self) -> io::Result<CameraSettings> {
+        let mut camera = CameraSettings::default();
+        let (width, height) = self.resolution;
+        camera.set_resolution(width, height);
+
+        // fov spans the shorter side of the image
+        camera.vfov = if width >= height {
+            self.fov
+        } else {
+            let half = (0.5 * self.fov).to_radians().tan() * height as f64 / width as f64;
+            2.0 * half.atan().to_degrees()
+        };
+
+        let (camera_from_world, at) = match &self.camera_from_world {
+            Some((m, at)) => (*m, at.clone()),
+            None => {
+                self.warnings
+                    .push("no Camera, looking down +z from the origin".to_string());
+                (
+                    IDENTITY_MATRIX,
+                    Location {
+                        file: None,
+                        line: 1,
+                    },
+                )
+            }
+        };
+        let world_from_camera = MIRROR
+            * camera_from_world
+                .inverse()
+                .ok_or_else(|| at.error("the camera transform is singular"))?;
+        camera.lookfrom = world_from_camera.transform_point(Point3::default());
+        camera.lookat = camera.lookfrom
+            + world_from_camera
+                .transform_vector(Vec3::new(0.0, 0.0, 1.0))
+                .unit_vector();
+        camera.vup = world_from_camera.transform_vector(Vec3::new(0.0, 1.0, 0.0));
+        if self.lens_radius > 0.0 {
+            camera.focus_dist = self.focal_distance;
+            camera.defocus_angle =
+                2.0 * (self.lens_radius / self.focal_distance).atan().to_degrees();
+        }
+        Ok(camera)
+    }
+}
+
+impl ImportedScene {
+    pub fn load_pbrt(file_name: &str) -> io::Result<ImportedScene> {
+        let text = fs::read_to_string(file_name)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", file_name, e)))?;
+        let dir = Path::new(file_name).parent().unwrap_or(Path::new(""));
+        ImportedScene::parse_pbrt(&text, dir)
+            .map_err(|e| invalid_data(format!("{}: {}", file_name, e)))
+    }
+
+    // A subset of the pbrt-v3 scene format, with Include and plymesh files looked up in dir:
+    // the camera, film, transforms, attributes, spheres and triangle meshes, the common
+    // materials, area lights and an infinite light as background. Anything else is skipped
+    // with a warning.
+    pub fn parse_pbrt(text: &str, dir: &Path) -> io::Result<ImportedScene> {
+        let default_material: Arc<dyn Material> =
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut parser = Parser {
+            tokens: tokenize(text, None)?,
+            pos: 0,
+            dir,
+            includes: 0,
+            state: GraphicsState {
+                ctm: IDENTITY_MATRIX,
+                material: Some(default_material),
+                area_light: None,
+            },
+            stack: Vec::new(),
+            named_materials: HashMap::new(),
+            in_object: false,
+            camera_from_world: None,
+            fov: 90.0,
+            lens_radius: 0.0,
+            focal_distance: 1e6,
+            resolution: (640, 480),
+            // without an infinite light nothing lights the background
+            render: RenderSettings {
+                background: Some(Color::default()),
+                ..Default::default()
+            },
+            objects: Vec::new(),
+            warnings: Vec::new(),
+        };
+        parser.parse()?;
+        let camera = parser.camera_settings()?;
+
+        let world = if parser.objects.is_empty() {
+            Hittable_List::new()
+        } else {
+            let len = parser.objects.len();
+            let bvh = BVH_Node::new_from_objects(&mut parser.objects, 0, len);
+            Hittable_List::new_from_hittable(Arc::new(bvh))
+        };
+        Ok(ImportedScene {
+            camera,
+            render: parser.render,
+            world,
+            warnings: parser.warnings,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{interval::Interval, ray::Ray};
+
+    const SCENE: &str = r#"
+# a sphere on a lit quad
+LookAt 0 0 -5  0 0 0  0 1 0
+Camera "perspective" "float fov" [ 30 ]
+Film "image" "integer xresolution" [ 200 ] "integer yresolution" 400 "string filename" "x.exr"
+Sampler "halton" "integer pixelsamples" 64
+WorldBegin
+LightSource "infinite" "rgb L" [ .1 .2 .3 ]
+AttributeBegin
+  Translate 1 0 0
+  Material "metal" "float roughness" 0.5
+  Shape "sphere" "float radius" 0.5 "float zmax" 0.2
+AttributeEnd
+AttributeBegin
+  AreaLightSource "diffuse" "rgb L" [ 4 4 4 ]
+  Shape "trianglemesh" "integer indices" [ 0 1 2 0 2 3 ]
+    "point P" [ -1 -1 2  1 -1 2  1 1 2  -1 1 2 ]
+AttributeEnd
+Volume "smoke"
+WorldEnd
+"#;
+
+    fn hit(scene: &ImportedScene, origin: Point3, dir: Vec3) -> Option<f64> {
+        let ray = Ray {
+            origin,
+            dir,
+            time: 0.0,
+            medium_sample: 0.0,
+        };
+        let interval = Interval {
+            min: 0.001,
+            max: f64::INFINITY,
+        };
+        scene.world.hit(&ray, interval).map(|rec| rec.t)
+    }
+
+    #[test]
+    fn reads_camera_shapes_and_lights() {
+        let scene = ImportedScene::parse_pbrt(SCENE, Path::new("")).unwrap();
+        assert_eq!(
+            scene.warnings,
+            vec![
+                "line 12: Shape \"sphere\": ignored \"float zmax\"",
+                "line 19: unsupported directive Volume, skipped",
+            ]
+        );
+
+        let camera = scene.camera;
+        assert!((camera.lookfrom - Point3::new(0.0, 0.0, -5.0)).length() < 1e-12);
+        assert!((camera.lookat - Point3::new(0.0, 0.0, -4.0)).length() < 1e-12);
+        // a portrait image, the fov is the horizontal one
+        let vfov = 2.0 * (15f64.to_radians().tan() * 2.0).atan().to_degrees();
+        assert!((camera.vfov - vfov).abs() < 1e-9);
+        assert_eq!(scene.render.samples_per_pixel, 64);
+        assert_eq!(scene.render.sampler, SamplerKind::Halton);
+        assert_eq!(scene.render.background.unwrap().z, 0.3);
+
+        // pbrt's +x is on the right looking down +z, here the mirror puts it at -x
+        let down_z = Vec3::new(0.0, 0.0, 1.0);
+        assert!((hit(&scene, Point3::new(-1.0, 0.0, -5.0), down_z).unwrap() - 4.5).abs() < 1e-9);
+        assert!((hit(&scene, Point3::new(0.5, 0.5, -5.0), down_z).unwrap() - 7.0).abs() < 1e-9);
+        assert!(hit(&scene, Point3::new(0.9, 0.0, -5.0), down_z).is_some_and(|t| t > 6.0));
+    }
+
+    #[test]
+    fn skips_shapes_without_material() {
+        let text = r#"
+WorldBegin
+Material "none"
+Shape "sphere"
+MakeNamedMaterial "interface" "string type" ""
+NamedMaterial "interface"
+Shape "trianglemesh" "point P" [ -1 -1 2  1 -1 2  1 1 2 ]
+AttributeBegin
+  AreaLightSource "diffuse" "rgb L" [ 4 4 4 ]
+  Shape "sphere" "float radius" 0.5
+AttributeEnd
+"#;
+        let scene = ImportedScene::parse_pbrt(text, Path::new("")).unwrap();
+        assert_eq!(
+            scene.warnings,
+            vec![
+                "line 4: shape \"sphere\" with material \"none\", skipped",
+                "line 7: shape \"trianglemesh\" with material \"none\", skipped",
+                "no Camera, looking down +z from the origin",
+            ]
+        );
+        // only the light is left, inside the skipped unit sphere
+        let t = hit(
+            &scene,
+            Point3::new(0.0, 0.0, -5.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        assert!(t.is_some_and(|t| (t - 4.5).abs() < 1e-9));
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let err = |text: &str| {
+            ImportedScene::parse_pbrt(text, Path::new(""))
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            err("WorldBegin\nAttributeEnd\n"),
+            "line 2: AttributeEnd without a begin"
+        );
+        assert_eq!(
+            err(
+                "Shape \"trianglemesh\"\n \"point P\" [0 0 0 1 0 0 0 1 0] \"integer indices\" [0 1 3]"
+            ),
+            "line 1: triangle 0 uses vertex 3 of 3"
+        );
+        assert_eq!(err("Translate 1 x 2"), "line 1: expected a number, found x");
+        assert_eq!(
+            err("NamedMaterial \"gold\""),
+            "line 1: undefined material \"gold\""
+        );
+    }
+}
@@ -0,0 +1,134 @@
+use rand::{Rng, SeedableRng, rngs::SmallRng};
+
+use crate::{
+    checkpoint::Fingerprint,
+    vec3::{Point3, Vec3, dot},
+};
+
+const POINT_COUNT: usize = 256;
+
+// Perlin gradient noise: random unit vectors on a lattice, hashed by permuting each integer
+// coordinate, with the dot products against them smoothly interpolated in between. The seed
+// picks the pattern.
+pub struct Perlin {
+    randvec: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = SmallRng::seed_from_u64(seed);
+        let mut component = || rng.random_range(-1.0..1.0);
+        let randvec = (0..POINT_COUNT)
+            .map(|_| Vec3::new(component(), component(), component()).unit_vector())
+            .collect();
+        Perlin {
+            randvec,
+            perm_x: Perlin::generate_perm(&mut rng),
+            perm_y: Perlin::generate_perm(&mut rng),
+            perm_z: Perlin::generate_perm(&mut rng),
+        }
+    }
+
+    // Shuffled 0..POINT_COUNT, Fisher-Yates from the back
+    fn generate_perm(rng: &mut SmallRng) -> Vec<usize> {
+        let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+        for i in (1..POINT_COUNT).rev() {
+            let target = rng.random_range(0..=i);
+            p.swap(i, target);
+        }
+        p
+    }
+
+    // In [-1, 1], zero on the lattice points
+    pub fn noise(&self, p: Point3) -> f64 {
+        let u = p.x - p.x.floor();
+        let v = p.y - p.y.floor();
+        let w = p.z - p.z.floor();
+        let i = p.x.floor() as i64;
+        let j = p.y.floor() as i64;
+        let k = p.z.floor() as i64;
+
+        let mut c = [[[Vec3::default(); 2]; 2]; 2];
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    *corner = self.randvec[self.perm_x[((i + di as i64) & 255) as usize]
+                        ^ self.perm_y[((j + dj as i64) & 255) as usize]
+                        ^ self.perm_z[((k + dk as i64) & 255) as usize]];
+                }
+            }
+        }
+        Perlin::perlin_interp(&c, u, v, w)
+    }
+
+    // Trilinear interpolation of the corner gradients with Hermite smoothing against the
+    // Mach bands of a linear blend
+    fn perlin_interp(c: &[[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
+        let uu = u * u * (3.0 - 2.0 * u);
+        let vv = v * v * (3.0 - 2.0 * v);
+        let ww = w * w * (3.0 - 2.0 * w);
+
+        let mut accum = 0.0;
+        for (i, plane) in c.iter().enumerate() {
+            for (j, row) in plane.iter().enumerate() {
+                for (k, corner) in row.iter().enumerate() {
+                    let (fi, fj, fk) = (i as f64, j as f64, k as f64);
+                    let weight = Vec3::new(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * dot(*corner, weight);
+                }
+            }
+        }
+        accum
+    }
+
+    // The lattice gradients and permutations
+    pub fn fingerprint(&self, f: &mut Fingerprint) {
+        self.randvec.iter().for_each(|v| f.vec3(*v));
+        let perms = [&self.perm_x, &self.perm_y, &self.perm_z];
+        perms
+            .iter()
+            .flat_map(|p| p.iter())
+            .for_each(|i| f.add(*i as u64));
+    }
+
+    // Sum of depth octaves of noise, each at twice the frequency and half the weight
+    pub fn turb(&self, p: Point3, depth: u32) -> f64 {
+        let mut accum = 0.0;
+        let mut temp_p = p;
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accum += weight * self.noise(temp_p);
+            weight *= 0.5;
+            temp_p *= 2.0;
+        }
+        accum.abs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise_vanishes_on_the_lattice_and_varies_smoothly() {
+        let perlin = Perlin::new(0);
+        assert_eq!(perlin.noise(Point3::new(3.0, -2.0, 5.0)), 0.0);
+        let p = Point3::new(0.3, 1.7, -2.2);
+        let step = Vec3::new(1e-6, 1e-6, 1e-6);
+        assert!((perlin.noise(p) - perlin.noise(p + step)).abs() < 1e-4);
+        for i in 0..100 {
+            let n = perlin.noise(Point3::new(
+                0.37 * i as f64,
+                0.11 * i as f64,
+                -0.23 * i as f64,
+            ));
+            assert!((-1.0..=1.0).contains(&n));
+        }
+    }
+}
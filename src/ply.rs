@@ -0,0 +1,422 @@
+use std::{fs, io};
+
+use crate::{
+    mesh::TriangleMesh,
+    utils::invalid_data,
+    vec3::{Color, Point3, Vec3},
+};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn from_name(name: &str) -> Option<Scalar> {
+        match name {
+            "char" | "int8" => Some(Scalar::I8),
+            "uchar" | "uint8" => Some(Scalar::U8),
+            "short" | "int16" => Some(Scalar::I16),
+            "ushort" | "uint16" => Some(Scalar::U16),
+            "int" | "int32" => Some(Scalar::I32),
+            "uint" | "uint32" => Some(Scalar::U32),
+            "float" | "float32" => Some(Scalar::F32),
+            "double" | "float64" => Some(Scalar::F64),
+            _ => None,
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    // Color channels stored as integers span the whole range of their type
+    fn color_scale(self) -> f64 {
+        match self {
+            Scalar::I8 => 127.0,
+            Scalar::U8 => 255.0,
+            Scalar::I16 => 32767.0,
+            Scalar::U16 => 65535.0,
+            Scalar::I32 => 2147483647.0,
+            Scalar::U32 => 4294967295.0,
+            Scalar::F32 | Scalar::F64 => 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Property {
+    Scalar(String, Scalar),
+    List(String, Scalar, Scalar), // name, count type, item type
+}
+
+impl Property {
+    fn name(&self) -> &str {
+        match self {
+            Property::Scalar(name, _) | Property::List(name, _, _) => name,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+// Reads the values of the body one at a time, whatever the format
+struct Body<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    format: Format,
+}
+
+impl Body<'_> {
+    fn value(&mut self, ty: Scalar) -> io::Result<f64> {
+        if self.format == Format::Ascii {
+            while self.pos < self.bytes.len() && self.bytes[self.pos].is_ascii_whitespace() {
+                self.pos += 1;
+            }
+            let start = self.pos;
+            while self.pos < self.bytes.len() && !self.bytes[self.pos].is_ascii_whitespace() {
+                self.pos += 1;
+            }
+            if start == self.pos {
+                return Err(invalid_data("unexpected end of data".to_string()));
+            }
+            let token = String::from_utf8_lossy(&self.bytes[start..self.pos]);
+            return token
+                .parse()
+                .map_err(|_| invalid_data(format!("expected a number, got {:?}", token)));
+        }
+
+        let size = ty.size();
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + size)
+            .ok_or_else(|| invalid_data("unexpected end of data".to_string()))?;
+        self.pos += size;
+        let mut b = [0u8; 8];
+        b[..size].copy_from_slice(bytes);
+        if self.format == Format::BinaryBigEndian {
+            b[..size].reverse();
+        }
+        Ok(match ty {
+            Scalar::I8 => b[0] as i8 as f64,
+            Scalar::U8 => b[0] as f64,
+            Scalar::I16 => i16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::U16 => u16::from_le_bytes([b[0], b[1]]) as f64,
+            Scalar::I32 => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::U32 => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            Scalar::F64 => f64::from_le_bytes(b),
+        })
+    }
+
+    // Every value of one property, a list as its items
+    fn property(&mut self, property: &Property, out: &mut Vec<f64>) -> io::Result<()> {
+        out.clear();
+        match property {
+            Property::Scalar(_, ty) => out.push(self.value(*ty)?),
+            Property::List(_, count_ty, item_ty) => {
+                let count = self.value(*count_ty)?;
+                if count < 0.0 || count.fract() != 0.0 {
+                    return Err(invalid_data(format!("invalid list length {}", count)));
+                }
+                for _ in 0..count as usize {
+                    out.push(self.value(*item_ty)?);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Header up to end_header, and the offset of the body after it
+fn parse_header(bytes: &[u8]) -> io::Result<(Format, Vec<Element>, usize)> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut pos = 0;
+    let mut line_number = 0;
+
+    loop {
+        let end = bytes[pos..]
+            .iter()
+            .position(|b| *b == b'\n')
+            .map(|i| pos + i)
+            .ok_or_else(|| invalid_data("missing end_header".to_string()))?;
+        let line = String::from_utf8_lossy(&bytes[pos..end]);
+        pos = end + 1;
+        line_number += 1;
+        let error = |msg: &str| invalid_data(format!("header line {}: {}", line_number, msg));
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["ply"] if line_number == 1 => {}
+            _ if line_number == 1 => return Err(error("not a PLY file")),
+            ["format", name, "1.0"] => {
+                format = Some(match *name {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(error(&format!("unknown format {}", name))),
+                });
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            ["element", name, count] => {
+                let count = count
+                    .parse()
+                    .map_err(|_| error(&format!("invalid element count {}", count)))?;
+                elements.push(Element {
+                    name: name.to_string(),
+                    count,
+                    properties: Vec::new(),
+                });
+            }
+            ["property", "list", count_ty, item_ty, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| error("property before any element"))?;
+                let count_ty = Scalar::from_name(count_ty)
+                    .ok_or_else(|| error(&format!("unknown type {}", count_ty)))?;
+                let item_ty = Scalar::from_name(item_ty)
+                    .ok_or_else(|| error(&format!("unknown type {}", item_ty)))?;
+                element
+                    .properties
+                    .push(Property::List(name.to_string(), count_ty, item_ty));
+            }
+            ["property", ty, name] => {
+                let element = elements
+                    .last_mut()
+                    .ok_or_else(|| error("property before any element"))?;
+                let ty =
+                    Scalar::from_name(ty).ok_or_else(|| error(&format!("unknown type {}", ty)))?;
+                element
+                    .properties
+                    .push(Property::Scalar(name.to_string(), ty));
+            }
+            ["end_header"] => break,
+            _ => return Err(error(&format!("unexpected {:?}", line.trim()))),
+        }
+    }
+
+    let format = format.ok_or_else(|| invalid_data("missing format line".to_string()))?;
+    Ok((format, elements, pos))
+}
+
+impl TriangleMesh {
+    pub fn load_ply(file_name: &str) -> io::Result<TriangleMesh> {
+        let bytes = fs::read(file_name)?;
+        TriangleMesh::parse_ply(&bytes).map_err(|e| invalid_data(format!("{}: {}", file_name, e)))
+    }
+
+    // Stanford PLY in ASCII or binary of either endianness. Reads the vertex positions,
+    // normals (nx, ny, nz) and colors (red, green, blue) and the faces' vertex_indices,
+    // polygons are split into triangles. Other elements and properties are skipped.
+    pub fn parse_ply(bytes: &[u8]) -> io::Result<TriangleMesh> {
+        let (format, elements, body_start) = parse_header(bytes)?;
+        let mut body = Body {
+            bytes,
+            pos: body_start,
+            format,
+        };
+
+        let mut mesh = TriangleMesh::default();
+        let mut values = Vec::new();
+        for element in &elements {
+            let find = |name: &str| element.properties.iter().position(|p| p.name() == name);
+            match element.name.as_str() {
+                "vertex" => {
+                    let [Some(x), Some(y), Some(z)] = ["x", "y", "z"].map(find) else {
+                        return Err(invalid_data("vertex without x, y and z".to_string()));
+                    };
+                    let normal = match ["nx", "ny", "nz"].map(find) {
+                        [Some(x), Some(y), Some(z)] => Some([x, y, z]),
+                        _ => None,
+                    };
+                    let color = match ["red", "green", "blue"].map(find) {
+                        [Some(r), Some(g), Some(b)] => Some([r, g, b]),
+                        _ => None,
+                    };
+
+                    for i in 0..element.count {
+                        let mut v = [0.0; 3];
+                        let mut n = [0.0; 3];
+                        let mut c = [0.0; 3];
+                        for (p, property) in element.properties.iter().enumerate() {
+                            body.property(property, &mut values)
+                                .map_err(|e| invalid_data(format!("vertex {}: {}", i, e)))?;
+                            let value = values.first().copied().unwrap_or(0.0);
+                            for axis in 0..3 {
+                                if p == [x, y, z][axis] {
+                                    v[axis] = value;
+                                }
+                                if normal.is_some_and(|n| n[axis] == p) {
+                                    n[axis] = value;
+                                }
+                                if let Some(color) = color
+                                    && color[axis] == p
+                                    && let Property::Scalar(_, ty) = property
+                                {
+                                    // 8-bit colors are gamma encoded, undo the output's gamma 2
+                                    let encoded = value / ty.color_scale();
+                                    c[axis] = encoded * encoded;
+                                }
+                            }
+                        }
+                        mesh.positions.push(Point3::new(v[0], v[1], v[2]));
+                        if normal.is_some() {
+                            mesh.normals.push(Vec3::new(n[0], n[1], n[2]));
+                        }
+                        if color.is_some() {
+                            mesh.colors.push(Color::new(c[0], c[1], c[2]));
+                        }
+                    }
+                }
+                "face" => {
+                    let indices = find("vertex_indices")
+                        .or_else(|| find("vertex_index"))
+                        .ok_or_else(|| invalid_data("face without vertex_indices".to_string()))?;
+                    let mut polygon = Vec::new();
+                    for i in 0..element.count {
+                        for (p, property) in element.properties.iter().enumerate() {
+                            body.property(property, &mut values)
+                                .map_err(|e| invalid_data(format!("face {}: {}", i, e)))?;
+                            if p == indices {
+                                polygon.clear();
+                                for index in &values {
+                                    if *index < 0.0 || index.fract() != 0.0 {
+                                        return Err(invalid_data(format!(
+                                            "face {}: invalid vertex index {}",
+                                            i, index
+                                        )));
+                                    }
+                                    polygon.push(*index as usize);
+                                }
+                            }
+                        }
+                        mesh.add_polygon(&polygon);
+                    }
+                }
+                _ => {
+                    for _ in 0..element.count {
+                        for property in &element.properties {
+                            body.property(property, &mut values)?;
+                        }
+                    }
+                }
+            }
+        }
+
+        mesh.validate()?;
+        Ok(mesh)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+";
+
+    #[test]
+    fn parses_ascii_with_colors_and_quads() {
+        let text = format!(
+            "ply\nformat ascii 1.0\ncomment made by hand\n{}\
+             0 0 0 255 0 0\n1 0 0 0 255 0\n1 1 0 0 0 255\n0 1 0 255 255 255\n4 0 1 2 3\n",
+            HEADER
+        );
+        let mesh = TriangleMesh::parse_ply(text.as_bytes()).unwrap();
+        assert_eq!(mesh.positions.len(), 4);
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(mesh.colors[1].y, 1.0);
+        assert!(mesh.normals.is_empty());
+    }
+
+    #[test]
+    fn binary_endianness_matches_ascii() {
+        let vertices: [[f32; 3]; 4] = [
+            [0.0, 0.0, 0.0],
+            [1.0, 0.0, 0.0],
+            [1.0, 1.0, 0.5],
+            [0.0, 1.0, 0.0],
+        ];
+        for (name, big) in [("binary_little_endian", false), ("binary_big_endian", true)] {
+            let mut bytes = format!("ply\nformat {} 1.0\n{}", name, HEADER).into_bytes();
+            for v in vertices {
+                for c in v {
+                    bytes.extend(if big {
+                        c.to_be_bytes()
+                    } else {
+                        c.to_le_bytes()
+                    });
+                }
+                bytes.extend([51, 102, 255]);
+            }
+            bytes.push(3);
+            for i in [0i32, 2, 3] {
+                bytes.extend(if big {
+                    i.to_be_bytes()
+                } else {
+                    i.to_le_bytes()
+                });
+            }
+
+            let mesh = TriangleMesh::parse_ply(&bytes).unwrap();
+            assert_eq!(mesh.positions[2].z, 0.5);
+            assert_eq!(mesh.triangles, vec![[0, 2, 3]]);
+            assert!((mesh.colors[0].x - 0.04).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn reports_broken_files() {
+        let err = |bytes: &[u8]| TriangleMesh::parse_ply(bytes).unwrap_err().to_string();
+        assert_eq!(err(b"ply\nformat ascii 1.0\n"), "missing end_header");
+        assert_eq!(
+            err(b"ply\nformat ascii 1.0\nproperty float x\nend_header\n"),
+            "header line 3: property before any element"
+        );
+        let truncated = format!("ply\nformat ascii 1.0\n{}0 0 0 1 1 1\n", HEADER);
+        assert_eq!(
+            err(truncated.as_bytes()),
+            "vertex 1: unexpected end of data"
+        );
+        let bad_index = format!(
+            "ply\nformat ascii 1.0\n{}{}3 0 1 7\n",
+            HEADER,
+            "0 0 0 0 0 0\n".repeat(4)
+        );
+        assert_eq!(err(bad_index.as_bytes()), "triangle 0 uses vertex 7 of 4");
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+// Unit of work a render just finished
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Stage {
+    // Fixed sampling, all samples of one scanline
+    Scanline {
+        remaining: u64,
+    },
+    // Progressive or adaptive sampling, passes is None for adaptive sampling which stops once
+    // no pixel is active anymore
+    Pass {
+        pass: u64,
+        passes: Option<u64>,
+        active_pixels: u64,
+    },
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Progress {
+    pub stage: Stage,
+    pub samples: u64,       // camera samples in the film, including resumed ones
+    pub start_samples: u64, // samples already in the film when the render started
+    // samples in the finished film, the max_samples budget for adaptive sampling
+    pub total_samples: u64,
+    pub rays: u64, // camera and scattered rays traced by this render
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    pub fn fraction(&self) -> f64 {
+        if self.total_samples == 0 {
+            return 1.0;
+        }
+        (self.samples as f64 / self.total_samples as f64).min(1.0)
+    }
+
+    pub fn percent(&self) -> f64 {
+        100.0 * self.fraction()
+    }
+
+    // Time left at the sampling rate so far, None before the first sample. Overestimates for
+    // adaptive sampling, most pixels converge well before the budget.
+    pub fn eta(&self) -> Option<Duration> {
+        let taken = self.samples.saturating_sub(self.start_samples);
+        if taken == 0 {
+            return None;
+        }
+        let remaining = self.total_samples.saturating_sub(self.samples);
+        Some(self.elapsed.mul_f64(remaining as f64 / taken as f64))
+    }
+
+    pub fn rays_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds <= 0.0 {
+            return 0.0;
+        }
+        self.rays as f64 / seconds
+    }
+}
+
+pub trait ProgressReporter: Send {
+    fn update(&mut self, progress: &Progress);
+    fn finish(&mut self, _progress: &Progress, _cancelled: bool) {}
+    // A note or warning of the render, like resuming from a checkpoint or a failed snapshot
+    fn message(&mut self, _text: &str) {}
+}
+
+// Overwrites a single status line on stderr
+pub struct StderrReporter;
+
+impl ProgressReporter for StderrReporter {
+    fn update(&mut self, progress: &Progress) {
+        match progress.stage {
+            Stage::Scanline { remaining } => eprint!("\rScanlines remaining: {} ", remaining),
+            Stage::Pass {
+                pass,
+                passes: Some(passes),
+                ..
+            } => eprint!("\rPass {}/{} ", pass, passes),
+            Stage::Pass {
+                pass,
+                passes: None,
+                active_pixels,
+            } => eprint!("\rPass {}: {} pixels sampled   ", pass, active_pixels),
+        }
+    }
+
+    fn finish(&mut self, _progress: &Progress, cancelled: bool) {
+        if cancelled {
+            eprintln!("\rCancelled.                     ");
+        } else {
+            eprintln!("\rDone.                          ");
+        }
+    }
+
+    fn message(&mut self, text: &str) {
+        eprintln!("\r{}", text);
+    }
+}
+
+// Reports nothing
+pub struct SilentReporter;
+
+impl ProgressReporter for SilentReporter {
+    fn update(&mut self, _progress: &Progress) {}
+}
+
+// Shared flag asking a render to stop, the render checks it between scanlines and returns
+// the samples taken so far. Clones cancel the same render, from any thread.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+// Counts the work of one render and forwards it to the reporter
+pub(crate) struct ProgressTracker {
+    reporter: Arc<Mutex<dyn ProgressReporter>>,
+    start: Instant,
+    progress: Progress,
+}
+
+impl ProgressTracker {
+    pub(crate) fn new(
+        reporter: Arc<Mutex<dyn ProgressReporter>>,
+        start_samples: u64,
+        total_samples: u64,
+    ) -> Self {
+        ProgressTracker {
+            reporter,
+            start: Instant::now(),
+            progress: Progress {
+                stage: Stage::Scanline { remaining: 0 },
+                samples: start_samples,
+                start_samples,
+                total_samples,
+                rays: 0,
+                elapsed: Duration::ZERO,
+            },
+        }
+    }
+
+    pub(crate) fn add_sample(&mut self, rays: u64) {
+        self.progress.samples += 1;
+        self.progress.rays += rays;
+    }
+
+    pub(crate) fn report(&mut self, stage: Stage) {
+        self.progress.stage = stage;
+        self.progress.elapsed = self.start.elapsed();
+        self.reporter.lock().unwrap().update(&self.progress);
+    }
+
+    pub(crate) fn finish(&mut self, cancelled: bool) {
+        self.progress.elapsed = self.start.elapsed();
+        self.reporter
+            .lock()
+            .unwrap()
+            .finish(&self.progress, cancelled);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eta_extrapolates_the_rate_of_this_run() {
+        let progress = Progress {
+            stage: Stage::Scanline { remaining: 1 },
+            samples: 60,
+            start_samples: 20,
+            total_samples: 100,
+            rays: 500,
+            elapsed: Duration::from_secs(4),
+        };
+        assert_eq!(progress.percent(), 60.0);
+        // 40 samples in 4 seconds, 40 to go
+        assert_eq!(progress.eta(), Some(Duration::from_secs(4)));
+        assert_eq!(progress.rays_per_second(), 125.0);
+    }
+
+    #[test]
+    fn cancel_is_shared_by_clones() {
+        let token = CancelToken::new();
+        let clone = token.clone();
+        assert!(!token.is_cancelled());
+        clone.cancel();
+        assert!(token.is_cancelled());
+    }
+}
@@ -0,0 +1,199 @@
+use std::{io, sync::Arc};
+
+use crate::{
+    aabb::AABB,
+    checkpoint::Fingerprint,
+    hittable::{Hit_Record, Hittable, Hittable_List},
+    interval::Interval,
+    material::Material,
+    ray::Ray,
+    scene::{ObjectDescription, SceneExport},
+    vec3::{Point3, Vec3, cross, dot},
+};
+
+// Parallelogram with corner q and edges u and v
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3, // n / (n . n), turns cross products with the edges into planar coordinates
+    material: Arc<dyn Material>,
+    bbox: AABB,
+    normal: Vec3,
+    d: f64, // plane equation normal . p = d
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, material: Arc<dyn Material>) -> Self {
+        let n = cross(u, v);
+        let normal = n.unit_vector();
+        let bbox_diagonal1 = AABB::new_from_extrema(q, q + u + v);
+        let bbox_diagonal2 = AABB::new_from_extrema(q + u, q + v);
+
+        Quad {
+            q,
+            u,
+            v,
+            w: n / dot(n, n),
+            material,
+            bbox: AABB::new_from_bbox(bbox_diagonal1, bbox_diagonal2).pad_to_minimums(),
+            normal,
+            d: dot(normal, q),
+        }
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, ray_t: Interval) -> Option<Hit_Record> {
+        let denom = dot(self.normal, ray.dir);
+        // parallel to the plane
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - dot(self.normal, ray.origin)) / denom;
+        if !ray_t.contains(t) {
+            return None;
+        }
+
+        // planar coordinates of the hit in the basis of the edges
+        let intersection = ray.at(t);
+        let planar_hitpt_vector = intersection - self.q;
+        let alpha = dot(self.w, cross(planar_hitpt_vector, self.v));
+        let beta = dot(self.w, cross(self.u, planar_hitpt_vector));
+        let unit_interval = Interval { min: 0.0, max: 1.0 };
+        if !unit_interval.contains(alpha) || !unit_interval.contains(beta) {
+            return None;
+        }
+
+        let mut rec = Hit_Record {
+            p: intersection,
+            normal: self.normal,
+            t,
+            u: alpha,
+            v: beta,
+            front_face: true,
+            material: Arc::clone(&self.material),
+            velocity: Vec3::default(),
+            object: std::ptr::from_ref(self).addr(),
+        };
+        rec.set_face_normal(ray, self.normal);
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> AABB {
+        self.bbox
+    }
+
+    fn fingerprint(&self, f: &mut Fingerprint) {
+        f.name("quad");
+        f.vec3(self.q);
+        f.vec3(self.u);
+        f.vec3(self.v);
+        self.material.fingerprint(f);
+    }
+
+    fn export(&self, scene: &mut SceneExport) -> io::Result<()> {
+        let material = scene.material(&self.material)?;
+        scene.add(ObjectDescription::Quad {
+            q: self.q,
+            u: self.u,
+            v: self.v,
+            material,
+        });
+        Ok(())
+    }
+}
+
+// The six sides of the box with opposite corners a and b
+pub fn quad_box(a: Point3, b: Point3, material: Arc<dyn Material>) -> Hittable_List {
+    let mut sides = Hittable_List::new();
+    for (q, u, v) in box_sides(a, b) {
+        sides.add(Arc::new(Quad::new(q, u, v, material.clone())));
+    }
+    sides
+}
+
+// Corner and edges of the quads quad_box makes, front, right, back, left, top and bottom
+pub fn box_sides(a: Point3, b: Point3) -> [(Point3, Vec3, Vec3); 6] {
+    let min = Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z));
+    let max = Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z));
+
+    let dx = Vec3::new(max.x - min.x, 0.0, 0.0);
+    let dy = Vec3::new(0.0, max.y - min.y, 0.0);
+    let dz = Vec3::new(0.0, 0.0, max.z - min.z);
+
+    [
+        (Point3::new(min.x, min.y, max.z), dx, dy),
+        (Point3::new(max.x, min.y, max.z), -dz, dy),
+        (Point3::new(max.x, min.y, min.z), -dx, dy),
+        (Point3::new(min.x, min.y, min.z), dz, dy),
+        (Point3::new(min.x, max.y, max.z), dx, -dz),
+        (Point3::new(min.x, min.y, min.z), dx, dz),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Lambertian, vec3::Color};
+
+    #[test]
+    fn hits_inside_the_parallelogram_only() {
+        let quad = Quad::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        );
+        let ray = |x: f64, y: f64| Ray {
+            origin: Point3::new(x, y, 1.0),
+            dir: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+            medium_sample: 0.0,
+        };
+        let everything = Interval {
+            min: 0.001,
+            max: f64::INFINITY,
+        };
+
+        let rec = quad.hit(&ray(1.5, 0.25), everything).unwrap();
+        assert_eq!((rec.t, rec.u, rec.v), (1.0, 0.75, 0.25));
+        assert!(rec.front_face);
+        assert!(quad.hit(&ray(2.5, 0.25), everything).is_none());
+        // flat along z, but still a box with some thickness
+        assert!(quad.bounding_box().z.size() > 0.0);
+    }
+
+    #[test]
+    fn boxes_are_closed() {
+        let cube = quad_box(
+            Point3::new(1.0, 1.0, 1.0),
+            Point3::new(-1.0, -1.0, -1.0),
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        );
+        let everything = Interval {
+            min: 0.001,
+            max: f64::INFINITY,
+        };
+        // from inside every ray leaves through some side
+        for dir in [
+            Vec3::new(1.0, 0.2, 0.3),
+            Vec3::new(-0.1, 1.0, 0.4),
+            Vec3::new(0.3, -0.2, -1.0),
+        ] {
+            let ray = Ray {
+                origin: Point3::new(0.1, 0.2, 0.3),
+                dir,
+                time: 0.0,
+                medium_sample: 0.0,
+            };
+            let rec = cube.hit(&ray, everything).unwrap();
+            assert!(!rec.front_face);
+            assert!((rec.p.x.abs().max(rec.p.y.abs()).max(rec.p.z.abs()) - 1.0).abs() < 1e-12);
+        }
+        let bbox = cube.bounding_box();
+        // the sides are padded a little across their planes
+        assert!((bbox.x.min + 1.0).abs() < 1e-3 && (bbox.y.max - 1.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,174 @@
+use std::ops::Mul;
+
+use crate::{
+    utils::degrees_to_radian,
+    vec3::{Vec3, cross, dot},
+};
+
+// Rotation as a unit quaternion, v the vector part and w the scalar part
+#[derive(Copy, Clone, Debug)]
+pub struct Quaternion {
+    pub v: Vec3,
+    pub w: f64,
+}
+
+pub const IDENTITY_QUATERNION: Quaternion = Quaternion {
+    v: Vec3 {
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    },
+    w: 1.0,
+};
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        IDENTITY_QUATERNION
+    }
+}
+
+impl Quaternion {
+    // Counter-clockwise rotation about axis, looking down the axis towards the origin, like
+    // Matrix4::rotation
+    pub fn from_axis_angle(axis: Vec3, degrees: f64) -> Self {
+        let (sin, cos) = (degrees_to_radian(degrees) / 2.0).sin_cos();
+        Quaternion {
+            v: sin * axis.unit_vector(),
+            w: cos,
+        }
+    }
+
+    // Rotation of the quaternion [x, y, z, w], the order of glTF and Matrix4::from_quaternion
+    pub fn from_array(q: [f64; 4]) -> Self {
+        Quaternion {
+            v: Vec3::new(q[0], q[1], q[2]),
+            w: q[3],
+        }
+        .normalize()
+    }
+
+    pub fn to_array(self) -> [f64; 4] {
+        [self.v.x, self.v.y, self.v.z, self.w]
+    }
+
+    pub fn dot(self, other: Quaternion) -> f64 {
+        dot(self.v, other.v) + self.w * other.w
+    }
+
+    pub fn normalize(self) -> Quaternion {
+        let length = self.dot(self).sqrt();
+        Quaternion {
+            v: self.v / length,
+            w: self.w / length,
+        }
+    }
+
+    pub fn conjugate(self) -> Quaternion {
+        Quaternion {
+            v: -self.v,
+            w: self.w,
+        }
+    }
+
+    pub fn rotate(self, v: Vec3) -> Vec3 {
+        // v + 2w (q x v) + 2 q x (q x v), q the vector part
+        let t = 2.0 * cross(self.v, v);
+        v + self.w * t + cross(self.v, t)
+    }
+
+    // Angle in radians of the rotation taking self to other, along the shorter way
+    pub fn angle_to(self, other: Quaternion) -> f64 {
+        2.0 * self.dot(other).abs().min(1.0).acos()
+    }
+
+    // Rotation turning at constant speed from self at t = 0 to other at t = 1, the shorter way
+    pub fn slerp(self, other: Quaternion, t: f64) -> Quaternion {
+        let mut cos_theta = self.dot(other);
+        // q and -q are the same rotation, pick the one less than half a turn away
+        let other = if cos_theta < 0.0 {
+            cos_theta = -cos_theta;
+            Quaternion {
+                v: -other.v,
+                w: -other.w,
+            }
+        } else {
+            other
+        };
+        if cos_theta > 0.9995 {
+            // nearly the same rotation, where sin(theta) is too small to divide by
+            return Quaternion {
+                v: self.v + t * (other.v - self.v),
+                w: self.w + t * (other.w - self.w),
+            }
+            .normalize();
+        }
+        let theta = cos_theta.acos();
+        let a = ((1.0 - t) * theta).sin() / theta.sin();
+        let b = (t * theta).sin() / theta.sin();
+        Quaternion {
+            v: a * self.v + b * other.v,
+            w: a * self.w + b * other.w,
+        }
+    }
+}
+
+// Rotation by rhs, then by self
+impl Mul for Quaternion {
+    type Output = Quaternion;
+
+    fn mul(self, rhs: Quaternion) -> Quaternion {
+        Quaternion {
+            v: self.w * rhs.v + rhs.w * self.v + cross(self.v, rhs.v),
+            w: self.w * rhs.w - dot(self.v, rhs.v),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::Matrix4;
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-12
+    }
+
+    #[test]
+    fn rotates_like_matrices() {
+        let axis = Vec3::new(1.0, 2.0, -0.5);
+        let q = Quaternion::from_axis_angle(axis, 70.0);
+        let v = Vec3::new(0.3, -1.0, 2.0);
+        let m = Matrix4::rotation(axis, 70.0);
+        assert!(close(q.rotate(v), m.transform_vector(v)));
+        assert!(close(
+            q.rotate(v),
+            Matrix4::from_quaternion(q.to_array()).transform_vector(v)
+        ));
+
+        let r = Quaternion::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), 30.0);
+        assert!(close((r * q).rotate(v), r.rotate(q.rotate(v))));
+        assert!(close(q.conjugate().rotate(q.rotate(v)), v));
+    }
+
+    #[test]
+    fn slerp_turns_at_constant_speed_the_short_way() {
+        let y = Vec3::new(0.0, 1.0, 0.0);
+        let a = Quaternion::from_axis_angle(y, 10.0);
+        let b = Quaternion::from_axis_angle(y, 130.0);
+        let x = Vec3::new(1.0, 0.0, 0.0);
+        let quarter = a.slerp(b, 0.25);
+        assert!(close(
+            quarter.rotate(x),
+            Quaternion::from_axis_angle(y, 40.0).rotate(x)
+        ));
+        assert!((a.angle_to(b) - degrees_to_radian(120.0)).abs() < 1e-12);
+
+        // 350 degrees is -10 degrees, halfway there from 0 is -5 and not 175
+        let back = Quaternion::from_axis_angle(y, 350.0);
+        let half = IDENTITY_QUATERNION.slerp(back, 0.5);
+        assert!(close(
+            half.rotate(x),
+            Quaternion::from_axis_angle(y, -5.0).rotate(x)
+        ));
+    }
+}
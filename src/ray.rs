@@ -1,9 +1,13 @@
 use crate::vec3::{Point3, Vec3};
 
+#[derive(Copy, Clone)]
 pub struct Ray {
     pub origin: Point3,
     pub dir: Vec3,
     pub time: f64,
+    // uniform sample in [0, 1) drawn for every traced ray, how far into a participating medium
+    // it scatters, see ConstantMedium
+    pub medium_sample: f64,
 }
 
 impl Ray {
@@ -0,0 +1,665 @@
+use std::{env, io, path::Path, sync::Arc};
+
+use crate::{
+    bvh::BVH_Node,
+    constant_medium::ConstantMedium,
+    hittable::{Hittable, Hittable_List, Rotate_Y, Sphere, Translate},
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+    quad::{Quad, quad_box},
+    scene::{CameraSettings, RenderSettings},
+    texture::{CheckerTexture, ImageTexture, NoiseTexture, Texture},
+    utils::{random_double, random_double_range, seed_random},
+    vec3::{Color, Point3, Vec3},
+};
+
+// A built-in scene with the camera and render settings it is meant to be seen with
+pub struct BuiltinScene {
+    pub camera: CameraSettings,
+    pub render: RenderSettings,
+    pub world: Hittable_List,
+}
+
+pub struct SceneEntry {
+    pub name: &'static str,
+    pub description: &'static str,
+    build: fn() -> io::Result<BuiltinScene>,
+}
+
+impl SceneEntry {
+    // Everything random, from the scene layout to every camera sample, derives from seed
+    pub fn build(&self, seed: u64) -> io::Result<BuiltinScene> {
+        seed_random(seed);
+        let mut scene = (self.build)()?;
+        scene.render.seed = seed;
+        Ok(scene)
+    }
+}
+
+// The reference scenes of Ray Tracing in One Weekend and The Next Week
+pub const SCENES: &[SceneEntry] = &[
+    SceneEntry {
+        name: "random_spheres",
+        description: "book 1 final scene, small random spheres around three big ones",
+        build: random_spheres,
+    },
+    SceneEntry {
+        name: "checkered_spheres",
+        description: "two spheres with a checker texture",
+        build: checkered_spheres,
+    },
+    SceneEntry {
+        name: "earth",
+        description: "a globe with an image texture, needs earthmap.ppm",
+        build: earth,
+    },
+    SceneEntry {
+        name: "perlin_spheres",
+        description: "marble spheres from Perlin turbulence",
+        build: perlin_spheres,
+    },
+    SceneEntry {
+        name: "quads",
+        description: "five colored quads facing the camera",
+        build: quads,
+    },
+    SceneEntry {
+        name: "simple_light",
+        description: "marble spheres lit by a sphere and a quad light",
+        build: simple_light,
+    },
+    SceneEntry {
+        name: "cornell_box",
+        description: "the Cornell box with two rotated boxes",
+        build: cornell_box,
+    },
+    SceneEntry {
+        name: "cornell_smoke",
+        description: "the Cornell box with boxes of black and white smoke",
+        build: cornell_smoke,
+    },
+    SceneEntry {
+        name: "final_scene",
+        description: "book 2 final scene, every feature at once, needs earthmap.ppm",
+        build: final_scene,
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static SceneEntry> {
+    SCENES.iter().find(|entry| entry.name == name)
+}
+
+// Texture images are looked up in the directory named by RTW_IMAGES, then in images/. Only
+// netpbm images load, convert the book's earthmap.jpg with e.g. `convert earthmap.jpg
+// earthmap.ppm`.
+fn load_texture(file_name: &str) -> io::Result<ImageTexture> {
+    let mut tried = Vec::new();
+    let dirs = env::var("RTW_IMAGES")
+        .into_iter()
+        .chain(["images".to_string()]);
+    for dir in dirs {
+        let path = Path::new(&dir).join(file_name);
+        if path.exists() {
+            return ImageTexture::load(&path.to_string_lossy());
+        }
+        tried.push(path.to_string_lossy().into_owned());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!(
+            "texture {} not found, looked for {}",
+            file_name,
+            tried.join(", ")
+        ),
+    ))
+}
+
+fn bvh(mut list: Hittable_List) -> Hittable_List {
+    Hittable_List::new_from_hittable(Arc::new(BVH_Node::new(&mut list)))
+}
+
+fn lambertian(albedo: Color) -> Arc<dyn Material> {
+    Arc::new(Lambertian::new(albedo))
+}
+
+fn random_spheres() -> io::Result<BuiltinScene> {
+    let mut world = Hittable_List::new();
+
+    let ground_mat = lambertian(Color::new(0.5, 0.5, 0.5));
+    world.add(Arc::new(Sphere::new_static(
+        Point3::new(0.0, -1000.5, 0.0),
+        1000.0,
+        ground_mat,
+    )));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let choose_mat = random_double();
+            let center = Point3::new(
+                a as f64 + 0.9 * random_double(),
+                0.2,
+                b as f64 + 0.9 * random_double(),
+            );
+
+            // move small balls away from the big balls
+            if (center - Point3::new(4.0, -0.2, 0.0)).length() > 0.9 {
+                if choose_mat < 0.8 {
+                    // diffuse
+                    let material = lambertian(Color::random() * Color::random());
+                    let center2 = center + Vec3::new(0.0, random_double_range(0.0, 0.5), 0.0);
+                    world.add(Arc::new(Sphere::new_moving(center, center2, 0.2, material)));
+                } else if choose_mat < 0.95 {
+                    // metal
+                    let material = Arc::new(Metal {
+                        albedo: Color::random_range(0.5, 1.0),
+                        fuzz: random_double_range(0.0, 0.5),
+                    });
+                    world.add(Arc::new(Sphere::new_static(center, 0.2, material)));
+                } else {
+                    // glass
+                    let material = Arc::new(Dielectric {
+                        refraction_index: 1.5,
+                    });
+                    world.add(Arc::new(Sphere::new_static(center, 0.2, material)));
+                }
+            }
+        }
+    }
+
+    let material1 = Arc::new(Dielectric {
+        refraction_index: 1.5,
+    });
+    world.add(Arc::new(Sphere::new_static(
+        Point3::new(0.0, 1.0, 0.0),
+        1.0,
+        material1,
+    )));
+    let material2 = lambertian(Color::new(0.4, 0.2, 0.1));
+    world.add(Arc::new(Sphere::new_static(
+        Point3::new(-4.0, 1.0, 0.0),
+        1.0,
+        material2,
+    )));
+    let material3 = Arc::new(Metal {
+        albedo: Color::new(0.8, 0.6, 0.5),
+        fuzz: 0.0,
+    });
+    world.add(Arc::new(Sphere::new_static(
+        Point3::new(4.0, 1.0, 0.0),
+        1.0,
+        material3,
+    )));
+
+    Ok(BuiltinScene {
+        camera: CameraSettings {
+            vfov: 20.0,
+            lookfrom: Point3::new(13.0, 2.0, 3.0),
+            lookat: Point3::new(0.0, 0.0, 0.0),
+            defocus_angle: 0.6,
+            focus_dist: 10.0,
+            ..Default::default()
+        },
+        render: RenderSettings::default(),
+        world: bvh(world),
+    })
+}
+
+// The view of the book 2 sphere scenes
+fn spheres_camera() -> CameraSettings {
+    CameraSettings {
+        vfov: 20.0,
+        lookfrom: Point3::new(13.0, 2.0, 3.0),
+        lookat: Point3::new(0.0, 0.0, 0.0),
+        ..Default::default()
+    }
+}
+
+fn checkered_spheres() -> io::Result<BuiltinScene> {
+    let mut world = Hittable_List::new();
+    let checker: Arc<dyn Texture> = Arc::new(CheckerTexture::from_colors(
+        0.32,
+        Color::new(0.2, 0.3, 0.1),
+        Color::new(0.9, 0.9, 0.9),
+    ));
+    let material: Arc<dyn Material> = Arc::new(Lambertian::from_texture(checker));
+    for y in [-10.0, 10.0] {
+        world.add(Arc::new(Sphere::new_static(
+            Point3::new(0.0, y, 0.0),
+            10.0,
+            material.clone(),
+        )));
+    }
+
+    Ok(BuiltinScene {
+        camera: spheres_camera(),
+        render: RenderSettings::default(),
+        world,
+    })
+}
+
+fn earth() -> io::Result<BuiltinScene> {
+    let earth_texture = Arc::new(load_texture("earthmap.ppm")?);
+    let earth_surface = Arc::new(Lambertian::from_texture(earth_texture));
+    let globe = Arc::new(Sphere::new_static(
+        Point3::new(0.0, 0.0, 0.0),
+        2.0,
+        earth_surface,
+    ));
+
+    Ok(BuiltinScene {
+        camera: CameraSettings {
+            vfov: 20.0,
+            lookfrom: Point3::new(0.0, 0.0, 12.0),
+            lookat: Point3::new(0.0, 0.0, 0.0),
+            ..Default::default()
+        },
+        render: RenderSettings::default(),
+        world: Hittable_List::new_from_hittable(globe),
+    })
+}
+
+// Marble ground with a marble sphere on it
+fn marble_spheres() -> Hittable_List {
+    let mut world = Hittable_List::new();
+    let pertext: Arc<dyn Texture> = Arc::new(NoiseTexture::new(4.0));
+    let material: Arc<dyn Material> = Arc::new(Lambertian::from_texture(pertext));
+    world.add(Arc::new(Sphere::new_static(
+        Point3::new(0.0, -1000.0, 0.0),
+        1000.0,
+        material.clone(),
+    )));
+    world.add(Arc::new(Sphere::new_static(
+        Point3::new(0.0, 2.0, 0.0),
+        2.0,
+        material,
+    )));
+    world
+}
+
+fn perlin_spheres() -> io::Result<BuiltinScene> {
+    Ok(BuiltinScene {
+        camera: spheres_camera(),
+        render: RenderSettings::default(),
+        world: marble_spheres(),
+    })
+}
+
+fn quads() -> io::Result<BuiltinScene> {
+    let mut world = Hittable_List::new();
+
+    let left_red = lambertian(Color::new(1.0, 0.2, 0.2));
+    let back_green = lambertian(Color::new(0.2, 1.0, 0.2));
+    let right_blue = lambertian(Color::new(0.2, 0.2, 1.0));
+    let upper_orange = lambertian(Color::new(1.0, 0.5, 0.0));
+    let lower_teal = lambertian(Color::new(0.2, 0.8, 0.8));
+
+    world.add(Arc::new(Quad::new(
+        Point3::new(-3.0, -2.0, 5.0),
+        Vec3::new(0.0, 0.0, -4.0),
+        Vec3::new(0.0, 4.0, 0.0),
+        left_red,
+    )));
+    world.add(Arc::new(Quad::new(
+        Point3::new(-2.0, -2.0, 0.0),
+        Vec3::new(4.0, 0.0, 0.0),
+        Vec3::new(0.0, 4.0, 0.0),
+        back_green,
+    )));
+    world.add(Arc::new(Quad::new(
+        Point3::new(3.0, -2.0, 1.0),
+        Vec3::new(0.0, 0.0, 4.0),
+        Vec3::new(0.0, 4.0, 0.0),
+        right_blue,
+    )));
+    world.add(Arc::new(Quad::new(
+        Point3::new(-2.0, 3.0, 1.0),
+        Vec3::new(4.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 4.0),
+        upper_orange,
+    )));
+    world.add(Arc::new(Quad::new(
+        Point3::new(-2.0, -3.0, 5.0),
+        Vec3::new(4.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -4.0),
+        lower_teal,
+    )));
+
+    Ok(BuiltinScene {
+        camera: CameraSettings {
+            aspect_ratio: 1.0,
+            vfov: 80.0,
+            lookfrom: Point3::new(0.0, 0.0, 9.0),
+            lookat: Point3::new(0.0, 0.0, 0.0),
+            ..Default::default()
+        },
+        render: RenderSettings::default(),
+        world,
+    })
+}
+
+fn simple_light() -> io::Result<BuiltinScene> {
+    let mut world = marble_spheres();
+
+    let difflight: Arc<dyn Material> = Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0)));
+    world.add(Arc::new(Sphere::new_static(
+        Point3::new(0.0, 7.0, 0.0),
+        2.0,
+        difflight.clone(),
+    )));
+    world.add(Arc::new(Quad::new(
+        Point3::new(3.0, 1.0, -2.0),
+        Vec3::new(2.0, 0.0, 0.0),
+        Vec3::new(0.0, 2.0, 0.0),
+        difflight,
+    )));
+
+    Ok(BuiltinScene {
+        camera: CameraSettings {
+            vfov: 20.0,
+            lookfrom: Point3::new(26.0, 3.0, 6.0),
+            lookat: Point3::new(0.0, 2.0, 0.0),
+            ..Default::default()
+        },
+        render: RenderSettings {
+            background: Some(Color::new(0.0, 0.0, 0.0)),
+            ..Default::default()
+        },
+        world,
+    })
+}
+
+// The walls of the Cornell box lit by the given ceiling light, and its two boxes rotated and
+// moved into place, unbounded by any BVH so they can be wrapped first
+fn cornell_walls(light: Quad) -> (Hittable_List, Arc<dyn Hittable>, Arc<dyn Hittable>) {
+    let mut world = Hittable_List::new();
+
+    let red = lambertian(Color::new(0.65, 0.05, 0.05));
+    let white = lambertian(Color::new(0.73, 0.73, 0.73));
+    let green = lambertian(Color::new(0.12, 0.45, 0.15));
+
+    let wall = |q: Point3, u: Vec3, v: Vec3, material: &Arc<dyn Material>| {
+        Arc::new(Quad::new(q, u, v, material.clone()))
+    };
+    world.add(wall(
+        Point3::new(555.0, 0.0, 0.0),
+        Vec3::new(0.0, 555.0, 0.0),
+        Vec3::new(0.0, 0.0, 555.0),
+        &green,
+    ));
+    world.add(wall(
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 555.0, 0.0),
+        Vec3::new(0.0, 0.0, 555.0),
+        &red,
+    ));
+    world.add(Arc::new(light));
+    world.add(wall(
+        Point3::new(0.0, 0.0, 0.0),
+        Vec3::new(555.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 555.0),
+        &white,
+    ));
+    world.add(wall(
+        Point3::new(555.0, 555.0, 555.0),
+        Vec3::new(-555.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -555.0),
+        &white,
+    ));
+    world.add(wall(
+        Point3::new(0.0, 0.0, 555.0),
+        Vec3::new(555.0, 0.0, 0.0),
+        Vec3::new(0.0, 555.0, 0.0),
+        &white,
+    ));
+
+    let box1 = quad_box(
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(165.0, 330.0, 165.0),
+        white.clone(),
+    );
+    let box1 = Arc::new(Rotate_Y::new(Arc::new(box1), 15.0));
+    let box1 = Arc::new(Translate::new(box1, Vec3::new(265.0, 0.0, 295.0)));
+
+    let box2 = quad_box(
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(165.0, 165.0, 165.0),
+        white,
+    );
+    let box2 = Arc::new(Rotate_Y::new(Arc::new(box2), -18.0));
+    let box2 = Arc::new(Translate::new(box2, Vec3::new(130.0, 0.0, 65.0)));
+
+    (world, box1, box2)
+}
+
+fn cornell_camera() -> CameraSettings {
+    CameraSettings {
+        aspect_ratio: 1.0,
+        image_width: 600,
+        vfov: 40.0,
+        lookfrom: Point3::new(278.0, 278.0, -800.0),
+        lookat: Point3::new(278.0, 278.0, 0.0),
+        ..Default::default()
+    }
+}
+
+fn cornell_render() -> RenderSettings {
+    RenderSettings {
+        samples_per_pixel: 200,
+        background: Some(Color::new(0.0, 0.0, 0.0)),
+        ..Default::default()
+    }
+}
+
+fn cornell_box() -> io::Result<BuiltinScene> {
+    let light = Quad::new(
+        Point3::new(343.0, 554.0, 332.0),
+        Vec3::new(-130.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -105.0),
+        Arc::new(DiffuseLight::new(Color::new(15.0, 15.0, 15.0))),
+    );
+    let (mut world, box1, box2) = cornell_walls(light);
+    world.add(box1);
+    world.add(box2);
+
+    Ok(BuiltinScene {
+        camera: cornell_camera(),
+        render: cornell_render(),
+        world,
+    })
+}
+
+fn cornell_smoke() -> io::Result<BuiltinScene> {
+    let light = Quad::new(
+        Point3::new(113.0, 554.0, 127.0),
+        Vec3::new(330.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 305.0),
+        Arc::new(DiffuseLight::new(Color::new(7.0, 7.0, 7.0))),
+    );
+    let (mut world, box1, box2) = cornell_walls(light);
+    world.add(Arc::new(ConstantMedium::new(
+        box1,
+        0.01,
+        Color::new(0.0, 0.0, 0.0),
+    )));
+    world.add(Arc::new(ConstantMedium::new(
+        box2,
+        0.01,
+        Color::new(1.0, 1.0, 1.0),
+    )));
+
+    Ok(BuiltinScene {
+        camera: cornell_camera(),
+        render: cornell_render(),
+        world,
+    })
+}
+
+fn final_scene() -> io::Result<BuiltinScene> {
+    // ground of boxes of random height
+    let mut boxes1 = Hittable_List::new();
+    let ground = lambertian(Color::new(0.48, 0.83, 0.53));
+    let boxes_per_side = 20;
+    for i in 0..boxes_per_side {
+        for j in 0..boxes_per_side {
+            let w = 100.0;
+            let x0 = -1000.0 + i as f64 * w;
+            let z0 = -1000.0 + j as f64 * w;
+            let y0 = 0.0;
+            let x1 = x0 + w;
+            let y1 = random_double_range(1.0, 101.0);
+            let z1 = z0 + w;
+            boxes1.add(Arc::new(quad_box(
+                Point3::new(x0, y0, z0),
+                Point3::new(x1, y1, z1),
+                ground.clone(),
+            )));
+        }
+    }
+
+    let mut world = Hittable_List::new();
+    world.add(Arc::new(BVH_Node::new(&mut boxes1)));
+
+    let light = Arc::new(DiffuseLight::new(Color::new(7.0, 7.0, 7.0)));
+    world.add(Arc::new(Quad::new(
+        Point3::new(123.0, 554.0, 147.0),
+        Vec3::new(300.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, 265.0),
+        light,
+    )));
+
+    let center1 = Point3::new(400.0, 400.0, 200.0);
+    let center2 = center1 + Vec3::new(30.0, 0.0, 0.0);
+    let sphere_material = lambertian(Color::new(0.7, 0.3, 0.1));
+    world.add(Arc::new(Sphere::new_moving(
+        center1,
+        center2,
+        50.0,
+        sphere_material,
+    )));
+
+    let glass = || -> Arc<dyn Material> {
+        Arc::new(Dielectric {
+            refraction_index: 1.5,
+        })
+    };
+    world.add(Arc::new(Sphere::new_static(
+        Point3::new(260.0, 150.0, 45.0),
+        50.0,
+        glass(),
+    )));
+    world.add(Arc::new(Sphere::new_static(
+        Point3::new(0.0, 150.0, 145.0),
+        50.0,
+        Arc::new(Metal {
+            albedo: Color::new(0.8, 0.8, 0.9),
+            fuzz: 1.0,
+        }),
+    )));
+
+    // glass ball filled with blue subsurface fog, and thin mist over everything
+    let boundary: Arc<dyn Hittable> = Arc::new(Sphere::new_static(
+        Point3::new(360.0, 150.0, 145.0),
+        70.0,
+        glass(),
+    ));
+    world.add(boundary.clone());
+    world.add(Arc::new(ConstantMedium::new(
+        boundary,
+        0.2,
+        Color::new(0.2, 0.4, 0.9),
+    )));
+    let boundary = Arc::new(Sphere::new_static(
+        Point3::new(0.0, 0.0, 0.0),
+        5000.0,
+        glass(),
+    ));
+    world.add(Arc::new(ConstantMedium::new(
+        boundary,
+        0.0001,
+        Color::new(1.0, 1.0, 1.0),
+    )));
+
+    let emat = Arc::new(Lambertian::from_texture(Arc::new(load_texture(
+        "earthmap.ppm",
+    )?)));
+    world.add(Arc::new(Sphere::new_static(
+        Point3::new(400.0, 200.0, 400.0),
+        100.0,
+        emat,
+    )));
+    let pertext = Arc::new(NoiseTexture::new(0.2));
+    world.add(Arc::new(Sphere::new_static(
+        Point3::new(220.0, 280.0, 300.0),
+        80.0,
+        Arc::new(Lambertian::from_texture(pertext)),
+    )));
+
+    // cube of small spheres
+    let mut boxes2 = Hittable_List::new();
+    let white = lambertian(Color::new(0.73, 0.73, 0.73));
+    let ns = 1000;
+    for _ in 0..ns {
+        boxes2.add(Arc::new(Sphere::new_static(
+            Point3::random_range(0.0, 165.0),
+            10.0,
+            white.clone(),
+        )));
+    }
+    world.add(Arc::new(Translate::new(
+        Arc::new(Rotate_Y::new(Arc::new(BVH_Node::new(&mut boxes2)), 15.0)),
+        Vec3::new(-100.0, 270.0, 395.0),
+    )));
+
+    Ok(BuiltinScene {
+        camera: CameraSettings {
+            aspect_ratio: 1.0,
+            image_width: 800,
+            vfov: 40.0,
+            lookfrom: Point3::new(478.0, 278.0, -600.0),
+            lookat: Point3::new(278.0, 278.0, 0.0),
+            ..Default::default()
+        },
+        render: RenderSettings {
+            samples_per_pixel: 10000,
+            max_depth: 40,
+            background: Some(Color::new(0.0, 0.0, 0.0)),
+            ..Default::default()
+        },
+        world,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scenes_build_with_their_camera() {
+        // the scenes with an image texture need the image file
+        for entry in SCENES
+            .iter()
+            .filter(|e| e.name != "earth" && e.name != "final_scene")
+        {
+            let scene = entry.build(7).unwrap();
+            assert!(!scene.world.objects.is_empty(), "{} is empty", entry.name);
+            let camera = scene.camera.build(&scene.render);
+            assert!(camera.image_width() > 0);
+        }
+        assert!(find("cornell_box").is_some());
+        assert!(find("teapot").is_none());
+    }
+
+    #[test]
+    fn the_seed_picks_the_layout() {
+        let entry = find("random_spheres").unwrap();
+        let hash = |seed| {
+            let scene = entry.build(seed).unwrap();
+            scene
+                .camera
+                .build(&scene.render)
+                .scene_hash(&scene.world)
+                .unwrap()
+        };
+        assert_eq!(hash(42), hash(42));
+        assert_ne!(hash(42), hash(43));
+    }
+}
@@ -0,0 +1,412 @@
+use rand::{Rng, SeedableRng, rngs::SmallRng};
+
+// Source of the uniform random numbers used to build one camera sample: the pixel offset,
+// time, lens position and every scattering decision along the path. Each call hands out the
+// next dimension of the current sample, low-discrepancy samplers spread the values of a
+// dimension evenly over the samples of a pixel.
+//
+// Every value is a pure function of (seed, pixel, sample index, dimension), so a render only
+// depends on its seed, never on the order pixels are visited in.
+pub trait Sampler {
+    // Reset the dimension counter for sample sample_index of pixel (x, y)
+    fn start_pixel_sample(&mut self, x: u64, y: u64, sample_index: u64);
+    fn get_1d(&mut self) -> f64;
+    fn get_2d(&mut self) -> (f64, f64);
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SamplerKind {
+    Independent, // uniform white noise
+    Stratified,  // one jittered sample per stratum, strata shuffled per pixel and dimension
+    Halton,      // radical inverse in prime bases with a random shift per pixel and dimension
+    Sobol,       // Owen-scrambled Sobol (0, 2) pairs with shuffled indices per dimension pair
+}
+
+impl SamplerKind {
+    pub fn build(self, samples_per_pixel: u64, seed: u64) -> Box<dyn Sampler> {
+        let state = SampleState::new(seed);
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, state)),
+            SamplerKind::Halton => Box::new(HaltonSampler { state }),
+            SamplerKind::Sobol => Box::new(SobolSampler { state }),
+        }
+    }
+}
+
+const ONE_MINUS_EPSILON: f64 = 1.0 - f64::EPSILON / 2.0;
+
+// 64 bit finalizer from splitmix64, a cheap way to get well distributed hash values
+pub(crate) fn mix_bits(mut v: u64) -> u64 {
+    v ^= v >> 31;
+    v = v.wrapping_mul(0x7fb5d329728ea185);
+    v ^= v >> 27;
+    v = v.wrapping_mul(0x81dadef4bc2dd44d);
+    v ^= v >> 33;
+    v
+}
+
+pub(crate) fn hash(values: &[u64]) -> u64 {
+    values
+        .iter()
+        .fold(0x9e3779b97f4a7c15, |h, v| mix_bits(h ^ mix_bits(*v)))
+}
+
+// Uniform value in [0, 1) from the top 53 bits of a hash
+fn hash_float(values: &[u64]) -> f64 {
+    (hash(values) >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+}
+
+// Element i of a pseudo-random permutation of 0..l selected by p, Kensler's
+// "Correlated Multi-Jittered Sampling" hash
+fn permutation_element(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < l {
+            break;
+        }
+    }
+    (i.wrapping_add(p)) % l
+}
+
+// Uniform white noise from a generator reseeded for every pixel sample
+pub struct IndependentSampler {
+    seed: u64,
+    rng: SmallRng,
+}
+
+impl IndependentSampler {
+    pub fn new(seed: u64) -> Self {
+        IndependentSampler {
+            seed,
+            rng: SmallRng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, x: u64, y: u64, sample_index: u64) {
+        self.rng = SmallRng::seed_from_u64(hash(&[self.seed, x, y, sample_index]));
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        self.rng.random()
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        (self.rng.random(), self.rng.random())
+    }
+}
+
+// Position of the current sample, shared by the hash based samplers
+struct SampleState {
+    seed: u64,
+    x: u64,
+    y: u64,
+    index: u64,
+    dimension: u64,
+}
+
+impl SampleState {
+    fn new(seed: u64) -> Self {
+        SampleState {
+            seed,
+            x: 0,
+            y: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn start(&mut self, x: u64, y: u64, sample_index: u64) {
+        self.x = x;
+        self.y = y;
+        self.index = sample_index;
+        self.dimension = 0;
+    }
+
+    // Claim the next n dimensions, returns the first one
+    fn next(&mut self, n: u64) -> u64 {
+        let dimension = self.dimension;
+        self.dimension += n;
+        dimension
+    }
+}
+
+pub struct StratifiedSampler {
+    samples_per_pixel: u64,
+    x_strata: u64,
+    y_strata: u64,
+    state: SampleState,
+}
+
+impl StratifiedSampler {
+    fn new(samples_per_pixel: u64, state: SampleState) -> Self {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        let x_strata = ((samples_per_pixel as f64).sqrt() as u64).max(1);
+        let y_strata = samples_per_pixel.div_ceil(x_strata);
+        StratifiedSampler {
+            samples_per_pixel,
+            x_strata,
+            y_strata,
+            state,
+        }
+    }
+
+    // Stratum of the current sample among count strata for the given dimension
+    fn stratum(&self, dimension: u64, count: u64) -> u64 {
+        let s = &self.state;
+        let p = hash(&[s.seed, s.x, s.y, dimension]) as u32;
+        permutation_element((s.index % count) as u32, count as u32, p) as u64
+    }
+
+    fn jitter(&self, dimension: u64) -> f64 {
+        let s = &self.state;
+        hash_float(&[s.seed, s.x, s.y, dimension, s.index])
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, x: u64, y: u64, sample_index: u64) {
+        self.state.start(x, y, sample_index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.state.next(1);
+        let stratum = self.stratum(dimension, self.samples_per_pixel);
+        ((stratum as f64 + self.jitter(dimension)) / self.samples_per_pixel as f64)
+            .min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let dimension = self.state.next(2);
+        let stratum = self.stratum(dimension, self.x_strata * self.y_strata);
+        let (sx, sy) = (stratum % self.x_strata, stratum / self.x_strata);
+        (
+            ((sx as f64 + self.jitter(dimension)) / self.x_strata as f64).min(ONE_MINUS_EPSILON),
+            ((sy as f64 + self.jitter(dimension + 1)) / self.y_strata as f64)
+                .min(ONE_MINUS_EPSILON),
+        )
+    }
+}
+
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
+    101, 103, 107, 109, 113, 127, 131,
+];
+
+// Mirror the base b digits of a around the radix point
+fn radical_inverse(base: u64, mut a: u64) -> f64 {
+    let inv_base = 1.0 / base as f64;
+    let mut inv_base_m = 1.0;
+    let mut reversed = 0u64;
+    while a > 0 {
+        let next = a / base;
+        let digit = a - next * base;
+        reversed = reversed * base + digit;
+        inv_base_m *= inv_base;
+        a = next;
+    }
+    (reversed as f64 * inv_base_m).min(ONE_MINUS_EPSILON)
+}
+
+pub struct HaltonSampler {
+    state: SampleState,
+}
+
+impl HaltonSampler {
+    // Halton value of the current sample for one dimension. Dimensions past the prime table
+    // reuse its bases, the per dimension shift keeps them decorrelated.
+    fn sample(&self, dimension: u64) -> f64 {
+        let s = &self.state;
+        let base = PRIMES[(dimension % PRIMES.len() as u64) as usize];
+        let shift = hash_float(&[s.seed, s.x, s.y, dimension]);
+        let v = radical_inverse(base, s.index) + shift;
+        (if v >= 1.0 { v - 1.0 } else { v }).min(ONE_MINUS_EPSILON)
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, x: u64, y: u64, sample_index: u64) {
+        self.state.start(x, y, sample_index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.state.next(1);
+        self.sample(dimension)
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let dimension = self.state.next(2);
+        (self.sample(dimension), self.sample(dimension + 1))
+    }
+}
+
+// First two Sobol dimensions: the van der Corput sequence and the one generated by the
+// polynomial x + 1. Together they form a (0, 2)-sequence in base 2.
+fn sobol_2d(index: u32) -> (u32, u32) {
+    let mut x = 0u32;
+    let mut y = 0u32;
+    let mut v = 1u32 << 31;
+    let mut i = index;
+    let mut bit = 0;
+    while i != 0 {
+        if i & 1 != 0 {
+            x ^= 1u32 << (31 - bit);
+            y ^= v;
+        }
+        v ^= v >> 1;
+        i >>= 1;
+        bit += 1;
+    }
+    (x, y)
+}
+
+// Laine and Karras' hash based approximation of a nested uniform permutation of the
+// reversed bits
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x
+}
+
+// Owen scramble: flips every bit depending on all the bits above it
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+fn u32_to_unit(x: u32) -> f64 {
+    (x as f64 / 4294967296.0).min(ONE_MINUS_EPSILON)
+}
+
+// Burley's "Practical Hash-based Owen Scrambling": every pair of dimensions is an Owen
+// scrambled 2D Sobol pattern, and the sample index is shuffled per pixel and pair so the pairs
+// do not correlate with each other.
+pub struct SobolSampler {
+    state: SampleState,
+}
+
+impl SobolSampler {
+    fn sample_pair(&self, dimension: u64) -> (f64, f64) {
+        let s = &self.state;
+        let seed = hash(&[s.seed, s.x, s.y, dimension]);
+        let index = nested_uniform_scramble(s.index as u32, seed as u32);
+        let (x, y) = sobol_2d(index);
+        (
+            u32_to_unit(nested_uniform_scramble(x, (seed >> 32) as u32)),
+            u32_to_unit(nested_uniform_scramble(y, mix_bits(seed) as u32)),
+        )
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, x: u64, y: u64, sample_index: u64) {
+        self.state.start(x, y, sample_index);
+    }
+
+    fn get_1d(&mut self) -> f64 {
+        let dimension = self.state.next(1);
+        self.sample_pair(dimension).0
+    }
+
+    fn get_2d(&mut self) -> (f64, f64) {
+        let dimension = self.state.next(2);
+        self.sample_pair(dimension)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every one of the n x n cells of the unit square holds exactly one of n^2 samples
+    fn assert_stratified_2d(sampler: &mut dyn Sampler, n: u64) {
+        let mut cells = vec![0; (n * n) as usize];
+        for i in 0..n * n {
+            sampler.start_pixel_sample(3, 7, i);
+            sampler.get_1d();
+            let (u, v) = sampler.get_2d();
+            assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+            let cell = (v * n as f64) as u64 * n + (u * n as f64) as u64;
+            cells[cell as usize] += 1;
+        }
+        assert!(cells.iter().all(|c| *c == 1), "cells {:?}", cells);
+    }
+
+    #[test]
+    fn stratified_sampler_fills_every_stratum() {
+        assert_stratified_2d(SamplerKind::Stratified.build(16, 0).as_mut(), 4);
+    }
+
+    #[test]
+    fn sobol_sampler_is_stratified() {
+        assert_stratified_2d(SamplerKind::Sobol.build(16, 0).as_mut(), 4);
+        assert_stratified_2d(SamplerKind::Sobol.build(64, 7).as_mut(), 8);
+    }
+
+    #[test]
+    fn permutation_element_is_a_permutation() {
+        for l in [1, 5, 16, 33] {
+            let mut seen: Vec<u32> = (0..l).map(|i| permutation_element(i, l, 1234)).collect();
+            seen.sort();
+            assert_eq!(seen, (0..l).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn radical_inverse_base_2() {
+        assert_eq!(radical_inverse(2, 1), 0.5);
+        assert_eq!(radical_inverse(2, 2), 0.25);
+        assert_eq!(radical_inverse(2, 3), 0.75);
+        assert_eq!(radical_inverse(3, 1), 1.0 / 3.0);
+    }
+
+    #[test]
+    fn samplers_repeat_a_sample_for_the_same_seed() {
+        for kind in [
+            SamplerKind::Independent,
+            SamplerKind::Stratified,
+            SamplerKind::Halton,
+            SamplerKind::Sobol,
+        ] {
+            let sample = |seed: u64, other_pixel_first: bool| {
+                let mut sampler = kind.build(16, seed);
+                if other_pixel_first {
+                    sampler.start_pixel_sample(9, 9, 0);
+                    sampler.get_2d();
+                }
+                sampler.start_pixel_sample(1, 2, 5);
+                (sampler.get_2d(), sampler.get_1d())
+            };
+
+            assert_eq!(sample(3, false), sample(3, true), "{:?}", kind);
+            assert_ne!(sample(3, false), sample(4, false), "{:?}", kind);
+        }
+    }
+}
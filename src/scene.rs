@@ -0,0 +1,2357 @@
+use std::{collections::HashMap, fs, io, mem, sync::Arc};
+
+use crate::{
+    animation::{Lerp, Timeline, Track},
+    bvh::BVH_Node,
+    camera::Camera,
+    constant_medium::ConstantMedium,
+    filter::{Filter, FilterKind},
+    hittable::{Hittable, Hittable_List, Sphere},
+    interval::Interval,
+    json::{Json, JsonValue, quote},
+    material::{Dielectric, DiffuseLight, Lambertian, Material, Metal},
+    mesh::TriangleMesh,
+    motion::{MotionTransform, Pose},
+    quad::{Quad, box_sides},
+    quaternion::Quaternion,
+    sampler::SamplerKind,
+    texture::{CheckerTexture, ImageTexture, NoiseTexture, SolidColor, Texture},
+    utils::{degrees_to_radian, invalid_data},
+    vec3::{Color, Point3, Vec3},
+};
+
+// Declarative scene, the JSON scene format read into plain data. Every key is optional unless
+// noted, missing ones take the defaults below.
+//
+// {
+//   "camera": { "aspect_ratio", "image_width", "vfov", "lookfrom", "lookat", "vup",
+//               "defocus_angle", "focus_dist",
+//               "keyframes": [ { "time", "lookfrom", "lookat", "vfov" } ] },
+//   "render": { "samples_per_pixel", "max_depth", "seed",
+//               "sampler": "independent" | "stratified" | "halton" | "sobol",
+//               "filter": { "type": "box" | "tent" | "gaussian" | "mitchell" | "lanczos",
+//                           "radius", "sigma", "b", "c", "tau" },
+//               "background" },
+//   "materials": { "<name>": { "type": "lambertian", "albedo" }
+//                          | { "type": "metal", "albedo", "fuzz" }
+//                          | { "type": "dielectric", "refraction_index" }
+//                          | { "type": "diffuse_light", "emit" },
+//                            each with "keyframes": [ { "time", <its parameters> } ] },
+//   textures, the albedo of lambertian, diffuse_light's emit and a medium's albedo: a color
+//              | { "type": "checker", "scale", "even" (required), "odd" (required) }
+//              | { "type": "noise", "scale", "seed" }
+//              | { "type": "image", "file" (required) },
+//   "objects": [ { "type": "sphere", "center", "radius", "material" }
+//              | { "type": "moving_sphere", "center", "center2" | "offset", "motion_time",
+//                  "radius", "material" }
+//              | { "type": "quad", "q", "u", "v", "material" }
+//              | { "type": "box", "a", "b", "material" }
+//              | { "type": "mesh", "file", "material", "keyframes": [ { "time", "file" } ] }
+//              | { "type": "constant_medium", "density", "albedo", "boundary": [...] }
+//              | { "type": "group", "transform": { "translate", "rotate", "scale" }
+//                                     | "keyframes": [ { "time", "translate", "rotate", "scale" } ],
+//                  "objects": [...] } ],
+//   "animation": { "fps", "frames" (required): [first, last], "shutter" }
+// }
+//
+// Vectors and colors are arrays of 3 numbers, angles are in degrees. Without a background
+// color rays that hit nothing see a sky gradient. A moving sphere goes from center to center2,
+// or center + offset, over motion_time [t0, t1], by default [0, 1]. Meshes are .ply or .stl
+// files, without a material they are diffuse in their vertex colors. A deforming mesh has
+// keyframes with files of the same vertices and faces in other poses, only their positions
+// are read. A quad is the parallelogram with corner q and edges u and v, a box has opposite
+// corners a and b. A constant medium fills its boundary objects, which should enclose a convex
+// volume, with fog of the density scattering in the albedo color. All their keys are required.
+//
+// A checker alternates between its even and odd textures in cubes with sides of scale, 1 by
+// default. Noise is Perlin marble, scale 1 and seed 0 unless given. Images are .ppm or .pgm
+// files mapped over the surface coordinates. Only a plain color can have keyframes.
+//
+// Keyframe times are in seconds. Camera and material keyframes only set the parameters they
+// list, the others keep their own keyframes or the static value, while a group keyframe is a
+// whole transform. Between keyframes values change linearly, so rotations turn through the
+// interpolated angles, and keyframed groups follow every keyframe while the shutter is open.
+// A scene with an animation renders its frames with Timeline's timing, default 24 fps and a
+// shutter open for half of every frame; a still shows time 0 with moving objects blurred over
+// the camera's shutter interval [0, 1].
+pub struct SceneDescription {
+    pub camera: CameraSettings,
+    pub render: RenderSettings,
+    pub materials: Vec<(String, MaterialDescription)>,
+    pub objects: Vec<ObjectDescription>,
+    pub animation: SceneAnimation,
+}
+
+// Keyframes of the camera and the materials, and the frames to render if it's an animation.
+// Groups carry their own keyframes.
+#[derive(Clone, Debug, Default)]
+pub struct SceneAnimation {
+    pub timeline: Option<Timeline>,
+    pub camera: CameraTracks,
+    pub materials: Vec<(String, MaterialTracks)>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CameraTracks {
+    pub lookfrom: Track<Point3>,
+    pub lookat: Track<Point3>,
+    pub vfov: Track<f64>,
+}
+
+impl CameraTracks {
+    pub fn apply(&self, camera: &mut CameraSettings, time: f64) {
+        camera.lookfrom = self.lookfrom.sample(time).unwrap_or(camera.lookfrom);
+        camera.lookat = self.lookat.sample(time).unwrap_or(camera.lookat);
+        camera.vfov = self.vfov.sample(time).unwrap_or(camera.vfov);
+    }
+}
+
+// Tracks of the parameters of a material, only those of its type are ever set
+#[derive(Clone, Debug, Default)]
+pub struct MaterialTracks {
+    pub albedo: Track<Color>,
+    pub fuzz: Track<f64>,
+    pub refraction_index: Track<f64>,
+    pub emit: Track<Color>,
+}
+
+impl MaterialTracks {
+    pub fn apply(&self, material: MaterialDescription, time: f64) -> MaterialDescription {
+        match material {
+            MaterialDescription::Lambertian { albedo } => MaterialDescription::Lambertian {
+                albedo: self
+                    .albedo
+                    .sample(time)
+                    .map_or(albedo, TextureDescription::Solid),
+            },
+            MaterialDescription::Metal { albedo, fuzz } => MaterialDescription::Metal {
+                albedo: self.albedo.sample(time).unwrap_or(albedo),
+                fuzz: self.fuzz.sample(time).unwrap_or(fuzz),
+            },
+            MaterialDescription::Dielectric { refraction_index } => {
+                MaterialDescription::Dielectric {
+                    refraction_index: self
+                        .refraction_index
+                        .sample(time)
+                        .unwrap_or(refraction_index),
+                }
+            }
+            MaterialDescription::DiffuseLight { emit } => MaterialDescription::DiffuseLight {
+                emit: self
+                    .emit
+                    .sample(time)
+                    .map_or(emit, TextureDescription::Solid),
+            },
+        }
+    }
+}
+
+// The parameters of Camera::new that describe the view
+#[derive(Copy, Clone, Debug)]
+pub struct CameraSettings {
+    pub aspect_ratio: f64,
+    pub image_width: u64,
+    pub vfov: f64,
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+    pub vup: Vec3,
+    pub defocus_angle: f64,
+    pub focus_dist: f64,
+}
+
+impl CameraSettings {
+    // Image of exactly width x height pixels. Camera::new derives the height from the aspect
+    // ratio, rounding down, so nudge the ratio until the quotient doesn't fall short.
+    pub fn set_resolution(&mut self, width: u64, height: u64) {
+        let mut aspect_ratio = width as f64 / height as f64;
+        while ((width as f64 / aspect_ratio) as u64) < height {
+            aspect_ratio = aspect_ratio.next_down();
+        }
+        self.image_width = width;
+        self.aspect_ratio = aspect_ratio;
+    }
+
+    pub fn build(&self, render: &RenderSettings) -> Camera {
+        let camera = Camera::new(
+            self.aspect_ratio,
+            self.image_width,
+            self.vfov,
+            self.lookfrom,
+            self.lookat,
+            self.vup,
+            self.defocus_angle,
+            self.focus_dist,
+            render.samples_per_pixel,
+            render.max_depth,
+        )
+        .with_seed(render.seed)
+        .with_sampler(render.sampler)
+        .with_filter(render.filter);
+        match render.background {
+            Some(background) => camera.with_background(background),
+            None => camera,
+        }
+    }
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        CameraSettings {
+            aspect_ratio: 16.0 / 9.0,
+            image_width: 400,
+            vfov: 90.0,
+            lookfrom: Point3::new(0.0, 0.0, 0.0),
+            lookat: Point3::new(0.0, 0.0, -1.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            defocus_angle: 0.0,
+            focus_dist: 10.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct RenderSettings {
+    pub samples_per_pixel: u16,
+    pub max_depth: i16,
+    pub seed: u64,
+    pub sampler: SamplerKind,
+    pub filter: Filter,
+    pub background: Option<Color>, // a sky gradient if None
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            samples_per_pixel: 100,
+            max_depth: 50,
+            seed: 0,
+            sampler: SamplerKind::Independent,
+            filter: Filter::default(),
+            background: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum MaterialDescription {
+    Lambertian { albedo: TextureDescription },
+    Metal { albedo: Color, fuzz: f64 },
+    Dielectric { refraction_index: f64 },
+    DiffuseLight { emit: TextureDescription },
+}
+
+#[derive(Clone, Debug)]
+pub enum TextureDescription {
+    Solid(Color),
+    Checker {
+        scale: f64,
+        even: Box<TextureDescription>,
+        odd: Box<TextureDescription>,
+    },
+    Noise {
+        scale: f64,
+        seed: u64,
+    },
+    Image {
+        file: String,
+    },
+}
+
+impl TextureDescription {
+    fn build(&self) -> io::Result<Arc<dyn Texture>> {
+        Ok(match self {
+            TextureDescription::Solid(albedo) => Arc::new(SolidColor { albedo: *albedo }),
+            TextureDescription::Checker { scale, even, odd } => {
+                Arc::new(CheckerTexture::new(*scale, even.build()?, odd.build()?))
+            }
+            TextureDescription::Noise { scale, seed } => {
+                Arc::new(NoiseTexture::with_seed(*scale, *seed))
+            }
+            TextureDescription::Image { file } => Arc::new(ImageTexture::load(file)?),
+        })
+    }
+}
+
+// Uniform scale, then rotation about x, y and z in that order, then translation
+#[derive(Copy, Clone, Debug)]
+pub struct Transform {
+    pub translate: Vec3,
+    pub rotate: Vec3, // degrees
+    pub scale: f64,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            translate: Vec3::default(),
+            rotate: Vec3::default(),
+            scale: 1.0,
+        }
+    }
+}
+
+// Each of translate, rotate and scale on its own
+impl Lerp for Transform {
+    fn lerp(self, other: Transform, t: f64) -> Transform {
+        Transform {
+            translate: self.translate.lerp(other.translate, t),
+            rotate: self.rotate.lerp(other.rotate, t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+}
+
+impl Transform {
+    pub fn apply(&self, p: Point3) -> Point3 {
+        self.apply_vector(p) + self.translate
+    }
+
+    // The rotations about x, y and z in one
+    pub fn rotation(&self) -> Quaternion {
+        let axis = |x, y, z, degrees| Quaternion::from_axis_angle(Vec3::new(x, y, z), degrees);
+        axis(0.0, 0.0, 1.0, self.rotate.z)
+            * axis(0.0, 1.0, 0.0, self.rotate.y)
+            * axis(1.0, 0.0, 0.0, self.rotate.x)
+    }
+
+    pub fn pose(&self) -> Pose {
+        Pose {
+            translate: self.translate,
+            rotation: self.rotation(),
+            scale: Vec3::new(self.scale, self.scale, self.scale),
+        }
+    }
+
+    // Scaled and rotated but not moved, for directions and edges
+    pub fn apply_vector(&self, v: Vec3) -> Vec3 {
+        let mut p = self.scale * v;
+        for (axis, degrees) in [self.rotate.x, self.rotate.y, self.rotate.z]
+            .into_iter()
+            .enumerate()
+        {
+            let (sin, cos) = degrees_to_radian(degrees).sin_cos();
+            p = match axis {
+                0 => Vec3::new(p.x, cos * p.y - sin * p.z, sin * p.y + cos * p.z),
+                1 => Vec3::new(cos * p.x + sin * p.z, p.y, -sin * p.x + cos * p.z),
+                _ => Vec3::new(cos * p.x - sin * p.y, sin * p.x + cos * p.y, p.z),
+            };
+        }
+        p
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ObjectDescription {
+    Sphere {
+        center: Point3,
+        radius: f64,
+        material: String,
+    },
+    // Moves from center at motion_time.min to center + offset at motion_time.max
+    MovingSphere {
+        center: Point3,
+        offset: Vec3,
+        motion_time: Interval,
+        radius: f64,
+        material: String,
+    },
+    // Triangle mesh from a file, None for a material of its vertex colors. keyframes are files
+    // with the vertices in other poses.
+    Mesh {
+        file: String,
+        material: Option<String>,
+        keyframes: Vec<(f64, String)>,
+    },
+    // Parallelogram with corner q and edges u and v
+    Quad {
+        q: Point3,
+        u: Vec3,
+        v: Vec3,
+        material: String,
+    },
+    // The six quads around the box with opposite corners a and b
+    Box {
+        a: Point3,
+        b: Point3,
+        material: String,
+    },
+    // Fog filling the volume the boundary objects enclose
+    ConstantMedium {
+        density: f64,
+        albedo: TextureDescription,
+        boundary: Vec<ObjectDescription>,
+    },
+    // keyframes replace transform when there are any
+    Group {
+        transform: Transform,
+        keyframes: Track<Transform>,
+        objects: Vec<ObjectDescription>,
+    },
+}
+
+// A scene ready to render
+pub struct Scene {
+    pub camera: Camera,
+    pub world: Hittable_List,
+}
+
+impl Scene {
+    pub fn load(file_name: &str) -> io::Result<Scene> {
+        SceneDescription::load(file_name)?.build()
+    }
+}
+
+// A scene read from another program's format. Whatever had no counterpart here was left out
+// or approximated, with a warning saying so.
+pub struct ImportedScene {
+    pub camera: CameraSettings,
+    pub render: RenderSettings,
+    pub world: Hittable_List,
+    pub warnings: Vec<String>,
+}
+
+// Entries of a JSON object, read one key at a time. finish reports the keys never read as
+// unknown, listing the ones that were expected.
+struct Fields<'a> {
+    json: &'a Json,
+    what: &'a str,
+    entries: &'a [(String, Json)],
+    read: Vec<&'static str>,
+}
+
+impl<'a> Fields<'a> {
+    fn new(json: &'a Json, what: &'a str) -> io::Result<Self> {
+        Ok(Fields {
+            json,
+            what,
+            entries: json.as_object()?,
+            read: Vec::new(),
+        })
+    }
+
+    fn optional(&mut self, key: &'static str) -> Option<&'a Json> {
+        self.read.push(key);
+        self.entries.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    fn required(&mut self, key: &'static str) -> io::Result<&'a Json> {
+        self.optional(key).ok_or_else(|| {
+            self.json
+                .error(format!("{} is missing \"{}\"", self.what, key))
+        })
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self
+            .entries
+            .iter()
+            .find(|(k, _)| !self.read.contains(&k.as_str()))
+        {
+            Some((key, value)) => Err(value.error(format!(
+                "unknown key \"{}\" in {}, expected one of: {}",
+                key,
+                self.what,
+                self.read.join(", ")
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+fn number(json: &Json) -> io::Result<f64> {
+    json.as_f64()
+}
+
+fn positive(json: &Json) -> io::Result<f64> {
+    let n = json.as_f64()?;
+    if n <= 0.0 {
+        return Err(json.error(format!("expected a positive number, found {}", n)));
+    }
+    Ok(n)
+}
+
+fn non_negative(json: &Json) -> io::Result<f64> {
+    let n = json.as_f64()?;
+    if n < 0.0 {
+        return Err(json.error(format!("expected a non-negative number, found {}", n)));
+    }
+    Ok(n)
+}
+
+// A whole number in [min, max]
+fn integer(json: &Json, min: f64, max: f64) -> io::Result<f64> {
+    let n = json.as_f64()?;
+    if n.fract() != 0.0 || n < min || n > max {
+        return Err(json.error(format!(
+            "expected a whole number from {} to {}, found {}",
+            min, max, n
+        )));
+    }
+    Ok(n)
+}
+
+fn vec3(json: &Json) -> io::Result<Vec3> {
+    match json.as_array()? {
+        [x, y, z] => Ok(Vec3::new(x.as_f64()?, y.as_f64()?, z.as_f64()?)),
+        items => Err(json.error(format!(
+            "expected an array of 3 numbers, found {} items",
+            items.len()
+        ))),
+    }
+}
+
+fn vfov(json: &Json) -> io::Result<f64> {
+    let vfov = positive(json)?;
+    if vfov >= 180.0 {
+        return Err(json.error("vfov must be below 180 degrees"));
+    }
+    Ok(vfov)
+}
+
+// Keyframes [{ "time", ... }] in increasing time order, read takes the other keys of each
+fn parse_keyframes(
+    json: &Json,
+    what: &str,
+    mut read: impl FnMut(f64, &mut Fields) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut last: Option<f64> = None;
+    for keyframe in json.as_array()? {
+        let mut fields = Fields::new(keyframe, what)?;
+        let time_json = fields.required("time")?;
+        let time = time_json.as_f64()?;
+        if let Some(last) = last
+            && time <= last
+        {
+            return Err(time_json.error(format!(
+                "keyframe times must increase, {} follows {}",
+                time, last
+            )));
+        }
+        last = Some(time);
+        read(time, &mut fields)?;
+        fields.finish()?;
+    }
+    Ok(())
+}
+
+fn parse_camera(json: &Json) -> io::Result<(CameraSettings, CameraTracks)> {
+    let mut camera = CameraSettings::default();
+    let mut tracks = CameraTracks::default();
+    let mut fields = Fields::new(json, "camera")?;
+    if let Some(v) = fields.optional("aspect_ratio") {
+        camera.aspect_ratio = positive(v)?;
+    }
+    if let Some(v) = fields.optional("image_width") {
+        camera.image_width = integer(v, 1.0, 65536.0)? as u64;
+    }
+    if let Some(v) = fields.optional("vfov") {
+        camera.vfov = vfov(v)?;
+    }
+    if let Some(v) = fields.optional("lookfrom") {
+        camera.lookfrom = vec3(v)?;
+    }
+    if let Some(v) = fields.optional("lookat") {
+        camera.lookat = vec3(v)?;
+    }
+    if let Some(v) = fields.optional("vup") {
+        camera.vup = vec3(v)?;
+    }
+    if let Some(v) = fields.optional("defocus_angle") {
+        camera.defocus_angle = non_negative(v)?;
+    }
+    if let Some(v) = fields.optional("focus_dist") {
+        camera.focus_dist = positive(v)?;
+    }
+    if let Some(v) = fields.optional("keyframes") {
+        let (mut lookfrom, mut lookat, mut vfovs) = (Vec::new(), Vec::new(), Vec::new());
+        parse_keyframes(v, "camera keyframe", |time, key| {
+            if let Some(v) = key.optional("lookfrom") {
+                lookfrom.push((time, vec3(v)?));
+            }
+            if let Some(v) = key.optional("lookat") {
+                lookat.push((time, vec3(v)?));
+            }
+            if let Some(v) = key.optional("vfov") {
+                vfovs.push((time, vfov(v)?));
+            }
+            Ok(())
+        })?;
+        tracks = CameraTracks {
+            lookfrom: Track::new(lookfrom)?,
+            lookat: Track::new(lookat)?,
+            vfov: Track::new(vfovs)?,
+        };
+    }
+    fields.finish()?;
+    Ok((camera, tracks))
+}
+
+fn parse_filter(json: &Json) -> io::Result<Filter> {
+    let mut fields = Fields::new(json, "filter")?;
+    let kind_json = fields.required("type")?;
+    let kind = match kind_json.as_str()? {
+        "box" => FilterKind::Box,
+        "tent" => FilterKind::Tent,
+        "gaussian" => FilterKind::Gaussian {
+            sigma: fields.optional("sigma").map_or(Ok(0.5), positive)?,
+        },
+        "mitchell" => FilterKind::Mitchell {
+            b: fields.optional("b").map_or(Ok(1.0 / 3.0), number)?,
+            c: fields.optional("c").map_or(Ok(1.0 / 3.0), number)?,
+        },
+        "lanczos" => FilterKind::Lanczos {
+            tau: fields.optional("tau").map_or(Ok(3.0), positive)?,
+        },
+        other => {
+            return Err(kind_json.error(format!(
+                "unknown filter \"{}\", expected box, tent, gaussian, mitchell or lanczos",
+                other
+            )));
+        }
+    };
+    let radius = match (fields.optional("radius"), kind) {
+        (Some(v), _) => positive(v)?,
+        (None, FilterKind::Box) => 0.5,
+        (None, FilterKind::Mitchell { .. }) => 2.0,
+        (None, FilterKind::Lanczos { .. }) => 3.0,
+        (None, _) => 1.5,
+    };
+    fields.finish()?;
+    Ok(Filter::new(kind, radius))
+}
+
+fn parse_render(json: &Json) -> io::Result<RenderSettings> {
+    let mut render = RenderSettings::default();
+    let mut fields = Fields::new(json, "render")?;
+    if let Some(v) = fields.optional("samples_per_pixel") {
+        render.samples_per_pixel = integer(v, 1.0, u16::MAX as f64)? as u16;
+    }
+    if let Some(v) = fields.optional("max_depth") {
+        render.max_depth = integer(v, 1.0, i16::MAX as f64)? as i16;
+    }
+    if let Some(v) = fields.optional("seed") {
+        // above 2^53 a JSON number no longer holds every integer
+        render.seed = integer(v, 0.0, 9007199254740992.0)? as u64;
+    }
+    if let Some(v) = fields.optional("sampler") {
+        render.sampler = match v.as_str()? {
+            "independent" => SamplerKind::Independent,
+            "stratified" => SamplerKind::Stratified,
+            "halton" => SamplerKind::Halton,
+            "sobol" => SamplerKind::Sobol,
+            other => {
+                return Err(v.error(format!(
+                    "unknown sampler \"{}\", expected independent, stratified, halton or sobol",
+                    other
+                )));
+            }
+        };
+    }
+    if let Some(v) = fields.optional("filter") {
+        render.filter = parse_filter(v)?;
+    }
+    if let Some(v) = fields.optional("background") {
+        render.background = Some(vec3(v)?);
+    }
+    fields.finish()?;
+    Ok(render)
+}
+
+// A color, or an object describing a texture
+fn parse_texture(json: &Json) -> io::Result<TextureDescription> {
+    if matches!(json.value, JsonValue::Array(_)) {
+        return Ok(TextureDescription::Solid(vec3(json)?));
+    }
+    let mut fields = Fields::new(json, "texture")?;
+    let kind = fields.required("type")?;
+    let texture = match kind.as_str()? {
+        "checker" => TextureDescription::Checker {
+            scale: fields.optional("scale").map_or(Ok(1.0), positive)?,
+            even: Box::new(parse_texture(fields.required("even")?)?),
+            odd: Box::new(parse_texture(fields.required("odd")?)?),
+        },
+        "noise" => TextureDescription::Noise {
+            scale: fields.optional("scale").map_or(Ok(1.0), non_negative)?,
+            seed: fields
+                .optional("seed")
+                .map_or(Ok(0.0), |v| integer(v, 0.0, 9007199254740992.0))? as u64,
+        },
+        "image" => TextureDescription::Image {
+            file: fields.required("file")?.as_str()?.to_string(),
+        },
+        other => {
+            return Err(kind.error(format!(
+                "unknown texture type \"{}\", expected checker, noise or image",
+                other
+            )));
+        }
+    };
+    fields.finish()?;
+    Ok(texture)
+}
+
+fn parse_material(json: &Json) -> io::Result<(MaterialDescription, Option<MaterialTracks>)> {
+    let mut fields = Fields::new(json, "material")?;
+    let kind = fields.required("type")?;
+    let material = match kind.as_str()? {
+        "lambertian" => MaterialDescription::Lambertian {
+            albedo: parse_texture(fields.required("albedo")?)?,
+        },
+        "metal" => MaterialDescription::Metal {
+            albedo: vec3(fields.required("albedo")?)?,
+            fuzz: fields.optional("fuzz").map_or(Ok(0.0), non_negative)?,
+        },
+        "dielectric" => MaterialDescription::Dielectric {
+            refraction_index: positive(fields.required("refraction_index")?)?,
+        },
+        "diffuse_light" => MaterialDescription::DiffuseLight {
+            emit: parse_texture(fields.required("emit")?)?,
+        },
+        other => {
+            return Err(kind.error(format!(
+                "unknown material type \"{}\", expected lambertian, metal, dielectric or \
+                 diffuse_light",
+                other
+            )));
+        }
+    };
+    let tracks = match fields.optional("keyframes") {
+        Some(v) => Some(parse_material_keyframes(v, &material)?),
+        None => None,
+    };
+    fields.finish()?;
+    Ok((material, tracks))
+}
+
+// Keyframes of the parameters of material's type
+fn parse_material_keyframes(
+    json: &Json,
+    material: &MaterialDescription,
+) -> io::Result<MaterialTracks> {
+    let (mut albedo, mut fuzz, mut refraction_index, mut emit) =
+        (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    let color = |texture: &TextureDescription, v: &Json| match texture {
+        TextureDescription::Solid(_) => vec3(v),
+        _ => Err(v.error("only a plain color can have keyframes, not a texture")),
+    };
+    parse_keyframes(json, "material keyframe", |time, key| {
+        match material {
+            MaterialDescription::Lambertian { albedo: texture } => {
+                if let Some(v) = key.optional("albedo") {
+                    albedo.push((time, color(texture, v)?));
+                }
+            }
+            MaterialDescription::Metal { .. } => {
+                if let Some(v) = key.optional("albedo") {
+                    albedo.push((time, vec3(v)?));
+                }
+            }
+            MaterialDescription::Dielectric { .. } => {
+                if let Some(v) = key.optional("refraction_index") {
+                    refraction_index.push((time, positive(v)?));
+                }
+            }
+            MaterialDescription::DiffuseLight { emit: texture } => {
+                if let Some(v) = key.optional("emit") {
+                    emit.push((time, color(texture, v)?));
+                }
+            }
+        }
+        if let MaterialDescription::Metal { .. } = material
+            && let Some(v) = key.optional("fuzz")
+        {
+            fuzz.push((time, non_negative(v)?));
+        }
+        Ok(())
+    })?;
+    Ok(MaterialTracks {
+        albedo: Track::new(albedo)?,
+        fuzz: Track::new(fuzz)?,
+        refraction_index: Track::new(refraction_index)?,
+        emit: Track::new(emit)?,
+    })
+}
+
+fn parse_transform(json: &Json) -> io::Result<Transform> {
+    let mut fields = Fields::new(json, "transform")?;
+    let transform = read_transform(&mut fields)?;
+    fields.finish()?;
+    Ok(transform)
+}
+
+fn parse_transform_keyframes(json: &Json) -> io::Result<Track<Transform>> {
+    let mut keys = Vec::new();
+    parse_keyframes(json, "group keyframe", |time, key| {
+        keys.push((time, read_transform(key)?));
+        Ok(())
+    })?;
+    Track::new(keys)
+}
+
+fn read_transform(fields: &mut Fields) -> io::Result<Transform> {
+    let mut transform = Transform::default();
+    if let Some(v) = fields.optional("translate") {
+        transform.translate = vec3(v)?;
+    }
+    if let Some(v) = fields.optional("rotate") {
+        transform.rotate = vec3(v)?;
+    }
+    if let Some(v) = fields.optional("scale") {
+        if matches!(v.value, JsonValue::Array(_)) {
+            return Err(v.error("group transforms only allow a uniform scale, expected a number"));
+        }
+        transform.scale = positive(v)?;
+    }
+    Ok(transform)
+}
+
+fn parse_timeline(json: &Json) -> io::Result<Timeline> {
+    let mut timeline = Timeline::default();
+    let mut fields = Fields::new(json, "animation")?;
+    if let Some(v) = fields.optional("fps") {
+        timeline.fps = positive(v)?;
+    }
+    let frames = fields.required("frames")?;
+    match frames.as_array()? {
+        [first, last] => {
+            timeline.first_frame = integer(first, 0.0, 9007199254740992.0)? as u64;
+            timeline.last_frame = integer(last, 0.0, 9007199254740992.0)? as u64;
+            if timeline.last_frame < timeline.first_frame {
+                return Err(last.error("the last frame comes before the first"));
+            }
+        }
+        items => {
+            return Err(frames.error(format!(
+                "expected an array of the first and last frame, found {} items",
+                items.len()
+            )));
+        }
+    }
+    if let Some(v) = fields.optional("shutter") {
+        timeline.shutter = non_negative(v)?;
+        if timeline.shutter > 1.0 {
+            return Err(v.error("the shutter can't stay open longer than a frame, expected 0 to 1"));
+        }
+    }
+    fields.finish()?;
+    Ok(timeline)
+}
+
+fn parse_material_name(
+    json: &Json,
+    materials: &[(String, MaterialDescription)],
+) -> io::Result<String> {
+    let name = json.as_str()?;
+    if !materials.iter().any(|(n, _)| n == name) {
+        return Err(json.error(format!("undefined material \"{}\"", name)));
+    }
+    Ok(name.to_string())
+}
+
+fn parse_object(
+    json: &Json,
+    materials: &[(String, MaterialDescription)],
+) -> io::Result<ObjectDescription> {
+    let mut fields = Fields::new(json, "object")?;
+    let kind = fields.required("type")?;
+    let object = match kind.as_str()? {
+        "sphere" => ObjectDescription::Sphere {
+            center: vec3(fields.required("center")?)?,
+            radius: positive(fields.required("radius")?)?,
+            material: parse_material_name(fields.required("material")?, materials)?,
+        },
+        "moving_sphere" => {
+            let center = vec3(fields.required("center")?)?;
+            let offset = match (fields.optional("center2"), fields.optional("offset")) {
+                (Some(center2), None) => vec3(center2)? - center,
+                (None, Some(offset)) => vec3(offset)?,
+                (Some(_), Some(offset)) => {
+                    return Err(offset.error("give either center2 or offset, not both"));
+                }
+                (None, None) => return Err(json.error("moving_sphere is missing \"center2\"")),
+            };
+            let motion_time = match fields.optional("motion_time") {
+                Some(v) => {
+                    let times = v.as_array()?;
+                    let [t0, t1] = times else {
+                        return Err(v.error(format!(
+                            "expected an array of 2 numbers, found {} items",
+                            times.len()
+                        )));
+                    };
+                    let (t0, t1) = (t0.as_f64()?, t1.as_f64()?);
+                    if t0 >= t1 {
+                        return Err(v.error("the motion must end after it starts"));
+                    }
+                    Interval { min: t0, max: t1 }
+                }
+                None => Interval { min: 0.0, max: 1.0 },
+            };
+            ObjectDescription::MovingSphere {
+                center,
+                offset,
+                motion_time,
+                radius: positive(fields.required("radius")?)?,
+                material: parse_material_name(fields.required("material")?, materials)?,
+            }
+        }
+        "quad" => ObjectDescription::Quad {
+            q: vec3(fields.required("q")?)?,
+            u: vec3(fields.required("u")?)?,
+            v: vec3(fields.required("v")?)?,
+            material: parse_material_name(fields.required("material")?, materials)?,
+        },
+        "box" => ObjectDescription::Box {
+            a: vec3(fields.required("a")?)?,
+            b: vec3(fields.required("b")?)?,
+            material: parse_material_name(fields.required("material")?, materials)?,
+        },
+        "constant_medium" => {
+            let boundary = fields.required("boundary")?;
+            let objects = parse_objects(boundary, materials)?;
+            if objects.is_empty() {
+                return Err(boundary.error("a medium needs boundary objects to fill"));
+            }
+            ObjectDescription::ConstantMedium {
+                density: positive(fields.required("density")?)?,
+                albedo: parse_texture(fields.required("albedo")?)?,
+                boundary: objects,
+            }
+        }
+        "mesh" => {
+            let mut keyframes = Vec::new();
+            if let Some(v) = fields.optional("keyframes") {
+                parse_keyframes(v, "mesh keyframe", |time, key| {
+                    keyframes.push((time, key.required("file")?.as_str()?.to_string()));
+                    Ok(())
+                })?;
+            }
+            ObjectDescription::Mesh {
+                file: fields.required("file")?.as_str()?.to_string(),
+                material: fields
+                    .optional("material")
+                    .map(|m| parse_material_name(m, materials))
+                    .transpose()?,
+                keyframes,
+            }
+        }
+        "group" => {
+            let transform = fields.optional("transform");
+            let keyframes = fields.optional("keyframes");
+            if let (Some(_), Some(keyframes)) = (transform, keyframes) {
+                return Err(keyframes.error("give either transform or keyframes, not both"));
+            }
+            ObjectDescription::Group {
+                transform: transform.map_or(Ok(Transform::default()), parse_transform)?,
+                keyframes: keyframes.map_or(Ok(Track::default()), parse_transform_keyframes)?,
+                objects: parse_objects(fields.required("objects")?, materials)?,
+            }
+        }
+        other => {
+            return Err(kind.error(format!(
+                "unknown object type \"{}\", expected sphere, moving_sphere, quad, box, mesh, \
+                 constant_medium or group",
+                other
+            )));
+        }
+    };
+    fields.finish()?;
+    Ok(object)
+}
+
+fn parse_objects(
+    json: &Json,
+    materials: &[(String, MaterialDescription)],
+) -> io::Result<Vec<ObjectDescription>> {
+    json.as_array()?
+        .iter()
+        .map(|object| parse_object(object, materials))
+        .collect()
+}
+
+impl SceneDescription {
+    pub fn parse(text: &str) -> io::Result<SceneDescription> {
+        let json = Json::parse(text)?;
+        let mut fields = Fields::new(&json, "scene")?;
+
+        let mut animation = SceneAnimation::default();
+        let camera = match fields.optional("camera") {
+            Some(v) => {
+                let (camera, tracks) = parse_camera(v)?;
+                animation.camera = tracks;
+                camera
+            }
+            None => CameraSettings::default(),
+        };
+        let render = fields
+            .optional("render")
+            .map_or(Ok(RenderSettings::default()), parse_render)?;
+        let mut materials = Vec::new();
+        if let Some(v) = fields.optional("materials") {
+            for (name, m) in v.as_object()? {
+                let (material, tracks) = parse_material(m)?;
+                materials.push((name.clone(), material));
+                if let Some(tracks) = tracks {
+                    animation.materials.push((name.clone(), tracks));
+                }
+            }
+        }
+        let objects = match fields.optional("objects") {
+            Some(v) => parse_objects(v, &materials)?,
+            None => Vec::new(),
+        };
+        if let Some(v) = fields.optional("animation") {
+            animation.timeline = Some(parse_timeline(v)?);
+        }
+        fields.finish()?;
+
+        Ok(SceneDescription {
+            camera,
+            render,
+            materials,
+            objects,
+            animation,
+        })
+    }
+
+    pub fn load(file_name: &str) -> io::Result<SceneDescription> {
+        let text = fs::read_to_string(file_name)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", file_name, e)))?;
+        SceneDescription::parse(&text).map_err(|e| invalid_data(format!("{}:{}", file_name, e)))
+    }
+
+    // Camera settings with the keyframed parameters at time
+    pub fn camera_at(&self, time: f64) -> CameraSettings {
+        let mut camera = self.camera;
+        self.animation.camera.apply(&mut camera, time);
+        camera
+    }
+
+    pub fn build_camera(&self) -> Camera {
+        self.camera_at(0.0).build(&self.render)
+    }
+
+    // The objects in a BVH, as a still at time 0 sees them over the default shutter interval
+    pub fn build_world(&self) -> io::Result<Hittable_List> {
+        self.build_world_over(Interval { min: 0.0, max: 1.0 })
+    }
+
+    // Frame of the animation, or of the default timeline for a scene without one
+    pub fn build_frame(&self, frame: u64) -> io::Result<Frame> {
+        let timeline = self.animation.timeline.unwrap_or_default();
+        let shutter = timeline.shutter_interval(frame);
+        Ok(Frame {
+            camera: self.camera_at(shutter.min),
+            shutter,
+            world: self.build_world_over(shutter)?,
+        })
+    }
+
+    // The objects in a BVH with materials as they are at shutter open. Keyframed groups are
+    // MotionTransforms placing their objects by the pose at each ray's time, the vertices of
+    // meshes move in a straight line between their own keyframes.
+    fn build_world_over(&self, shutter: Interval) -> io::Result<Hittable_List> {
+        let materials = self
+            .materials
+            .iter()
+            .map(|(name, m)| {
+                let m = match self.animation.materials.iter().find(|(n, _)| n == name) {
+                    Some((_, tracks)) => tracks.apply(m.clone(), shutter.min),
+                    None => m.clone(),
+                };
+                let material: Arc<dyn Material> = match m {
+                    MaterialDescription::Lambertian { albedo } => {
+                        Arc::new(Lambertian::from_texture(albedo.build()?))
+                    }
+                    MaterialDescription::Metal { albedo, fuzz } => Arc::new(Metal { albedo, fuzz }),
+                    MaterialDescription::Dielectric { refraction_index } => {
+                        Arc::new(Dielectric { refraction_index })
+                    }
+                    MaterialDescription::DiffuseLight { emit } => {
+                        Arc::new(DiffuseLight::from_texture(emit.build()?))
+                    }
+                };
+                Ok((name.as_str(), material))
+            })
+            .collect::<io::Result<Vec<(&str, Arc<dyn Material>)>>>()?;
+
+        let placement = Placement {
+            transforms: Vec::new(),
+            shutter,
+        };
+        let mut world = Hittable_List::new();
+        for object in &self.objects {
+            add_object(&mut world, object, &placement, &materials)?;
+        }
+        if world.objects.is_empty() {
+            return Ok(world);
+        }
+        let bvh = BVH_Node::new(&mut world);
+        Ok(Hittable_List::new_from_hittable(Arc::new(bvh)))
+    }
+
+    pub fn build(&self) -> io::Result<Scene> {
+        Ok(Scene {
+            camera: self.build_camera(),
+            world: self.build_world()?,
+        })
+    }
+}
+
+// One frame of an animation, the camera as it is when the shutter opens and the objects
+// moving over the shutter interval
+pub struct Frame {
+    pub camera: CameraSettings,
+    pub shutter: Interval,
+    pub world: Hittable_List,
+}
+
+// Error for an object or material the scene format has no way to describe
+pub(crate) fn cannot_save(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("{} can't be saved in a scene file", what),
+    )
+}
+
+// A world taken apart into descriptions while saving it, filled by Hittable::export. Equal
+// materials share one name, numbered in the order they are met.
+#[derive(Default)]
+pub struct SceneExport {
+    materials: Vec<(String, MaterialDescription)>,
+    names: HashMap<String, String>, // material JSON to name
+    objects: Vec<ObjectDescription>,
+}
+
+impl SceneExport {
+    // Name of material in the saved scene
+    pub fn material(&mut self, material: &Arc<dyn Material>) -> io::Result<String> {
+        let description = material.describe()?;
+        let json = write_material(&description, None)?;
+        if let Some(name) = self.names.get(&json) {
+            return Ok(name.clone());
+        }
+        let name = format!("material_{}", self.materials.len());
+        self.names.insert(json, name.clone());
+        self.materials.push((name.clone(), description));
+        Ok(name)
+    }
+
+    pub fn add(&mut self, object: ObjectDescription) {
+        self.objects.push(object);
+    }
+
+    // The objects export adds, kept apart from the others, e.g. the children of an instance
+    pub fn objects(
+        &mut self,
+        export: impl FnOnce(&mut SceneExport) -> io::Result<()>,
+    ) -> io::Result<Vec<ObjectDescription>> {
+        let outer = mem::take(&mut self.objects);
+        let result = export(self);
+        let inner = mem::replace(&mut self.objects, outer);
+        result.map(|()| inner)
+    }
+}
+
+// JSON number, which has no infinities or NaN. Display prints the shortest decimal that
+// parses back to the same f64, so values survive a save and load exactly.
+fn write_number(x: f64) -> io::Result<String> {
+    if !x.is_finite() {
+        return Err(invalid_data(format!("can't save {} in a scene file", x)));
+    }
+    Ok(format!("{}", x))
+}
+
+fn write_vec3(v: Vec3) -> io::Result<String> {
+    Ok(format!(
+        "[{}, {}, {}]",
+        write_number(v.x)?,
+        write_number(v.y)?,
+        write_number(v.z)?
+    ))
+}
+
+fn write_material(
+    material: &MaterialDescription,
+    tracks: Option<&MaterialTracks>,
+) -> io::Result<String> {
+    let keyframes = match tracks {
+        Some(tracks) => write_keyframes(&[
+            ("albedo", vec3_keys(&tracks.albedo)?),
+            ("fuzz", number_keys(&tracks.fuzz)?),
+            ("refraction_index", number_keys(&tracks.refraction_index)?),
+            ("emit", vec3_keys(&tracks.emit)?),
+        ])?,
+        None => String::new(),
+    };
+    Ok(match material {
+        MaterialDescription::Lambertian { albedo } => format!(
+            "{{\"type\": \"lambertian\", \"albedo\": {}{}}}",
+            write_texture(albedo)?,
+            keyframes
+        ),
+        MaterialDescription::Metal { albedo, fuzz } => format!(
+            "{{\"type\": \"metal\", \"albedo\": {}, \"fuzz\": {}{}}}",
+            write_vec3(*albedo)?,
+            write_number(*fuzz)?,
+            keyframes
+        ),
+        MaterialDescription::Dielectric { refraction_index } => format!(
+            "{{\"type\": \"dielectric\", \"refraction_index\": {}{}}}",
+            write_number(*refraction_index)?,
+            keyframes
+        ),
+        MaterialDescription::DiffuseLight { emit } => format!(
+            "{{\"type\": \"diffuse_light\", \"emit\": {}{}}}",
+            write_texture(emit)?,
+            keyframes
+        ),
+    })
+}
+
+fn write_texture(texture: &TextureDescription) -> io::Result<String> {
+    Ok(match texture {
+        TextureDescription::Solid(color) => write_vec3(*color)?,
+        TextureDescription::Checker { scale, even, odd } => format!(
+            "{{\"type\": \"checker\", \"scale\": {}, \"even\": {}, \"odd\": {}}}",
+            write_number(*scale)?,
+            write_texture(even)?,
+            write_texture(odd)?
+        ),
+        TextureDescription::Noise { scale, seed } => format!(
+            "{{\"type\": \"noise\", \"scale\": {}, \"seed\": {}}}",
+            write_number(*scale)?,
+            seed
+        ),
+        TextureDescription::Image { file } => {
+            format!("{{\"type\": \"image\", \"file\": {}}}", quote(file))
+        }
+    })
+}
+
+fn vec3_keys(track: &Track<Vec3>) -> io::Result<Vec<(f64, String)>> {
+    track
+        .keys()
+        .iter()
+        .map(|(t, v)| Ok((*t, write_vec3(*v)?)))
+        .collect()
+}
+
+fn number_keys(track: &Track<f64>) -> io::Result<Vec<(f64, String)>> {
+    track
+        .keys()
+        .iter()
+        .map(|(t, v)| Ok((*t, write_number(*v)?)))
+        .collect()
+}
+
+// The "keyframes" key, merging the keys the named tracks have at the same time into one
+// keyframe, or nothing if the tracks have no keys
+fn write_keyframes(tracks: &[(&str, Vec<(f64, String)>)]) -> io::Result<String> {
+    let mut times: Vec<f64> = tracks
+        .iter()
+        .flat_map(|(_, keys)| keys.iter().map(|(t, _)| *t))
+        .collect();
+    if times.is_empty() {
+        return Ok(String::new());
+    }
+    times.sort_by(f64::total_cmp);
+    times.dedup();
+    let mut keyframes = Vec::new();
+    for time in times {
+        let mut keyframe = format!("{{\"time\": {}", write_number(time)?);
+        for (name, keys) in tracks {
+            if let Some((_, value)) = keys.iter().find(|(t, _)| *t == time) {
+                keyframe.push_str(&format!(", \"{}\": {}", name, value));
+            }
+        }
+        keyframe.push('}');
+        keyframes.push(keyframe);
+    }
+    Ok(format!(", \"keyframes\": [{}]", keyframes.join(", ")))
+}
+
+fn write_transform(transform: &Transform) -> io::Result<String> {
+    Ok(format!(
+        "\"translate\": {}, \"rotate\": {}, \"scale\": {}",
+        write_vec3(transform.translate)?,
+        write_vec3(transform.rotate)?,
+        write_number(transform.scale)?
+    ))
+}
+
+fn write_camera(camera: &CameraSettings, tracks: &CameraTracks) -> io::Result<String> {
+    let keyframes = write_keyframes(&[
+        ("lookfrom", vec3_keys(&tracks.lookfrom)?),
+        ("lookat", vec3_keys(&tracks.lookat)?),
+        ("vfov", number_keys(&tracks.vfov)?),
+    ])?;
+    Ok(format!(
+        "{{\"aspect_ratio\": {}, \"image_width\": {}, \"vfov\": {}, \"lookfrom\": {}, \
+         \"lookat\": {}, \"vup\": {}, \"defocus_angle\": {}, \"focus_dist\": {}{}}}",
+        write_number(camera.aspect_ratio)?,
+        camera.image_width,
+        write_number(camera.vfov)?,
+        write_vec3(camera.lookfrom)?,
+        write_vec3(camera.lookat)?,
+        write_vec3(camera.vup)?,
+        write_number(camera.defocus_angle)?,
+        write_number(camera.focus_dist)?,
+        keyframes
+    ))
+}
+
+fn write_render(render: &RenderSettings) -> io::Result<String> {
+    if render.seed > 1 << 53 {
+        return Err(invalid_data(format!(
+            "can't save seed {}, JSON numbers hold whole numbers up to 2^53",
+            render.seed
+        )));
+    }
+    let sampler = match render.sampler {
+        SamplerKind::Independent => "independent",
+        SamplerKind::Stratified => "stratified",
+        SamplerKind::Halton => "halton",
+        SamplerKind::Sobol => "sobol",
+    };
+    let filter = match render.filter.kind {
+        FilterKind::Box => "\"type\": \"box\"".to_string(),
+        FilterKind::Tent => "\"type\": \"tent\"".to_string(),
+        FilterKind::Gaussian { sigma } => {
+            format!(
+                "\"type\": \"gaussian\", \"sigma\": {}",
+                write_number(sigma)?
+            )
+        }
+        FilterKind::Mitchell { b, c } => format!(
+            "\"type\": \"mitchell\", \"b\": {}, \"c\": {}",
+            write_number(b)?,
+            write_number(c)?
+        ),
+        FilterKind::Lanczos { tau } => {
+            format!("\"type\": \"lanczos\", \"tau\": {}", write_number(tau)?)
+        }
+    };
+    let background = match render.background {
+        Some(color) => format!(", \"background\": {}", write_vec3(color)?),
+        None => String::new(),
+    };
+    Ok(format!(
+        "{{\"samples_per_pixel\": {}, \"max_depth\": {}, \"seed\": {}, \"sampler\": \"{}\", \
+         \"filter\": {{{}, \"radius\": {}}}{}}}",
+        render.samples_per_pixel,
+        render.max_depth,
+        render.seed,
+        sampler,
+        filter,
+        write_number(render.filter.radius)?,
+        background
+    ))
+}
+
+// One object per line, the objects of groups indented below them
+fn write_objects(objects: &[ObjectDescription], indent: usize, out: &mut String) -> io::Result<()> {
+    let pad = " ".repeat(indent);
+    for (i, object) in objects.iter().enumerate() {
+        let separator = if i + 1 < objects.len() { "," } else { "" };
+        match object {
+            ObjectDescription::Sphere {
+                center,
+                radius,
+                material,
+            } => out.push_str(&format!(
+                "{}{{\"type\": \"sphere\", \"center\": {}, \"radius\": {}, \"material\": {}}}{}\n",
+                pad,
+                write_vec3(*center)?,
+                write_number(*radius)?,
+                quote(material),
+                separator
+            )),
+            ObjectDescription::MovingSphere {
+                center,
+                offset,
+                motion_time,
+                radius,
+                material,
+            } => out.push_str(&format!(
+                "{}{{\"type\": \"moving_sphere\", \"center\": {}, \"offset\": {}, \
+                 \"motion_time\": [{}, {}], \"radius\": {}, \"material\": {}}}{}\n",
+                pad,
+                write_vec3(*center)?,
+                write_vec3(*offset)?,
+                write_number(motion_time.min)?,
+                write_number(motion_time.max)?,
+                write_number(*radius)?,
+                quote(material),
+                separator
+            )),
+            ObjectDescription::Quad { q, u, v, material } => out.push_str(&format!(
+                "{}{{\"type\": \"quad\", \"q\": {}, \"u\": {}, \"v\": {}, \"material\": {}}}{}\n",
+                pad,
+                write_vec3(*q)?,
+                write_vec3(*u)?,
+                write_vec3(*v)?,
+                quote(material),
+                separator
+            )),
+            ObjectDescription::Box { a, b, material } => out.push_str(&format!(
+                "{}{{\"type\": \"box\", \"a\": {}, \"b\": {}, \"material\": {}}}{}\n",
+                pad,
+                write_vec3(*a)?,
+                write_vec3(*b)?,
+                quote(material),
+                separator
+            )),
+            ObjectDescription::ConstantMedium {
+                density,
+                albedo,
+                boundary,
+            } => {
+                out.push_str(&format!(
+                    "{}{{\"type\": \"constant_medium\", \"density\": {}, \"albedo\": {}, \
+                     \"boundary\": [\n",
+                    pad,
+                    write_number(*density)?,
+                    write_texture(albedo)?
+                ));
+                write_objects(boundary, indent + 2, out)?;
+                out.push_str(&format!("{}]}}{}\n", pad, separator));
+            }
+            ObjectDescription::Mesh {
+                file,
+                material,
+                keyframes,
+            } => {
+                let material = match material {
+                    Some(name) => format!(", \"material\": {}", quote(name)),
+                    None => String::new(),
+                };
+                let files = keyframes.iter().map(|(t, f)| (*t, quote(f))).collect();
+                out.push_str(&format!(
+                    "{}{{\"type\": \"mesh\", \"file\": {}{}{}}}{}\n",
+                    pad,
+                    quote(file),
+                    material,
+                    write_keyframes(&[("file", files)])?,
+                    separator
+                ));
+            }
+            ObjectDescription::Group {
+                transform,
+                keyframes,
+                objects,
+            } => {
+                let motion = if keyframes.is_empty() {
+                    format!("\"transform\": {{{}}}", write_transform(transform)?)
+                } else {
+                    let keyframes = keyframes
+                        .keys()
+                        .iter()
+                        .map(|(time, t)| {
+                            Ok(format!(
+                                "{{\"time\": {}, {}}}",
+                                write_number(*time)?,
+                                write_transform(t)?
+                            ))
+                        })
+                        .collect::<io::Result<Vec<_>>>()?;
+                    format!("\"keyframes\": [{}]", keyframes.join(", "))
+                };
+                out.push_str(&format!(
+                    "{}{{\"type\": \"group\", {}, \"objects\": [\n",
+                    pad, motion
+                ));
+                write_objects(objects, indent + 2, out)?;
+                out.push_str(&format!("{}]}}{}\n", pad, separator));
+            }
+        }
+    }
+    Ok(())
+}
+
+impl SceneDescription {
+    // Freeze a world built in code, e.g. a random scene, into a description that builds the
+    // same world again
+    pub fn from_world(
+        camera: CameraSettings,
+        render: RenderSettings,
+        world: &dyn Hittable,
+    ) -> io::Result<SceneDescription> {
+        let mut export = SceneExport::default();
+        world.export(&mut export)?;
+        Ok(SceneDescription {
+            camera,
+            render,
+            materials: export.materials,
+            objects: export.objects,
+            animation: SceneAnimation::default(),
+        })
+    }
+
+    // The description in the JSON scene format, one material or object per line so saved
+    // scenes diff well
+    pub fn to_json(&self) -> io::Result<String> {
+        let mut out = String::from("{\n");
+        out.push_str(&format!(
+            "  \"camera\": {},\n",
+            write_camera(&self.camera, &self.animation.camera)?
+        ));
+        out.push_str(&format!("  \"render\": {},\n", write_render(&self.render)?));
+        out.push_str("  \"materials\": {\n");
+        for (i, (name, material)) in self.materials.iter().enumerate() {
+            let separator = if i + 1 < self.materials.len() {
+                ","
+            } else {
+                ""
+            };
+            let tracks = self.animation.materials.iter().find(|(n, _)| n == name);
+            out.push_str(&format!(
+                "    {}: {}{}\n",
+                quote(name),
+                write_material(material, tracks.map(|(_, t)| t))?,
+                separator
+            ));
+        }
+        out.push_str("  },\n  \"objects\": [\n");
+        write_objects(&self.objects, 4, &mut out)?;
+        out.push_str("  ]");
+        if let Some(timeline) = &self.animation.timeline {
+            out.push_str(&format!(
+                ",\n  \"animation\": {{\"fps\": {}, \"frames\": [{}, {}], \"shutter\": {}}}",
+                write_number(timeline.fps)?,
+                timeline.first_frame,
+                timeline.last_frame,
+                write_number(timeline.shutter)?
+            ));
+        }
+        out.push_str("\n}\n");
+        Ok(out)
+    }
+
+    pub fn save(&self, file_name: &str) -> io::Result<()> {
+        fs::write(file_name, self.to_json()?)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", file_name, e)))
+    }
+}
+
+// Transforms of the static groups around an object, innermost first. A keyframed group starts
+// a new placement inside the MotionTransform it builds.
+#[derive(Clone)]
+struct Placement {
+    transforms: Vec<Transform>,
+    shutter: Interval,
+}
+
+// Slerp turns the shorter way, so keyframes further apart than this about any axis get keys in
+// between, each stretch then turning less than half a turn
+const MAX_KEY_ROTATION: f64 = 45.0;
+
+impl Placement {
+    fn point(&self, p: Point3) -> Point3 {
+        self.transforms.iter().fold(p, |p, t| t.apply(p))
+    }
+
+    fn vector(&self, v: Vec3) -> Vec3 {
+        self.transforms.iter().fold(v, |v, t| t.apply_vector(v))
+    }
+
+    fn quad(&self, q: Point3, u: Vec3, v: Vec3, material: Arc<dyn Material>) -> Quad {
+        Quad::new(self.point(q), self.vector(u), self.vector(v), material)
+    }
+
+    // The keyframes of a group as poses in the space of the groups around it. The static
+    // transforms are composed into every key, which interpolates the same as applying them
+    // to the interpolated pose.
+    fn poses(&self, keyframes: &Track<Transform>) -> io::Result<Track<Pose>> {
+        let keys = keyframes.keys();
+        let mut transforms = vec![keys[0]];
+        for stretch in keys.windows(2) {
+            let ((t0, a), (t1, b)) = (stretch[0], stretch[1]);
+            let turn = b.rotate - a.rotate;
+            let widest = turn.x.abs().max(turn.y.abs()).max(turn.z.abs());
+            let steps = (widest / MAX_KEY_ROTATION).ceil().max(1.0) as usize;
+            for i in 1..=steps {
+                let s = i as f64 / steps as f64;
+                transforms.push((t0 + s * (t1 - t0), a.lerp(b, s)));
+            }
+        }
+        let poses = transforms
+            .into_iter()
+            .map(|(time, transform)| {
+                let pose = self
+                    .transforms
+                    .iter()
+                    .fold(transform.pose(), |pose, t| Pose {
+                        translate: t.apply(pose.translate),
+                        rotation: t.rotation() * pose.rotation,
+                        scale: t.scale * pose.scale,
+                    });
+                (time, pose)
+            })
+            .collect();
+        Track::new(poses)
+    }
+}
+
+// Add object to world with the transforms of its enclosing static groups baked in
+fn add_object(
+    world: &mut Hittable_List,
+    object: &ObjectDescription,
+    placement: &Placement,
+    materials: &[(&str, Arc<dyn Material>)],
+) -> io::Result<()> {
+    let material = |name: &str| {
+        materials
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, m)| m.clone())
+            .ok_or_else(|| invalid_data(format!("undefined material \"{}\"", name)))
+    };
+    let point = |p: Point3| placement.point(p);
+    let scale: f64 = placement.transforms.iter().map(|t| t.scale).product();
+
+    let hittable: Arc<dyn Hittable> = match object {
+        ObjectDescription::Sphere {
+            center,
+            radius,
+            material: name,
+        } => Arc::new(Sphere::new_static(
+            point(*center),
+            radius * scale,
+            material(name)?,
+        )),
+        ObjectDescription::MovingSphere {
+            center,
+            offset,
+            motion_time,
+            radius,
+            material: name,
+        } => Arc::new(if placement.transforms.is_empty() {
+            Sphere::new_moving_by(*center, *offset, *motion_time, *radius, material(name)?)
+        } else {
+            Sphere::new_moving_between(
+                point(*center),
+                point(*center + *offset),
+                *motion_time,
+                radius * scale,
+                material(name)?,
+            )
+        }),
+        ObjectDescription::Quad {
+            q,
+            u,
+            v,
+            material: name,
+        } => Arc::new(placement.quad(*q, *u, *v, material(name)?)),
+        ObjectDescription::Box {
+            a,
+            b,
+            material: name,
+        } => {
+            let material = material(name)?;
+            for (q, u, v) in box_sides(*a, *b) {
+                world.add(Arc::new(placement.quad(q, u, v, material.clone())));
+            }
+            return Ok(());
+        }
+        ObjectDescription::ConstantMedium {
+            density,
+            albedo,
+            boundary,
+        } => {
+            let mut inside = Hittable_List::new();
+            for object in boundary {
+                add_object(&mut inside, object, placement, materials)?;
+            }
+            let boundary = match inside.objects.as_slice() {
+                [object] => object.clone(),
+                _ => Arc::new(inside),
+            };
+            Arc::new(ConstantMedium::from_texture(
+                boundary,
+                *density,
+                albedo.build()?,
+            ))
+        }
+        ObjectDescription::Mesh {
+            file,
+            material: name,
+            keyframes,
+        } => {
+            let mut mesh = TriangleMesh::load(file)?;
+            for (time, key_file) in keyframes {
+                let key = TriangleMesh::load(key_file)?;
+                if key.positions.len() != mesh.positions.len() || key.triangles != mesh.triangles {
+                    return Err(invalid_data(format!(
+                        "{}: the vertices or faces differ from those of {}",
+                        key_file, file
+                    )));
+                }
+                mesh.position_keys.push((*time, key.positions));
+            }
+            if !mesh.position_keys.is_empty() {
+                // keys at shutter open and close and at the mesh keyframes in between
+                let shutter = placement.shutter;
+                let mut times = vec![shutter.min];
+                times.extend(
+                    mesh.position_keys
+                        .iter()
+                        .map(|(t, _)| *t)
+                        .filter(|t| shutter.min < *t && *t < shutter.max),
+                );
+                if shutter.size() > 0.0 {
+                    times.push(shutter.max);
+                }
+                let keys = times
+                    .iter()
+                    .map(|time| {
+                        let positions = mesh.positions_at(*time);
+                        (*time, positions.iter().map(|p| point(*p)).collect())
+                    })
+                    .collect();
+                mesh.positions = mesh.positions_at(shutter.min);
+                mesh.position_keys = keys;
+            }
+            for p in &mut mesh.positions {
+                *p = point(*p);
+            }
+            // the scale is uniform, so normals only turn with the rotations
+            for n in &mut mesh.normals {
+                *n = placement.vector(*n).unit_vector();
+            }
+            let triangles = match name {
+                Some(name) => mesh.build(material(name)?),
+                None => mesh
+                    .build_vertex_colored(|t| Arc::new(Lambertian::from_texture(t)))
+                    .map_err(|e| invalid_data(format!("{}: {}, give it a material", file, e)))?,
+            };
+            for triangle in triangles.objects {
+                world.add(triangle);
+            }
+            return Ok(());
+        }
+        ObjectDescription::Group {
+            transform,
+            keyframes,
+            objects,
+        } if keyframes.is_empty() => {
+            let mut inner = placement.clone();
+            inner.transforms.insert(0, *transform);
+            for object in objects {
+                add_object(world, object, &inner, materials)?;
+            }
+            return Ok(());
+        }
+        ObjectDescription::Group {
+            keyframes, objects, ..
+        } => {
+            // the objects in the group's own space, placed by its pose at each ray's time
+            let inner = Placement {
+                transforms: Vec::new(),
+                shutter: placement.shutter,
+            };
+            let mut local = Hittable_List::new();
+            for object in objects {
+                add_object(&mut local, object, &inner, materials)?;
+            }
+            if local.objects.is_empty() {
+                return Ok(());
+            }
+            Arc::new(MotionTransform::new_over(
+                Arc::new(BVH_Node::new(&mut local)),
+                placement.poses(keyframes)?,
+                placement.shutter,
+            ))
+        }
+    };
+    world.add(hittable);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Isotropic, ray::Ray, texture::Texture};
+
+    fn error(text: &str) -> String {
+        match SceneDescription::parse(text) {
+            Ok(_) => panic!("{} parsed", text),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn example_scene_parses() {
+        let scene = SceneDescription::parse(include_str!("../scenes/three_spheres.json")).unwrap();
+        assert_eq!(scene.materials.len(), 4);
+        assert_eq!(scene.objects.len(), 3);
+        assert_eq!(scene.render.sampler, SamplerKind::Sobol);
+        let scene = scene.build().unwrap();
+        assert_eq!(scene.camera.image_width(), 400);
+    }
+
+    #[test]
+    fn groups_bake_their_transform_into_spheres() {
+        let t = Transform {
+            translate: Vec3::new(1.0, 0.0, 0.0),
+            rotate: Vec3::new(0.0, 0.0, 90.0),
+            scale: 2.0,
+        };
+        let p = t.apply(Point3::new(1.0, 0.0, 0.0));
+        assert!((p - Point3::new(1.0, 2.0, 0.0)).length() < 1e-12);
+
+        // keyframed groups place their objects by the same transform as a pose
+        let t = Transform {
+            rotate: Vec3::new(30.0, -70.0, 110.0),
+            ..t
+        };
+        let p = Point3::new(0.3, -1.2, 2.0);
+        assert!((t.pose().apply(p) - t.apply(p)).length() < 1e-12);
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_value() {
+        assert_eq!(
+            error("{\"camera\": {\"vfov\": 40, \"fov\": 20}}"),
+            "1:32: unknown key \"fov\" in camera, expected one of: aspect_ratio, image_width, \
+             vfov, lookfrom, lookat, vup, defocus_angle, focus_dist, keyframes"
+        );
+        assert_eq!(
+            error("{\"render\": {\"samples_per_pixel\": 2.5}}"),
+            "1:34: expected a whole number from 1 to 65535, found 2.5"
+        );
+        assert_eq!(
+            error("{\"objects\": [\n  {\"type\": \"sphere\", \"center\": [0, 0], \"radius\": 1}]}"),
+            "2:32: expected an array of 3 numbers, found 2 items"
+        );
+        assert_eq!(
+            error(
+                "{\"objects\": [{\"type\": \"sphere\", \"center\": [0, 0, 0], \"radius\": 1, \
+                 \"material\": \"gold\"}]}"
+            ),
+            "1:79: undefined material \"gold\""
+        );
+        assert_eq!(
+            error("{\"materials\": {\"m\": {\"type\": \"lambertian\"}}}"),
+            "1:21: material is missing \"albedo\""
+        );
+        assert_eq!(
+            error(
+                "{\"objects\": [{\"type\": \"group\", \"transform\": {\"scale\": [1, 2, 1]}, \
+                 \"objects\": []}]}"
+            ),
+            "1:55: group transforms only allow a uniform scale, expected a number"
+        );
+    }
+
+    #[test]
+    fn frames_sample_the_keyframes_at_shutter_open() {
+        let scene = SceneDescription::parse(include_str!("../scenes/bouncing_ball.json")).unwrap();
+        let timeline = scene.animation.timeline.unwrap();
+        assert_eq!((timeline.first_frame, timeline.last_frame), (0, 47));
+
+        // frame 12 opens at 0.5 s, a quarter of the way between the keyframes at 0 and 2 s. vfov
+        // only has the one at 2 s, which holds before it.
+        let frame = scene.build_frame(12).unwrap();
+        assert_eq!(
+            (frame.shutter.min, frame.shutter.max),
+            (0.5, 0.5 + 0.5 / 24.0)
+        );
+        assert_eq!((frame.camera.lookfrom.x, frame.camera.vfov), (-1.5, 25.0));
+
+        // the ball is at its lowest, [-1, 0.5, 0], and bounces back up while the shutter is open
+        let down = |time: f64| Ray {
+            origin: Point3::new(-1.0, 5.0, 0.0),
+            dir: Vec3::new(0.0, -1.0, 0.0),
+            time,
+            medium_sample: 0.0,
+        };
+        let everywhere = Interval {
+            min: 0.001,
+            max: f64::INFINITY,
+        };
+        let open = frame.world.hit(&down(0.5), everywhere).unwrap();
+        assert!((open.p.y - 1.0).abs() < 1e-9);
+        assert!((open.material.albedo(&open).x - 0.65).abs() < 1e-12);
+        let close = frame
+            .world
+            .hit(&down(frame.shutter.max), everywhere)
+            .unwrap();
+        assert!(close.p.y > 1.03);
+
+        let json = scene.to_json().unwrap();
+        assert_eq!(
+            SceneDescription::parse(&json).unwrap().to_json().unwrap(),
+            json
+        );
+    }
+
+    #[test]
+    fn keyframes_must_move_forward_in_time() {
+        assert_eq!(
+            error(
+                "{\"camera\": {\"keyframes\": [{\"time\": 1, \"vfov\": 30}, \
+                 {\"time\": 0.5, \"vfov\": 40}]}}"
+            ),
+            "1:61: keyframe times must increase, 0.5 follows 1"
+        );
+        assert_eq!(
+            error(
+                "{\"materials\": {\"m\": {\"type\": \"lambertian\", \"albedo\": [1, 1, 1], \
+                 \"keyframes\": [{\"time\": 0, \"fuzz\": 1}]}}}"
+            ),
+            "1:99: unknown key \"fuzz\" in material keyframe, expected one of: time, albedo"
+        );
+        assert_eq!(
+            error(
+                "{\"objects\": [{\"type\": \"group\", \"transform\": {}, \"keyframes\": [], \
+                 \"objects\": []}]}"
+            ),
+            "1:62: give either transform or keyframes, not both"
+        );
+        assert_eq!(
+            error("{\"animation\": {\"frames\": [10, 2]}}"),
+            "1:31: the last frame comes before the first"
+        );
+    }
+
+    #[test]
+    fn textures_describe_albedo_and_emission() {
+        let text = "{\"materials\": {
+              \"floor\": {\"type\": \"lambertian\", \"albedo\": {\"type\": \"checker\",
+                          \"scale\": 2, \"even\": [1, 1, 1],
+                          \"odd\": {\"type\": \"noise\", \"scale\": 4, \"seed\": 3}}},
+              \"lamp\": {\"type\": \"diffuse_light\", \"emit\": {\"type\": \"checker\",
+                         \"even\": [4, 4, 4], \"odd\": [0, 0, 0]}}},
+            \"objects\": [{\"type\": \"sphere\", \"center\": [0, -100, 0], \"radius\": 100,
+                           \"material\": \"floor\"}]}";
+        let scene = SceneDescription::parse(text).unwrap();
+        let world = scene.build_world().unwrap();
+        let down = |x: f64| Ray {
+            origin: Point3::new(x, 1.0, 0.5),
+            dir: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+            medium_sample: 0.0,
+        };
+        let everywhere = Interval {
+            min: 0.001,
+            max: f64::INFINITY,
+        };
+        // the cube [0, 2) of the hit at y just below 0 is odd, [2, 4) is even
+        let rec = world.hit(&down(3.0), everywhere).unwrap();
+        assert_eq!(rec.material.albedo(&rec).x, 1.0);
+        let rec = world.hit(&down(1.0), everywhere).unwrap();
+        let noise = NoiseTexture::with_seed(4.0, 3).value(rec.u, rec.v, rec.p);
+        assert_eq!(rec.material.albedo(&rec).x, noise.x);
+
+        let json = scene.to_json().unwrap();
+        assert!(json.contains(
+            "\"albedo\": {\"type\": \"checker\", \"scale\": 2, \"even\": [1, 1, 1], \
+             \"odd\": {\"type\": \"noise\", \"scale\": 4, \"seed\": 3}}"
+        ));
+        assert_eq!(
+            SceneDescription::parse(&json).unwrap().to_json().unwrap(),
+            json
+        );
+
+        assert_eq!(
+            error(
+                "{\"materials\": {\"m\": {\"type\": \"lambertian\", \
+                 \"albedo\": {\"type\": \"noise\", \"scal\": 4}}}}"
+            ),
+            "1:80: unknown key \"scal\" in texture, expected one of: type, scale, seed"
+        );
+        assert_eq!(
+            error(
+                "{\"materials\": {\"m\": {\"type\": \"lambertian\", \
+                 \"albedo\": {\"type\": \"marble\"}}}}"
+            ),
+            "1:63: unknown texture type \"marble\", expected checker, noise or image"
+        );
+        assert_eq!(
+            error(
+                "{\"materials\": {\"m\": {\"type\": \"lambertian\", \
+                 \"albedo\": {\"type\": \"image\", \"file\": \"a.ppm\"}, \
+                 \"keyframes\": [{\"time\": 0, \"albedo\": [1, 1, 1]}]}}}"
+            ),
+            "1:126: only a plain color can have keyframes, not a texture"
+        );
+    }
+
+    #[test]
+    fn boxes_and_media_take_the_group_transform() {
+        let scene = |objects: &str| {
+            let text = format!(
+                "{{\"materials\": {{\"m\": {{\"type\": \"lambertian\", \"albedo\": [1, 1, 1]}}}},
+                  \"objects\": [{}]}}",
+                objects
+            );
+            SceneDescription::parse(&text)?.build_world()
+        };
+        let world = scene(
+            "{\"type\": \"group\", \"transform\": {\"translate\": [0, 0, -2], \"rotate\": [0, 90, 0]},
+              \"objects\": [{\"type\": \"box\", \"a\": [0, 0, 0], \"b\": [1, 1, 2], \"material\": \"m\"},
+                            {\"type\": \"constant_medium\", \"density\": 1e9, \"albedo\": [0, 1, 0],
+                             \"boundary\": [{\"type\": \"box\", \"a\": [0, 2, 0], \"b\": [1, 3, 1],
+                                            \"material\": \"m\"}]}]}",
+        )
+        .unwrap();
+        // the quarter turn about y takes the box's z extent [0, 2] to x, the flat sides of the
+        // box pad its bounds a little
+        let bbox = world.bounding_box();
+        assert!(bbox.x.min.abs() < 1e-3 && (bbox.x.max - 2.0).abs() < 1e-3);
+        assert!((bbox.z.min + 3.0).abs() < 1e-3 && (bbox.z.max + 2.0).abs() < 1e-3);
+        let down = |y: f64| Ray {
+            origin: Point3::new(0.5, y, -2.5),
+            dir: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+            medium_sample: 0.5,
+        };
+        let everywhere = Interval {
+            min: 0.001,
+            max: f64::INFINITY,
+        };
+        let rec = world.hit(&down(1.5), everywhere).unwrap();
+        assert!((rec.p.y - 1.0).abs() < 1e-9);
+        // the dense fog scatters right where the ray enters it
+        let rec = world.hit(&down(5.0), everywhere).unwrap();
+        assert!((rec.p.y - 3.0).abs() < 1e-6);
+        assert_eq!(rec.material.albedo(&rec).y, 1.0);
+
+        let err = |objects: &str| scene(objects).err().unwrap().to_string();
+        assert_eq!(
+            err(
+                "{\"type\": \"constant_medium\", \"density\": 1, \"albedo\": [1, 1, 1], \
+                 \"boundary\": []}"
+            ),
+            "2:106: a medium needs boundary objects to fill"
+        );
+
+        // a box in a keyframed group rides along with it
+        let world = scene(
+            "{\"type\": \"group\", \"keyframes\": [{\"time\": 0}, {\"time\": 1, \"translate\": [0, 0, -3]}],
+              \"objects\": [{\"type\": \"box\", \"a\": [0, 0, -3], \"b\": [1, 1, -2], \"material\": \"m\"}]}",
+        )
+        .unwrap();
+        let mut ray = down(1.5);
+        assert!((world.hit(&ray, everywhere).unwrap().p.y - 1.0).abs() < 1e-9);
+        ray.time = 1.0;
+        assert!(world.hit(&ray, everywhere).is_none());
+    }
+
+    #[test]
+    fn moving_spheres_in_keyframed_groups_stop_where_their_motion_ends() {
+        let text = "{\"materials\": {\"m\": {\"type\": \"lambertian\", \"albedo\": [1, 1, 1]}},
+            \"objects\": [{\"type\": \"group\",
+                           \"keyframes\": [{\"time\": 0}, {\"time\": 1, \"translate\": [0, 0, 1]}],
+                           \"objects\": [{\"type\": \"moving_sphere\", \"center\": [0, 0, 0],
+                                         \"offset\": [1, 0, 0], \"motion_time\": [0, 0.25],
+                                         \"radius\": 0.5, \"material\": \"m\"}]}]}";
+        let world = SceneDescription::parse(text)
+            .unwrap()
+            .build_world()
+            .unwrap();
+        // at shutter close the sphere has been resting at x = 1 since 0.25, not carried on to 4
+        let ray = Ray {
+            origin: Point3::new(1.0, 5.0, 1.0),
+            dir: Vec3::new(0.0, -1.0, 0.0),
+            time: 1.0,
+            medium_sample: 0.0,
+        };
+        let everywhere = Interval {
+            min: 0.001,
+            max: f64::INFINITY,
+        };
+        let rec = world.hit(&ray, everywhere).unwrap();
+        assert!((rec.p.y - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn rotating_groups_blur_along_their_arc() {
+        let text = "{\"materials\": {\"m\": {\"type\": \"lambertian\", \"albedo\": [1, 1, 1]}},
+            \"objects\": [{\"type\": \"group\",
+                           \"keyframes\": [{\"time\": 0}, {\"time\": 1, \"rotate\": [0, 180, 0]}],
+                           \"objects\": [{\"type\": \"sphere\", \"center\": [2, 0, 0],
+                                         \"radius\": 0.25, \"material\": \"m\"}]}]}";
+        let world = SceneDescription::parse(text)
+            .unwrap()
+            .build_world()
+            .unwrap();
+        let down = |x: f64, z: f64, time: f64| Ray {
+            origin: Point3::new(x, 5.0, z),
+            dir: Vec3::new(0.0, -1.0, 0.0),
+            time,
+            medium_sample: 0.0,
+        };
+        let everywhere = Interval {
+            min: 0.001,
+            max: f64::INFINITY,
+        };
+        // halfway through the half turn the sphere is a quarter of the way round at z = -2,
+        // not cutting through the middle of the chord from x = 2 to x = -2
+        let rec = world.hit(&down(0.0, -2.0, 0.5), everywhere).unwrap();
+        assert!((rec.p.y - 0.25).abs() < 1e-9);
+        assert!(world.hit(&down(0.0, 0.0, 0.5), everywhere).is_none());
+        // moving along the arc, a half turn of radius 2 per unit of time
+        assert!((rec.velocity.x + 2.0 * std::f64::consts::PI).abs() < 1e-3);
+        let rec = world.hit(&down(-2.0, 0.0, 1.0), everywhere).unwrap();
+        assert!((rec.p.y - 0.25).abs() < 1e-9);
+        let bbox = world.bounding_box();
+        assert!(bbox.z.min < -2.25 && bbox.z.max < 0.5);
+    }
+
+    #[test]
+    fn keyframed_groups_follow_every_key_inside_the_shutter() {
+        let world = |keyframes: &str| {
+            let text = format!(
+                "{{\"materials\": {{\"m\": {{\"type\": \"lambertian\", \"albedo\": [1, 1, 1]}}}},
+                  \"objects\": [{{\"type\": \"group\", \"keyframes\": {},
+                                  \"objects\": [{{\"type\": \"sphere\", \"center\": [2, 0, 0],
+                                                 \"radius\": 0.25, \"material\": \"m\"}}]}}]}}",
+                keyframes
+            );
+            SceneDescription::parse(&text)
+                .unwrap()
+                .build_world()
+                .unwrap()
+        };
+        let down = |x: f64, z: f64, time: f64| Ray {
+            origin: Point3::new(x, 5.0, z),
+            dir: Vec3::new(0.0, -1.0, 0.0),
+            time,
+            medium_sample: 0.0,
+        };
+        let hits = |world: &Hittable_List, x: f64, z: f64, time: f64| {
+            let everywhere = Interval {
+                min: 0.001,
+                max: f64::INFINITY,
+            };
+            world.hit(&down(x, z, time), everywhere).is_some()
+        };
+
+        // out to x = 4 and back while the shutter is open, where open and close agree
+        let there_and_back =
+            world("[{\"time\": 0}, {\"time\": 0.5, \"translate\": [2, 0, 0]}, {\"time\": 1}]");
+        assert!(hits(&there_and_back, 4.0, 0.0, 0.5));
+        assert!(!hits(&there_and_back, 2.0, 0.0, 0.5));
+        assert!(hits(&there_and_back, 2.0, 0.0, 1.0));
+        assert!(there_and_back.bounding_box().x.max > 4.25);
+
+        // a whole turn between two keys goes all the way round instead of standing still
+        let spin = world("[{\"time\": 0}, {\"time\": 1, \"rotate\": [0, 360, 0]}]");
+        assert!(hits(&spin, -2.0, 0.0, 0.5));
+        assert!(hits(&spin, 0.0, 2.0, 0.75));
+        assert!(hits(&spin, 2.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn meshes_load_with_the_group_transform() {
+        let file = std::env::temp_dir().join(format!("{}_tri.stl", std::process::id()));
+        std::fs::write(
+            &file,
+            "solid t\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\n\
+             vertex 0 1 0\nendloop\nendfacet\nendsolid t\n",
+        )
+        .unwrap();
+        let world = |mesh: String| {
+            let text = format!(
+                "{{\"materials\": {{\"m\": {{\"type\": \"lambertian\", \"albedo\": [1, 1, 1]}}}},
+                  \"objects\": [{{\"type\": \"group\", \"transform\": {{\"translate\": [0, 0, -2]}},
+                                  \"objects\": [{}]}}]}}",
+                mesh
+            );
+            SceneDescription::parse(&text).unwrap().build_world()
+        };
+
+        let bbox = world(format!(
+            "{{\"type\": \"mesh\", \"file\": {:?}, \"material\": \"m\"}}",
+            file
+        ))
+        .unwrap()
+        .bounding_box();
+        let err = world(format!("{{\"type\": \"mesh\", \"file\": {:?}}}", file))
+            .err()
+            .unwrap()
+            .to_string();
+        std::fs::remove_file(&file).unwrap();
+        assert!(bbox.z.min < -2.0 && bbox.z.max > -2.0 && bbox.z.size() < 0.001);
+        assert_eq!((bbox.x.min, bbox.x.max), (0.0, 1.0));
+        assert!(err.ends_with("the mesh has no vertex colors, give it a material"));
+    }
+
+    #[test]
+    fn mesh_keyframes_deform_over_the_shutter() {
+        let stl = |name: &str, x: f64| {
+            let file = std::env::temp_dir().join(format!("{}_{}.stl", std::process::id(), name));
+            std::fs::write(
+                &file,
+                format!(
+                    "solid t\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\n\
+                     vertex {} 1 0\nendloop\nendfacet\nendsolid t\n",
+                    x
+                ),
+            )
+            .unwrap();
+            file
+        };
+        let (rest, bent) = (stl("rest", 0.0), stl("bent", 2.0));
+        let text = format!(
+            "{{\"materials\": {{\"m\": {{\"type\": \"lambertian\", \"albedo\": [1, 1, 1]}}}},
+              \"objects\": [{{\"type\": \"mesh\", \"file\": {:?}, \"material\": \"m\",
+                              \"keyframes\": [{{\"time\": 0, \"file\": {:?}}},
+                                              {{\"time\": 1, \"file\": {:?}}}]}}]}}",
+            rest, rest, bent
+        );
+        let scene = SceneDescription::parse(&text).unwrap();
+        let world = scene.build_world().unwrap();
+        let saved = scene.to_json().unwrap();
+        std::fs::remove_file(&rest).unwrap();
+        std::fs::remove_file(&bent).unwrap();
+
+        // the top vertex slides from x = 0 to x = 2, so (1.3, 0.5) is only covered late
+        let hit = |time: f64| {
+            let ray = Ray {
+                origin: Point3::new(1.3, 0.5, 1.0),
+                dir: Vec3::new(0.0, 0.0, -1.0),
+                time,
+                medium_sample: 0.0,
+            };
+            world.hit(
+                &ray,
+                Interval {
+                    min: 0.001,
+                    max: f64::INFINITY,
+                },
+            )
+        };
+        assert!(hit(0.2).is_none());
+        let rec = hit(0.9).unwrap();
+        assert!((rec.velocity.x - 1.0).abs() < 1e-9 && rec.velocity.y.abs() < 1e-9);
+        assert_eq!(world.bounding_box().x.max, 2.0);
+        assert!(saved.contains("\"keyframes\": [{\"time\": 0, \"file\": "));
+    }
+
+    #[test]
+    fn generated_scenes_save_and_reload_identically() {
+        let mut built = crate::registry::find("random_spheres")
+            .unwrap()
+            .build(7)
+            .unwrap();
+        built.camera.set_resolution(16, 9);
+        built.render.samples_per_pixel = 2;
+        built.render.background = Some(Color::new(0.1, 0.2, 0.3));
+        let moving = Sphere::new_moving_by(
+            Point3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.5, 0.0),
+            Interval {
+                min: 0.25,
+                max: 0.75,
+            },
+            0.2,
+            Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0))),
+        );
+        built.world.add(Arc::new(moving));
+
+        let saved = SceneDescription::from_world(built.camera, built.render, &built.world)
+            .unwrap()
+            .to_json()
+            .unwrap();
+        let reloaded = SceneDescription::parse(&saved).unwrap();
+        assert_eq!(reloaded.to_json().unwrap(), saved);
+
+        let pixels = |camera: &CameraSettings, render: &RenderSettings, world: &Hittable_List| {
+            let pixels = camera.build(render).render_pixels(world);
+            pixels.iter().map(|c| (c.x, c.y, c.z)).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            pixels(
+                &reloaded.camera,
+                &reloaded.render,
+                &reloaded.build_world().unwrap()
+            ),
+            pixels(&built.camera, &built.render, &built.world)
+        );
+    }
+
+    #[test]
+    fn registry_scenes_save_and_reload() {
+        // the scenes with an image texture need the image file
+        for entry in crate::registry::SCENES
+            .iter()
+            .filter(|e| e.name != "earth" && e.name != "final_scene")
+        {
+            let built = entry.build(7).unwrap();
+            let saved = SceneDescription::from_world(built.camera, built.render, &built.world)
+                .unwrap()
+                .to_json()
+                .unwrap();
+            let reloaded = SceneDescription::parse(&saved).unwrap();
+            assert_eq!(reloaded.to_json().unwrap(), saved, "{}", entry.name);
+            let world = reloaded.build_world().unwrap();
+
+            // rays fanning out from the camera, off the edges where walls meet, hit the same
+            // surfaces in the same colors. The instances only differ from the quads baked in
+            // place by rounding.
+            let forward = built.camera.lookat - built.camera.lookfrom;
+            let everywhere = Interval {
+                min: 0.001,
+                max: f64::INFINITY,
+            };
+            let near = |a: Color, b: Color| (a - b).length() < 1e-9;
+            for i in 0..21 {
+                for j in 0..21 {
+                    let offset = Vec3::new(i as f64 - 10.37, j as f64 - 10.61, 0.0);
+                    let ray = Ray {
+                        origin: built.camera.lookfrom,
+                        dir: forward + forward.length() / 31.3 * offset,
+                        time: 0.5,
+                        medium_sample: 0.5,
+                    };
+                    match (
+                        built.world.hit(&ray, everywhere),
+                        world.hit(&ray, everywhere),
+                    ) {
+                        (None, None) => {}
+                        (Some(a), Some(b)) => {
+                            assert!((a.t - b.t).abs() < 1e-9 * a.t, "{}", entry.name);
+                            assert!(near(a.material.albedo(&a), b.material.albedo(&b)));
+                            assert!(near(
+                                a.material.emitted(a.u, a.v, a.p),
+                                b.material.emitted(b.u, b.v, b.p)
+                            ));
+                        }
+                        _ => panic!("{}: only one world was hit at ({}, {})", entry.name, i, j),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unsupported_objects_are_not_saved() {
+        let mut world = Hittable_List::new();
+        world.add(Arc::new(Sphere::new_static(
+            Point3::new(0.0, 0.0, 0.0),
+            1.0,
+            Arc::new(Isotropic::new(Color::new(0.5, 0.5, 0.5))),
+        )));
+        let err = SceneDescription::from_world(
+            CameraSettings::default(),
+            RenderSettings::default(),
+            &world,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err.to_string(), "Isotropic can't be saved in a scene file");
+    }
+
+    #[test]
+    fn resolution_is_exact() {
+        let mut camera = CameraSettings::default();
+        for (w, h) in [(400, 225), (1920, 1080), (7, 3), (1000, 999), (3, 1000)] {
+            camera.set_resolution(w, h);
+            let camera = camera.build(&RenderSettings::default());
+            assert_eq!((camera.image_width(), camera.image_height()), (w, h));
+        }
+    }
+}
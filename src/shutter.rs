@@ -0,0 +1,178 @@
+// Shutter efficiency over the normalized exposure [0, 1], how much light passes at each moment
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShutterCurve {
+    // Instantly open for the whole interval
+    Box,
+    // Linear ramps taking the given fractions of the interval to fully open and to close
+    Trapezoid { open: f64, close: f64 },
+    // Piecewise linear efficiency sampled at evenly spaced moments from open to close
+    Tabulated(Vec<f64>),
+}
+
+// When the camera shutter lets light in. Ray times are drawn from [open, close] weighted by the
+// curve; a rolling shutter delays the exposure of every scanline, top to bottom, by up to
+// readout_time.
+#[derive(Clone, Debug)]
+pub struct Shutter {
+    pub open: f64,
+    pub close: f64,
+    pub readout_time: f64,
+    curve: ShutterCurve,
+    // curve as (x, efficiency) vertices and the cumulative area at every vertex
+    points: Vec<(f64, f64)>,
+    cdf: Vec<f64>,
+}
+
+impl Default for Shutter {
+    fn default() -> Self {
+        Shutter::new(0.0, 1.0)
+    }
+}
+
+impl Shutter {
+    pub fn new(open: f64, close: f64) -> Self {
+        assert!(open <= close, "shutter must open before it closes");
+        let mut shutter = Shutter {
+            open,
+            close,
+            readout_time: 0.0,
+            curve: ShutterCurve::Box,
+            points: Vec::new(),
+            cdf: Vec::new(),
+        };
+        shutter.tabulate();
+        shutter
+    }
+
+    pub fn with_curve(mut self, curve: ShutterCurve) -> Self {
+        self.curve = curve;
+        self.tabulate();
+        self
+    }
+
+    pub fn with_rolling(mut self, readout_time: f64) -> Self {
+        self.readout_time = readout_time;
+        self
+    }
+
+    pub fn curve(&self) -> &ShutterCurve {
+        &self.curve
+    }
+
+    // Whole time span any ray can have, the exposure of the last scanline included
+    pub fn time_span(&self) -> (f64, f64) {
+        (self.open, self.close + self.readout_time)
+    }
+
+    fn tabulate(&mut self) {
+        self.points = match &self.curve {
+            ShutterCurve::Box => vec![(0.0, 1.0), (1.0, 1.0)],
+            ShutterCurve::Trapezoid { open, close } => {
+                assert!(
+                    *open >= 0.0 && *close >= 0.0 && open + close <= 1.0,
+                    "shutter ramps must fit in the exposure"
+                );
+                vec![(0.0, 0.0), (*open, 1.0), (1.0 - close, 1.0), (1.0, 0.0)]
+            }
+            ShutterCurve::Tabulated(values) => {
+                assert!(values.len() >= 2, "a shutter curve needs at least 2 values");
+                let n = (values.len() - 1) as f64;
+                values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, e)| (i as f64 / n, e.max(0.0)))
+                    .collect()
+            }
+        };
+
+        self.cdf = vec![0.0];
+        for pair in self.points.windows(2) {
+            let ((x0, e0), (x1, e1)) = (pair[0], pair[1]);
+            let area = 0.5 * (e0 + e1) * (x1 - x0);
+            self.cdf.push(self.cdf.last().unwrap() + area);
+        }
+        assert!(*self.cdf.last().unwrap() > 0.0, "shutter curve never opens");
+    }
+
+    // Position in the normalized exposure for a uniform random number u in [0, 1), by
+    // inverting the cumulative area under the piecewise linear curve
+    fn sample_exposure(&self, u: f64) -> f64 {
+        let target = u * self.cdf.last().unwrap();
+        let i = self
+            .cdf
+            .windows(2)
+            .position(|c| target < c[1])
+            .unwrap_or(self.cdf.len() - 2);
+
+        let ((x0, e0), (x1, e1)) = (self.points[i], self.points[i + 1]);
+        let area = self.cdf[i + 1] - self.cdf[i];
+        if area <= 0.0 {
+            return x0;
+        }
+        let local = (target - self.cdf[i]) / area;
+
+        // solve for f in [0, 1]: e0 * f + (e1 - e0) * f^2 / 2 = local * (e0 + e1) / 2
+        let slope = e1 - e0;
+        let f = if slope.abs() < 1e-12 {
+            local
+        } else {
+            (-e0 + (e0 * e0 + slope * local * (e0 + e1)).sqrt()) / slope
+        };
+        x0 + f.clamp(0.0, 1.0) * (x1 - x0)
+    }
+
+    // Ray time for a uniform random number u, row is the scanline position in [0, 1] from the top
+    pub fn sample_time(&self, u: f64, row: f64) -> f64 {
+        let start = self.open + self.readout_time * row;
+        start + self.sample_exposure(u) * (self.close - self.open)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn box_shutter_is_uniform_over_interval() {
+        let shutter = Shutter::new(2.0, 4.0);
+
+        assert!((shutter.sample_time(0.0, 0.0) - 2.0).abs() < 1e-12);
+        assert!((shutter.sample_time(0.25, 0.0) - 2.5).abs() < 1e-12);
+        assert!((shutter.sample_time(0.5, 0.0) - 3.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn trapezoid_shutter_concentrates_samples_in_the_middle() {
+        let shutter = Shutter::new(0.0, 1.0).with_curve(ShutterCurve::Trapezoid {
+            open: 0.5,
+            close: 0.5,
+        });
+
+        // symmetric triangle: the median is the peak, a quarter of the area lies before 0.5^1.5
+        assert!((shutter.sample_time(0.5, 0.0) - 0.5).abs() < 1e-12);
+        assert!((shutter.sample_time(0.125, 0.0) - 0.25).abs() < 1e-12);
+        assert!((shutter.sample_time(0.875, 0.0) - 0.75).abs() < 1e-12);
+    }
+
+    #[test]
+    fn tabulated_curve_is_monotonic() {
+        let shutter = Shutter::new(0.0, 1.0)
+            .with_curve(ShutterCurve::Tabulated(vec![0.0, 1.0, 0.2, 0.8, 0.0]));
+
+        let mut last = 0.0;
+        for i in 0..100 {
+            let t = shutter.sample_time(i as f64 / 100.0, 0.0);
+            assert!(t >= last && t <= 1.0);
+            last = t;
+        }
+    }
+
+    #[test]
+    fn rolling_shutter_delays_lower_rows() {
+        let shutter = Shutter::new(0.0, 0.5).with_rolling(1.0);
+
+        assert!((shutter.sample_time(0.5, 0.0) - 0.25).abs() < 1e-12);
+        assert!((shutter.sample_time(0.5, 1.0) - 1.25).abs() < 1e-12);
+        assert_eq!(shutter.time_span(), (0.0, 1.5));
+    }
+}
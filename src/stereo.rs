@@ -0,0 +1,153 @@
+use std::io;
+
+use crate::{camera::Camera, hittable::Hittable_List, vec3::Color};
+
+// How the two eye images are packed into one output image
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum StereoLayout {
+    SideBySide, // left eye on the left half
+    TopBottom,  // left eye on the top half
+}
+
+// A pair of cameras separated along the camera's u axis. Perspective eyes keep parallel
+// optical axes and use asymmetric (off-axis) frusta that coincide at convergence_dist, an
+// equirectangular camera turns into omni-directional stereo.
+pub struct StereoRig {
+    camera: Camera,
+    interocular_distance: f64,
+    convergence_dist: f64,
+    layout: StereoLayout,
+}
+
+impl StereoRig {
+    pub fn new(
+        camera: Camera,
+        interocular_distance: f64,
+        convergence_dist: f64,
+        layout: StereoLayout,
+    ) -> Self {
+        StereoRig {
+            camera,
+            interocular_distance,
+            convergence_dist,
+            layout,
+        }
+    }
+
+    pub fn left_eye(&self) -> Camera {
+        self.camera
+            .stereo_eye(-self.interocular_distance / 2.0, self.convergence_dist)
+    }
+
+    pub fn right_eye(&self) -> Camera {
+        self.camera
+            .stereo_eye(self.interocular_distance / 2.0, self.convergence_dist)
+    }
+
+    // Written like Camera::render writes, in the output format and with the denoiser of the
+    // left eye
+    pub fn render(&self, world: &Hittable_List, file_name: &str) -> io::Result<()> {
+        // both eyes share the image size and crop window
+        let (left_eye, right_eye) = (self.left_eye(), self.right_eye());
+        let left = left_eye.output_film(left_eye.render_film(world));
+        let right = right_eye.output_film(right_eye.render_film(world));
+
+        let (out_width, out_height, pixels) = compose(
+            self.layout,
+            left.width,
+            left.height,
+            &left_eye.output_colors(&left),
+            &right_eye.output_colors(&right),
+        );
+        left_eye.write_colors(file_name, out_width, out_height, &pixels)
+    }
+}
+
+// Pack two row-major eye images of the same size into one, returns (width, height, pixels)
+fn compose(
+    layout: StereoLayout,
+    width: u64,
+    height: u64,
+    left: &[Color],
+    right: &[Color],
+) -> (u64, u64, Vec<Color>) {
+    let w = width as usize;
+    match layout {
+        StereoLayout::SideBySide => {
+            let mut pixels = Vec::with_capacity(left.len() + right.len());
+            for (l, r) in left.chunks(w).zip(right.chunks(w)) {
+                pixels.extend_from_slice(l);
+                pixels.extend_from_slice(r);
+            }
+            (width * 2, height, pixels)
+        }
+        StereoLayout::TopBottom => {
+            let mut pixels = left.to_vec();
+            pixels.extend_from_slice(right);
+            (width, height * 2, pixels)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{film::ImageFormat, progress::SilentReporter, vec3::Vec3};
+
+    #[test]
+    fn compose_packs_eyes_by_layout() {
+        let l = Color::new(1.0, 0.0, 0.0);
+        let r = Color::new(0.0, 0.0, 1.0);
+        let left = vec![l; 4];
+        let right = vec![r; 4];
+
+        let (w, h, side_by_side) = compose(StereoLayout::SideBySide, 2, 2, &left, &right);
+        assert_eq!((w, h), (4, 2));
+        let reds: Vec<bool> = side_by_side.iter().map(|c| c.x == 1.0).collect();
+        assert_eq!(reds, [true, true, false, false, true, true, false, false]);
+
+        let (w, h, top_bottom) = compose(StereoLayout::TopBottom, 2, 2, &left, &right);
+        assert_eq!((w, h), (2, 4));
+        let reds: Vec<bool> = top_bottom.iter().map(|c| c.x == 1.0).collect();
+        assert_eq!(reds, [true, true, true, true, false, false, false, false]);
+    }
+
+    #[test]
+    fn rig_writes_in_the_output_format_of_the_camera() {
+        let camera = Camera::new(
+            1.0,
+            4,
+            90.0,
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            0.0,
+            1.0,
+            1,
+            1,
+        )
+        .with_progress_reporter(Arc::new(Mutex::new(SilentReporter)));
+        let file = std::env::temp_dir().join(format!("{}_stereo", std::process::id()));
+        let file_name =
+            |extension: &str| file.with_extension(extension).to_str().unwrap().to_string();
+        let header = |file_name: &str, rig: StereoRig| {
+            let world = Hittable_List::new();
+            rig.render(&world, file_name).unwrap();
+            let bytes = std::fs::read(file_name).unwrap();
+            std::fs::remove_file(file_name).unwrap();
+            bytes[..8].to_vec()
+        };
+        let rig = |camera: Camera| StereoRig::new(camera, 0.1, 1.0, StereoLayout::SideBySide);
+
+        // by the file extension, or the format the camera was given
+        assert_eq!(
+            header(&file_name("pfm"), rig(camera.clone())),
+            b"PF\n8 4\n-"
+        );
+        assert_eq!(header(&file_name("ppm"), rig(camera.clone()))[..2], *b"P3");
+        let png = camera.with_output_format(ImageFormat::Png);
+        assert_eq!(header(&file_name("ppm"), rig(png)), b"\x89PNG\r\n\x1a\n");
+    }
+}
@@ -0,0 +1,123 @@
+use std::{fs, io};
+
+use crate::{mesh::TriangleMesh, utils::invalid_data, vec3::Point3};
+
+impl TriangleMesh {
+    pub fn load_stl(file_name: &str) -> io::Result<TriangleMesh> {
+        let bytes = fs::read(file_name)?;
+        TriangleMesh::parse_stl(&bytes).map_err(|e| invalid_data(format!("{}: {}", file_name, e)))
+    }
+
+    // STL in binary or ASCII. Facets share no vertices and the stored facet normals are
+    // ignored, the winding gives the same normal and is what exporters get right.
+    pub fn parse_stl(bytes: &[u8]) -> io::Result<TriangleMesh> {
+        // ASCII files start with "solid" too, so tell binary apart by its exact length
+        if bytes.len() >= 84 {
+            let count = u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize;
+            if count.checked_mul(50).and_then(|n| n.checked_add(84)) == Some(bytes.len()) {
+                return Ok(parse_binary(&bytes[84..]));
+            }
+        }
+        parse_ascii(bytes)
+    }
+}
+
+fn parse_binary(facets: &[u8]) -> TriangleMesh {
+    let mut mesh = TriangleMesh::default();
+    for facet in facets.chunks_exact(50) {
+        // 12 bytes of normal, three vertices, 2 bytes of attributes
+        let f = |i: usize| {
+            let at = 12 + 4 * i;
+            f32::from_le_bytes([facet[at], facet[at + 1], facet[at + 2], facet[at + 3]]) as f64
+        };
+        let first = mesh.positions.len();
+        for v in 0..3 {
+            mesh.positions
+                .push(Point3::new(f(3 * v), f(3 * v + 1), f(3 * v + 2)));
+        }
+        mesh.triangles.push([first, first + 1, first + 2]);
+    }
+    mesh
+}
+
+fn parse_ascii(bytes: &[u8]) -> io::Result<TriangleMesh> {
+    let text =
+        std::str::from_utf8(bytes).map_err(|_| invalid_data("not an STL file".to_string()))?;
+    let mut mesh = TriangleMesh::default();
+    let mut facet = Vec::new();
+
+    for (n, line) in text.lines().enumerate() {
+        let error = |msg: String| invalid_data(format!("line {}: {}", n + 1, msg));
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            _ if n == 0 && words.first() != Some(&"solid") => {
+                return Err(error("not an STL file".to_string()));
+            }
+            ["solid", ..] | ["endsolid", ..] | ["facet", ..] | ["outer", "loop"] | [] => {}
+            ["vertex", x, y, z] => {
+                let coordinate = |s: &str| {
+                    s.parse::<f64>()
+                        .map_err(|_| error(format!("expected a number, got {:?}", s)))
+                };
+                facet.push(Point3::new(coordinate(x)?, coordinate(y)?, coordinate(z)?));
+            }
+            ["endloop"] => {
+                if facet.len() != 3 {
+                    return Err(error(format!("facet with {} vertices", facet.len())));
+                }
+                let first = mesh.positions.len();
+                mesh.positions.append(&mut facet);
+                mesh.triangles.push([first, first + 1, first + 2]);
+            }
+            ["endfacet"] => {}
+            _ => return Err(error(format!("unexpected {:?}", line.trim()))),
+        }
+    }
+    Ok(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ascii() {
+        let text = "solid tri
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 1 0.5
+    endloop
+  endfacet
+endsolid tri
+";
+        let mesh = TriangleMesh::parse_stl(text.as_bytes()).unwrap();
+        assert_eq!(mesh.triangles, vec![[0, 1, 2]]);
+        assert_eq!(mesh.positions[2].z, 0.5);
+
+        let err = TriangleMesh::parse_stl(b"solid\nfacet\nouter loop\nvertex 0 0 x\n")
+            .unwrap_err()
+            .to_string();
+        assert_eq!(err, "line 4: expected a number, got \"x\"");
+    }
+
+    #[test]
+    fn parses_binary_even_with_a_solid_header() {
+        let mut bytes = b"solid but binary".to_vec();
+        bytes.resize(80, 0);
+        bytes.extend(2u32.to_le_bytes());
+        for z in [0.0f32, 2.0] {
+            bytes.extend([0u8; 12]);
+            for v in [[0.0, 0.0, z], [1.0, 0.0, z], [0.0, 1.0, z]] {
+                for c in v {
+                    bytes.extend(f32::to_le_bytes(c));
+                }
+            }
+            bytes.extend([0u8; 2]);
+        }
+        let mesh = TriangleMesh::parse_stl(&bytes).unwrap();
+        assert_eq!(mesh.triangles, vec![[0, 1, 2], [3, 4, 5]]);
+        assert_eq!((mesh.positions[4].x, mesh.positions[4].z), (1.0, 2.0));
+    }
+}
@@ -0,0 +1,213 @@
+use std::{any::type_name, io, sync::Arc};
+
+use crate::{
+    checkpoint::Fingerprint,
+    image::Image,
+    perlin::Perlin,
+    scene::{TextureDescription, cannot_save},
+    utils::random_int_range,
+    vec3::{Color, Point3},
+};
+
+// Color varying over a surface, looked up by the surface coordinates (u, v) of a hit and its
+// position p
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, p: Point3) -> Color;
+
+    // Add this texture to the fingerprint of a render, by default it can't be checkpointed
+    fn fingerprint(&self, f: &mut Fingerprint) {
+        f.unknown(type_name::<Self>());
+    }
+
+    // This texture in the scene format, to save a scene
+    fn describe(&self) -> io::Result<TextureDescription> {
+        Err(cannot_save(
+            type_name::<Self>().rsplit("::").next().unwrap_or_default(),
+        ))
+    }
+}
+
+pub struct SolidColor {
+    pub albedo: Color,
+}
+
+impl Texture for SolidColor {
+    fn fingerprint(&self, f: &mut Fingerprint) {
+        f.name("solid");
+        f.vec3(self.albedo);
+    }
+
+    fn value(&self, _u: f64, _v: f64, _p: Point3) -> Color {
+        self.albedo
+    }
+
+    fn describe(&self) -> io::Result<TextureDescription> {
+        Ok(TextureDescription::Solid(self.albedo))
+    }
+}
+
+// 3D checkerboard of cubes with side scale, alternating between two textures
+pub struct CheckerTexture {
+    scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        CheckerTexture { scale, even, odd }
+    }
+
+    pub fn from_colors(scale: f64, even: Color, odd: Color) -> Self {
+        CheckerTexture::new(
+            scale,
+            Arc::new(SolidColor { albedo: even }),
+            Arc::new(SolidColor { albedo: odd }),
+        )
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn fingerprint(&self, f: &mut Fingerprint) {
+        f.name("checker");
+        f.number(self.scale);
+        self.even.fingerprint(f);
+        self.odd.fingerprint(f);
+    }
+
+    fn value(&self, u: f64, v: f64, p: Point3) -> Color {
+        let x = (p.x / self.scale).floor() as i64;
+        let y = (p.y / self.scale).floor() as i64;
+        let z = (p.z / self.scale).floor() as i64;
+        if (x + y + z) % 2 == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+
+    fn describe(&self) -> io::Result<TextureDescription> {
+        Ok(TextureDescription::Checker {
+            scale: self.scale,
+            even: Box::new(self.even.describe()?),
+            odd: Box::new(self.odd.describe()?),
+        })
+    }
+}
+
+// Image stretched over the (u, v) unit square, v pointing up
+pub struct ImageTexture {
+    image: Image,
+    file: Option<String>, // where the image was loaded from, if it was
+}
+
+impl ImageTexture {
+    pub fn new(image: Image) -> Self {
+        ImageTexture { image, file: None }
+    }
+
+    pub fn load(file_name: &str) -> io::Result<Self> {
+        Ok(ImageTexture {
+            image: Image::load_pnm(file_name)?,
+            file: Some(file_name.to_string()),
+        })
+    }
+}
+
+impl Texture for ImageTexture {
+    fn fingerprint(&self, f: &mut Fingerprint) {
+        f.name("image");
+        f.add(self.image.width as u64);
+        f.add(self.image.height as u64);
+        self.image.pixels.iter().for_each(|c| f.vec3(*c));
+    }
+
+    fn value(&self, u: f64, v: f64, _p: Point3) -> Color {
+        // cyan makes a missing image stand out
+        if self.image.height == 0 {
+            return Color::new(0.0, 1.0, 1.0);
+        }
+        let u = u.clamp(0.0, 1.0);
+        let v = 1.0 - v.clamp(0.0, 1.0); // image rows go top to bottom
+        let i = ((u * self.image.width as f64) as usize).min(self.image.width - 1);
+        let j = ((v * self.image.height as f64) as usize).min(self.image.height - 1);
+        // image files are gamma encoded, undo the gamma 2 the output applies
+        let c = self.image.pixel(i, j);
+        c * c
+    }
+
+    fn describe(&self) -> io::Result<TextureDescription> {
+        match &self.file {
+            Some(file) => Ok(TextureDescription::Image { file: file.clone() }),
+            None => Err(cannot_save("an image texture not loaded from a file")),
+        }
+    }
+}
+
+// Marble-like veins: a sine along z phase shifted by Perlin turbulence
+pub struct NoiseTexture {
+    noise: Perlin,
+    scale: f64,
+    seed: u64,
+}
+
+impl NoiseTexture {
+    // Noise with a seed drawn from the scene generation random numbers, so seed_random picks
+    // the pattern. Seeds stay below 2^53 to fit a JSON number.
+    pub fn new(scale: f64) -> Self {
+        NoiseTexture::with_seed(scale, random_int_range(0, 1 << 53) as u64)
+    }
+
+    pub fn with_seed(scale: f64, seed: u64) -> Self {
+        NoiseTexture {
+            noise: Perlin::new(seed),
+            scale,
+            seed,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn fingerprint(&self, f: &mut Fingerprint) {
+        f.name("noise");
+        f.number(self.scale);
+        self.noise.fingerprint(f);
+    }
+
+    fn value(&self, _u: f64, _v: f64, p: Point3) -> Color {
+        Color::new(0.5, 0.5, 0.5) * (1.0 + (self.scale * p.z + 10.0 * self.noise.turb(p, 7)).sin())
+    }
+
+    fn describe(&self) -> io::Result<TextureDescription> {
+        Ok(TextureDescription::Noise {
+            scale: self.scale,
+            seed: self.seed,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checker_alternates_between_cells() {
+        let checker =
+            CheckerTexture::from_colors(1.0, Color::new(1.0, 1.0, 1.0), Color::new(0.0, 0.0, 0.0));
+        assert_eq!(checker.value(0.0, 0.0, Point3::new(0.5, 0.5, 0.5)).x, 1.0);
+        assert_eq!(checker.value(0.0, 0.0, Point3::new(1.5, 0.5, 0.5)).x, 0.0);
+        assert_eq!(checker.value(0.0, 0.0, Point3::new(-0.5, 0.5, 0.5)).x, 0.0);
+    }
+
+    #[test]
+    fn image_texture_maps_v_up() {
+        let image = Image {
+            width: 1,
+            height: 2,
+            pixels: vec![Color::new(1.0, 1.0, 1.0), Color::new(0.5, 0.5, 0.5)],
+        };
+        let texture = ImageTexture::new(image);
+        assert_eq!(texture.value(0.5, 0.9, Point3::default()).x, 1.0);
+        assert_eq!(texture.value(0.5, 0.1, Point3::default()).x, 0.25);
+    }
+}
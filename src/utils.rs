@@ -1,20 +1,27 @@
-use std::f64::consts::PI;
+use std::{cell::RefCell, f64::consts::PI, io};
 
-use rand::Rng;
+use rand::{Rng, SeedableRng, rngs::SmallRng};
+
+thread_local! {
+    // Generator behind the random_* helpers, used for scene generation. Seeded with 0 so runs
+    // are reproducible unless seed_random picks another seed.
+    static RNG: RefCell<SmallRng> = RefCell::new(SmallRng::seed_from_u64(0));
+}
+
+pub fn seed_random(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(seed));
+}
 
 pub fn random_double() -> f64 {
-    let mut rng = rand::rng();
-    rng.random::<f64>()
+    RNG.with(|rng| rng.borrow_mut().random::<f64>())
 }
 
 pub fn random_double_range(min: f64, max: f64) -> f64 {
-    let mut rng = rand::rng();
-    rng.random_range(min..max)
+    RNG.with(|rng| rng.borrow_mut().random_range(min..max))
 }
 
 pub fn random_int_range(min: i64, max: i64) -> i64 {
-    let mut rng = rand::rng();
-    rng.random_range(min..max)
+    RNG.with(|rng| rng.borrow_mut().random_range(min..max))
 }
 
 pub fn linear_to_gamma(val: f64) -> f64 {
@@ -28,3 +35,8 @@ pub fn degrees_to_radian(d: f64) -> f64 {
 pub fn double_eq(a: f64, b: f64) -> bool {
     (a - b).abs() < f64::EPSILON
 }
+
+// Error of a file or description that doesn't parse or make sense
+pub(crate) fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -1,8 +1,10 @@
-use std::ops::{Add, AddAssign, Div, DivAssign, Index, Mul, MulAssign, Neg, Sub};
+use std::ops::{Add, AddAssign, Div, DivAssign, Index, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use std::f64::consts::{FRAC_PI_4, PI};
 
 use crate::utils::{random_double, random_double_range};
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Default)]
 pub struct Vec3 {
     pub x: f64,
     pub y: f64,
@@ -77,6 +79,31 @@ impl Vec3 {
             }
         }
     }
+
+    // Deterministic counterparts of the random_* functions, mapping a uniform 2D sample
+    // without rejection so stratification of the sample carries over
+
+    pub fn sample_unit_vector(u: (f64, f64)) -> Vec3 {
+        let z = 1.0 - 2.0 * u.0;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.1;
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
+    }
+
+    // Shirley-Chiu concentric mapping of the square onto the disk
+    pub fn sample_in_unit_disk(u: (f64, f64)) -> Vec3 {
+        let ox = 2.0 * u.0 - 1.0;
+        let oy = 2.0 * u.1 - 1.0;
+        if ox == 0.0 && oy == 0.0 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        let (r, theta) = if ox.abs() > oy.abs() {
+            (ox, FRAC_PI_4 * (oy / ox))
+        } else {
+            (oy, 2.0 * FRAC_PI_4 - FRAC_PI_4 * (ox / oy))
+        };
+        Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+    }
 }
 impl Index<usize> for Vec3 {
     type Output = f64;
@@ -138,6 +165,14 @@ impl Sub for Vec3 {
     }
 }
 
+impl SubAssign for Vec3 {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.x -= rhs.x;
+        self.y -= rhs.y;
+        self.z -= rhs.z;
+    }
+}
+
 impl Mul<f64> for Vec3 {
     type Output = Vec3;
 
@@ -194,6 +229,18 @@ impl Div<f64> for Vec3 {
     }
 }
 
+impl Div<Vec3> for Vec3 {
+    type Output = Vec3;
+
+    fn div(self, v: Vec3) -> Self::Output {
+        Vec3 {
+            x: self.x / v.x,
+            y: self.y / v.y,
+            z: self.z / v.z,
+        }
+    }
+}
+
 impl DivAssign<f64> for Vec3 {
     fn div_assign(&mut self, t: f64) {
         self.x /= t;
@@ -261,6 +308,14 @@ mod tests {
         assert_eq!(dot(v1, v2), 4.0 + 10.0 + 18.0); // 32.0
     }
 
+    #[test]
+    fn test_vec3_sample_mappings() {
+        for (a, b) in [(0.0, 0.0), (0.1, 0.9), (0.5, 0.5), (0.99, 0.3)] {
+            assert!((Vec3::sample_unit_vector((a, b)).length() - 1.0).abs() < 1e-12);
+            assert!(Vec3::sample_in_unit_disk((a, b)).length() <= 1.0 + 1e-12);
+        }
+    }
+
     #[test]
     fn test_vec3_cross() {
         let v1 = Vec3::new(1.0, 0.0, 0.0);